@@ -0,0 +1,40 @@
+#![no_main]
+use chess_engine::search::{SearchStats, Searcher};
+use cozy_chess::{Board, GameStatus};
+use libfuzzer_sys::fuzz_target;
+
+const MAX_PLIES: usize = 80;
+
+// Walks a random legal line from the start position (each input byte selects a move), then
+// checks that a shallow search of the resulting position returns a legal move.
+fuzz_target!(|data: &[u8]| {
+    let Some((&depth, plies)) = data.split_first() else {
+        return;
+    };
+
+    let mut board = Board::startpos();
+    for &choice in plies.iter().take(MAX_PLIES) {
+        let mut legal = Vec::new();
+        board.generate_moves(|moves| {
+            legal.extend(moves);
+            false
+        });
+        if legal.is_empty() {
+            break;
+        }
+        board.play_unchecked(legal[usize::from(choice) % legal.len()]);
+    }
+
+    if board.status() != GameStatus::Ongoing {
+        return;
+    }
+
+    let mut searcher = Searcher::new(1 << 20);
+    let (bm, _) = searcher.search_fixed_depth(
+        &mut board.clone(),
+        &Vec::new(),
+        &mut SearchStats::default(),
+        depth % 4 + 1,
+    );
+    assert!(board.is_legal(bm), "illegal best move {bm} in {board}");
+});
@@ -0,0 +1,15 @@
+#![no_main]
+use std::sync::mpsc;
+
+use chess_engine::uci::UciState;
+use libfuzzer_sys::fuzz_target;
+
+// Feeds arbitrary GUI input through the UCI front end, which must never panic
+fuzz_target!(|data: &[u8]| {
+    let input = String::from_utf8_lossy(data);
+    let (tx, _rx) = mpsc::channel();
+    let mut state = UciState::new();
+    for line in input.lines() {
+        let _ = state.handle_line(line, &tx);
+    }
+});
@@ -0,0 +1,356 @@
+use std::{
+    fmt::Write as _,
+    fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use cozy_chess::{Board, Move};
+
+use crate::{
+    checkpoint::Checkpoint,
+    cli::Flags,
+    search::{SearchStats, Searcher},
+    types::{Depth, Value},
+    utils::{to_uci_move, uci_line},
+};
+
+const DEFAULT_DEPTH: Depth = 8;
+const DEFAULT_HASH_MB: usize = 16;
+const STARTPOS_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReportFormat {
+    Json,
+    Csv,
+}
+
+// Search budget spent on every position of the file, or none at all for the static eval
+#[derive(Debug, Clone, Copy)]
+enum Budget {
+    Depth(Depth),
+    MoveTime(Duration),
+    Static,
+}
+
+#[derive(Debug)]
+struct AnalyzeConfig {
+    input: String,
+    output: Option<String>,
+    budget: Budget,
+    threads: usize,
+    hash_mb: usize,
+    format: ReportFormat,
+}
+
+#[derive(Debug)]
+struct AnalysisResult {
+    fen: String,
+    // None for the static eval
+    best_move: Option<Move>,
+    score: Value,
+    depth: Depth,
+    nodes: u64,
+    time: Duration,
+    pv: Vec<Move>,
+}
+
+// Entry point of the `analyze-file` subcommand, also reached with `analyze --file`. Every
+// position of the file is searched, or only evaluated with `--static`, which is handy for
+// building datasets or comparing eval versions. The threads share the `--hash` budget, each
+// searching with its own slice of it.
+// Usage: analyze-file <file> [--depth N | --movetime MS | --static] [--threads N] [--hash MB]
+//        [--format json|csv] [--output FILE]
+pub fn run(args: &[String]) -> Result<(), String> {
+    let config = parse_args(args)?;
+    let contents = fs::read_to_string(&config.input)
+        .map_err(|e| format!("could not read {}: {e}", config.input))?;
+
+    let mut boards = Vec::new();
+    for (line_num, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        match parse_epd_line(line) {
+            Some(board) => boards.push(board),
+            None => eprintln!(
+                "analyze-file: skipping invalid position on line {}",
+                line_num + 1
+            ),
+        }
+    }
+
+    let start = Instant::now();
+    let results = analyze_positions(&boards, &config);
+    eprintln!(
+        "analyze-file: {} positions in {:.3}s",
+        results.len(),
+        start.elapsed().as_secs_f64()
+    );
+
+    let report = match config.format {
+        ReportFormat::Json => format_json(&results),
+        ReportFormat::Csv => format_csv(&results),
+    };
+
+    match &config.output {
+        Some(path) => fs::write(path, report).map_err(|e| format!("could not write {path}: {e}")),
+        None => {
+            print!("{report}");
+            Ok(())
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<AnalyzeConfig, String> {
+    let flags = Flags::parse_with_switches(
+        args,
+        &[
+            "--depth",
+            "--movetime",
+            "--threads",
+            "--hash",
+            "--file",
+            "--format",
+            "--output",
+        ],
+        &["--static"],
+    )?;
+    let input = match (flags.positional(), flags.get::<String>("--file")?) {
+        ([], Some(path)) | ([path], None) => path.clone(),
+        ([], None) => return Err("missing input file".to_owned()),
+        ([extra, ..], Some(_)) | ([_, extra, ..], None) => {
+            return Err(format!("unexpected argument {extra}"))
+        }
+    };
+    let budget = if flags.has("--static") {
+        Budget::Static
+    } else if let Some(ms) = flags.get::<u64>("--movetime")? {
+        Budget::MoveTime(Duration::from_millis(ms))
+    } else {
+        Budget::Depth(flags.get_or("--depth", DEFAULT_DEPTH)?)
+    };
+    let format = match flags.get::<String>("--format")?.as_deref() {
+        None | Some("json") => ReportFormat::Json,
+        Some("csv") => ReportFormat::Csv,
+        Some(other) => return Err(format!("unknown format {other}")),
+    };
+
+    Ok(AnalyzeConfig {
+        input,
+        output: flags.get("--output")?,
+        budget,
+        threads: flags
+            .get_or(
+                "--threads",
+                thread::available_parallelism().map_or(1, usize::from),
+            )?
+            .max(1),
+        hash_mb: flags.get_or("--hash", DEFAULT_HASH_MB)?.max(1),
+        format,
+    })
+}
+
+// Entry point of the `analyze` subcommand, for long analyses of a single position. Without a
+// depth or movetime limit the analysis runs until interrupted. With `--checkpoint` the analysis
+// state is saved after every completed depth, and `--resume` continues from such a file.
+// Usage: analyze [FEN] [--depth N | --movetime MS] [--hash MB] [--checkpoint FILE]
+//        [--resume FILE]
+pub fn run_position(args: &[String]) -> Result<(), String> {
+    // A file of positions is analyzed like with `analyze-file`
+    if args.iter().any(|arg| arg == "--file") {
+        return run(args);
+    }
+
+    let flags = Flags::parse(
+        args,
+        &[
+            "--depth",
+            "--movetime",
+            "--hash",
+            "--checkpoint",
+            "--resume",
+        ],
+    )?;
+    let fen = match flags.positional() {
+        [] => None,
+        [fen] => Some(fen.as_str()),
+        [_, extra, ..] => return Err(format!("unexpected argument {extra}")),
+    };
+    let depth = flags.get_or("--depth", Depth::MAX)?;
+    let move_time = flags
+        .get::<u64>("--movetime")?
+        .map_or(Duration::MAX, Duration::from_millis);
+    let hash_mb = flags.get_or("--hash", DEFAULT_HASH_MB)?.max(1);
+    let checkpoint_path = flags.get::<PathBuf>("--checkpoint")?;
+    let resume_path = flags.get::<PathBuf>("--resume")?;
+
+    let mut searcher = Searcher::new(hash_mb * 1024 * 1024);
+    let mut board = if let Some(path) = &resume_path {
+        let checkpoint = Checkpoint::load(path)
+            .map_err(|e| format!("could not load {}: {e}", path.display()))?;
+        eprintln!(
+            "analyze: resuming {} from depth {}, best move {} ({})",
+            checkpoint.fen, checkpoint.depth, checkpoint.best_move, checkpoint.best_value
+        );
+        searcher
+            .restore(&checkpoint)
+            .ok_or("invalid position in checkpoint")?
+    } else {
+        let fen = fen.unwrap_or(STARTPOS_FEN);
+        Board::from_fen(fen, false).map_err(|_| format!("invalid FEN {fen}"))?
+    };
+
+    // Resumed analyses keep saving to the file they were resumed from by default
+    searcher.set_checkpoint_path(checkpoint_path.or(resume_path));
+
+    let mut stats = SearchStats::default();
+    let (best_move, score) = searcher.search(&mut board, &Vec::new(), &mut stats, depth, move_time);
+    let pv = searcher.principal_variation(&board, stats.depth);
+    println!(
+        "bestmove {} score {score} depth {} nodes {} pv {}",
+        to_uci_move(&board, best_move, false),
+        stats.depth,
+        stats.nodes_visited,
+        format_line(&uci_line(&board, &pv, false)),
+    );
+    Ok(())
+}
+
+// EPD lines only carry the first four FEN fields followed by operations (`bm`, `id`, ...).
+// Plain FEN lines are accepted as well.
+fn parse_epd_line(line: &str) -> Option<Board> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    if fields.len() < 4 {
+        return None;
+    }
+
+    let counters_present =
+        fields.len() >= 6 && fields[4].parse::<u32>().is_ok() && fields[5].parse::<u32>().is_ok();
+    let fen = if counters_present {
+        fields[..6].join(" ")
+    } else {
+        format!("{} 0 1", fields[..4].join(" "))
+    };
+
+    Board::from_fen(&fen, false).ok()
+}
+
+fn analyze_positions(boards: &[Board], config: &AnalyzeConfig) -> Vec<AnalysisResult> {
+    // Threads pull positions off a shared counter, so uneven positions balance out
+    let next_idx = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(boards.len()));
+
+    // The hash budget is split between the threads rather than given to each of them
+    let threads = config.threads.min(boards.len());
+    let tt_size = config.hash_mb * 1024 * 1024 / threads.max(1);
+    thread::scope(|s| {
+        for _ in 0..threads {
+            s.spawn(|| {
+                let mut searcher = Searcher::new(tt_size);
+                loop {
+                    let idx = next_idx.fetch_add(1, Ordering::Relaxed);
+                    let Some(board) = boards.get(idx) else {
+                        break;
+                    };
+                    let result = analyze_position(&mut searcher, board, config.budget);
+                    results.lock().unwrap().push((idx, result));
+                }
+            });
+        }
+    });
+
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|&(idx, _)| idx);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+fn analyze_position(searcher: &mut Searcher, board: &Board, budget: Budget) -> AnalysisResult {
+    let start = Instant::now();
+    if matches!(budget, Budget::Static) {
+        return AnalysisResult {
+            fen: board.to_string(),
+            best_move: None,
+            score: searcher.static_eval(board),
+            depth: 0,
+            nodes: 0,
+            time: start.elapsed(),
+            pv: Vec::new(),
+        };
+    }
+
+    searcher.new_game();
+    let mut board = board.clone();
+    let mut stats = SearchStats::default();
+    let moves = Vec::new();
+    let (best_move, score) = match budget {
+        Budget::Depth(depth) => searcher.search_fixed_depth(&mut board, &moves, &mut stats, depth),
+        Budget::MoveTime(time) => searcher.search_for_time(&mut board, &moves, &mut stats, time),
+        Budget::Static => unreachable!(),
+    };
+    let time = start.elapsed();
+    let pv = searcher.principal_variation(&board, stats.depth);
+
+    AnalysisResult {
+        fen: board.to_string(),
+        best_move: Some(to_uci_move(&board, best_move, false)),
+        score,
+        depth: stats.depth,
+        nodes: stats.nodes_visited,
+        time,
+        pv: uci_line(&board, &pv, false),
+    }
+}
+
+fn format_line(line: &[Move]) -> String {
+    line.iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn format_json(results: &[AnalysisResult]) -> String {
+    let mut out = String::from("[\n");
+    for (i, res) in results.iter().enumerate() {
+        let separator = if i + 1 == results.len() { "" } else { "," };
+        let best_move = res
+            .best_move
+            .map_or_else(|| "null".to_owned(), |mv| format!("\"{mv}\""));
+        let _ = writeln!(
+            out,
+            "  {{\"fen\": \"{}\", \"best_move\": {best_move}, \"score\": {}, \"depth\": {}, \"nodes\": {}, \"time_ms\": {}, \"pv\": \"{}\"}}{separator}",
+            res.fen,
+            res.score,
+            res.depth,
+            res.nodes,
+            res.time.as_millis(),
+            format_line(&res.pv),
+        );
+    }
+    out.push_str("]\n");
+    out
+}
+
+fn format_csv(results: &[AnalysisResult]) -> String {
+    let mut out = String::from("fen,best_move,score,depth,nodes,time_ms,pv\n");
+    for res in results {
+        let _ = writeln!(
+            out,
+            "{},{},{},{},{},{},{}",
+            res.fen,
+            res.best_move.map_or_else(String::new, |mv| mv.to_string()),
+            res.score,
+            res.depth,
+            res.nodes,
+            res.time.as_millis(),
+            format_line(&res.pv),
+        );
+    }
+    out
+}
@@ -0,0 +1,246 @@
+use std::{fs, time::Duration};
+
+use cozy_chess::{Color, GameStatus, Move};
+
+use crate::{
+    cli::Flags,
+    pgn::{parse_games, to_san, write_game, Game},
+    search::{is_mate_score, mate_distance, SearchStats, Searcher, MATE_VALUE},
+    types::{Depth, Value},
+    utils::uci_line,
+    wdl::wdl,
+};
+
+const DEFAULT_DEPTH: Depth = 10;
+const DEFAULT_HASH_MB: usize = 16;
+// Drops in the expected score of the side that moved, in permille, from which a move is an
+// inaccuracy, a mistake or a blunder
+const INACCURACY: i32 = 100;
+const MISTAKE: i32 = 200;
+const BLUNDER: i32 = 300;
+
+// How bad a move was, by how much it lowered the expected score of the side that played it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Judgement {
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl Judgement {
+    const fn from_drop(drop: i32) -> Self {
+        if drop >= BLUNDER {
+            Self::Blunder
+        } else if drop >= MISTAKE {
+            Self::Mistake
+        } else if drop >= INACCURACY {
+            Self::Inaccuracy
+        } else {
+            Self::Good
+        }
+    }
+
+    // The move suffix and the word for the comment
+    const fn annotation(self) -> Option<(&'static str, &'static str)> {
+        match self {
+            Self::Good => None,
+            Self::Inaccuracy => Some(("?!", "Inaccuracy")),
+            Self::Mistake => Some(("?", "Mistake")),
+            Self::Blunder => Some(("??", "Blunder")),
+        }
+    }
+}
+
+// Expected score of the side to move in permille, a win counting as 1 and a draw as 1/2. Score
+// swings are measured in it, so that throwing away a pawn of a won position matters less than
+// throwing away the only pawn of advantage.
+#[allow(clippy::cast_possible_wrap)]
+fn expected_score(value: Value) -> i32 {
+    let (win, draw, _) = wdl(value);
+    (2 * win + draw) as i32 / 2
+}
+
+// Formats a score from white's point of view like the `[%eval]` command of PGN comments: in pawns,
+// or `#N` for a mate in N, negative if black mates
+fn format_eval(value: Value, side_to_move: Color) -> String {
+    let value = if side_to_move == Color::White {
+        value
+    } else {
+        -value
+    };
+    if is_mate_score(value) {
+        let moves_to_mate = mate_distance(value).div_ceil(2);
+        format!("#{}{moves_to_mate}", if value < 0 { "-" } else { "" })
+    } else {
+        format!("{:.2}", f64::from(value) / 100.0)
+    }
+}
+
+// Best move and score of every position of the game, the last one included. Positions where the
+// game is over have no best move, and the score the result gives them.
+fn search_positions(
+    searcher: &mut Searcher,
+    game: &Game,
+    depth: Depth,
+    move_time: Duration,
+) -> Vec<(Option<Move>, Value)> {
+    searcher.new_game();
+    let mut board = game.start.clone();
+    let mut scores = Vec::new();
+    for ply in 0..=game.moves.len() {
+        scores.push(match board.status() {
+            GameStatus::Won => (None, -MATE_VALUE),
+            GameStatus::Drawn => (None, 0),
+            GameStatus::Ongoing => {
+                // The moves so far, so that the search knows about repetitions
+                let mut root = game.start.clone();
+                let moves = uci_line(&game.start, &game.moves[..ply], false);
+                let mut stats = SearchStats::default();
+                let (best_move, score) =
+                    searcher.search(&mut root, &moves, &mut stats, depth, move_time);
+                (Some(best_move), score)
+            }
+        });
+        if let Some(&mv) = game.moves.get(ply) {
+            board.play_unchecked(mv);
+        }
+    }
+    scores
+}
+
+// The suffix and comment of every move: the eval after it, and for a bad move what it was and
+// the move the engine would have played instead
+fn annotate_game(
+    game: &Game,
+    scores: &[(Option<Move>, Value)],
+) -> Vec<(Option<&'static str>, Option<String>)> {
+    let mut board = game.start.clone();
+    let mut annotations = Vec::new();
+    for (ply, &mv) in game.moves.iter().enumerate() {
+        let (best_move, before) = scores[ply];
+        let (next_best_move, after) = scores[ply + 1];
+        let after = -after;
+        let drop = if best_move == Some(mv) {
+            0
+        } else {
+            expected_score(before) - expected_score(after)
+        };
+        let annotation = Judgement::from_drop(drop).annotation();
+
+        let mut comment = Vec::new();
+        // Once the game is over, the result says it all
+        if next_best_move.is_some() {
+            comment.push(format!(
+                "[%eval {}]",
+                format_eval(after, board.side_to_move())
+            ));
+        }
+        if let (Some((_, word)), Some(best_move)) = (annotation, best_move) {
+            comment.push(format!("{word}. {} was best.", to_san(&board, best_move)));
+        }
+        annotations.push((
+            annotation.map(|(suffix, _)| suffix),
+            (!comment.is_empty()).then(|| comment.join(" ")),
+        ));
+        board.play_unchecked(mv);
+    }
+    annotations
+}
+
+// Entry point of the `annotate` subcommand, which searches every position of the games of a PGN
+// file and writes them back with the eval after every move as a `[%eval]` comment. Moves that
+// lose a good deal of the expected score are marked as inaccuracies (`?!`), mistakes (`?`) or
+// blunders (`??`), with the move the engine prefers.
+// Usage: annotate FILE [--depth N | --movetime MS] [--hash MB] [--output FILE]
+pub fn run(args: &[String]) -> Result<(), String> {
+    let flags = Flags::parse(args, &["--depth", "--movetime", "--hash", "--output"])?;
+    let [path] = flags.positional() else {
+        return Err("expected a PGN file".to_owned());
+    };
+    let (depth, move_time) = match flags.get::<u64>("--movetime")? {
+        Some(ms) => (
+            flags.get_or("--depth", Depth::MAX)?,
+            Duration::from_millis(ms),
+        ),
+        None => (flags.get_or("--depth", DEFAULT_DEPTH)?, Duration::MAX),
+    };
+    let hash_mb = flags.get_or("--hash", DEFAULT_HASH_MB)?.max(1);
+
+    let text = fs::read_to_string(path).map_err(|e| format!("could not read {path}: {e}"))?;
+    let games = parse_games(&text)?;
+    let mut searcher = Searcher::new(hash_mb * 1024 * 1024);
+    let mut out = String::new();
+    for (i, game) in games.iter().enumerate() {
+        eprintln!("annotate: game {} of {}", i + 1, games.len());
+        let scores = search_positions(&mut searcher, game, depth, move_time);
+        out.push_str(&write_game(game, &annotate_game(game, &scores)));
+    }
+
+    match flags.get::<String>("--output")? {
+        Some(path) => fs::write(&path, out).map_err(|e| format!("could not write {path}: {e}")),
+        None => {
+            print!("{out}");
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cozy_chess::Color;
+
+    use crate::{
+        pgn::{parse_games, parse_san},
+        search::MATE_VALUE,
+    };
+
+    use super::{annotate_game, expected_score, format_eval, Judgement};
+
+    #[test]
+    fn judge_by_expected_score() {
+        assert_eq!(expected_score(0), 500);
+        assert_eq!(Judgement::from_drop(50), Judgement::Good);
+        assert_eq!(Judgement::from_drop(250), Judgement::Mistake);
+        // A pawn less hurts more in a balanced position than in a won one
+        assert!(
+            expected_score(0) - expected_score(-100) > expected_score(800) - expected_score(700)
+        );
+
+        assert_eq!(format_eval(35, Color::White), "0.35");
+        assert_eq!(format_eval(35, Color::Black), "-0.35");
+        assert_eq!(format_eval(MATE_VALUE - 3, Color::Black), "#-2");
+    }
+
+    #[test]
+    fn blunders_are_marked() {
+        let game = &parse_games("1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0").unwrap()[0];
+        let mut board = game.start.clone();
+        for &mv in &game.moves[..5] {
+            board.play_unchecked(mv);
+        }
+        let defence = parse_san(&board, "Qe7");
+        let scores = [
+            (Some(game.moves[0]), 30),
+            (Some(game.moves[1]), -30),
+            (Some(game.moves[2]), 20),
+            (Some(game.moves[3]), -20),
+            (Some(game.moves[4]), 20),
+            (defence, -20),
+            (Some(game.moves[6]), MATE_VALUE - 1),
+            (None, -MATE_VALUE),
+        ];
+        let annotations = annotate_game(game, &scores);
+        assert_eq!(annotations.len(), 7);
+        assert_eq!(annotations[4], (None, Some("[%eval 0.20]".to_owned())));
+        assert_eq!(
+            annotations[5],
+            (
+                Some("??"),
+                Some("[%eval #1] Blunder. Qe7 was best.".to_owned())
+            )
+        );
+        // The result speaks for itself
+        assert_eq!(annotations[6], (None, None));
+    }
+}
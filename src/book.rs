@@ -0,0 +1,108 @@
+use std::{fs, io, path::Path};
+
+use cozy_chess::{Board, File, Move, Piece, Rank, Square};
+
+use crate::{rng::Rng, zobrist::polyglot_key};
+
+const ENTRY_SIZE: usize = 16;
+
+#[derive(Debug, Clone, Copy)]
+struct BookEntry {
+    key: u64,
+    mv: u16,
+    weight: u16,
+}
+
+// A Polyglot `.bin` opening book. Entries are sorted by key, so lookups are a binary search.
+#[derive(Debug)]
+pub struct Book {
+    entries: Vec<BookEntry>,
+}
+
+impl Book {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        let entries = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(|entry| BookEntry {
+                key: u64::from_be_bytes(entry[0..8].try_into().unwrap()),
+                mv: u16::from_be_bytes([entry[8], entry[9]]),
+                weight: u16::from_be_bytes([entry[10], entry[11]]),
+            })
+            .collect();
+        Ok(Self { entries })
+    }
+
+    // Picks one of the book moves for this position with probability proportional to its weight
+    pub fn probe(&self, board: &Board, rng: &mut Rng) -> Option<Move> {
+        let key = polyglot_key(board);
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let candidates = self.entries[start..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+            .filter_map(|entry| Some((decode_move(entry.mv)?, entry.weight)))
+            .filter(|&(mv, weight)| weight > 0 && board.is_legal(mv))
+            .collect::<Vec<_>>();
+
+        let total = candidates
+            .iter()
+            .map(|&(_, weight)| usize::from(weight))
+            .sum::<usize>();
+        if total == 0 {
+            return None;
+        }
+        let mut pick = rng.below(total);
+        for (mv, weight) in candidates {
+            if pick < usize::from(weight) {
+                return Some(mv);
+            }
+            pick -= usize::from(weight);
+        }
+        None
+    }
+}
+
+// Polyglot packs moves as to file, to rank, from file, from rank and promotion piece, three bits
+// each. Castling is written as the king taking its own rook, like cozy-chess does internally.
+fn decode_move(mv: u16) -> Option<Move> {
+    let square = |bits: u16| {
+        Square::new(
+            File::index(usize::from(bits & 7)),
+            Rank::index(usize::from((bits >> 3) & 7)),
+        )
+    };
+    let promotion = match (mv >> 12) & 7 {
+        0 => None,
+        1 => Some(Piece::Knight),
+        2 => Some(Piece::Bishop),
+        3 => Some(Piece::Rook),
+        4 => Some(Piece::Queen),
+        _ => return None,
+    };
+    Some(Move {
+        from: square(mv >> 6),
+        to: square(mv),
+        promotion,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use cozy_chess::{Move, Square};
+
+    use super::decode_move;
+
+    #[test]
+    fn decodes_polyglot_moves() {
+        assert_eq!(
+            decode_move(796),
+            Some(Move {
+                from: Square::E2,
+                to: Square::E4,
+                promotion: None
+            })
+        );
+        // White short castling is stored as e1h1
+        assert_eq!(decode_move(263).map(|mv| mv.to), Some(Square::H1));
+    }
+}
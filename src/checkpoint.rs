@@ -0,0 +1,206 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use cozy_chess::{Move, Piece, Square};
+
+use crate::{
+    transposition_table::{NodeType, TTEntry},
+    types::{Depth, Value},
+};
+
+const MAGIC: &[u8; 4] = b"CECK";
+const VERSION: u8 = 3;
+
+// Snapshot of a running analysis, so that multi-day analyses survive restarts. Restoring the TT
+// lets a resumed search get back to the saved depth almost immediately.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pub fen: String,
+    pub depth: Depth,
+    pub best_move: Move,
+    pub best_value: Value,
+    pub root_scores: Vec<(Move, Value)>,
+    pub tt_entries: Vec<TTEntry>,
+    // Buckets of the table the entries were saved from, whose hashes only stand for their bucket
+    // in a table of that size, see `TranspositionTable::entries`
+    pub tt_buckets: usize,
+}
+
+impl Checkpoint {
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        // Write to a temporary file first so a crash mid-write keeps the previous checkpoint
+        let tmp_path = path.with_extension("tmp");
+        let mut w = BufWriter::new(File::create(&tmp_path)?);
+
+        w.write_all(MAGIC)?;
+        w.write_all(&[VERSION])?;
+        write_len(&mut w, self.fen.len())?;
+        w.write_all(self.fen.as_bytes())?;
+        w.write_all(&[self.depth])?;
+        w.write_all(&pack_move(self.best_move).to_le_bytes())?;
+        w.write_all(&self.best_value.to_le_bytes())?;
+
+        write_len(&mut w, self.root_scores.len())?;
+        for &(mv, value) in &self.root_scores {
+            w.write_all(&pack_move(mv).to_le_bytes())?;
+            w.write_all(&value.to_le_bytes())?;
+        }
+
+        write_len(&mut w, self.tt_buckets)?;
+        write_len(&mut w, self.tt_entries.len())?;
+        for tte in &self.tt_entries {
+            w.write_all(&tte.hash.to_le_bytes())?;
+            w.write_all(&pack_move(tte.best_move).to_le_bytes())?;
+            w.write_all(&tte.best_value.to_le_bytes())?;
+            w.write_all(&tte.static_eval.to_le_bytes())?;
+            w.write_all(&[tte.depth, tte.node_type as u8])?;
+        }
+
+        w.into_inner()?.sync_all()?;
+        std::fs::rename(tmp_path, path)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut r = BufReader::new(File::open(path)?);
+
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC || read_u8(&mut r)? != VERSION {
+            return Err(invalid_data("not a checkpoint file"));
+        }
+
+        let mut fen = vec![0; read_len(&mut r)?];
+        r.read_exact(&mut fen)?;
+        let fen = String::from_utf8(fen).map_err(|_| invalid_data("invalid FEN"))?;
+        let depth = read_u8(&mut r)?;
+        let best_move = read_move(&mut r)?;
+        let best_value = read_value(&mut r)?;
+
+        let root_scores = (0..read_len(&mut r)?)
+            .map(|_| Ok((read_move(&mut r)?, read_value(&mut r)?)))
+            .collect::<io::Result<_>>()?;
+
+        let tt_buckets = read_len(&mut r)?;
+        let tt_entries = (0..read_len(&mut r)?)
+            .map(|_| {
+                let mut hash = [0; 8];
+                r.read_exact(&mut hash)?;
+                Ok(TTEntry {
+                    hash: u64::from_le_bytes(hash),
+                    best_move: read_move(&mut r)?,
+                    best_value: read_value(&mut r)?,
+                    static_eval: read_value(&mut r)?,
+                    depth: read_u8(&mut r)?,
+                    node_type: match read_u8(&mut r)? {
+                        0 => NodeType::Exact,
+                        1 => NodeType::UpperBound,
+                        2 => NodeType::LowerBound,
+                        _ => return Err(invalid_data("invalid node type")),
+                    },
+                })
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(Self {
+            fen,
+            depth,
+            best_move,
+            best_value,
+            root_scores,
+            tt_entries,
+            tt_buckets,
+        })
+    }
+}
+
+// Moves are stored as 6 bits for each square and 3 bits for the promotion piece
+pub fn pack_move(mv: Move) -> u16 {
+    let promotion = mv.promotion.map_or(0, |piece| piece as u16 + 1);
+    mv.from as u16 | (mv.to as u16) << 6 | promotion << 12
+}
+
+pub fn unpack_move(packed: u16) -> Option<Move> {
+    let promotion = match packed >> 12 {
+        0 => None,
+        p => Some(Piece::try_index(usize::from(p - 1))?),
+    };
+    Some(Move {
+        from: Square::index(usize::from(packed & 0x3F)),
+        to: Square::index(usize::from((packed >> 6) & 0x3F)),
+        promotion,
+    })
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn write_len(w: &mut impl Write, len: usize) -> io::Result<()> {
+    w.write_all(&(len as u64).to_le_bytes())
+}
+
+fn read_len(r: &mut impl Read) -> io::Result<usize> {
+    let mut buf = [0; 8];
+    r.read_exact(&mut buf)?;
+    usize::try_from(u64::from_le_bytes(buf)).map_err(|_| invalid_data("length too large"))
+}
+
+fn read_u8(r: &mut impl Read) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+fn read_value(r: &mut impl Read) -> io::Result<Value> {
+    let mut buf = [0; 2];
+    r.read_exact(&mut buf)?;
+    Ok(Value::from_le_bytes(buf))
+}
+
+fn read_move(r: &mut impl Read) -> io::Result<Move> {
+    let mut buf = [0; 2];
+    r.read_exact(&mut buf)?;
+    unpack_move(u16::from_le_bytes(buf)).ok_or_else(|| invalid_data("invalid move"))
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use cozy_chess::Board;
+
+    use crate::search::{SearchStats, Searcher};
+
+    use super::Checkpoint;
+
+    #[test]
+    fn checkpoint_round_trip() {
+        let path = env::temp_dir().join(format!("checkpoint-{}.ckpt", std::process::id()));
+        let mut board = Board::startpos();
+        let mut searcher = Searcher::new(1 << 16);
+        let (best_move, best_value) =
+            searcher.search_fixed_depth(&mut board, &Vec::new(), &mut SearchStats::default(), 3);
+        let checkpoint = searcher.checkpoint(&board, 3, best_move, best_value);
+        checkpoint.save(&path).unwrap();
+
+        let loaded = Checkpoint::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.fen, checkpoint.fen);
+        assert_eq!(loaded.best_move, best_move);
+        assert_eq!(loaded.best_value, best_value);
+        assert_eq!(loaded.root_scores, checkpoint.root_scores);
+        assert_eq!(loaded.tt_entries.len(), checkpoint.tt_entries.len());
+
+        assert_eq!(loaded.tt_buckets, checkpoint.tt_buckets);
+
+        // Also with a smaller `Hash` than the checkpoint was saved with
+        for tt_size in [1 << 16, 1 << 14] {
+            let mut resumed = Searcher::new(tt_size);
+            assert_eq!(resumed.restore(&loaded), Some(board.clone()));
+            assert_eq!(resumed.tt.get(board.hash()).unwrap().best_move, best_move);
+        }
+    }
+}
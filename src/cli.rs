@@ -0,0 +1,101 @@
+use std::str::FromStr;
+
+// Arguments of a command line subcommand: `--name value` flags, `--name` switches, and
+// positional arguments
+#[derive(Debug, Default)]
+pub struct Flags {
+    values: Vec<(String, String)>,
+    switches: Vec<String>,
+    positional: Vec<String>,
+}
+
+impl Flags {
+    // `known` lists the flags the subcommand accepts, all of which take a value
+    pub fn parse(args: &[String], known: &[&str]) -> Result<Self, String> {
+        Self::parse_with_switches(args, known, &[])
+    }
+
+    // Like `parse`, with `switches` listing the flags that take no value
+    pub fn parse_with_switches(
+        args: &[String],
+        known: &[&str],
+        switches: &[&str],
+    ) -> Result<Self, String> {
+        let mut flags = Self::default();
+        let mut it = args.iter();
+        while let Some(arg) = it.next() {
+            if !arg.starts_with("--") {
+                flags.positional.push(arg.clone());
+            } else if switches.contains(&arg.as_str()) {
+                flags.switches.push(arg.clone());
+            } else if known.contains(&arg.as_str()) {
+                let value = it
+                    .next()
+                    .ok_or_else(|| format!("missing value for {arg}"))?;
+                flags.values.push((arg.clone(), value.clone()));
+            } else {
+                return Err(format!("unknown flag {arg}"));
+            }
+        }
+        Ok(flags)
+    }
+
+    pub fn positional(&self) -> &[String] {
+        &self.positional
+    }
+
+    pub fn has(&self, switch: &str) -> bool {
+        self.switches.iter().any(|arg| arg == switch)
+    }
+
+    // The last value given for a flag wins
+    pub fn get<T: FromStr>(&self, name: &str) -> Result<Option<T>, String> {
+        self.values
+            .iter()
+            .rev()
+            .find(|(flag, _)| flag == name)
+            .map(|(_, value)| {
+                value
+                    .parse()
+                    .map_err(|_| format!("invalid value {value} for {name}"))
+            })
+            .transpose()
+    }
+
+    pub fn get_or<T: FromStr>(&self, name: &str, default: T) -> Result<T, String> {
+        Ok(self.get(name)?.unwrap_or(default))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Flags;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn parse_flags_and_positionals() {
+        let flags =
+            Flags::parse(&args("start --depth 5 kiwipete --depth 9"), &["--depth"]).unwrap();
+        assert_eq!(flags.positional(), ["start", "kiwipete"]);
+        assert_eq!(flags.get::<u8>("--depth"), Ok(Some(9)));
+        assert_eq!(flags.get_or::<usize>("--hash", 16), Ok(16));
+
+        assert!(Flags::parse(&args("--depth"), &["--depth"]).is_err());
+        assert!(Flags::parse(&args("--hash 16"), &["--depth"]).is_err());
+        let flags = Flags::parse(&args("--depth x"), &["--depth"]).unwrap();
+        assert!(flags.get::<u8>("--depth").is_err());
+    }
+
+    #[test]
+    fn switches_take_no_value() {
+        let flags =
+            Flags::parse_with_switches(&args("--static a.epd"), &["--depth"], &["--static"])
+                .unwrap();
+        assert!(flags.has("--static"));
+        assert_eq!(flags.positional(), ["a.epd"]);
+        assert!(!Flags::parse(&args("a.epd"), &[]).unwrap().has("--static"));
+    }
+}
@@ -0,0 +1,138 @@
+use std::{env, fs, io, path::Path};
+
+pub const CONFIG_FILE: &str = "engine.toml";
+
+// Default option values read from `engine.toml` next to the binary, so that tournament setups
+// don't depend on what the GUI sends. Keys are UCI option names, quoted when they contain
+// spaces, and only flat `key = value` pairs are supported:
+//
+//     Hash = 256
+//     Threads = 4
+//     OwnBook = true
+//     Book = "books/performance.bin"
+//     "Skill Level" = 20
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EngineConfig {
+    pub options: Vec<(String, String)>,
+}
+
+impl EngineConfig {
+    // A missing file is not an error, it just sets no options
+    pub fn load_default() -> Result<Self, String> {
+        let Some(path) = env::current_exe()
+            .ok()
+            .and_then(|exe| Some(exe.parent()?.join(CONFIG_FILE)))
+        else {
+            return Ok(Self::default());
+        };
+        Self::load(&path)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text).map_err(|e| format!("{}: {e}", path.display())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("could not read {}: {e}", path.display())),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut options = Vec::new();
+        for (line_num, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |msg: &str| format!("line {}: {msg}", line_num + 1);
+            if line.starts_with('[') {
+                return Err(error("tables are not supported"));
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected key = value"))?;
+            let key = parse_key(key.trim()).ok_or_else(|| error("invalid key"))?;
+            let value = parse_value(value.trim()).ok_or_else(|| error("invalid value"))?;
+            options.push((key, value));
+        }
+        Ok(Self { options })
+    }
+}
+
+fn parse_key(key: &str) -> Option<String> {
+    if let Some(quoted) = key.strip_prefix('"') {
+        return quoted
+            .strip_suffix('"')
+            .filter(|name| !name.is_empty() && !name.contains('"'))
+            .map(str::to_owned);
+    }
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    bare.then(|| key.to_owned())
+}
+
+// Strings, integers and booleans, each followed by an optional comment
+fn parse_value(value: &str) -> Option<String> {
+    if let Some(rest) = value.strip_prefix('"') {
+        let mut string = String::new();
+        let mut chars = rest.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => {
+                    let tail = chars.as_str().trim();
+                    return (tail.is_empty() || tail.starts_with('#')).then_some(string);
+                }
+                '\\' => match chars.next()? {
+                    '\\' => string.push('\\'),
+                    '"' => string.push('"'),
+                    _ => return None,
+                },
+                c => string.push(c),
+            }
+        }
+        return None;
+    }
+
+    let value = value.split('#').next().unwrap_or("").trim();
+    let valid = value == "true" || value == "false" || value.parse::<i64>().is_ok();
+    valid.then(|| value.to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::EngineConfig;
+
+    #[test]
+    fn parse_config() {
+        let config = EngineConfig::parse(
+            "# tournament settings\n\
+             Hash = 256\n\
+             Threads = 4 # one per core\n\
+             OwnBook = true\n\
+             Book = \"books/main \\\"v2\\\".bin\"\n\
+             \"Skill Level\" = 20\n",
+        )
+        .unwrap();
+        let options: Vec<_> = config
+            .options
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+        assert_eq!(
+            options,
+            [
+                ("Hash", "256"),
+                ("Threads", "4"),
+                ("OwnBook", "true"),
+                ("Book", "books/main \"v2\".bin"),
+                ("Skill Level", "20"),
+            ]
+        );
+
+        assert!(EngineConfig::parse("[engine]\nHash = 1").is_err());
+        assert!(EngineConfig::parse("Hash 256").is_err());
+        assert!(EngineConfig::parse("Book = books/main.bin").is_err());
+        assert!(EngineConfig::parse("Skill Level = 3").is_err());
+    }
+}
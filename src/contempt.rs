@@ -0,0 +1,127 @@
+use crate::types::Value;
+
+// Rough playing strength of the engine, used to compare against the opponent's rating
+const ENGINE_RATING: i32 = 2200;
+// Rating difference worth one centipawn of contempt
+const RATING_PER_CP: i32 = 10;
+pub const MAX_CONTEMPT: Value = 100;
+const MAX_AUTO_CONTEMPT: Value = 50;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Opponent {
+    pub title: Option<String>,
+    pub rating: Option<i32>,
+    pub is_computer: bool,
+    pub name: String,
+}
+
+impl Opponent {
+    // Parses the `UCI_Opponent` value: `<title|none> <rating|none> <computer|human> <name>`
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut fields = value.split_whitespace();
+        let title = match fields.next()? {
+            "none" => None,
+            title => Some(title.to_owned()),
+        };
+        let rating = match fields.next()? {
+            "none" => None,
+            rating => Some(rating.parse().ok()?),
+        };
+        let is_computer = match fields.next()? {
+            "computer" => true,
+            "human" => false,
+            _ => return None,
+        };
+        let name = fields.collect::<Vec<_>>().join(" ");
+
+        Some(Self {
+            title,
+            rating,
+            is_computer,
+            name,
+        })
+    }
+}
+
+// Contempt is the score (from the engine's point of view) below which a draw is preferred.
+// A positive value makes the engine avoid draws, a negative one makes it accept them.
+#[derive(Debug, Clone)]
+pub struct ContemptSettings {
+    pub manual: Value,
+    pub auto: bool,
+    pub opponent: Option<Opponent>,
+    // Analysis wants the objective score, so contempt is off while `UCI_AnalyseMode` is set
+    pub analyse_mode: bool,
+    // Contempt of the personality, see `Personality::contempt`
+    pub personality: Value,
+}
+
+impl Default for ContemptSettings {
+    fn default() -> Self {
+        Self {
+            manual: 0,
+            auto: true,
+            opponent: None,
+            analyse_mode: false,
+            personality: 0,
+        }
+    }
+}
+
+impl ContemptSettings {
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn contempt(&self) -> Value {
+        if self.analyse_mode {
+            return 0;
+        }
+        // Press for wins against weaker opponents and take draws against stronger ones.
+        // The manual value is used when adaptation is disabled or the rating is unknown.
+        let contempt = match (self.auto, self.opponent.as_ref().and_then(|opp| opp.rating)) {
+            (true, Some(rating)) => ((ENGINE_RATING - rating) / RATING_PER_CP)
+                .clamp(i32::from(-MAX_AUTO_CONTEMPT), i32::from(MAX_AUTO_CONTEMPT))
+                as Value,
+            _ => self.manual,
+        };
+        (contempt + self.personality).clamp(-MAX_CONTEMPT, MAX_CONTEMPT)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ContemptSettings, Opponent};
+
+    #[test]
+    fn parse_opponent() {
+        let opp = Opponent::parse("GM 2800 human Magnus Carlsen").unwrap();
+        assert_eq!(opp.title.as_deref(), Some("GM"));
+        assert_eq!(opp.rating, Some(2800));
+        assert!(!opp.is_computer);
+        assert_eq!(opp.name, "Magnus Carlsen");
+
+        let opp = Opponent::parse("none none computer Stockfish").unwrap();
+        assert_eq!(opp.title, None);
+        assert_eq!(opp.rating, None);
+        assert!(opp.is_computer);
+
+        assert!(Opponent::parse("GM 2800").is_none());
+    }
+
+    #[test]
+    fn contempt_follows_rating() {
+        let mut settings = ContemptSettings {
+            manual: 5,
+            ..ContemptSettings::default()
+        };
+        assert_eq!(settings.contempt(), 5);
+
+        settings.opponent = Opponent::parse("none 1200 human weak");
+        assert!(settings.contempt() > 0);
+        settings.opponent = Opponent::parse("GM 2900 computer strong");
+        assert!(settings.contempt() < 0);
+        settings.analyse_mode = true;
+        assert_eq!(settings.contempt(), 0);
+
+        settings.auto = false;
+        assert_eq!(settings.contempt(), 5);
+    }
+}
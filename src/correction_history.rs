@@ -0,0 +1,117 @@
+use cozy_chess::{Board, Color, Piece};
+
+use crate::types::{Depth, Value};
+
+const ENTRIES: usize = 1 << 14;
+// Corrections are kept in 1/GRAIN centipawns, so that small updates don't round away
+const GRAIN: i32 = 256;
+// Weight of one update is depth + 1 out of this, capped at `MAX_WEIGHT`
+const WEIGHT_SCALE: i32 = 256;
+const MAX_WEIGHT: i32 = 16;
+// Corrections are at most this many centipawns either way
+const MAX_CORRECTION: i32 = 64;
+
+// Eval Correction History
+// The static eval misjudges some kinds of positions in a consistent way, e.g. certain pawn
+// structures or material imbalances. Whenever a search finds a score different from the static
+// eval, the difference is averaged into tables indexed by the pawn structure and by the
+// material of the position, and later static evals of positions sharing them are corrected by
+// that average. Deeper searches carry more weight.
+#[derive(Debug)]
+pub struct CorrectionHistory {
+    // Indexed by side to move, then by key
+    pawn: Vec<i16>,
+    material: Vec<i16>,
+}
+
+impl Default for CorrectionHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CorrectionHistory {
+    pub fn new() -> Self {
+        Self {
+            pawn: vec![0; 2 * ENTRIES],
+            material: vec![0; 2 * ENTRIES],
+        }
+    }
+
+    // The static eval with the correction for its position applied
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn correct(&self, board: &Board, static_eval: Value) -> Value {
+        let (pawn_idx, material_idx) = indices(board);
+        let correction =
+            (i32::from(self.pawn[pawn_idx]) + i32::from(self.material[material_idx])) / 2 / GRAIN;
+        let corrected = i32::from(static_eval) + correction;
+        corrected.clamp(-i32::from(Value::MAX), i32::from(Value::MAX)) as Value
+    }
+
+    // Moves the corrections of the position towards the difference between the search score and
+    // the uncorrected static eval
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn update(&mut self, board: &Board, depth: Depth, static_eval: Value, score: Value) {
+        let (pawn_idx, material_idx) = indices(board);
+        let diff = i32::from(score) - i32::from(static_eval);
+        let diff = diff.clamp(-MAX_CORRECTION, MAX_CORRECTION);
+        let weight = (i32::from(depth) + 1).min(MAX_WEIGHT);
+        for entry in [&mut self.pawn[pawn_idx], &mut self.material[material_idx]] {
+            let updated = (i32::from(*entry) * (WEIGHT_SCALE - weight) + diff * GRAIN * weight)
+                / WEIGHT_SCALE;
+            *entry = updated.clamp(-MAX_CORRECTION * GRAIN, MAX_CORRECTION * GRAIN) as i16;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.pawn.fill(0);
+        self.material.fill(0);
+    }
+}
+
+// Multiplicative hashing, good enough to spread the keys over the table
+#[allow(clippy::cast_possible_truncation)]
+const fn table_index(key: u64) -> usize {
+    (key.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 50) as usize
+}
+
+fn indices(board: &Board) -> (usize, usize) {
+    let pawns = |color| board.colored_pieces(color, Piece::Pawn).0;
+    let pawn_key = pawns(Color::White) ^ pawns(Color::Black).rotate_left(32).wrapping_mul(3);
+
+    // Four bits per piece count, which no count outgrows in practice
+    let mut material_key = 0_u64;
+    for color in Color::ALL {
+        for piece in Piece::ALL {
+            material_key =
+                (material_key << 4) | u64::from(board.colored_pieces(color, piece).len() & 0xF);
+        }
+    }
+
+    let side = board.side_to_move() as usize * ENTRIES;
+    (
+        side + table_index(pawn_key),
+        side + table_index(material_key),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use cozy_chess::Board;
+
+    use super::CorrectionHistory;
+
+    #[test]
+    fn corrections_follow_search_scores() {
+        let board = Board::startpos();
+        let mut history = CorrectionHistory::new();
+        assert_eq!(history.correct(&board, 20), 20);
+        for _ in 0..100 {
+            history.update(&board, 10, 20, 60);
+        }
+        let corrected = history.correct(&board, 20);
+        assert!(corrected > 50 && corrected <= 60, "{corrected}");
+        history.clear();
+        assert_eq!(history.correct(&board, 20), 20);
+    }
+}
@@ -0,0 +1,209 @@
+use cozy_chess::{
+    get_between_rays, get_bishop_moves, get_king_moves, get_knight_moves, get_rook_moves, BitBoard,
+    Board, BoardBuilder, Color, Piece, Square,
+};
+
+const TABLE_SIZE: usize = 1 << 13;
+// Reversible moves between two squares, counted once for both directions
+const NUM_MOVES: usize = 3668;
+
+// Cuckoo Table
+// Holds the hash difference of every reversible move, i.e. a piece other than a pawn going from
+// one square to another and the side to move changing. If the hashes of the current position and
+// of an earlier one with the other side to move differ by such a move, the side to move can
+// repeat that earlier position with a single move, as long as nothing stands between the two
+// squares. Stored with cuckoo hashing, so a lookup checks two slots. This is the "upcoming
+// repetition" detection of Stockfish, after a paper by Kennedy.
+#[derive(Debug)]
+pub struct CuckooTable {
+    keys: Vec<u64>,
+    moves: Vec<(Square, Square)>,
+}
+
+impl Default for CuckooTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+const fn h1(key: u64) -> usize {
+    (key & (TABLE_SIZE as u64 - 1)) as usize
+}
+
+#[allow(clippy::cast_possible_truncation)]
+const fn h2(key: u64) -> usize {
+    ((key >> 16) & (TABLE_SIZE as u64 - 1)) as usize
+}
+
+impl CuckooTable {
+    pub fn new() -> Self {
+        let mut table = Self {
+            keys: vec![0; TABLE_SIZE],
+            moves: vec![(Square::A1, Square::A1); TABLE_SIZE],
+        };
+        let side_key = side_key();
+        let mut count = 0;
+        for color in Color::ALL {
+            for piece in [
+                Piece::Knight,
+                Piece::Bishop,
+                Piece::Rook,
+                Piece::Queen,
+                Piece::King,
+            ] {
+                for from in Square::ALL {
+                    for to in empty_board_moves(piece, from) {
+                        if (from as usize) < (to as usize) {
+                            let key = placement_key(color, piece, from, to) ^ side_key;
+                            table.insert(key, (from, to));
+                            count += 1;
+                        }
+                    }
+                }
+            }
+        }
+        debug_assert_eq!(count, NUM_MOVES);
+        table
+    }
+
+    fn insert(&mut self, mut key: u64, mut mv: (Square, Square)) {
+        let mut idx = h1(key);
+        loop {
+            std::mem::swap(&mut self.keys[idx], &mut key);
+            std::mem::swap(&mut self.moves[idx], &mut mv);
+            // The slot was empty
+            if key == 0 {
+                return;
+            }
+            // Move the entry that was pushed out to its other slot
+            idx = if idx == h1(key) { h2(key) } else { h1(key) };
+        }
+    }
+
+    fn get(&self, key: u64) -> Option<(Square, Square)> {
+        [h1(key), h2(key)]
+            .into_iter()
+            .find(|&idx| self.keys[idx] == key)
+            .map(|idx| self.moves[idx])
+    }
+
+    // Whether the side to move can go back to a position of the current line with one move.
+    // `history` holds the hashes of the positions before this one, the last being the position
+    // one ply ago, and `ply` is the distance of this position from the root. Only positions
+    // within the search tree count, like for the repetition draws of the search.
+    pub fn has_upcoming_repetition(&self, board: &Board, history: &[u64], ply: usize) -> bool {
+        let end = usize::from(board.halfmove_clock()).min(history.len());
+        let hash = board.hash();
+        // The earlier position has the other side to move, so it was an odd number of plies ago.
+        // One ply ago is the opponent's last move, which isn't a repetition.
+        (3..=end).step_by(2).take_while(|&i| i < ply).any(|i| {
+            self.get(hash ^ history[history.len() - i])
+                .is_some_and(|(a, b)| (get_between_rays(a, b) & board.occupied()).is_empty())
+        })
+    }
+}
+
+fn empty_board_moves(piece: Piece, sq: Square) -> BitBoard {
+    match piece {
+        Piece::Knight => get_knight_moves(sq),
+        Piece::Bishop => get_bishop_moves(sq, BitBoard::EMPTY),
+        Piece::Rook => get_rook_moves(sq, BitBoard::EMPTY),
+        Piece::Queen => {
+            get_bishop_moves(sq, BitBoard::EMPTY) | get_rook_moves(sq, BitBoard::EMPTY)
+        }
+        Piece::King => get_king_moves(sq),
+        Piece::Pawn => BitBoard::EMPTY,
+    }
+}
+
+// The Zobrist keys of cozy-chess are not public, but hashes are the XOR of a key per piece on a
+// square and of the side to move, so differences between hashes of legal boards recover them.
+fn build_hash(pieces: &[(Piece, Color, Square)], side_to_move: Color) -> u64 {
+    let mut builder = BoardBuilder::empty();
+    for &(piece, color, sq) in pieces {
+        *builder.square_mut(sq) = Some((piece, color));
+    }
+    builder.side_to_move = side_to_move;
+    builder
+        .build()
+        .expect("positions for cuckoo keys are legal")
+        .hash()
+}
+
+fn side_key() -> u64 {
+    let kings = [
+        (Piece::King, Color::White, Square::A1),
+        (Piece::King, Color::Black, Square::H8),
+    ];
+    build_hash(&kings, Color::White) ^ build_hash(&kings, Color::Black)
+}
+
+// Difference of the hashes with a piece on `from` and on `to`. The other side is to move, so the
+// piece may give check, and the kings are put where they are out of the way.
+fn placement_key(color: Color, piece: Piece, from: Square, to: Square) -> u64 {
+    let corners = [Square::A1, Square::H8, Square::A8, Square::H1];
+    let mut pieces = Vec::new();
+    if piece == Piece::King {
+        let far = |sq: Square| !get_king_moves(sq).has(from) && !get_king_moves(sq).has(to);
+        let enemy_king = corners
+            .into_iter()
+            .find(|&sq| sq != from && sq != to && far(sq))
+            .unwrap();
+        pieces.push((Piece::King, !color, enemy_king));
+    } else {
+        let kings = [
+            (Square::A1, Square::H8),
+            (Square::A8, Square::H1),
+            (Square::D1, Square::D8),
+        ];
+        let (own_king, enemy_king) = kings
+            .into_iter()
+            .find(|&(own, enemy)| ![own, enemy].contains(&from) && ![own, enemy].contains(&to))
+            .unwrap();
+        pieces.push((Piece::King, color, own_king));
+        pieces.push((Piece::King, !color, enemy_king));
+    }
+
+    pieces.push((piece, color, from));
+    let with_from = build_hash(&pieces, !color);
+    pieces.last_mut().unwrap().2 = to;
+    with_from ^ build_hash(&pieces, !color)
+}
+
+#[cfg(test)]
+mod test {
+    use cozy_chess::{Board, Move};
+
+    use super::{CuckooTable, NUM_MOVES};
+
+    #[test]
+    fn all_moves_stored() {
+        let table = CuckooTable::new();
+        assert_eq!(table.keys.iter().filter(|&&key| key != 0).count(), NUM_MOVES);
+    }
+
+    #[test]
+    fn finds_upcoming_repetition() {
+        let table = CuckooTable::new();
+        let mut board = Board::startpos();
+        let mut history = Vec::new();
+        for mv in ["g1f3", "g8f6", "f3g1"] {
+            history.push(board.hash());
+            board.play(mv.parse::<Move>().unwrap());
+        }
+        // Nf6-g8 goes back to the start position
+        assert!(table.has_upcoming_repetition(&board, &history, 4));
+        // which is before the root of a search three plies ago
+        assert!(!table.has_upcoming_repetition(&board, &history, 3));
+
+        // Two knight moves away from the start position, one move doesn't get back
+        let mut board = Board::startpos();
+        let mut history = Vec::new();
+        for mv in ["g1f3", "g8f6", "b1c3"] {
+            history.push(board.hash());
+            board.play(mv.parse::<Move>().unwrap());
+        }
+        assert!(!table.has_upcoming_repetition(&board, &history, 4));
+    }
+}
@@ -0,0 +1,427 @@
+use cozy_chess::{get_king_moves, BitBoard, Board, Color, File, Piece, Rank, Square};
+
+use crate::{
+    evaluate::{is_insufficient_material, is_passed, PIECE_VALUES},
+    kpk,
+};
+
+// A won KPK is worth a bit less than the queen the pawn becomes, so that promoting it still looks
+// like progress, plus this much for every rank the pawn has advanced and for every square our
+// king is closer to the promotion square than the farthest it could be
+const KPK_WIN_VALUE: i32 = 800;
+const KPK_WIN_RANK: i32 = 10;
+const KPK_PROXIMITY: i32 = 2;
+// Bonuses for KBN vs K per square the lone king is closer to a corner the bishop can attack than
+// the farthest it could be, and per square our king is closer to it
+const KBNK_CORNER: i32 = 20;
+const KBNK_PROXIMITY: i32 = 4;
+// Percentages of the eval kept in drawish endings: opposite coloured bishops alone and with other
+// pieces, rook endings a pawn up without a passed pawn, no pawns with at most a minor piece up,
+// all pawns on the wing of the defending king, and a last pawn the defender can give a piece for
+const OPPOSITE_BISHOPS_SCALE: i32 = 50;
+const OPPOSITE_BISHOPS_WITH_PIECES_SCALE: i32 = 80;
+const ROOK_ENDING_SCALE: i32 = 60;
+const NO_PAWNS_SCALE: i32 = 25;
+const ONE_WING_SCALE: i32 = 75;
+const LAST_PAWN_SCALE: i32 = 50;
+// Scaling rules, from the most specific to the most general. The first one that matches decides.
+const SCALE_RULES: [fn(&Board, &Signature) -> Option<i32>; 5] = [
+    no_pawns,
+    opposite_bishops,
+    rook_ending,
+    pawns_on_one_wing,
+    last_pawn,
+];
+
+// What a recognizer knows about an ending, from the point of view of the side with the material
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    // The eval of the position, replacing the generic one
+    Exact(i32),
+    // Percentage of the generic eval to keep, 0 for a draw
+    Scale(i32),
+}
+
+// Endgame Knowledge
+// Some endings are decided by rules the generic eval knows nothing about: KPK turns on the
+// opposition and the square of the pawn, KBN vs K is only mated in a corner of the bishop's
+// colour, and a rook pawn with a bishop of the wrong colour can't be promoted past a king in the
+// corner. Recognizers are picked by the material on the board, which is cheap to check, and get
+// the final say over the eval when they match. Returns the verdict from the point of view of the
+// side to move.
+pub fn recognize(board: &Board) -> Option<Verdict> {
+    for strong in Color::ALL {
+        let weak = !strong;
+        if board.colors(weak) != board.colored_pieces(weak, Piece::King) {
+            continue;
+        }
+        let verdict = match material(board, strong) {
+            [1, 0, 0, 0, 0] => Some(kpk(board, strong)),
+            [0, 1, 1, 0, 0] => Some(kbnk(board, strong)),
+            [_, 0, 1, 0, 0] => wrong_rook_pawn(board, strong),
+            _ => None,
+        }?;
+        return Some(match verdict {
+            Verdict::Exact(eval) if board.side_to_move() != strong => Verdict::Exact(-eval),
+            verdict => verdict,
+        });
+    }
+    None
+}
+
+// Pawns, knights, bishops, rooks and queens of `color`
+fn material(board: &Board, color: Color) -> [u32; 5] {
+    [
+        Piece::Pawn,
+        Piece::Knight,
+        Piece::Bishop,
+        Piece::Rook,
+        Piece::Queen,
+    ]
+    .map(|piece| board.colored_pieces(color, piece).len())
+}
+
+fn king(board: &Board, color: Color) -> Square {
+    board
+        .colored_pieces(color, Piece::King)
+        .next_square()
+        .unwrap()
+}
+
+fn distance(a: Square, b: Square) -> i32 {
+    let files = (a.file() as i32 - b.file() as i32).abs();
+    let ranks = (a.rank() as i32 - b.rank() as i32).abs();
+    files.max(ranks)
+}
+
+fn promotion_square(pawn: Square, color: Color) -> Square {
+    Square::new(pawn.file(), Rank::Eighth.relative_to(color))
+}
+
+// Whether the king of `color` stands on or next to `sq`
+fn king_guards(board: &Board, color: Color, sq: Square) -> bool {
+    (get_king_moves(sq) | sq.bitboard()).has(king(board, color))
+}
+
+// KPK is looked up in the bitbase, which knows every position of it exactly. Anything it doesn't
+// win is a draw.
+#[allow(clippy::cast_possible_wrap)]
+fn kpk(board: &Board, strong: Color) -> Verdict {
+    if !kpk::is_win(board, strong) {
+        return Verdict::Scale(0);
+    }
+    let pawn = board
+        .colored_pieces(strong, Piece::Pawn)
+        .next_square()
+        .unwrap();
+    let rank = pawn.rank().relative_to(strong) as i32;
+    let promotion = promotion_square(pawn, strong);
+    Verdict::Exact(
+        KPK_WIN_VALUE
+            + KPK_WIN_RANK * rank
+            + KPK_PROXIMITY * (7 - distance(king(board, strong), promotion)),
+    )
+}
+
+// Squares on the ranks in front of `sq`, as seen by `color`
+pub fn forward_ranks(sq: Square, color: Color) -> BitBoard {
+    let rank = sq.rank().relative_to(color) as usize;
+    Rank::ALL[rank + 1..]
+        .iter()
+        .fold(BitBoard::EMPTY, |ranks, rank| {
+            ranks | rank.relative_to(color).bitboard()
+        })
+}
+
+// KBN vs K is a win, but only in a corner the bishop can attack, so the lone king is driven there
+// rather than to the nearest corner the generic mop-up would choose
+fn kbnk(board: &Board, strong: Color) -> Verdict {
+    let weak_king = king(board, !strong);
+    let bishop = board
+        .colored_pieces(strong, Piece::Bishop)
+        .next_square()
+        .unwrap();
+    let corners = [Square::A1, Square::H8, Square::A8, Square::H1];
+    let corner_distance = corners
+        .into_iter()
+        .filter(|&corner| square_color(corner) == square_color(bishop))
+        .map(|corner| distance(weak_king, corner))
+        .min()
+        .unwrap();
+    let eval = i32::from(PIECE_VALUES[1] + PIECE_VALUES[2])
+        + KBNK_CORNER * (7 - corner_distance)
+        + KBNK_PROXIMITY * (7 - distance(king(board, strong), weak_king));
+    Verdict::Exact(eval)
+}
+
+// Rook pawns and a bishop that can't attack their promotion square are a draw once the enemy
+// king reaches the corner, as nothing can drive it out
+fn wrong_rook_pawn(board: &Board, strong: Color) -> Option<Verdict> {
+    let pawns = board.colored_pieces(strong, Piece::Pawn);
+    let pawn = pawns.next_square()?;
+    let file = pawn.file();
+    if !matches!(file, File::A | File::H) || !(pawns & !file.bitboard()).is_empty() {
+        return None;
+    }
+    let promotion = promotion_square(pawn, strong);
+    let bishop = board.colored_pieces(strong, Piece::Bishop).next_square()?;
+    let wrong_bishop = square_color(bishop) != square_color(promotion);
+    (wrong_bishop && king_guards(board, !strong, promotion)).then_some(Verdict::Scale(0))
+}
+
+// Material Signature
+// The material of both sides, from the point of view of the side that is ahead, which is what
+// the scaling rules look at first
+struct Signature {
+    strong: Color,
+    // Pawns, knights, bishops, rooks and queens of the side that is ahead, and of the other side
+    strong_material: [u32; 5],
+    weak_material: [u32; 5],
+}
+
+// Drawish Endings
+// Some endings are much harder to win than the material suggests. Opposite coloured bishops can't
+// contest each other's squares, so even two pawns up often can't be converted. A rook ending a
+// pawn up without a passed pawn is usually held, and without pawns, being a minor piece up rarely
+// mates. Pulling the eval towards 0 keeps the engine from overpressing in these, and from trading
+// into them when ahead. This is the one stage where the eval is scaled by the kind of ending:
+// dead draws and the recognizers that only scale go through it too, and a new rule only needs to
+// be added to `SCALE_RULES`. Returns the percentage of `eval`, relative to the side to move, to
+// keep.
+pub fn scale_factor(board: &Board, eval: i32) -> i32 {
+    // The tables would still favour one side, but nothing can come of it
+    if is_insufficient_material(board) {
+        return 0;
+    }
+    if let Some(Verdict::Scale(percent)) = recognize(board) {
+        return percent;
+    }
+    let strong = if eval >= 0 {
+        board.side_to_move()
+    } else {
+        !board.side_to_move()
+    };
+    let signature = Signature {
+        strong,
+        strong_material: material(board, strong),
+        weak_material: material(board, !strong),
+    };
+    SCALE_RULES
+        .iter()
+        .find_map(|rule| rule(board, &signature))
+        .unwrap_or(100)
+}
+
+fn no_pawns(board: &Board, signature: &Signature) -> Option<i32> {
+    let strong = signature.strong;
+    let minor = i32::from(PIECE_VALUES[Piece::Bishop as usize]);
+    (signature.strong_material[0] == 0
+        && piece_value(board, strong) <= piece_value(board, !strong) + minor)
+        .then_some(NO_PAWNS_SCALE)
+}
+
+fn opposite_bishops(board: &Board, signature: &Signature) -> Option<i32> {
+    let Signature {
+        strong,
+        strong_material,
+        weak_material,
+    } = *signature;
+    if strong_material[2] != 1 || weak_material[2] != 1 {
+        return None;
+    }
+    let [strong_bishop, weak_bishop] = [strong, !strong].map(|color| {
+        let bishop = board
+            .colored_pieces(color, Piece::Bishop)
+            .next_square()
+            .unwrap();
+        square_color(bishop)
+    });
+    if strong_bishop == weak_bishop {
+        return None;
+    }
+    Some(
+        if pieces(strong_material) == 1 && pieces(weak_material) == 1 {
+            OPPOSITE_BISHOPS_SCALE
+        } else {
+            OPPOSITE_BISHOPS_WITH_PIECES_SCALE
+        },
+    )
+}
+
+fn rook_ending(board: &Board, signature: &Signature) -> Option<i32> {
+    let rooks_only = |material: [u32; 5]| material[1..] == [0, 0, 1, 0];
+    let Signature {
+        strong,
+        strong_material,
+        weak_material,
+    } = *signature;
+    (rooks_only(strong_material)
+        && rooks_only(weak_material)
+        && strong_material[0] <= weak_material[0] + 1
+        && !has_passed_pawn(board, strong))
+    .then_some(ROOK_ENDING_SCALE)
+}
+
+// With every pawn on one wing, at most one more of them, no more pieces and nothing passed, the
+// defending king on that wing can often trade off the last pawns or hold a fortress
+fn pawns_on_one_wing(board: &Board, signature: &Signature) -> Option<i32> {
+    let Signature {
+        strong,
+        strong_material,
+        weak_material,
+    } = *signature;
+    let pawns = board.pieces(Piece::Pawn);
+    let wing = [&File::ALL[..4], &File::ALL[4..]]
+        .map(|files| {
+            files
+                .iter()
+                .fold(BitBoard::EMPTY, |wing, file| wing | file.bitboard())
+        })
+        .into_iter()
+        .find(|&wing| (pawns & !wing).is_empty())?;
+    (strong_material[0] > 0
+        && strong_material[0] <= weak_material[0] + 1
+        && piece_value(board, strong) <= piece_value(board, !strong)
+        && !has_passed_pawn(board, strong)
+        && wing.has(king(board, !strong)))
+    .then_some(ONE_WING_SCALE)
+}
+
+// A last pawn without more pieces, against a side that has a piece to give for it, rarely
+// promotes
+fn last_pawn(board: &Board, signature: &Signature) -> Option<i32> {
+    let strong = signature.strong;
+    (signature.strong_material[0] == 1
+        && pieces(signature.weak_material) > 0
+        && piece_value(board, strong) <= piece_value(board, !strong))
+    .then_some(LAST_PAWN_SCALE)
+}
+
+// Number of knights, bishops, rooks and queens in `material`
+fn pieces(material: [u32; 5]) -> u32 {
+    material[1..].iter().sum()
+}
+
+// Value of the knights, bishops, rooks and queens of `color`
+#[allow(clippy::cast_possible_wrap)]
+fn piece_value(board: &Board, color: Color) -> i32 {
+    [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen]
+        .into_iter()
+        .map(|piece| {
+            let count = board.colored_pieces(color, piece).len();
+            i32::from(PIECE_VALUES[piece as usize]) * count as i32
+        })
+        .sum()
+}
+
+fn has_passed_pawn(board: &Board, color: Color) -> bool {
+    board
+        .colored_pieces(color, Piece::Pawn)
+        .into_iter()
+        .any(|pawn| is_passed(board, pawn, color))
+}
+
+fn square_color(sq: Square) -> usize {
+    (sq.file() as usize + sq.rank() as usize) % 2
+}
+
+#[cfg(test)]
+mod test {
+    use cozy_chess::Board;
+
+    use super::{recognize, scale_factor, Verdict};
+
+    fn verdict(fen: &str) -> Option<Verdict> {
+        recognize(&fen.parse::<Board>().unwrap())
+    }
+
+    #[test]
+    fn king_and_pawn_against_king() {
+        // The king is outside the square, unless it is its move, and then the rook pawn draws
+        assert!(matches!(
+            verdict("7k/8/8/P7/8/8/8/4K3 w - - 0 1"),
+            Some(Verdict::Exact(eval)) if eval > 800
+        ));
+        assert!(matches!(
+            verdict("7k/8/8/P7/8/8/8/4K3 b - - 0 1"),
+            Some(Verdict::Exact(eval)) if eval < -800
+        ));
+        assert_eq!(
+            verdict("4k3/8/8/P7/8/8/8/4K3 b - - 0 1"),
+            Some(Verdict::Scale(0))
+        );
+        // From the starting square the pawn moves two squares
+        assert_eq!(
+            verdict("8/8/8/4k3/8/8/P7/7K w - - 0 1"),
+            Some(Verdict::Scale(0))
+        );
+        assert!(matches!(
+            verdict("8/8/8/6k1/8/8/P7/7K w - - 0 1"),
+            Some(Verdict::Exact(_))
+        ));
+        // The opposition decides with the king in front of its pawn
+        assert!(matches!(
+            verdict("8/4k3/8/4K3/4P3/8/8/8 b - - 0 1"),
+            Some(Verdict::Exact(eval)) if eval < -800
+        ));
+        assert_eq!(
+            verdict("8/4k3/8/4K3/4P3/8/8/8 w - - 0 1"),
+            Some(Verdict::Scale(0))
+        );
+        // A rook pawn against a king in the corner is a draw
+        assert_eq!(
+            verdict("k7/8/8/P7/8/8/8/4K3 w - - 0 1"),
+            Some(Verdict::Scale(0))
+        );
+    }
+
+    #[test]
+    fn bishop_and_knight_mate_in_the_right_corner() {
+        let eval = |fen| match verdict(fen) {
+            Some(Verdict::Exact(eval)) => eval,
+            other => panic!("unexpected verdict {other:?}"),
+        };
+        // The dark squared bishop mates on a1 and h8, not on a8
+        let right_corner = eval("7k/8/5K2/8/8/8/8/2B1N3 w - - 0 1");
+        let wrong_corner = eval("k7/8/2K5/8/8/8/8/2B1N3 w - - 0 1");
+        assert!(right_corner > wrong_corner);
+    }
+
+    #[test]
+    fn wrong_rook_pawn_draws() {
+        // The light squared bishop can't drive the king from h8
+        assert_eq!(
+            verdict("7k/8/8/7P/8/8/8/3BK3 w - - 0 1"),
+            Some(Verdict::Scale(0))
+        );
+        // With the right bishop, or a king far from the corner, it wins as usual
+        assert_eq!(verdict("7k/8/8/7P/8/8/8/2B1K3 w - - 0 1"), None);
+        assert_eq!(verdict("8/8/8/3k3P/8/8/8/3BK3 w - - 0 1"), None);
+    }
+
+    #[test]
+    fn drawish_endings_are_scaled() {
+        let scale = |fen: &str, eval| scale_factor(&fen.parse::<Board>().unwrap(), eval);
+        // Opposite coloured bishops, alone and with rooks, against bishops of the same colour
+        assert_eq!(scale("4k3/5p2/2b5/8/8/2B1P3/5P2/4K3 w - - 0 1", 100), 50);
+        assert_eq!(scale("r3k3/5p2/2b5/8/8/2B1P3/5P2/R3K3 w - - 0 1", 100), 80);
+        assert_eq!(scale("4k3/p4p2/3b4/8/8/2B1P3/P4P2/4K3 w - - 0 1", 100), 100);
+        // A rook ending a pawn up, without and with a passed pawn, from either side's view
+        assert_eq!(scale("4k3/r4pp1/8/8/8/8/5PPP/R5K1 w - - 0 1", 50), 60);
+        assert_eq!(scale("4k3/r4pp1/8/8/8/8/5PPP/R5K1 b - - 0 1", -50), 60);
+        assert_eq!(scale("4k3/r5pp/8/8/8/8/P5PP/R5K1 w - - 0 1", 50), 100);
+        // A minor piece up without pawns, against a queen against a rook
+        assert_eq!(scale("4k3/8/8/8/8/8/8/2BRK3 w - - 0 1", 250), 100);
+        assert_eq!(scale("4k3/3r4/8/8/8/8/8/3RKB2 w - - 0 1", 250), 25);
+        assert_eq!(scale("4k3/3r4/8/8/8/8/8/3QK3 w - - 0 1", 400), 100);
+        // Pawns on the defending king's wing only, unless it is elsewhere
+        assert_eq!(scale("4k3/5p2/3b4/8/8/2B1P3/5P2/4K3 w - - 0 1", 100), 75);
+        assert_eq!(scale("8/5p2/3b4/8/8/k1B1P3/5P2/4K3 w - - 0 1", 100), 100);
+        // A last pawn against a piece of the same worth
+        assert_eq!(scale("8/8/4k3/8/3PK3/8/8/3R1r2 w - - 0 1", 100), 50);
+        assert_eq!(scale("8/8/4k3/8/3PK3/8/8/2NR1r2 w - - 0 1", 100), 100);
+        // Dead draws and scaling recognizers go through the same stage
+        assert_eq!(scale("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1", 300), 0);
+        assert_eq!(scale("7k/8/8/7P/8/8/8/3BK3 w - - 0 1", 300), 0);
+    }
+}
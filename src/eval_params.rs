@@ -0,0 +1,501 @@
+use std::{fs, io, path::Path, slice};
+
+use crate::psqts::{EG_TABLE, EG_VALUE, MG_TABLE, MG_VALUE};
+
+// Weights the tuner optimizes: middle and end game piece values, the two piece-square tables,
+// then the weights of `LINEAR_SECTIONS`
+pub const PARAM_COUNT: usize = TABLE_WEIGHTS + 80;
+// The piece values and tables, which come first among the tuned weights
+pub const TABLE_WEIGHTS: usize = 6 + 6 + 64 * 6 + 64 * 6;
+// Sections of term weights the eval is linear in, so that the tuner can optimize them like the
+// tables. The others scale or divide what other weights add up to.
+const LINEAR_SECTIONS: [&str; 27] = [
+    "mobility_mg",
+    "mobility_eg",
+    "rook_open_file",
+    "rook_semi_open_file",
+    "rook_seventh_rank",
+    "rook_behind_passed_pawn",
+    "king_tropism",
+    "shield_pushed",
+    "shield_missing",
+    "pawn_storm",
+    "advanced_shield",
+    "threat_mg",
+    "threat_eg",
+    "pawn_threat",
+    "connected_pawn",
+    "bad_bishop_pawn",
+    "weak_king_square",
+    "missing_fianchetto",
+    "mop_up_edge",
+    "mop_up_proximity",
+    "undeveloped_minor",
+    "early_queen",
+    "castled_king",
+    "castling_right",
+    "doubled_rooks",
+    "battery",
+    "rook_lift",
+];
+// Groups of weights in a weights file, one per field of `EvalParams`
+const SECTION_COUNT: usize = 37;
+// The first sections, the piece values and tables, which are tuned by the `tune` subcommand
+// rather than as UCI options
+const TABLE_SECTIONS: usize = 4;
+// Term weights as UCI options range from 0 to twice their default, and at least to this
+const MIN_OPTION_MAX: i32 = 10;
+// Anything larger than this is certainly not a centipawn weight
+const MAX_WEIGHT: i32 = 10_000;
+
+// Weights of the evaluation, either the built-in ones or ones loaded through the `EvalFile`
+// option, so that tuned weights can be tried without rebuilding. Pairs are middle and end game
+// values, and arrays by piece type are in the order pawn, knight, bishop, rook, queen, king.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalParams {
+    pub mg_value: [i32; 6],
+    pub eg_value: [i32; 6],
+    pub mg_table: [i32; 64 * 6],
+    pub eg_table: [i32; 64 * 6],
+    // Safe squares a piece of each type typically reaches, and the middle and end game bonus for
+    // every square above or below that
+    pub mobility_baseline: [i32; 6],
+    pub mobility_mg: [i32; 6],
+    pub mobility_eg: [i32; 6],
+    // Bonuses of a rook on a file without pawns, on a file without friendly pawns, on the seventh
+    // rank, and behind a passed pawn of either side
+    pub rook_open_file: [i32; 2],
+    pub rook_semi_open_file: [i32; 2],
+    pub rook_seventh_rank: [i32; 2],
+    pub rook_behind_passed_pawn: [i32; 2],
+    // Middle game bonus of each piece type per square it is closer to the enemy king than the
+    // farthest it could be
+    pub king_tropism: [i32; 6],
+    // Attack units of each piece type per square of the king zone it attacks, centipawns per
+    // unit, and the percentage of the units that counts by the number of attacking pieces
+    pub king_attack_units: [i32; 6],
+    pub king_attack_unit_value: i32,
+    pub king_attacker_scale: [i32; 8],
+    // Penalties for each file of the pawn shield with its pawn two squares ahead of the king, and
+    // without a pawn in front of the king at all
+    pub shield_pushed: i32,
+    pub shield_missing: i32,
+    // With the kings on opposite wings, danger from an enemy pawn on the king's files by the rank
+    // it has reached, and from our own pawn there by the rank it has advanced to, both as seen by
+    // the pawn's side
+    pub pawn_storm: [i32; 8],
+    pub advanced_shield: [i32; 8],
+    // Safe squares of the space term count for a centipawn per this many pieces, and squares
+    // behind a pawn count this many times
+    pub space_divisor: i32,
+    pub space_behind_pawns: i32,
+    // Middle and end game bonuses for threatening each piece type, and for threatening a piece
+    // with a pawn on top of that
+    pub threat_mg: [i32; 6],
+    pub threat_eg: [i32; 6],
+    pub pawn_threat: [i32; 2],
+    // Bonus of a pawn standing next to or defended by another, by its relative rank
+    pub connected_pawn: [i32; 8],
+    // Penalties for each blocked pawn on the colour of our bishop, for each square of that colour
+    // around our king without a bishop of it while the enemy has one, and for a pawn pushed from
+    // in front of a fianchetto without the bishop
+    pub bad_bishop_pawn: [i32; 2],
+    pub weak_king_square: i32,
+    pub missing_fianchetto: i32,
+    // Mop-up bonuses per square the lone king is from the centre, and per square our king is
+    // closer to it than the farthest it could be
+    pub mop_up_edge: i32,
+    pub mop_up_proximity: i32,
+    // Penalties for each knight and bishop on its starting square, and for each of them once the
+    // queen has left hers, and bonuses for a castled king and for each castling right of a king
+    // that hasn't castled
+    pub undeveloped_minor: i32,
+    pub early_queen: i32,
+    pub castled_king: i32,
+    pub castling_right: i32,
+    // Bonuses for two rooks on a file that see each other, for a queen lined up with a bishop or
+    // rook whose line reaches the enemy king, and for a rook that can lift to the third rank and
+    // swing over to the enemy king's files
+    pub doubled_rooks: [i32; 2],
+    pub battery: i32,
+    pub rook_lift: i32,
+}
+
+pub static BUILTIN: EvalParams = EvalParams {
+    mg_value: MG_VALUE,
+    eg_value: EG_VALUE,
+    mg_table: MG_TABLE,
+    eg_table: EG_TABLE,
+    mobility_baseline: [0, 4, 6, 7, 13, 0],
+    mobility_mg: [0, 4, 5, 2, 1, 0],
+    mobility_eg: [0, 4, 5, 4, 2, 0],
+    rook_open_file: [25, 10],
+    rook_semi_open_file: [12, 5],
+    rook_seventh_rank: [10, 20],
+    rook_behind_passed_pawn: [5, 25],
+    king_tropism: [0, 2, 1, 1, 2, 0],
+    king_attack_units: [0, 2, 2, 3, 5, 0],
+    king_attack_unit_value: 20,
+    king_attacker_scale: [0, 0, 50, 75, 88, 94, 97, 99],
+    shield_pushed: 10,
+    shield_missing: 25,
+    pawn_storm: [0, 0, 0, 10, 20, 35, 50, 0],
+    advanced_shield: [0, 0, 0, 10, 20, 30, 30, 0],
+    space_divisor: 2,
+    space_behind_pawns: 2,
+    threat_mg: [5, 30, 30, 45, 50, 0],
+    threat_eg: [15, 30, 30, 40, 50, 0],
+    pawn_threat: [40, 30],
+    connected_pawn: [0, 5, 7, 10, 17, 30, 50, 0],
+    bad_bishop_pawn: [3, 7],
+    weak_king_square: 8,
+    missing_fianchetto: 20,
+    mop_up_edge: 10,
+    mop_up_proximity: 4,
+    undeveloped_minor: 10,
+    early_queen: 8,
+    castled_king: 30,
+    castling_right: 10,
+    doubled_rooks: [15, 10],
+    battery: 15,
+    rook_lift: 10,
+};
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        BUILTIN.clone()
+    }
+}
+
+impl EvalParams {
+    // Weights files are whitespace separated integers, and everything after a `#` on a line is a
+    // comment. A word names the section the following integers go to, with the sections named
+    // and ordered like the fields of `EvalParams`, and without one they start at the first. So
+    // only the sections to change need to be given, and a bare list of the piece values and
+    // tables, in the layout of psqts.rs, works too. Sections left out keep the built-in weights.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text).map_err(|msg| io::Error::new(io::ErrorKind::InvalidData, msg))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut params = BUILTIN.clone();
+        let mut sections = params.sections_mut();
+        let mut section = 0;
+        let mut filled = 0;
+        for word in text
+            .lines()
+            .flat_map(|line| line.split('#').next().unwrap_or("").split_whitespace())
+        {
+            let Ok(weight) = word.parse::<i32>() else {
+                if filled > 0 {
+                    let (name, weights) = &sections[section];
+                    return Err(format!(
+                        "expected {} weights in {name}, found {filled}",
+                        weights.len()
+                    ));
+                }
+                section = sections
+                    .iter()
+                    .position(|(name, _)| *name == word)
+                    .ok_or_else(|| format!("invalid weight or section {word}"))?;
+                continue;
+            };
+            if weight.abs() > MAX_WEIGHT {
+                return Err(format!("invalid weight {word}"));
+            }
+            let Some((_, weights)) = sections.get_mut(section) else {
+                return Err("more weights than sections".to_owned());
+            };
+            weights[filled] = weight;
+            filled += 1;
+            if filled == weights.len() {
+                section += 1;
+                filled = 0;
+            }
+        }
+        if filled > 0 {
+            let (name, weights) = &sections[section];
+            return Err(format!(
+                "expected {} weights in {name}, found {filled}",
+                weights.len()
+            ));
+        }
+        if params.space_divisor <= 0 {
+            return Err("space_divisor must be positive".to_owned());
+        }
+        Ok(params)
+    }
+
+    // A weights file with every section, which `load` reads back to the same weights
+    pub fn to_text(&self) -> String {
+        let mut params = self.clone();
+        let mut text = String::from("# Evaluation weights, see EvalParams for their meaning\n");
+        for (name, weights) in params.sections_mut() {
+            text.push('\n');
+            text.push_str(name);
+            text.push('\n');
+            // Tables are written a rank per line, like in psqts.rs
+            for row in weights.chunks(8) {
+                let row = row.iter().map(ToString::to_string).collect::<Vec<_>>();
+                text.push_str(&row.join(" "));
+                text.push('\n');
+            }
+        }
+        text
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_text())
+    }
+
+    // Every field with its name, in the order of a weights file
+    fn sections_mut(&mut self) -> [(&'static str, &mut [i32]); SECTION_COUNT] {
+        [
+            ("mg_value", &mut self.mg_value),
+            ("eg_value", &mut self.eg_value),
+            ("mg_table", &mut self.mg_table),
+            ("eg_table", &mut self.eg_table),
+            ("mobility_baseline", &mut self.mobility_baseline),
+            ("mobility_mg", &mut self.mobility_mg),
+            ("mobility_eg", &mut self.mobility_eg),
+            ("rook_open_file", &mut self.rook_open_file),
+            ("rook_semi_open_file", &mut self.rook_semi_open_file),
+            ("rook_seventh_rank", &mut self.rook_seventh_rank),
+            ("rook_behind_passed_pawn", &mut self.rook_behind_passed_pawn),
+            ("king_tropism", &mut self.king_tropism),
+            ("king_attack_units", &mut self.king_attack_units),
+            (
+                "king_attack_unit_value",
+                slice::from_mut(&mut self.king_attack_unit_value),
+            ),
+            ("king_attacker_scale", &mut self.king_attacker_scale),
+            ("shield_pushed", slice::from_mut(&mut self.shield_pushed)),
+            ("shield_missing", slice::from_mut(&mut self.shield_missing)),
+            ("pawn_storm", &mut self.pawn_storm),
+            ("advanced_shield", &mut self.advanced_shield),
+            ("space_divisor", slice::from_mut(&mut self.space_divisor)),
+            (
+                "space_behind_pawns",
+                slice::from_mut(&mut self.space_behind_pawns),
+            ),
+            ("threat_mg", &mut self.threat_mg),
+            ("threat_eg", &mut self.threat_eg),
+            ("pawn_threat", &mut self.pawn_threat),
+            ("connected_pawn", &mut self.connected_pawn),
+            ("bad_bishop_pawn", &mut self.bad_bishop_pawn),
+            (
+                "weak_king_square",
+                slice::from_mut(&mut self.weak_king_square),
+            ),
+            (
+                "missing_fianchetto",
+                slice::from_mut(&mut self.missing_fianchetto),
+            ),
+            ("mop_up_edge", slice::from_mut(&mut self.mop_up_edge)),
+            (
+                "mop_up_proximity",
+                slice::from_mut(&mut self.mop_up_proximity),
+            ),
+            (
+                "undeveloped_minor",
+                slice::from_mut(&mut self.undeveloped_minor),
+            ),
+            ("early_queen", slice::from_mut(&mut self.early_queen)),
+            ("castled_king", slice::from_mut(&mut self.castled_king)),
+            ("castling_right", slice::from_mut(&mut self.castling_right)),
+            ("doubled_rooks", &mut self.doubled_rooks),
+            ("battery", slice::from_mut(&mut self.battery)),
+            ("rook_lift", slice::from_mut(&mut self.rook_lift)),
+        ]
+    }
+
+    // Scales the weights of the named sections by a percentage, see `personality::Personality`
+    pub fn scale(&mut self, sections: &[&str], percent: i32) {
+        for (section, weights) in self.sections_mut() {
+            if sections.contains(&section) {
+                for weight in weights {
+                    *weight = *weight * percent / 100;
+                }
+            }
+        }
+    }
+
+    // Sets the term weight with the given option name, see `param_options`, clamped to its range.
+    // Returns whether there is such a weight.
+    pub fn set(&mut self, name: &str, value: i32) -> bool {
+        let Some((_, _, min, max)) = param_options()
+            .into_iter()
+            .find(|(option, ..)| name.eq_ignore_ascii_case(option))
+        else {
+            return false;
+        };
+        if let Some((_, weight)) = self
+            .option_weights()
+            .find(|(option, _)| name.eq_ignore_ascii_case(option))
+        {
+            *weight = value.clamp(min, max);
+        }
+        // The space term divides by it
+        self.space_divisor = self.space_divisor.max(1);
+        true
+    }
+
+    // Every term weight with its option name
+    fn option_weights(&mut self) -> impl Iterator<Item = (String, &mut i32)> {
+        self.sections_mut()
+            .into_iter()
+            .skip(TABLE_SECTIONS)
+            .flat_map(|(section, weights)| {
+                let name = section
+                    .split('_')
+                    .map(|word| word[..1].to_uppercase() + &word[1..])
+                    .collect::<String>();
+                let single = weights.len() == 1;
+                weights.iter_mut().enumerate().map(move |(i, weight)| {
+                    let option = if single {
+                        format!("Eval{name}")
+                    } else {
+                        format!("Eval{name}{i}")
+                    };
+                    (option, weight)
+                })
+            })
+    }
+
+    // The sections the tuner optimizes, in the order of `weights`
+    fn tuned_sections_mut(&mut self) -> impl Iterator<Item = &mut [i32]> {
+        self.sections_mut()
+            .into_iter()
+            .enumerate()
+            .filter(|(i, (name, _))| *i < TABLE_SECTIONS || LINEAR_SECTIONS.contains(name))
+            .map(|(_, (_, weights))| weights)
+    }
+
+    // These weights with the tuned ones replaced by `PARAM_COUNT` others in the order of
+    // `weights`, or `None` if there is a different number of them
+    pub fn with_weights(&self, weights: &[i32]) -> Option<Self> {
+        if weights.len() != PARAM_COUNT {
+            return None;
+        }
+        let mut params = self.clone();
+        let mut rest = weights;
+        for section in params.tuned_sections_mut() {
+            let (tuned, others) = rest.split_at(section.len());
+            section.copy_from_slice(tuned);
+            rest = others;
+        }
+        Some(params)
+    }
+
+    // The piece values and tables, then the linear term weights: the weights the tuner optimizes
+    pub fn weights(&self) -> Vec<i32> {
+        self.clone()
+            .tuned_sections_mut()
+            .collect::<Vec<_>>()
+            .concat()
+    }
+}
+
+// Eval Parameters
+// With the `tune` feature, each term weight of the eval is a UCI spin option like the search
+// parameters, so that the terms can be tuned with SPSA in games, which the `tune` subcommand can't
+// do for terms that aren't linear. An option is named after the weight's section of the weights
+// file, with its index for arrays, e.g. `EvalThreatMg1` for threatening a knight. Weights that are
+// 0 by default are left out, as they are mostly never used, like a king's mobility. Returns the
+// option name, default, minimum and maximum of every option.
+pub fn param_options() -> Vec<(String, i32, i32, i32)> {
+    BUILTIN
+        .clone()
+        .option_weights()
+        .filter(|(_, weight)| **weight != 0)
+        .map(|(option, &mut default)| (option, default, 0, (2 * default).max(MIN_OPTION_MAX)))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::{env, fs};
+
+    use super::{param_options, EvalParams, BUILTIN, PARAM_COUNT, TABLE_WEIGHTS};
+
+    #[test]
+    fn load_weights_file() {
+        let path = env::temp_dir().join(format!("weights-{}.txt", std::process::id()));
+        let weights = [
+            &BUILTIN.mg_value[..],
+            &BUILTIN.eg_value,
+            &BUILTIN.mg_table,
+            &BUILTIN.eg_table,
+        ]
+        .concat();
+        let text = weights
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(" ");
+        fs::write(&path, format!("# built-in weights\n{text}\n")).unwrap();
+        assert_eq!(EvalParams::load(&path).unwrap(), BUILTIN);
+
+        fs::write(&path, "1 2 3").unwrap();
+        assert!(EvalParams::load(&path).is_err());
+
+        BUILTIN.save(&path).unwrap();
+        assert_eq!(EvalParams::load(&path).unwrap(), BUILTIN);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn tuned_weights_round_trip() {
+        let mut weights = BUILTIN.weights();
+        assert_eq!(weights.len(), PARAM_COUNT);
+        assert_eq!(weights[..6], BUILTIN.mg_value);
+        assert_eq!(
+            weights[TABLE_WEIGHTS..TABLE_WEIGHTS + 6],
+            BUILTIN.mobility_mg
+        );
+        assert_eq!(weights[PARAM_COUNT - 1], BUILTIN.rook_lift);
+
+        weights[TABLE_WEIGHTS + 1] += 3;
+        let params = BUILTIN.with_weights(&weights).unwrap();
+        assert_eq!(params.mobility_mg[1], BUILTIN.mobility_mg[1] + 3);
+        assert_eq!(params.weights(), weights);
+        assert!(BUILTIN.with_weights(&weights[1..]).is_none());
+    }
+
+    #[test]
+    fn parse_named_sections() {
+        let params = EvalParams::parse("pawn_threat 60 45 # stronger\nmop_up_edge 12 8").unwrap();
+        assert_eq!(params.pawn_threat, [60, 45]);
+        assert_eq!((params.mop_up_edge, params.mop_up_proximity), (12, 8));
+        assert_eq!(params.mg_table, BUILTIN.mg_table);
+
+        assert!(EvalParams::parse("pawn_threat 60").is_err());
+        assert!(EvalParams::parse("pawn_threats 60 45").is_err());
+        assert!(EvalParams::parse("space_divisor 0").is_err());
+        assert!(EvalParams::parse("mop_up_proximity 4 5").is_err());
+    }
+
+    #[test]
+    fn options_set_term_weights() {
+        let options = param_options();
+        assert!(options
+            .iter()
+            .any(|(option, default, ..)| option == "EvalThreatMg1" && *default == 30));
+        for (option, default, min, max) in &options {
+            let mut params = BUILTIN.clone();
+            assert!(min <= default && default <= max, "{option}");
+            assert!(params.set(option, *default));
+            assert_eq!(params, BUILTIN, "{option}");
+        }
+
+        let mut params = BUILTIN.clone();
+        assert!(params.set("evalshieldmissing", 10_000));
+        assert_eq!(params.shield_missing, 50);
+        assert!(params.set("EvalSpaceDivisor", 0));
+        assert_eq!(params.space_divisor, 1);
+        // Unused weights and the tables aren't options
+        assert!(!params.set("EvalKingTropism0", 5));
+        assert!(!params.set("EvalMgTable0", 5));
+    }
+}
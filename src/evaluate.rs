@@ -1,48 +1,1204 @@
-use cozy_chess::{Board, Color, Square};
+use arrayvec::ArrayVec;
+use cozy_chess::{
+    get_king_moves, get_line_rays, get_pawn_attacks, get_rook_moves, BitBoard, Board, Color, File,
+    Move, Piece, Rank, Square,
+};
 
 use crate::{
-    psqts::{EG_TABLE, EG_VALUE, GAME_PHASE_INC, MG_TABLE, MG_VALUE},
+    endgame::{self, Verdict},
+    eval_params::{EvalParams, BUILTIN},
+    nnue::Network,
+    psqts::GAME_PHASE_INC,
+    see::piece_attacks,
     types::Value,
 };
 
 pub const PIECE_VALUES: [Value; 6] = [100, 250, 300, 500, 900, 10000];
+// Evals are scaled down from this halfmove clock on, reaching 0 where the 50-move rule draws
+const HALFMOVE_SCALE_START: i32 = 20;
+const HALFMOVE_LIMIT: i32 = 100;
+// The positional terms rarely change the eval of the material and tables by more than this
+const LAZY_MARGIN: i32 = 400;
+// Squares of the colour of a1
+const DARK_SQUARES: BitBoard = BitBoard(0xAA55_AA55_AA55_AA55);
+// The pieces whose mobility counts, and which attack the king
+const PIECES: [Piece; 4] = [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen];
 
-#[allow(clippy::cast_possible_truncation)]
 pub fn evaluate(board: &Board) -> Value {
+    evaluate_with(board, &BUILTIN)
+}
+
+pub fn evaluate_with(board: &Board, params: &EvalParams) -> Value {
+    Evaluator::Psqt(params).evaluate(board)
+}
+
+// Evaluation in use: the piece-square tables, with the built-in weights or ones from `EvalFile`,
+// or a network loaded through `NnueFile`
+#[derive(Debug, Clone, Copy)]
+pub enum Evaluator<'a> {
+    Psqt(&'a EvalParams),
+    Nnue(&'a Network),
+}
+
+// The eval of a node, and whether it was cut short, see Lazy Evaluation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WindowEval {
+    pub value: Value,
+    // Only the material and tables were counted, so the value only settles the window it was
+    // computed for, and isn't stored or learned from
+    pub lazy: bool,
+}
+
+impl Evaluator<'_> {
+    pub fn evaluate(self, board: &Board) -> Value {
+        self.evaluate_within(board, game_phase(board), -Value::MAX, Value::MAX)
+            .value
+    }
+
+    // Lazy Evaluation
+    // The positional terms cost far more than the material and tables, and rarely move the eval
+    // by more than LAZY_MARGIN. When the material and tables alone put the eval that far outside
+    // alpha..beta, the node fails high or low either way, so the rest is skipped. A network is
+    // always evaluated whole. The search keeps track of the game phase of its nodes, see
+    // `game_phase_change`, and passes it in.
+    pub fn evaluate_within(
+        self,
+        board: &Board,
+        game_phase: i32,
+        alpha: Value,
+        beta: Value,
+    ) -> WindowEval {
+        let (eval, lazy) = match self {
+            Self::Psqt(params) => lazy_psqt_eval(board, params, game_phase, |base| {
+                let base = i32::from(adjust_eval(board, base));
+                base - LAZY_MARGIN >= i32::from(beta) || base + LAZY_MARGIN <= i32::from(alpha)
+            }),
+            Self::Nnue(network) => (network.evaluate(board), false),
+        };
+        WindowEval {
+            value: adjust_eval(board, eval),
+            lazy,
+        }
+    }
+}
+
+// Turns the raw eval of the tables or the network into the one the search sees
+pub fn adjust_eval(board: &Board, eval: i32) -> Value {
+    let eval = match endgame::recognize(board) {
+        Some(Verdict::Exact(eval)) => eval,
+        _ => eval * endgame::scale_factor(board, eval) / 100,
+    };
+    scale_by_halfmove_clock(board, eval)
+}
+
+// 50-Move Rule Scaling
+// An advantage that makes no progress is worth less and less as the 50-move rule draws closer,
+// so the side that is better looks for a capture or pawn move to reset the clock, and the side
+// that is worse steers towards the draw.
+#[allow(clippy::cast_possible_truncation)]
+pub fn scale_by_halfmove_clock(board: &Board, eval: i32) -> Value {
+    let halfmoves = i32::from(board.halfmove_clock()).clamp(HALFMOVE_SCALE_START, HALFMOVE_LIMIT);
+    (eval * (HALFMOVE_LIMIT - halfmoves) / (HALFMOVE_LIMIT - HALFMOVE_SCALE_START)) as Value
+}
+
+pub fn psqt_eval(board: &Board, params: &EvalParams) -> i32 {
+    lazy_psqt_eval(board, params, game_phase(board), |_| false).0
+}
+
+// The eval of the tables and the positional terms, or of the tables alone if `settled` says
+// they decide the node by themselves, along with whether it was cut short
+fn lazy_psqt_eval(
+    board: &Board,
+    params: &EvalParams,
+    game_phase: i32,
+    settled: impl Fn(i32) -> bool,
+) -> (i32, bool) {
+    let mut breakdown = EvalBreakdown::tables(board, params, game_phase);
+    let tables = breakdown.total();
+    if settled(tables) {
+        return (tables, true);
+    }
+    breakdown.add_positional_terms(board, params);
+    (breakdown.total(), false)
+}
+
+pub fn evaluate_trace(board: &Board) -> EvalBreakdown {
+    evaluate_trace_with(board, &BUILTIN)
+}
+
+// Every term of the piece-square table eval, for tools and tests to inspect
+pub fn evaluate_trace_with(board: &Board, params: &EvalParams) -> EvalBreakdown {
+    let mut breakdown = EvalBreakdown::tables(board, params, game_phase(board));
+    breakdown.add_positional_terms(board, params);
+    breakdown
+}
+
+// Middle and end game values of one eval term, for white and black
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TermScore {
+    pub mg: [i32; 2],
+    pub eg: [i32; 2],
+}
+
+impl TermScore {
+    fn add(&mut self, color: Color, mg: i32, eg: i32) {
+        self.mg[color as usize] += mg;
+        self.eg[color as usize] += eg;
+    }
+}
+
+// The piece-square table eval, term by term. Mop-up is added after the game phase blends the
+// middle and end game, so it has a single value per side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalBreakdown {
+    pub side_to_move: Color,
+    // Game phase, from 0 in a pawn ending to 24 with all pieces on the board
+    pub game_phase: i32,
+    pub material: TermScore,
+    pub psqt: TermScore,
+    pub mobility: TermScore,
+    pub rook_placement: TermScore,
+    pub king_tropism: TermScore,
+    pub king_safety: TermScore,
+    pub space: TermScore,
+    pub threats: TermScore,
+    pub pawn_structure: TermScore,
+    pub color_complexes: TermScore,
+    pub development: TermScore,
+    pub coordination: TermScore,
+    pub mop_up: [i32; 2],
+}
+
+impl EvalBreakdown {
     // Piece-Square Tables
     // These tables (in psqts.rs) assign a value to a particular piece being in a particular position
     // in the middle and end game. Pieces are preferred to be in certain locations at certain stages
     // of the game, and we reward them for doing so. Tables of piece value are also used to account
     // for material difference between the two sides.
-    let cur_side = board.side_to_move();
-    let oth_side = !cur_side;
-    let mut eg = [0; 2];
-    let mut mg = [0; 2];
-    let mut game_phase = 0;
-
-    let empty = !board.occupied();
-    for i in Square::ALL {
-        if empty.has(i) {
+    #[allow(clippy::cast_possible_wrap)]
+    fn tables(board: &Board, params: &EvalParams, game_phase: i32) -> Self {
+        let mut breakdown = Self {
+            side_to_move: board.side_to_move(),
+            game_phase: game_phase.min(24),
+            material: TermScore::default(),
+            psqt: TermScore::default(),
+            mobility: TermScore::default(),
+            rook_placement: TermScore::default(),
+            king_tropism: TermScore::default(),
+            king_safety: TermScore::default(),
+            space: TermScore::default(),
+            threats: TermScore::default(),
+            pawn_structure: TermScore::default(),
+            color_complexes: TermScore::default(),
+            development: TermScore::default(),
+            coordination: TermScore::default(),
+            mop_up: [0; 2],
+        };
+
+        // Piece by piece, as looking up what stands on every square costs more than the tables
+        for color in Color::ALL {
+            // The tables list the far rank first, so white's squares are mirrored vertically
+            let flip = if color == Color::White { 0b111_000 } else { 0 };
+            for piece in Piece::ALL {
+                let pieces = board.colored_pieces(color, piece);
+                let count = pieces.len() as i32;
+                breakdown.material.add(
+                    color,
+                    params.mg_value[piece as usize] * count,
+                    params.eg_value[piece as usize] * count,
+                );
+                for sq in pieces {
+                    let tb_idx = piece as usize * 64 + (sq as usize ^ flip);
+                    breakdown
+                        .psqt
+                        .add(color, params.mg_table[tb_idx], params.eg_table[tb_idx]);
+                }
+            }
+        }
+        breakdown
+    }
+
+    fn add_positional_terms(&mut self, board: &Board, params: &EvalParams) {
+        let attacks = AttackMaps::new(board);
+        for color in Color::ALL {
+            let (mg, eg) = mobility(board, color, &attacks, params);
+            self.mobility.add(color, mg, eg);
+            let (mg, eg) = rook_placement(board, color, params);
+            self.rook_placement.add(color, mg, eg);
+            self.king_tropism
+                .add(color, king_tropism(board, color, params), 0);
+            self.king_safety
+                .add(color, -king_danger(board, color, &attacks, params), 0);
+            self.space.add(color, space(board, color, params), 0);
+            let (mg, eg) = threats(board, color, &attacks, params);
+            self.threats.add(color, mg, eg);
+            let bonus = connected_pawns(board, color, params);
+            self.pawn_structure.add(color, bonus, bonus);
+            let (mg, eg) = color_weaknesses(board, color, params);
+            self.color_complexes.add(color, -mg, -eg);
+            self.development
+                .add(color, development(board, color, params), 0);
+            let (mg, eg) = coordination(board, color, params);
+            self.coordination.add(color, mg, eg);
+            self.mop_up[color as usize] = mop_up(board, color, params);
+        }
+    }
+
+    // The terms blended by the game phase, by name
+    pub const fn terms(&self) -> [(&'static str, TermScore); 12] {
+        [
+            ("material", self.material),
+            ("psqt", self.psqt),
+            ("mobility", self.mobility),
+            ("rook_placement", self.rook_placement),
+            ("king_tropism", self.king_tropism),
+            ("king_safety", self.king_safety),
+            ("space", self.space),
+            ("threats", self.threats),
+            ("pawn_structure", self.pawn_structure),
+            ("color_complexes", self.color_complexes),
+            ("development", self.development),
+            ("coordination", self.coordination),
+        ]
+    }
+
+    // The eval for the side to move, before `adjust_eval`
+    pub fn total(&self) -> i32 {
+        let cur_side = self.side_to_move as usize;
+        let oth_side = !self.side_to_move as usize;
+        let terms = self.terms();
+        let mg_eval = terms
+            .iter()
+            .map(|(_, term)| term.mg[cur_side] - term.mg[oth_side])
+            .sum::<i32>();
+        let eg_eval = terms
+            .iter()
+            .map(|(_, term)| term.eg[cur_side] - term.eg[oth_side])
+            .sum::<i32>();
+        let mg_phase = self.game_phase;
+        let eg_phase = 24 - mg_phase;
+        (mg_eval * mg_phase + eg_eval * eg_phase) / 24 + self.mop_up[cur_side]
+            - self.mop_up[oth_side]
+    }
+}
+
+// Attack Maps
+// Mobility, king safety and threats all look at what every piece attacks. Working that out costs
+// more than anything else in the eval, so it is done once per eval for every piece of both sides
+// and shared by the terms.
+struct AttackMaps {
+    // Every piece with the squares it attacks, by colour
+    pieces: [ArrayVec<(Piece, BitBoard), 16>; 2],
+    // Squares attacked by the pawns, and by any piece, of each colour
+    pawns: [BitBoard; 2],
+    all: [BitBoard; 2],
+}
+
+impl AttackMaps {
+    fn new(board: &Board) -> Self {
+        let occupied = board.occupied();
+        let mut maps = Self {
+            pieces: [ArrayVec::new(), ArrayVec::new()],
+            pawns: [BitBoard::EMPTY; 2],
+            all: [BitBoard::EMPTY; 2],
+        };
+        for color in Color::ALL {
+            let side = color as usize;
+            for piece in Piece::ALL {
+                for sq in board.colored_pieces(color, piece) {
+                    let attacks = piece_attacks(piece, color, sq, occupied);
+                    if piece == Piece::Pawn {
+                        maps.pawns[side] |= attacks;
+                    }
+                    maps.all[side] |= attacks;
+                    maps.pieces[side].push((piece, attacks));
+                }
+            }
+        }
+        maps
+    }
+
+    // The pieces of `color` of the given types, with the squares each of them attacks
+    fn of<'a>(
+        &'a self,
+        color: Color,
+        pieces: &'a [Piece],
+    ) -> impl Iterator<Item = (Piece, BitBoard)> + 'a {
+        self.pieces[color as usize]
+            .iter()
+            .copied()
+            .filter(|(piece, _)| pieces.contains(piece))
+    }
+}
+
+// Game Phase
+// How far the game is from the end game, by the pieces left on the board: from 0 in a pawn ending
+// to 24 with all pieces, or more after promotions, which the eval caps at 24. Only captures and
+// promotions change it, so the search updates it move by move with `game_phase_change` instead
+// of counting the pieces at every node.
+#[allow(clippy::cast_possible_wrap)]
+pub fn game_phase(board: &Board) -> i32 {
+    Piece::ALL
+        .into_iter()
+        .map(|piece| GAME_PHASE_INC[piece as usize] * board.pieces(piece).len() as i32)
+        .sum()
+}
+
+pub fn game_phase_change(board: &Board, mv: Move) -> i32 {
+    // Castling is a king capturing its own rook, which changes nothing
+    let captured = board
+        .piece_on(mv.to)
+        .filter(|_| board.colors(!board.side_to_move()).has(mv.to));
+    mv.promotion
+        .map_or(0, |piece| GAME_PHASE_INC[piece as usize])
+        - captured.map_or(0, |piece| GAME_PHASE_INC[piece as usize])
+}
+
+// Connected Pawns
+// Pawns side by side (a phalanx) control the squares in front of both, and a pawn defended by
+// another can't be won by attacking it. Either is worth more the further the pawns have come, and
+// twice as much for a pawn that is both. A connected pawn without enemy pawns in front of it is a
+// candidate to become passed, and a connected passed pawn is the hardest to stop of all. Returns
+// the bonus of `color`'s pawns, the same in the middle and end game.
+fn connected_pawns(board: &Board, color: Color, params: &EvalParams) -> i32 {
+    let pawns = board.colored_pieces(color, Piece::Pawn);
+    let enemy_pawns = board.colored_pieces(!color, Piece::Pawn);
+    let mut bonus = 0;
+    for pawn in pawns {
+        let rank = pawn.rank();
+        let phalanx =
+            !(pawns & rank.bitboard() & file_and_neighbours(pawn.file()) & !pawn.bitboard())
+                .is_empty();
+        // Our pawns defending this one stand where an enemy pawn on it would attack
+        let supported = !(pawns & get_pawn_attacks(pawn, !color)).is_empty();
+        if !phalanx && !supported {
             continue;
         }
-        let ptype = board.piece_on(i).unwrap();
-        let pcol = board.color_on(i).unwrap();
 
-        let mut tb_idx = i as usize;
-        if pcol == Color::White {
-            tb_idx ^= 0b111_000;
+        let base = params.connected_pawn[rank.relative_to(color) as usize]
+            * (i32::from(phalanx) + i32::from(supported));
+        let ahead = pawn.file().bitboard() & endgame::forward_ranks(pawn, color);
+        bonus += if is_passed(board, pawn, color) {
+            base * 2
+        } else if (enemy_pawns & ahead).is_empty() {
+            base * 3 / 2
+        } else {
+            base
+        };
+    }
+    bonus
+}
+
+// Whether no enemy pawns stand in front of `pawn` on its own or the adjacent files
+pub fn is_passed(board: &Board, pawn: Square, color: Color) -> bool {
+    let enemy_pawns = board.colored_pieces(!color, Piece::Pawn);
+    (enemy_pawns & file_and_neighbours(pawn.file()) & endgame::forward_ranks(pawn, color))
+        .is_empty()
+}
+
+fn file_and_neighbours(file: File) -> BitBoard {
+    let file = file as usize;
+    File::ALL[file.saturating_sub(1)..(file + 2).min(8)]
+        .iter()
+        .fold(BitBoard::EMPTY, |files, file| files | file.bitboard())
+}
+
+// Colour Complexes
+// Pawns and bishops decide who holds the squares of each colour. Our pawns fixed on the colour of
+// our bishop hem it in and leave the other colour to the enemy. Without a bishop of some colour,
+// the squares of that colour around our king can only be held by pawns, and an enemy bishop of
+// that colour walks into the holes. The worst of them comes from pushing the pawn in front of a
+// fianchetto without the bishop there to plug the gap. Returns the middle and end game penalty
+// of `color`.
+#[allow(clippy::cast_possible_wrap)]
+fn color_weaknesses(board: &Board, color: Color, params: &EvalParams) -> (i32, i32) {
+    let pawns = board.colored_pieces(color, Piece::Pawn);
+    let bishops = board.colored_pieces(color, Piece::Bishop);
+    let enemy_bishops = board.colored_pieces(!color, Piece::Bishop);
+    let occupied = board.occupied();
+    let blocked = pawns
+        & match color {
+            Color::White => BitBoard(occupied.0 >> 8),
+            Color::Black => BitBoard(occupied.0 << 8),
+        };
+    let pawn_attacks = pawns.into_iter().fold(BitBoard::EMPTY, |attacks, sq| {
+        attacks | get_pawn_attacks(sq, color)
+    });
+    let king = board.colored_pieces(color, Piece::King).next_square();
+    let zone = king.map_or(BitBoard::EMPTY, |king| {
+        get_king_moves(king) | king.bitboard()
+    });
+
+    let mut mg = 0;
+    let mut eg = 0;
+    for complex in [DARK_SQUARES, !DARK_SQUARES] {
+        if (bishops & complex).is_empty() {
+            if !(enemy_bishops & complex).is_empty() {
+                let holes = (zone & complex & !pawn_attacks).len() as i32;
+                mg += params.weak_king_square * holes;
+            }
+        } else {
+            let fixed = (blocked & complex).len() as i32;
+            mg += params.bad_bishop_pawn[0] * fixed;
+            eg += params.bad_bishop_pawn[1] * fixed;
+        }
+    }
+
+    let Some(king) = king else {
+        return (mg, eg);
+    };
+    for (file, wing) in [
+        (File::B, king.file() <= File::C),
+        (File::G, king.file() >= File::F),
+    ] {
+        let fianchetto = Square::new(file, Rank::Second.relative_to(color));
+        let pushed = Square::new(file, Rank::Third.relative_to(color));
+        let complex = if DARK_SQUARES.has(fianchetto) {
+            DARK_SQUARES
+        } else {
+            !DARK_SQUARES
+        };
+        if wing && pawns.has(pushed) && (bishops & complex).is_empty() {
+            mg += params.missing_fianchetto;
+        }
+    }
+    (mg, eg)
+}
+
+// Development
+// In the opening, every move should bring a piece into play or the king to safety. Knights and
+// bishops still on their starting squares are penalized, and all the more once the queen has gone
+// out ahead of them, as she only gets chased around by the minors developing with tempo. A castled
+// king is rewarded, and so is one that can still castle, a bit less. Being part of the middle game
+// eval, this fades out as pieces come off. Returns the bonus of `color`.
+#[allow(clippy::cast_possible_wrap)]
+fn development(board: &Board, color: Color, params: &EvalParams) -> i32 {
+    let back_rank = Rank::First.relative_to(color);
+    let home = |file: File| Square::new(file, back_rank);
+    let undeveloped = [
+        (Piece::Knight, File::B),
+        (Piece::Bishop, File::C),
+        (Piece::Bishop, File::F),
+        (Piece::Knight, File::G),
+    ]
+    .into_iter()
+    .filter(|&(piece, file)| board.colored_pieces(color, piece).has(home(file)))
+    .count() as i32;
+    let queens = board.colored_pieces(color, Piece::Queen);
+    let early_queen = !queens.is_empty() && !queens.has(home(File::D));
+    let mut bonus =
+        -(params.undeveloped_minor + i32::from(early_queen) * params.early_queen) * undeveloped;
+
+    let Some(king) = board.colored_pieces(color, Piece::King).next_square() else {
+        return bonus;
+    };
+    let castled = king.rank() == back_rank
+        && matches!(king.file(), File::A | File::B | File::C | File::G | File::H);
+    let rights = board.castle_rights(color);
+    bonus += if castled {
+        params.castled_king
+    } else {
+        params.castling_right
+            * (i32::from(rights.short.is_some()) + i32::from(rights.long.is_some()))
+    };
+    bonus
+}
+
+// Piece Coordination
+// Attacks come from pieces working together. Two rooks on a file back each other up, and a queen
+// lined up behind a bishop or rook (or in front of it) hits the end of their line twice, which is
+// hard to meet when that is next to the enemy king. A rook still at home joins the attack by
+// lifting to the third rank over the pawns and swinging over to the king's files. Returns the
+// middle and end game bonus of `color`.
+#[allow(clippy::cast_possible_wrap)]
+fn coordination(board: &Board, color: Color, params: &EvalParams) -> (i32, i32) {
+    let occupied = board.occupied();
+    let rooks = board.colored_pieces(color, Piece::Rook);
+    let doubled = rooks
+        .into_iter()
+        .filter(|&rook| {
+            !(get_rook_moves(rook, occupied) & rooks & rook.file().bitboard()).is_empty()
+        })
+        .count() as i32
+        / 2;
+    let mut mg = params.doubled_rooks[0] * doubled;
+    let eg = params.doubled_rooks[1] * doubled;
+
+    let Some(enemy_king) = board.colored_pieces(!color, Piece::King).next_square() else {
+        return (mg, eg);
+    };
+    let zone = get_king_moves(enemy_king) | enemy_king.bitboard();
+    for queen in board.colored_pieces(color, Piece::Queen) {
+        for partner_piece in [Piece::Bishop, Piece::Rook] {
+            let partners = board.colored_pieces(color, partner_piece)
+                & piece_attacks(partner_piece, color, queen, occupied);
+            // Either piece sees through the other along their line
+            let xray = |sq: Square, through: Square| {
+                piece_attacks(partner_piece, color, sq, occupied & !through.bitboard())
+            };
+            for partner in partners {
+                let seen = xray(queen, partner) | xray(partner, queen);
+                if !(seen & get_line_rays(queen, partner) & zone).is_empty() {
+                    mg += params.battery;
+                }
+            }
+        }
+    }
+
+    // Only a king still behind its pawns is worth lifting a rook against
+    if enemy_king.rank().relative_to(!color) <= Rank::Second {
+        let third = Rank::Third.relative_to(color);
+        let king_files = file_and_neighbours(enemy_king.file());
+        for rook in rooks {
+            if rook.rank().relative_to(color) > Rank::Second {
+                continue;
+            }
+            let lift = Square::new(rook.file(), third);
+            let swing = (get_rook_moves(lift, occupied) & third.bitboard()) | lift.bitboard();
+            if !occupied.has(lift)
+                && get_rook_moves(rook, occupied).has(lift)
+                && !(swing & king_files).is_empty()
+            {
+                mg += params.rook_lift;
+            }
+        }
+    }
+    (mg, eg)
+}
+
+// Threats
+// A piece attacked by something worth less, or attacked and not defended at all, is likely lost
+// unless it moves. Quiescence search only resolves this when there is a capture to play on the
+// spot, so at quiet leaves the eval has to see it by itself. Pawns attacking pieces are the most
+// forcing threats of all and count extra. Returns the middle and end game bonus of `color` for
+// its threats against the enemy.
+fn threats(board: &Board, color: Color, attacks: &AttackMaps, params: &EvalParams) -> (i32, i32) {
+    let targets = board.colors(!color) & !board.pieces(Piece::King);
+    let undefended = targets & !attacks.all[!color as usize];
+    let mut threatened = BitBoard::EMPTY;
+    for (piece, attacked) in attacks.of(color, &Piece::ALL) {
+        let worth_more = Piece::ALL
+            .into_iter()
+            .filter(|&target| PIECE_VALUES[target as usize] > PIECE_VALUES[piece as usize])
+            .fold(BitBoard::EMPTY, |worth_more, target| {
+                worth_more | board.pieces(target)
+            });
+        threatened |= attacked & targets & (worth_more | undefended);
+    }
+    let pawn_attacks = attacks.pawns[color as usize];
+
+    let mut mg = 0;
+    let mut eg = 0;
+    for sq in threatened {
+        let piece = board.piece_on(sq).unwrap();
+        mg += params.threat_mg[piece as usize];
+        eg += params.threat_eg[piece as usize];
+        if piece != Piece::Pawn && pawn_attacks.has(sq) {
+            mg += params.pawn_threat[0];
+            eg += params.pawn_threat[1];
+        }
+    }
+    (mg, eg)
+}
+
+// Space
+// In closed positions, the side with more room behind its pawns can regroup its pieces, while the
+// other side's pieces get in each other's way. Central squares on our second to fourth ranks that
+// no enemy pawn attacks count, double if one of our pawns shields them from the front. More room
+// matters more with more pieces to use it, so the count is weighted by them. Returns the middle
+// game bonus of `color`.
+#[allow(clippy::cast_possible_wrap)]
+fn space(board: &Board, color: Color, params: &EvalParams) -> i32 {
+    let pawns = board.colored_pieces(color, Piece::Pawn);
+    let enemy_pawn_attacks = board
+        .colored_pieces(!color, Piece::Pawn)
+        .into_iter()
+        .fold(BitBoard::EMPTY, |attacks, sq| {
+            attacks | get_pawn_attacks(sq, !color)
+        });
+    let central_files = [File::C, File::D, File::E, File::F]
+        .into_iter()
+        .fold(BitBoard::EMPTY, |files, file| files | file.bitboard());
+    let own_half = [Rank::Second, Rank::Third, Rank::Fourth]
+        .into_iter()
+        .fold(BitBoard::EMPTY, |ranks, rank| {
+            ranks | rank.relative_to(color).bitboard()
+        });
+    let safe = central_files & own_half & !pawns & !enemy_pawn_attacks;
+
+    let forward = if color == Color::White { 1 } else { -1 };
+    let behind_pawns = safe
+        .into_iter()
+        .filter(|&sq| {
+            (1..=3).any(|ahead| {
+                usize::try_from(sq.rank() as i32 + forward * ahead)
+                    .ok()
+                    .and_then(Rank::try_index)
+                    .is_some_and(|rank| pawns.has(Square::new(sq.file(), rank)))
+            })
+        })
+        .count() as i32;
+    let squares = safe.len() as i32 + (params.space_behind_pawns - 1) * behind_pawns;
+    let pieces = (board.colors(color) & !pawns).len() as i32 - 1;
+    squares * pieces / params.space_divisor
+}
+
+// Mobility
+// Pieces with many squares to go to are more useful than ones that are boxed in. Only squares that
+// aren't occupied by friendly pieces or attacked by enemy pawns count, as a piece moving there is
+// either impossible or likely lost. Returns the middle and end game bonus of `color`'s pieces.
+#[allow(clippy::cast_possible_wrap)]
+fn mobility(board: &Board, color: Color, attacks: &AttackMaps, params: &EvalParams) -> (i32, i32) {
+    let safe = !(board.colors(color) | attacks.pawns[!color as usize]);
+
+    let mut mg = 0;
+    let mut eg = 0;
+    for (piece, attacked) in attacks.of(color, &PIECES) {
+        let squares = (attacked & safe).len() as i32;
+        let surplus = squares - params.mobility_baseline[piece as usize];
+        mg += surplus * params.mobility_mg[piece as usize];
+        eg += surplus * params.mobility_eg[piece as usize];
+    }
+    (mg, eg)
+}
+
+// Rook Placement
+// Rooks want open lines. A file without pawns lets a rook reach the far side of the board, and a
+// file with only enemy pawns lets it pressure them. On the seventh rank, a rook attacks the pawns
+// still on their starting squares and cuts off the king behind them, which only matters while one
+// of the two is actually there. Behind a passed pawn, a rook supports our own pawn all the way
+// to promotion, or keeps the enemy's in check from behind, and gains more room the further the
+// pawn gets. Returns the middle and end game bonus of `color`'s rooks.
+fn rook_placement(board: &Board, color: Color, params: &EvalParams) -> (i32, i32) {
+    let own_pawns = board.colored_pieces(color, Piece::Pawn);
+    let enemy_pawns = board.colored_pieces(!color, Piece::Pawn);
+    let seventh = Rank::Seventh.relative_to(color);
+    let eighth = Rank::Eighth.relative_to(color);
+    let enemy_king = board.colored_pieces(!color, Piece::King);
+    let seventh_matters = !(enemy_pawns & seventh.bitboard()).is_empty()
+        || !(enemy_king & eighth.bitboard()).is_empty();
+
+    let mut mg = 0;
+    let mut eg = 0;
+    for sq in board.colored_pieces(color, Piece::Rook) {
+        let file = sq.file().bitboard();
+        let [file_mg, file_eg] = if (own_pawns & file).is_empty() {
+            if (enemy_pawns & file).is_empty() {
+                params.rook_open_file
+            } else {
+                params.rook_semi_open_file
+            }
+        } else {
+            [0, 0]
+        };
+        mg += file_mg;
+        eg += file_eg;
+        if sq.rank() == seventh && seventh_matters {
+            mg += params.rook_seventh_rank[0];
+            eg += params.rook_seventh_rank[1];
+        }
+
+        let attacks = piece_attacks(Piece::Rook, color, sq, board.occupied());
+        let behind_passed_pawn = Color::ALL.into_iter().any(|pawn_color| {
+            (board.colored_pieces(pawn_color, Piece::Pawn) & attacks & file)
+                .into_iter()
+                .any(|pawn| {
+                    endgame::forward_ranks(sq, pawn_color).has(pawn)
+                        && is_passed(board, pawn, pawn_color)
+                })
+        });
+        if behind_passed_pawn {
+            mg += params.rook_behind_passed_pawn[0];
+            eg += params.rook_behind_passed_pawn[1];
+        }
+    }
+    (mg, eg)
+}
+
+// Mop-Up
+// Against a lone king, a queen or rook wins for sure, but the tables don't show the way to the
+// mate and the engine shuffles until the 50-move rule draws. Mating needs the enemy king on the
+// edge and our king next to it, so both are rewarded. Returns the bonus of `color`.
+fn mop_up(board: &Board, color: Color, params: &EvalParams) -> i32 {
+    let heavy_pieces =
+        board.colored_pieces(color, Piece::Queen) | board.colored_pieces(color, Piece::Rook);
+    let enemy_king = board.colored_pieces(!color, Piece::King);
+    if heavy_pieces.is_empty() || board.colors(!color) != enemy_king {
+        return 0;
+    }
+    let (Some(king), Some(enemy_king)) = (
+        board.colored_pieces(color, Piece::King).next_square(),
+        enemy_king.next_square(),
+    ) else {
+        return 0;
+    };
+
+    let centre_distance =
+        |file: i32, rank: i32| (3 - file).max(file - 4) + (3 - rank).max(rank - 4);
+    let (file, rank) = (enemy_king.file() as i32, enemy_king.rank() as i32);
+    let king_distance = (king.file() as i32 - file).abs() + (king.rank() as i32 - rank).abs();
+    params.mop_up_edge * centre_distance(file, rank)
+        + params.mop_up_proximity * (14 - king_distance)
+}
+
+// King Safety
+// The piece-square tables know where the king should stand, but not whether it is exposed. A
+// king whose pawn shield is gone, or with enemy pieces bearing down on the squares around it, is
+// penalized. The attack penalty grows quickly with the number of attackers, as one piece rarely
+// mates on its own. Kings are only attacked while there is material to attack with, so this is
+// part of the middle game eval and fades out with the game phase.
+#[allow(clippy::cast_possible_wrap)]
+fn king_danger(board: &Board, color: Color, attacks: &AttackMaps, params: &EvalParams) -> i32 {
+    let Some(king) = board.colored_pieces(color, Piece::King).next_square() else {
+        return 0;
+    };
+    let zone = get_king_moves(king) | king.bitboard();
+    let mut attackers = 0;
+    let mut units = 0;
+    for (piece, attacked) in attacks.of(!color, &PIECES) {
+        let hits = (attacked & zone).len();
+        if hits > 0 {
+            attackers += 1;
+            units += params.king_attack_units[piece as usize] * hits as i32;
+        }
+    }
+    let attack =
+        units * params.king_attack_unit_value * params.king_attacker_scale[attackers.min(7)] / 100;
+
+    let pawns = board.colored_pieces(color, Piece::Pawn);
+    let forward = if color == Color::White { 1 } else { -1 };
+    let king_file = king.file() as i32;
+    let king_rank = king.rank() as i32;
+    let has_pawn = |file: i32, ranks_ahead: i32| {
+        let file = usize::try_from(file).ok().and_then(File::try_index);
+        let rank = usize::try_from(king_rank + forward * ranks_ahead)
+            .ok()
+            .and_then(Rank::try_index);
+        file.zip(rank)
+            .is_some_and(|(file, rank)| pawns.has(Square::new(file, rank)))
+    };
+    let shield = (king_file - 1..=king_file + 1)
+        .filter(|&file| (0..8).contains(&file))
+        .map(|file| {
+            if has_pawn(file, 1) {
+                0
+            } else if has_pawn(file, 2) {
+                params.shield_pushed
+            } else {
+                params.shield_missing
+            }
+        })
+        .sum::<i32>();
+
+    attack + shield + pawn_storm(board, color, king, params)
+}
+
+// Pawn Storm
+// With the kings castled on opposite wings, each side can throw its pawns at the other king
+// without weakening its own. Enemy pawns advancing on our king's files pry them open, and our own
+// pawns advanced there only lend them a hand. Returns the danger to `color`'s king on `king`.
+fn pawn_storm(board: &Board, color: Color, king: Square, params: &EvalParams) -> i32 {
+    let Some(enemy_king) = board.colored_pieces(!color, Piece::King).next_square() else {
+        return 0;
+    };
+    let wing = |sq: Square| match sq.file() {
+        File::A | File::B | File::C => Some(File::A),
+        File::F | File::G | File::H => Some(File::H),
+        File::D | File::E => None,
+    };
+    if wing(king).is_none() || wing(enemy_king).is_none() || wing(king) == wing(enemy_king) {
+        return 0;
+    }
+
+    let files = file_and_neighbours(king.file());
+    let storm = (board.colored_pieces(!color, Piece::Pawn) & files)
+        .into_iter()
+        .map(|pawn| params.pawn_storm[pawn.rank().relative_to(!color) as usize])
+        .sum::<i32>();
+    let advanced = (board.colored_pieces(color, Piece::Pawn) & files)
+        .into_iter()
+        .map(|pawn| params.advanced_shield[pawn.rank().relative_to(color) as usize])
+        .sum::<i32>();
+    storm + advanced
+}
+
+// King Tropism
+// Pieces near the enemy king take part in an attack, pieces on the other wing don't. This rewards
+// closeness itself, before any square around the king is attacked, so pieces gather for an attack
+// the king safety term then rewards. Returns the bonus of `color`'s pieces.
+fn king_tropism(board: &Board, color: Color, params: &EvalParams) -> i32 {
+    let Some(king) = board.colored_pieces(!color, Piece::King).next_square() else {
+        return 0;
+    };
+    let mut bonus = 0;
+    for piece in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+        for sq in board.colored_pieces(color, piece) {
+            let distance = (sq.file() as i32 - king.file() as i32).abs()
+                + (sq.rank() as i32 - king.rank() as i32).abs();
+            bonus += params.king_tropism[piece as usize] * (14 - distance);
+        }
+    }
+    bonus
+}
+
+// Dead draws, where neither side can possibly mate: bare kings, a single knight or bishop, or
+// one bishop each on squares of the same colour
+pub fn is_insufficient_material(board: &Board) -> bool {
+    match board.occupied().len() {
+        2 => true,
+        3 => !(board.pieces(Piece::Knight) | board.pieces(Piece::Bishop)).is_empty(),
+        4 => {
+            let white = board.colored_pieces(Color::White, Piece::Bishop);
+            let black = board.colored_pieces(Color::Black, Piece::Bishop);
+            let square_color = |sq: Square| (sq.file() as usize + sq.rank() as usize) % 2;
+            match (white.into_iter().next(), black.into_iter().next()) {
+                (Some(w), Some(b)) => square_color(w) == square_color(b),
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cozy_chess::{Board, Color, Rank, Square};
+
+    use crate::{eval_params::BUILTIN, see::is_defended};
+
+    use super::{
+        color_weaknesses, connected_pawns, coordination, development, evaluate, evaluate_trace,
+        game_phase, game_phase_change, is_insufficient_material, king_danger, king_tropism,
+        mobility, mop_up, pawn_storm, psqt_eval, rook_placement, space, threats, AttackMaps,
+        Evaluator, WindowEval,
+    };
+
+    #[test]
+    fn dead_draws() {
+        for fen in [
+            "8/8/4k3/8/8/3K4/8/8 w - - 0 1",
+            "8/8/4k3/8/8/3K4/5N2/8 w - - 0 1",
+            "8/8/4k3/8/8/3K4/5B2/8 b - - 0 1",
+            "8/5b2/4k3/8/8/3K4/6B1/8 w - - 0 1",
+        ] {
+            let board: Board = fen.parse().unwrap();
+            assert!(is_insufficient_material(&board), "{fen}");
+            assert_eq!(evaluate(&board), 0);
+        }
+        for fen in [
+            // Bishops on squares of different colours can still mate in a corner
+            "8/4b3/4k3/8/8/3K4/6B1/8 w - - 0 1",
+            "8/8/4k3/8/8/3K4/4NN2/8 w - - 0 1",
+            "8/8/4k3/8/8/3K4/4P3/8 w - - 0 1",
+        ] {
+            let board: Board = fen.parse().unwrap();
+            assert!(!is_insufficient_material(&board), "{fen}");
+        }
+    }
+
+    #[test]
+    fn exposed_kings_are_in_danger() {
+        let danger = |fen: &str| {
+            let board = fen.parse::<Board>().unwrap();
+            king_danger(&board, Color::White, &AttackMaps::new(&board), &BUILTIN)
+        };
+        // Intact shield, one pawn pushed, and no shield at all
+        assert_eq!(danger("6k1/8/8/8/8/8/5PPP/6K1 w - - 0 1"), 0);
+        assert_eq!(danger("6k1/8/8/8/8/6P1/5P1P/6K1 w - - 0 1"), 10);
+        assert_eq!(danger("6k1/8/8/8/8/8/8/6K1 w - - 0 1"), 75);
+        // A lone queen next to the king counts for nothing, a queen and a rook for a lot
+        let queen = danger("6k1/8/8/8/8/7q/5PPP/6K1 w - - 0 1");
+        let queen_and_rook = danger("6k1/8/8/8/8/7q/5PPP/4r1K1 w - - 0 1");
+        assert_eq!(queen, 0);
+        assert!(queen_and_rook > 100);
+    }
+
+    #[test]
+    fn active_pieces_are_more_mobile() {
+        let mobility = |fen: &str| {
+            let board = fen.parse::<Board>().unwrap();
+            mobility(&board, Color::White, &AttackMaps::new(&board), &BUILTIN)
+        };
+        // A centralized knight against one in the corner, and one kept out of its squares by pawns
+        let centre = mobility("6k1/8/8/8/3N4/8/8/6K1 w - - 0 1");
+        let corner = mobility("6k1/8/8/8/8/8/8/N5K1 w - - 0 1");
+        let guarded = mobility("6k1/8/2p1p3/8/3N4/8/8/6K1 w - - 0 1");
+        assert_eq!(centre, (16, 16));
+        assert_eq!(corner, (-8, -8));
+        assert!(guarded.0 < centre.0);
+        // Squares blocked by friendly pieces don't count
+        let start = mobility("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert!(start.0 < 0);
+    }
+
+    #[test]
+    fn rooks_prefer_open_files_and_the_seventh() {
+        let rooks =
+            |fen: &str, color| rook_placement(&fen.parse::<Board>().unwrap(), color, &BUILTIN);
+        // Open, semi-open and closed files
+        assert_eq!(
+            rooks("6k1/1p6/8/8/8/8/1P6/R5K1 w - - 0 1", Color::White),
+            (25, 10)
+        );
+        assert_eq!(
+            rooks("6k1/p7/8/8/8/8/1P6/1R4K1 w - - 0 1", Color::White),
+            (0, 0)
+        );
+        assert_eq!(
+            rooks("6k1/1p6/8/8/8/8/P7/1R4K1 w - - 0 1", Color::White),
+            (12, 5)
+        );
+        // The seventh rank counts with the enemy king on its back rank, the black rook's too
+        assert_eq!(
+            rooks("6k1/1R6/8/8/8/8/8/6K1 w - - 0 1", Color::White),
+            (35, 30)
+        );
+        assert_eq!(
+            rooks("8/1R6/6k1/8/8/8/8/6K1 w - - 0 1", Color::White),
+            (25, 10)
+        );
+        assert_eq!(
+            rooks("6k1/8/8/8/8/8/1r6/6K1 w - - 0 1", Color::Black),
+            (35, 30)
+        );
+    }
+
+    #[test]
+    fn lone_kings_are_driven_to_the_edge() {
+        let mop_up = |fen: &str| mop_up(&fen.parse::<Board>().unwrap(), Color::White, &BUILTIN);
+        let centre = mop_up("8/8/8/3k4/8/8/8/Q3K3 w - - 0 1");
+        let corner = mop_up("k7/8/8/8/8/8/8/Q3K3 w - - 0 1");
+        let close = mop_up("k7/8/1K6/8/8/8/8/Q7 w - - 0 1");
+        assert!(centre < corner && corner < close);
+        assert_eq!(close, 6 * 10 + (14 - 3) * 4);
+        // Only against a lone king, and only with a heavy piece
+        assert_eq!(mop_up("k7/p7/8/8/8/8/8/Q3K3 w - - 0 1"), 0);
+        assert_eq!(mop_up("k7/8/8/8/8/8/8/B3K3 w - - 0 1"), 0);
+        assert!(evaluate(&"k7/8/8/8/8/8/8/1Q2K3 b - - 0 1".parse().unwrap()) < -900);
+    }
+
+    #[test]
+    fn pieces_near_the_enemy_king() {
+        let tropism =
+            |fen: &str| king_tropism(&fen.parse::<Board>().unwrap(), Color::White, &BUILTIN);
+        let near = tropism("6k1/8/5N2/8/8/8/8/4K3 w - - 0 1");
+        let far = tropism("6k1/8/8/8/8/8/8/N3K3 w - - 0 1");
+        assert_eq!(near, 2 * (14 - 3));
+        assert_eq!(far, 2 * (14 - 13));
+        assert_eq!(tropism("6k1/8/8/8/8/8/8/4K3 w - - 0 1"), 0);
+    }
+
+    #[test]
+    fn lazy_eval_far_outside_the_window() {
+        // Black is a queen down and its mobility suffers, so the positional terms count
+        let board = "rnb1kbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+            .parse::<Board>()
+            .unwrap();
+        let evaluator = Evaluator::Psqt(&BUILTIN);
+        let full = evaluator.evaluate(&board);
+        let phase = game_phase(&board);
+        assert_eq!(
+            evaluator.evaluate_within(&board, phase, full - 50, full + 50),
+            WindowEval {
+                value: full,
+                lazy: false
+            }
+        );
+        let lazy = evaluator.evaluate_within(&board, phase, -10, 10);
+        assert!(lazy.lazy);
+        assert_ne!(lazy.value, full);
+        assert!(lazy.value > 500);
+    }
+
+    #[test]
+    fn game_phase_follows_captures_and_promotions() {
+        let mut board = Board::default();
+        assert_eq!(game_phase(&board), 24);
+        // Castling moves the king onto its own rook, which isn't a capture
+        for mv in [
+            "e2e4", "d7d5", "e4d5", "d8d5", "b1c3", "d5a2", "g1f3", "a2b1", "f1e2", "c8g4", "e1h1",
+            "g4f3", "g2f3", "b1a1", "c3b5", "a1c1", "b5a7",
+        ] {
+            let mv = mv.parse().unwrap();
+            let phase = game_phase(&board) + game_phase_change(&board, mv);
+            board.play(mv);
+            assert_eq!(phase, game_phase(&board), "{mv}");
+        }
+        let board = "4k3/1P6/8/8/8/8/8/4K3 w - - 0 1".parse::<Board>().unwrap();
+        assert_eq!(game_phase_change(&board, "b7b8q".parse().unwrap()), 4);
+    }
+
+    #[test]
+    fn weak_colour_complexes() {
+        let weaknesses =
+            |fen: &str| color_weaknesses(&fen.parse::<Board>().unwrap(), Color::White, &BUILTIN);
+        // The dark squared bishop behind a pawn fixed on d4, unlike a light squared one
+        assert_eq!(weaknesses("4k3/8/8/3p4/3P4/8/8/2B1K3 w - - 0 1"), (3, 7));
+        assert_eq!(weaknesses("4k3/8/8/3p4/3P4/8/8/4KB2 w - - 0 1"), (0, 0));
+        // A fianchetto without its bishop, with the enemy's bishop eyeing f1, g2 and h1
+        assert_eq!(weaknesses("6k1/8/8/8/8/6P1/5PBP/6K1 w - - 0 1"), (0, 0));
+        assert_eq!(weaknesses("6k1/8/8/8/8/6P1/5P1P/6K1 w - - 0 1"), (20, 0));
+        assert_eq!(
+            weaknesses("6k1/8/8/8/2b5/6P1/5P1P/6K1 w - - 0 1"),
+            (3 * 8 + 20, 0)
+        );
+    }
+
+    #[test]
+    fn development_in_the_opening() {
+        let development =
+            |fen: &str| development(&fen.parse::<Board>().unwrap(), Color::White, &BUILTIN);
+        // Four undeveloped minors against both castling rights
+        let start = development("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(start, -4 * 10 + 2 * 10);
+        let developed =
+            development("r1bqkbnr/pppppppp/2n5/8/4P3/2N2N2/PPPP1PPP/R1BQKB1R w KQkq - 0 1");
+        let queen_out = development("rnbqkbnr/pppppppp/8/8/4P2Q/8/PPPP1PPP/RNB1KBNR w KQkq - 0 1");
+        assert!(queen_out < start && start < developed);
+        assert_eq!(queen_out, -4 * (10 + 8) + 2 * 10);
+        // Castled, against having lost the rights by moving the king
+        let castled = development("4k3/8/8/8/8/8/5PPP/5RK1 w - - 0 1");
+        let walked = development("4k3/8/8/8/8/8/5PPP/4KR2 w - - 0 1");
+        assert_eq!(castled, 30);
+        assert_eq!(walked, 0);
+    }
+
+    #[test]
+    fn rooks_and_batteries_coordinate() {
+        let coordination =
+            |fen: &str, color| coordination(&fen.parse::<Board>().unwrap(), color, &BUILTIN);
+        // Doubled rooks, the front one of which can also lift over to the king's wing
+        assert_eq!(
+            coordination("6k1/5ppp/8/8/8/8/3R1PPP/3R2K1 w - - 0 1", Color::White),
+            (
+                BUILTIN.doubled_rooks[0] + BUILTIN.rook_lift,
+                BUILTIN.doubled_rooks[1]
+            )
+        );
+        assert_eq!(
+            coordination("6k1/5ppp/8/8/8/8/3R1PPP/3R2K1 w - - 0 1", Color::Black),
+            (0, 0)
+        );
+        // A queen behind a bishop aiming at h7, and the same pieces not lined up
+        assert_eq!(
+            coordination("6k1/5ppp/8/8/8/3B4/2Q2PPP/6K1 w - - 0 1", Color::White),
+            (BUILTIN.battery, 0)
+        );
+        assert_eq!(
+            coordination("6k1/5ppp/8/8/8/3B4/1Q3PPP/6K1 w - - 0 1", Color::White),
+            (0, 0)
+        );
+    }
+
+    #[test]
+    fn trace_adds_up_to_the_eval() {
+        let board = "r1bq1rk1/ppp2ppp/2n2n2/3pp3/1bPP4/2N1PN2/PP1B1PPP/R2QKB1R w KQ - 0 7"
+            .parse::<Board>()
+            .unwrap();
+        let trace = evaluate_trace(&board);
+        assert_eq!(trace.total(), psqt_eval(&board, &BUILTIN));
+        assert_eq!(trace.material.mg[0], trace.material.mg[1]);
+        assert_eq!(trace.game_phase, 24);
+        assert_eq!(trace.king_tropism.eg, [0, 0]);
+        assert_eq!(trace.mop_up, [0, 0]);
+    }
+
+    #[test]
+    fn space_behind_the_pawn_chain() {
+        let space = |fen: &str| space(&fen.parse::<Board>().unwrap(), Color::White, &BUILTIN);
+        // Advanced central pawns gain room behind them, which enemy pawns take away
+        let start = space("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        let advanced = space("rnbqkbnr/pp3ppp/2p1p3/3pP3/3P4/8/PPP2PPP/RNBQKBNR w KQkq - 0 1");
+        assert!(advanced > start);
+        // Without pieces, space is worth nothing
+        assert_eq!(
+            space("4k3/pp3ppp/2p1p3/3pP3/3P4/8/PPP2PPP/4K3 w - - 0 1"),
+            0
+        );
+    }
+
+    #[test]
+    fn attack_maps_see_defenders() {
+        let start = Board::default();
+        let maps = AttackMaps::new(&start);
+        assert_eq!(maps.pawns[Color::White as usize], Rank::Third.bitboard());
+        assert_eq!(maps.pieces[Color::Black as usize].len(), 16);
+        for fen in [
+            "r1bq1rk1/ppp2ppp/2n2n2/3pp3/1bPP4/2N1PN2/PP1B1PPP/R2QKB1R w KQ - 0 7",
+            "4k3/2p5/3n4/8/8/8/7B/4K3 w - - 0 1",
+        ] {
+            let board = fen.parse::<Board>().unwrap();
+            let maps = AttackMaps::new(&board);
+            for sq in board.occupied() {
+                let color = board.color_on(sq).unwrap();
+                assert_eq!(maps.all[color as usize].has(sq), is_defended(&board, sq));
+            }
         }
-        tb_idx += ptype as usize * 64;
+    }
+
+    #[test]
+    fn threatened_pieces_count() {
+        let threats = |fen: &str| {
+            let board = fen.parse::<Board>().unwrap();
+            threats(&board, Color::White, &AttackMaps::new(&board), &BUILTIN)
+        };
+        // A knight attacked by a pawn, a hanging knight, and a defended one attacked by a bishop
+        assert_eq!(threats("4k3/8/8/3n4/4P3/8/8/4K3 w - - 0 1"), (70, 60));
+        assert_eq!(threats("4k3/8/8/3n4/8/8/3R4/4K3 w - - 0 1"), (30, 30));
+        assert_eq!(threats("4k3/2p5/3n4/8/8/8/7B/4K3 w - - 0 1"), (0, 0));
+        assert_eq!(threats("4k3/8/8/8/8/8/8/4K3 w - - 0 1"), (0, 0));
+    }
 
-        eg[pcol as usize] += EG_VALUE[ptype as usize] + EG_TABLE[tb_idx];
-        mg[pcol as usize] += MG_VALUE[ptype as usize] + MG_TABLE[tb_idx];
-        game_phase += GAME_PHASE_INC[ptype as usize];
+    #[test]
+    fn pawn_storms_against_opposite_castling() {
+        let storm = |fen: &str| {
+            let board = fen.parse::<Board>().unwrap();
+            pawn_storm(&board, Color::White, Square::G1, &BUILTIN)
+        };
+        // Black pawns on g4 and h5 against the king on g1, with the black king on c8 and on g8
+        assert_eq!(storm("2k5/8/8/7p/6p1/8/5PPP/6K1 w - - 0 1"), 20 + 10);
+        assert_eq!(storm("6k1/8/8/7p/6p1/8/5PPP/6K1 w - - 0 1"), 0);
+        // Our own pawns pushed in front of the king
+        assert_eq!(storm("2k5/8/8/8/6P1/7P/5P2/6K1 w - - 0 1"), 10);
     }
 
-    let mg_eval = mg[cur_side as usize] - mg[oth_side as usize];
-    let eg_eval = eg[cur_side as usize] - eg[oth_side as usize];
-    let mg_phase = game_phase.min(24);
-    let eg_phase = 24 - mg_phase;
+    #[test]
+    fn connected_pawns_by_rank() {
+        let connected =
+            |fen: &str| connected_pawns(&fen.parse::<Board>().unwrap(), Color::White, &BUILTIN);
+        // Isolated pawns, a phalanx on the fourth rank, and a chain blocked by enemy pawns
+        assert_eq!(connected("4k3/pppp4/8/8/8/8/P1P1P3/4K3 w - - 0 1"), 0);
+        assert_eq!(connected("4k3/pppp4/8/8/2PP4/8/8/4K3 w - - 0 1"), 2 * 10);
+        assert_eq!(
+            connected("4k3/8/2p1p3/3p4/8/2P1P3/3P4/4K3 w - - 0 1"),
+            2 * 7
+        );
+        // Passed pawns connected in a phalanx on the sixth rank
+        assert_eq!(connected("4k3/8/2PP4/8/8/8/8/4K3 w - - 0 1"), 2 * 2 * 30);
+    }
 
-    ((mg_eval * mg_phase + eg_eval * eg_phase) / 24) as Value
+    #[test]
+    fn rooks_behind_passed_pawns() {
+        let rooks =
+            |fen: &str| rook_placement(&fen.parse::<Board>().unwrap(), Color::White, &BUILTIN);
+        // Behind our own passed pawn and behind the enemy's, on top of the semi-open file
+        assert_eq!(rooks("6k1/8/8/P7/8/8/8/R5K1 w - - 0 1"), (5, 25));
+        assert_eq!(rooks("R5k1/8/8/8/p7/8/8/6K1 w - - 0 1"), (12 + 5, 5 + 25));
+        // In front of the pawn, or with a piece in between, it doesn't count
+        assert_eq!(rooks("R5k1/8/8/P7/8/8/8/6K1 w - - 0 1"), (0, 0));
+        assert_eq!(rooks("6k1/8/8/P7/8/8/N7/R5K1 w - - 0 1"), (0, 0));
+    }
+
+    #[test]
+    fn scaled_by_halfmove_clock() {
+        let eval_at = |halfmoves: u32| {
+            let fen = format!("4k3/8/8/8/8/8/8/R3K3 w - - {halfmoves} 80");
+            evaluate(&fen.parse::<Board>().unwrap())
+        };
+        let full = eval_at(0);
+        assert!(full > 0);
+        assert_eq!(eval_at(20), full);
+        assert!(eval_at(60) < full && eval_at(60) > 0);
+        assert_eq!(eval_at(100), 0);
+    }
 }
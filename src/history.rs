@@ -9,6 +9,12 @@ pub struct HistoryTable {
     table: [i16; 12 * 64],
 }
 
+impl Default for HistoryTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl HistoryTable {
     pub const fn new() -> Self {
         Self {
@@ -33,6 +39,16 @@ impl HistoryTable {
         }
     }
 
+    // Lowers the score of a quiet move that was searched before another one caused a cutoff
+    pub fn penalize(&mut self, board: &Board, mv: Move, depth: Depth) {
+        let entry = self.get_mut(board, mv);
+        let delta = history_delta(i16::from(depth));
+        *entry -= delta;
+        if *entry <= -HISTORY_LIMIT {
+            self.normalize();
+        }
+    }
+
     pub fn normalize(&mut self) {
         for x in self.table.iter_mut() {
             *x /= 2;
@@ -0,0 +1,209 @@
+use std::sync::OnceLock;
+
+use cozy_chess::{get_king_moves, get_pawn_attacks, Board, Color, File, Piece, Rank, Square};
+
+// Pawn squares in the bitbase: files a to d, ranks 2 to 7
+const PAWN_SQUARES: usize = 4 * 6;
+const POSITIONS: usize = 2 * 64 * 64 * PAWN_SQUARES;
+
+// Results during the generation, which combine with `|` over the moves of a position
+const INVALID: u8 = 0;
+const UNKNOWN: u8 = 1;
+const DRAW: u8 = 2;
+const WIN: u8 = 4;
+
+static BITBASE: OnceLock<Vec<u64>> = OnceLock::new();
+
+// KPK Bitbase
+// Whether king and pawn against king is won, for every placement of the three pieces and either
+// side to move. Rules of thumb get this ending wrong often enough, e.g. with the opposition or a
+// rook pawn, and the search can't see far enough to promote on its own. It is computed by
+// retrograde analysis the first time it is needed, which takes a moment, and kept for the rest of
+// the run. Squares are seen from the side with the pawn, mirrored so that the pawn is on the
+// queen side.
+pub fn is_win(board: &Board, strong: Color) -> bool {
+    let square = |color, piece| board.colored_pieces(color, piece).next_square().unwrap();
+    let pawn = square(strong, Piece::Pawn);
+    let mirror = pawn.file() > File::D;
+    let normalize = |sq: Square| {
+        let file = if mirror {
+            File::index(7 - sq.file() as usize)
+        } else {
+            sq.file()
+        };
+        Square::new(file, sq.rank().relative_to(strong))
+    };
+    let index = index(
+        board.side_to_move() == strong,
+        normalize(square(strong, Piece::King)),
+        normalize(square(!strong, Piece::King)),
+        normalize(pawn),
+    );
+    let bitbase = BITBASE.get_or_init(generate);
+    bitbase[index / 64] & (1 << (index % 64)) != 0
+}
+
+// Positions are indexed by side to move, white's king, black's king and the pawn, always white's
+fn index(white_to_move: bool, white_king: Square, black_king: Square, pawn: Square) -> usize {
+    let pawn = pawn.file() as usize * 6 + pawn.rank() as usize - 1;
+    ((usize::from(white_to_move) * 64 + white_king as usize) * 64 + black_king as usize)
+        * PAWN_SQUARES
+        + pawn
+}
+
+fn generate() -> Vec<u64> {
+    let mut results = vec![INVALID; POSITIONS];
+    for white_to_move in [false, true] {
+        for white_king in Square::ALL {
+            for black_king in Square::ALL {
+                for file in &File::ALL[..4] {
+                    for rank in &Rank::ALL[1..7] {
+                        let pawn = Square::new(*file, *rank);
+                        let index = index(white_to_move, white_king, black_king, pawn);
+                        results[index] = initial(white_to_move, white_king, black_king, pawn);
+                    }
+                }
+            }
+        }
+    }
+
+    // Positions are settled from those of their moves until nothing changes anymore. The ones
+    // still unknown then are draws, as white never manages to force a win.
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for white_to_move in [false, true] {
+            for white_king in Square::ALL {
+                for black_king in Square::ALL {
+                    for file in &File::ALL[..4] {
+                        for rank in &Rank::ALL[1..7] {
+                            let pawn = Square::new(*file, *rank);
+                            let index = index(white_to_move, white_king, black_king, pawn);
+                            if results[index] != UNKNOWN {
+                                continue;
+                            }
+                            let result =
+                                classify(&results, white_to_move, white_king, black_king, pawn);
+                            if result != UNKNOWN {
+                                results[index] = result;
+                                changed = true;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let mut bitbase = vec![0; POSITIONS / 64];
+    for (index, &result) in results.iter().enumerate() {
+        if result == WIN {
+            bitbase[index / 64] |= 1 << (index % 64);
+        }
+    }
+    bitbase
+}
+
+// The result of a position that can be told without looking at its moves
+fn initial(white_to_move: bool, white_king: Square, black_king: Square, pawn: Square) -> u8 {
+    let white_attacks = get_king_moves(white_king);
+    let black_attacks = get_king_moves(black_king);
+    let pawn_attacks = get_pawn_attacks(pawn, Color::White);
+    if white_king == black_king
+        || white_attacks.has(black_king)
+        || white_king == pawn
+        || black_king == pawn
+        || (white_to_move && pawn_attacks.has(black_king))
+    {
+        return INVALID;
+    }
+
+    let promotion = Square::new(pawn.file(), Rank::Eighth);
+    if white_to_move
+        && pawn.rank() == Rank::Seventh
+        && white_king != promotion
+        && black_king != promotion
+        && (!black_attacks.has(promotion) || white_attacks.has(promotion))
+    {
+        return WIN;
+    }
+    // Stalemate, or the pawn taken for free
+    let safe = black_attacks & !white_attacks & !pawn_attacks;
+    if !white_to_move && (safe.is_empty() || (black_attacks & !white_attacks).has(pawn)) {
+        return DRAW;
+    }
+    UNKNOWN
+}
+
+// The result of a position from those of its moves: white wins if one of its moves wins, and
+// black draws if one of its moves draws
+fn classify(
+    results: &[u8],
+    white_to_move: bool,
+    white_king: Square,
+    black_king: Square,
+    pawn: Square,
+) -> u8 {
+    let mut result = INVALID;
+    if white_to_move {
+        for sq in get_king_moves(white_king) {
+            result |= results[index(false, sq, black_king, pawn)];
+        }
+        let kings = white_king.bitboard() | black_king.bitboard();
+        // Pushes to the eighth rank are already known wins, or lose the pawn
+        if pawn.rank() < Rank::Seventh {
+            let push = Square::new(pawn.file(), Rank::index(pawn.rank() as usize + 1));
+            if !kings.has(push) {
+                result |= results[index(false, white_king, black_king, push)];
+                let double = Square::new(pawn.file(), Rank::Fourth);
+                if pawn.rank() == Rank::Second && !kings.has(double) {
+                    result |= results[index(false, white_king, black_king, double)];
+                }
+            }
+        }
+        if result & WIN != 0 {
+            WIN
+        } else if result & UNKNOWN != 0 {
+            UNKNOWN
+        } else {
+            DRAW
+        }
+    } else {
+        for sq in get_king_moves(black_king) {
+            result |= results[index(true, white_king, sq, pawn)];
+        }
+        if result & DRAW != 0 {
+            DRAW
+        } else if result & UNKNOWN != 0 {
+            UNKNOWN
+        } else {
+            WIN
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cozy_chess::{Board, Color};
+
+    use super::is_win;
+
+    #[test]
+    fn opposition_and_rook_pawns() {
+        let win = |fen: &str, strong| is_win(&fen.parse::<Board>().unwrap(), strong);
+        // The king in front of its pawn wins with the opposition, and draws without it
+        assert!(win("8/4k3/8/4K3/4P3/8/8/8 b - - 0 1", Color::White));
+        assert!(!win("8/4k3/8/4K3/4P3/8/8/8 w - - 0 1", Color::White));
+        // Two ranks in front, it always wins
+        assert!(win("4k3/8/4K3/8/4P3/8/8/8 w - - 0 1", Color::White));
+        assert!(win("4k3/8/4K3/8/4P3/8/8/8 b - - 0 1", Color::White));
+        // A king in the corner holds a rook pawn
+        assert!(!win("k7/8/1K6/P7/8/8/8/8 w - - 0 1", Color::White));
+        // Outside the square of the pawn, the king can't catch it
+        assert!(win("8/8/k7/8/7P/8/8/K7 w - - 0 1", Color::White));
+        assert!(!win("8/8/4k3/8/7P/8/8/K7 w - - 0 1", Color::White));
+        // The same for black, mirrored
+        assert!(win("8/8/8/4p3/4k3/8/4K3/8 w - - 0 1", Color::Black));
+        assert!(!win("8/8/8/4p3/4k3/8/4K3/8 b - - 0 1", Color::Black));
+    }
+}
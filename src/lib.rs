@@ -0,0 +1,54 @@
+#![warn(clippy::all, clippy::pedantic, clippy::nursery)]
+#![allow(
+    clippy::similar_names,
+    clippy::module_name_repetitions,
+    clippy::too_many_lines,
+    clippy::must_use_candidate,
+    clippy::missing_panics_doc,
+    clippy::missing_errors_doc
+)]
+pub mod analyze;
+pub mod annotate;
+pub mod book;
+pub mod checkpoint;
+pub mod cli;
+pub mod config;
+pub mod contempt;
+pub mod correction_history;
+pub mod cuckoo;
+pub mod endgame;
+pub mod eval_params;
+pub mod evaluate;
+pub mod history;
+pub mod kpk;
+pub mod lmr_table;
+pub mod mate_proof;
+pub mod mcts;
+pub mod move_ordering;
+pub mod nnue;
+pub mod perft;
+pub mod personality;
+pub mod pgn;
+pub mod pool;
+pub mod positions;
+pub mod psqts;
+pub mod pv_table;
+pub mod rng;
+pub mod search;
+pub mod search_params;
+pub mod search_stack;
+pub mod see;
+pub mod selfcheck;
+pub mod selfplay;
+pub mod strength;
+pub mod summary;
+pub mod tablebase;
+pub mod trace;
+pub mod transposition_table;
+pub mod tuner;
+pub mod types;
+pub mod uci;
+pub mod uci_log;
+pub mod utils;
+pub mod wdl;
+pub mod zobrist;
@@ -1,27 +1,39 @@
-use crate::types::Depth;
+use crate::{search_params::SearchParams, types::Depth};
 
-const LMR_BASE: f64 = 0.75;
-const LMR_DIVISOR: f64 = 2.25;
-
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct LMRTable {
     table: [[Depth; 64]; 64],
 }
 
+impl Default for LMRTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl LMRTable {
+    pub fn new() -> Self {
+        let params = SearchParams::default();
+        Self::with_params(params.lmr_base, params.lmr_divisor)
+    }
+
+    // Reductions of base + ln(depth) * ln(move number) / divisor, both given in hundredths, see
+    // `SearchParams`
     #[allow(
         clippy::needless_range_loop,
         clippy::cast_possible_truncation,
         clippy::cast_sign_loss,
         clippy::cast_precision_loss
     )]
-    pub fn new() -> Self {
+    pub fn with_params(base: u32, divisor: u32) -> Self {
+        let base_f = f64::from(base) / 100.0;
+        let divisor_f = f64::from(divisor.max(1)) / 100.0;
         let mut table = [[0; 64]; 64];
 
         for move_num in 0..64 {
             for depth in 0..64 {
-                table[move_num][depth] = (LMR_BASE
-                    + f64::ln(depth.max(1) as f64) * f64::ln(move_num.max(1) as f64) / LMR_DIVISOR)
+                table[move_num][depth] = (base_f
+                    + f64::ln(depth.max(1) as f64) * f64::ln(move_num.max(1) as f64) / divisor_f)
                     as Depth;
             }
         }
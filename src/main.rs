@@ -5,211 +5,683 @@
     clippy::too_many_lines
 )]
 use std::{
-    env,
-    io::stdin,
-    sync::mpsc::{self, Sender},
+    env, fs,
+    path::Path,
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver},
+        Arc,
+    },
     thread,
     time::{Duration, Instant},
 };
 
-use cozy_chess::{Board, Color, Move};
-use cozy_uci::{
-    command::UciCommand,
-    remark::{UciIdInfo, UciRemark},
-    UciFormatOptions, UciParseErrorKind,
+use chess_engine::{
+    analyze, annotate,
+    book::Book,
+    cli::Flags,
+    eval_params::EvalParams,
+    mate_proof::{MateProof, MateProver},
+    nnue::Network,
+    perft::{divide, perft},
+    personality::Personality,
+    positions::named_position,
+    rng::Rng,
+    search::{is_mate_score, is_mate_within, nps, uci_score, SearchStats, Searcher},
+    selfcheck, selfplay,
+    strength::{EloLimit, SkillLevel, MAX_ELO},
+    summary::{GameSummary, MoveRecord, MoveSource},
+    tablebase::Tablebase,
+    tuner,
+    types::Depth,
+    uci::{uci_handler, ThreadMessage, TimeLimit, DEFAULT_HASH_MB},
+    uci_println,
+    utils::{ascii_board, play_uci_moves, to_uci_move, uci_line},
 };
-use search::Searcher;
-use UciParseErrorKind::UnknownMessageKind;
-
-use crate::{search::SearchStats, utils::kxr_to_uci_move};
-mod evaluate;
-mod history;
-mod lmr_table;
-mod move_ordering;
-mod psqts;
-mod search;
-mod transposition_table;
-mod types;
-mod utils;
-
-#[derive(Debug)]
-enum ThreadMessage {
-    SearchTask {
-        board: Board,
-        moves: Vec<Move>,
-        time_left: Duration,
-        time_inc: Duration,
-    },
-    NewGame,
-}
+use cozy_chess::Board;
+use cozy_uci::{remark::UciRemark, UciFormatOptions};
+
+// The search recurses deeply, so give it more than the default thread stack
+const SEARCH_STACK_SIZE: usize = 64 * 1024 * 1024;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() > 1 {
-        if args[1] == "bench" {
-            run_benchmark();
-        }
-        if args[1] == "hyperfine" {
-            hyperfine();
+    if let Some(command) = args.get(1) {
+        let rest = &args[2..];
+        let result = match command.as_str() {
+            "bench" => parse_bench_args(rest).map(|config| run_benchmark(&config)),
+            "perft" => run_perft(rest),
+            "provemate" => run_provemate(rest),
+            "analyze" => analyze::run_position(rest),
+            "analyze-file" => analyze::run(rest),
+            "annotate" => annotate::run(rest),
+            "selfplay" => selfplay::run(rest),
+            "selfcheck" => selfcheck::run(rest),
+            "tune" => tuner::run(rest),
+            "weights" => write_weights(rest),
+            "hyperfine" => {
+                hyperfine();
+                Ok(())
+            }
+            "help" | "--help" | "-h" => {
+                print_usage();
+                Ok(())
+            }
+            other => Err(format!("unknown subcommand {other}")),
+        };
+        if let Err(msg) = result {
+            eprintln!("{command}: {msg}");
+            print_usage();
+            process::exit(1);
         }
         return;
     }
 
     let (tx, rx) = mpsc::channel::<ThreadMessage>();
 
-    let _handler = thread::spawn(move || {
-        uci_handler(tx);
-    });
+    let stop = Arc::new(AtomicBool::new(false));
+    let ponder = Arc::new(AtomicBool::new(false));
+    let searching = Arc::new(AtomicBool::new(false));
+    let mut searcher = Searcher::new(DEFAULT_HASH_MB * 1024 * 1024);
+    searcher.set_stop_flag(Arc::clone(&stop));
+    searcher.set_ponder_flag(Arc::clone(&ponder));
+    searcher.set_reporting(true);
 
-    let mut searcher = Searcher::new(100_000_000);
+    // Searches run on their own thread, so that the UCI thread can always answer the GUI
+    let worker = {
+        let searching = Arc::clone(&searching);
+        thread::Builder::new()
+            .name("search".to_owned())
+            .stack_size(SEARCH_STACK_SIZE)
+            .spawn(move || search_worker(&rx, searcher, &searching))
+    };
+    let worker = match worker {
+        Ok(worker) => worker,
+        Err(e) => {
+            eprintln!("could not start the search thread: {e}");
+            process::exit(1);
+        }
+    };
+    if let Err(e) = uci_handler(tx, stop, ponder, searching) {
+        eprintln!("{e}");
+    }
+    if worker.join().is_err() {
+        eprintln!("the search thread panicked");
+        process::exit(1);
+    }
+}
 
-    let options = UciFormatOptions::default();
-    loop {
-        let task = match rx.recv() {
-            Ok(r) => r,
-            Err(e) => {
-                panic!("AAA {e}");
-            }
-        };
+// Handles messages from the UCI thread until it quits
+fn search_worker(rx: &Receiver<ThreadMessage>, mut searcher: Searcher, searching: &AtomicBool) {
+    let mut summary = GameSummary::default();
 
+    let mut options = UciFormatOptions::default();
+    let mut chess960 = false;
+    let mut debug = false;
+    let mut limit_strength = false;
+    let mut elo = MAX_ELO;
+    let mut own_book = false;
+    let mut analyse_mode = false;
+    // The weights of `EvalFile` and the eval options, before the personality scales them
+    let mut eval_params = EvalParams::default();
+    let mut personality = Personality::default();
+    let mut book_file = String::new();
+    let mut book = None;
+    let mut book_rng = Rng::from_time();
+    // The UCI thread only hangs up after sending `Quit`, or when it failed, and either way there
+    // is nothing left to do
+    while let Ok(task) = rx.recv() {
         match task {
             ThreadMessage::SearchTask {
                 mut board,
                 moves,
-                time_left,
-                time_inc,
+                limit,
+                depth,
+                node_limit,
+                mate,
+                search_moves,
+                avoid_moves,
             } => {
+                // Book moves are played instantly in games, but not when analysing, nor when some
+                // moves must be avoided, which the book doesn't know about
+                if own_book
+                    && !analyse_mode
+                    && avoid_moves.is_empty()
+                    && matches!(limit, TimeLimit::Clock { .. } | TimeLimit::MoveTime(_))
+                {
+                    let position = play_uci_moves(&board, &moves, chess960);
+                    if let Some(mv) = book
+                        .as_ref()
+                        .and_then(|book: &Book| book.probe(&position, &mut book_rng))
+                    {
+                        summary.record(MoveRecord {
+                            depth: 0,
+                            nodes: 0,
+                            time: Duration::ZERO,
+                            value: 0,
+                            source: MoveSource::Book,
+                        });
+                        uci_println!(
+                            "{}",
+                            UciRemark::BestMove {
+                                mv: to_uci_move(&position, mv, chess960),
+                                ponder: None
+                            }
+                            .format(&options)
+                        );
+                        searching.store(false, Ordering::Relaxed);
+                        continue;
+                    }
+                }
+
+                searcher.set_node_limit(node_limit);
+                searcher.set_search_moves(search_moves);
+                searcher.set_avoid_moves(avoid_moves);
                 let mut stats = SearchStats::default();
-                let (mut bm, _bv) = searcher.search_for_time(
-                    &mut board,
-                    &moves,
-                    &mut stats,
-                    time_left / 20 + time_inc / 2,
-                );
+                let start = Instant::now();
+                let move_time = limit.move_time();
+                searcher.set_hard_time_limit(limit.hard_time());
+                if debug {
+                    uci_println!(
+                        "info string time limit {limit:?}, allocated {move_time:?}, hard limit {:?}",
+                        limit.hard_time()
+                    );
+                }
+                let (bm, bv) = if matches!(limit, TimeLimit::Infinite) {
+                    searcher.search_infinite(&mut board, &moves, &mut stats)
+                } else if let Some(mate_moves) = mate {
+                    let result =
+                        searcher.search_mate(&mut board, &moves, &mut stats, mate_moves, move_time);
+                    if !is_mate_within(result.1, mate_moves) {
+                        uci_println!("info string no mate in {mate_moves} found");
+                    }
+                    result
+                } else {
+                    let depth = depth.unwrap_or(Depth::MAX);
+                    searcher.search(&mut board, &moves, &mut stats, depth, move_time)
+                };
+                summary.record(MoveRecord {
+                    depth: stats.depth,
+                    nodes: stats.nodes_visited,
+                    time: start.elapsed(),
+                    value: bv,
+                    source: if stats.tb_hits > 0 {
+                        MoveSource::Tablebase
+                    } else {
+                        MoveSource::Search
+                    },
+                });
 
-                println!("info nodes {}", stats.nodes_visited);
-                println!("info depth {}", stats.depth);
-                kxr_to_uci_move(&board, &mut bm);
-                println!(
+                if is_mate_score(bv) {
+                    let line = uci_line(&board, &searcher.mate_line(&board, bv), chess960);
+                    uci_println!(
+                        "info score {} pv {}",
+                        uci_score(bv),
+                        line.iter()
+                            .map(ToString::to_string)
+                            .collect::<Vec<_>>()
+                            .join(" ")
+                    );
+                }
+                let mut line = vec![bm];
+                line.extend(searcher.ponder_move(&board, bm));
+                let line = uci_line(&board, &line, chess960);
+                uci_println!(
                     "{}",
                     UciRemark::BestMove {
-                        mv: bm,
-                        ponder: None
+                        mv: line[0],
+                        ponder: line.get(1).copied()
                     }
                     .format(&options)
                 );
+                searching.store(false, Ordering::Relaxed);
             }
             ThreadMessage::NewGame => {
+                // A new game means the previous one is over
+                for line in summary.report() {
+                    uci_println!("info string {line}");
+                }
+                summary.clear();
                 searcher.new_game();
             }
+            ThreadMessage::SetContempt(contempt) => {
+                searcher.set_contempt(contempt);
+            }
+            ThreadMessage::SetPersonality(style) => {
+                personality = style;
+                searcher.set_eval_params(Arc::new(personality.apply(&eval_params)));
+            }
+            ThreadMessage::SetVariety(variety) => {
+                searcher.set_variety(variety);
+            }
+            ThreadMessage::SetRootJitter(root_jitter) => {
+                searcher.set_root_jitter(root_jitter);
+            }
+            ThreadMessage::SetRootJitterMoves(moves) => {
+                searcher.set_root_jitter_moves(moves);
+            }
+            ThreadMessage::SetSearchParam(name, value) => {
+                searcher.set_search_param(&name, value);
+            }
+            ThreadMessage::SetEvalParam(name, value) => {
+                eval_params.set(&name, value);
+                searcher.set_eval_params(Arc::new(personality.apply(&eval_params)));
+            }
+            ThreadMessage::SetHeuristic(heuristic, enabled) => {
+                searcher.set_heuristic(heuristic, enabled);
+            }
+            ThreadMessage::SetDeterministic(deterministic) => {
+                searcher.set_deterministic(deterministic);
+            }
+            ThreadMessage::SetSeed(seed) => {
+                searcher.set_seed(seed);
+            }
+            ThreadMessage::SetHash(mb) => {
+                searcher.resize_tt(mb);
+            }
+            ThreadMessage::SetLimitStrength(enabled) => {
+                limit_strength = enabled;
+                searcher.set_elo_limit(limit_strength.then(|| EloLimit::new(elo)));
+            }
+            ThreadMessage::SetElo(rating) => {
+                elo = rating;
+                searcher.set_elo_limit(limit_strength.then(|| EloLimit::new(elo)));
+            }
+            ThreadMessage::SetSkillLevel(level) => {
+                searcher.set_skill_level(SkillLevel::new(level));
+            }
+            ThreadMessage::ClearHash => {
+                searcher.clear_tt();
+            }
+            ThreadMessage::SetThreads(threads) => {
+                searcher.set_threads(threads);
+            }
+            ThreadMessage::SetSearchMode(mode) => {
+                searcher.set_search_mode(mode);
+            }
+            ThreadMessage::SetSearchBackend(backend) => {
+                searcher.set_search_backend(backend);
+            }
+            ThreadMessage::SetMultiPv(lines) => {
+                searcher.set_multi_pv(lines);
+            }
+            ThreadMessage::SetChess960(enabled) => {
+                chess960 = enabled;
+                options.chess960 = enabled;
+                searcher.set_chess960(enabled);
+            }
+            ThreadMessage::SetAnalyseMode(enabled) => {
+                analyse_mode = enabled;
+                searcher.set_analyse_mode(enabled);
+            }
+            ThreadMessage::SetDebug(enabled) => {
+                debug = enabled;
+                searcher.set_debug(enabled);
+            }
+            ThreadMessage::SetEvalFile(path) => {
+                // Falls back to the built-in weights rather than playing with a broken evaluation
+                eval_params = if path.is_empty() || path == "<empty>" {
+                    EvalParams::default()
+                } else {
+                    EvalParams::load(Path::new(&path)).unwrap_or_else(|e| {
+                        uci_println!(
+                            "info string could not load {path}: {e}, using built-in weights"
+                        );
+                        EvalParams::default()
+                    })
+                };
+                searcher.set_eval_params(Arc::new(personality.apply(&eval_params)));
+            }
+            ThreadMessage::SetNnueFile(path) => {
+                // An empty path goes back to the piece-square tables, and so does a broken file
+                let network = if path.is_empty() || path == "<empty>" {
+                    None
+                } else {
+                    match Network::load(Path::new(&path)) {
+                        Ok(network) => {
+                            uci_println!(
+                                "info string loaded {path} with {} hidden neurons",
+                                network.hidden_size()
+                            );
+                            Some(Arc::new(network))
+                        }
+                        Err(e) => {
+                            uci_println!(
+                                "info string could not load {path}: {e}, using the piece-square tables"
+                            );
+                            None
+                        }
+                    }
+                };
+                searcher.set_nnue(network);
+            }
+            ThreadMessage::SetTraceFile(path) => {
+                let path = Some(path).filter(|p| !p.is_empty() && p != "<empty>");
+                if let Err(e) = searcher.set_trace_file(path.as_deref().map(Path::new)) {
+                    uci_println!("info string could not create trace file: {e}");
+                }
+            }
+            ThreadMessage::SetSyzygyPath(path) => {
+                let tablebase = if path.is_empty() || path == "<empty>" {
+                    None
+                } else {
+                    match Tablebase::open(&path) {
+                        Ok(tablebase) => Some(Arc::new(tablebase)),
+                        Err(e) => {
+                            uci_println!("info string could not load tablebases: {e}");
+                            None
+                        }
+                    }
+                };
+                searcher.set_tablebase(tablebase);
+            }
+            ThreadMessage::SetSyzygyProbeLimit(pieces) => {
+                searcher.set_tb_probe_limit(pieces);
+            }
+            ThreadMessage::SetShowRefutations(enabled) => {
+                searcher.set_show_refutations(enabled);
+            }
+            ThreadMessage::SetShowCurrLine(enabled) => {
+                searcher.set_show_currline(enabled);
+            }
+            ThreadMessage::SetShowWdl(enabled) => {
+                options.wdl = enabled;
+                searcher.set_show_wdl(enabled);
+            }
+            ThreadMessage::SetOwnBook(enabled) => {
+                own_book = enabled;
+            }
+            ThreadMessage::SetBookFile(path) => {
+                book_file = path;
+                book = load_book(&book_file);
+            }
+            ThreadMessage::Bench => run_benchmark(&BenchConfig::default()),
+            ThreadMessage::Display { board, moves } => {
+                let board = play_uci_moves(&board, &moves, chess960);
+                let fen = board.to_string();
+                uci_println!("{}", ascii_board(&board).trim_end());
+                uci_println!("Fen: {fen}");
+                uci_println!("Key: {:016x}", board.hash());
+                uci_println!("Side to move: {:?}", board.side_to_move());
+                uci_println!("Castling rights: {}", fen.split(' ').nth(2).unwrap_or("-"));
+                uci_println!("Static eval: {}", searcher.static_eval(&board));
+            }
+            ThreadMessage::Perft {
+                board,
+                moves,
+                depth,
+            } => {
+                let board = play_uci_moves(&board, &moves, chess960);
+                if depth == 0 {
+                    uci_println!("Nodes searched: 1");
+                    continue;
+                }
+                let split = divide(&board, depth, chess960);
+                for (mv, nodes) in &split {
+                    uci_println!("{mv}: {nodes}");
+                }
+                uci_println!();
+                uci_println!(
+                    "Nodes searched: {}",
+                    split.iter().map(|&(_, nodes)| nodes).sum::<u64>()
+                );
+            }
+            ThreadMessage::IsReady => {
+                uci_println!("{}", UciRemark::ReadyOk.format(&options));
+            }
+            ThreadMessage::Quit => break,
         }
     }
 }
 
-#[allow(clippy::needless_pass_by_value)]
-fn uci_handler(tx: Sender<ThreadMessage>) {
-    let options = UciFormatOptions::default();
-    let mut cur_board = Board::startpos();
-    let mut moves = Vec::new();
-    moves.reserve(512);
-
-    loop {
-        let mut line = String::new();
-        stdin().read_line(&mut line).unwrap();
-
-        #[allow(clippy::match_same_arms)]
-        match UciCommand::parse_from(&line, &options) {
-            Ok(cmd) => match cmd {
-                UciCommand::Uci => {
-                    println!(
-                        "{:}",
-                        UciRemark::Id(UciIdInfo::Name("toy-engine".to_owned())).format(&options)
-                    );
+// Loads the opening book, unless none is configured
+fn load_book(book_file: &str) -> Option<Book> {
+    if book_file.is_empty() || book_file == "<empty>" {
+        return None;
+    }
+    match Book::open(Path::new(book_file)) {
+        Ok(book) => Some(book),
+        Err(e) => {
+            uci_println!("info string could not load book: {e}");
+            None
+        }
+    }
+}
 
-                    println!(
-                        "{:}",
-                        UciRemark::Id(UciIdInfo::Author("Aayush Sabharwal".to_owned()))
-                            .format(&options)
-                    );
+fn print_usage() {
+    eprintln!(
+        "Usage: chess-engine [SUBCOMMAND]
+Without a subcommand the engine speaks UCI on stdin and stdout.
 
-                    println!("{:}", UciRemark::UciOk.format(&options));
-                }
-                UciCommand::Debug(_) => {}
-                UciCommand::IsReady => println!("{:}", UciRemark::ReadyOk.format(&options)),
-                UciCommand::Position {
-                    init_pos,
-                    moves: mvs,
-                } => {
-                    cur_board = Board::from(init_pos);
-
-                    moves.clear();
-                    for mv in mvs {
-                        moves.push(mv);
-                    }
-                }
-                UciCommand::SetOption { name: _, value: _ } => {}
-                UciCommand::UciNewGame => {
-                    tx.send(ThreadMessage::NewGame).unwrap();
-                }
-                UciCommand::Stop => {}
-                UciCommand::PonderHit => {}
-                UciCommand::Quit => {}
-                UciCommand::Go(opts) => {
-                    tx.send(ThreadMessage::SearchTask {
-                        board: cur_board.clone(),
-                        moves: moves.clone(),
-                        time_left: match cur_board.side_to_move() {
-                            Color::White => opts.wtime.unwrap(),
-                            Color::Black => opts.btime.unwrap(),
-                        },
-                        time_inc: match cur_board.side_to_move() {
-                            Color::White => opts.winc.unwrap(),
-                            Color::Black => opts.binc.unwrap(),
-                        },
-                    })
-                    .unwrap();
-                }
-            },
-            Err(err) => {
-                if !matches!(err.kind, UnknownMessageKind(_)) {
-                    println!("{err}");
-                    continue;
-                }
-            }
+Subcommands:
+  bench [POSITION...] [--fen FEN] [--file FILE] [--depth N] [--hash MB] [--threads N]
+  perft DEPTH [FEN]
+  provemate FEN N
+  analyze [FEN] [--depth N | --movetime MS] [--hash MB] [--checkpoint FILE] [--resume FILE]
+  analyze-file FILE [--depth N | --movetime MS | --static] [--threads N] [--hash MB]
+               [--format json|csv] [--output FILE]
+               (also as analyze --file FILE ...; the threads share the --hash budget)
+  annotate PGN [--depth N | --movetime MS] [--hash MB] [--output FILE]
+  selfplay [FEN] [--games N] [--depth N | --movetime MS] [--hash MB] [--variety CP]
+  selfcheck [FILE] [--weights FILE]
+  tune DATASET [--iterations N] [--rate R] [--start FILE] [--output FILE] [--weights FILE]
+  weights [FILE]
+  help"
+    );
+}
+
+const BENCH_DEPTH: u8 = 7;
+const BENCH_POSITIONS: usize = 50;
+const BENCH_TT_SIZE: usize = 100_000_000;
+
+// The defaults are what OpenBench runs, so plain `bench` must keep its node count
+struct BenchConfig {
+    fens: Vec<String>,
+    depth: u8,
+    tt_size: usize,
+    threads: usize,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            fens: include_str!("fen.csv")
+                .split('\n')
+                .take(BENCH_POSITIONS)
+                .map(str::to_owned)
+                .collect(),
+            depth: BENCH_DEPTH,
+            tt_size: BENCH_TT_SIZE,
+            threads: 1,
         }
     }
 }
 
-fn run_benchmark() {
-    let mut searcher: Searcher = Searcher::new(100_000_000);
-    let mut total_nodes = 0;
-    let mut total_time = 0.0;
+// Positions are named positions, a FEN given with `--fen`, or a file of FENs given with `--file`.
+// Without any of them the embedded suite is used.
+fn parse_bench_args(args: &[String]) -> Result<BenchConfig, String> {
+    let flags = Flags::parse(args, &["--fen", "--file", "--depth", "--hash", "--threads"])?;
+    let mut config = BenchConfig::default();
+    let mut fens = Vec::new();
+    for name in flags.positional() {
+        let fen = named_position(name).ok_or_else(|| format!("unknown position {name}"))?;
+        fens.push(fen.to_owned());
+    }
+    if let Some(fen) = flags.get::<String>("--fen")? {
+        fens.push(fen);
+    }
+    if let Some(path) = flags.get::<String>("--file")? {
+        let contents =
+            fs::read_to_string(&path).map_err(|e| format!("could not read {path}: {e}"))?;
+        fens.extend(
+            contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_owned),
+        );
+    }
+    for fen in &fens {
+        Board::from_fen(fen, false).map_err(|_| format!("invalid FEN {fen}"))?;
+    }
+    if !fens.is_empty() {
+        config.fens = fens;
+    }
+
+    config.depth = flags.get_or("--depth", config.depth)?;
+    if let Some(mb) = flags.get::<usize>("--hash")? {
+        config.tt_size = mb.max(1) * 1024 * 1024;
+    }
+    config.threads = flags.get_or("--threads", config.threads)?.max(1);
+    Ok(config)
+}
+
+// Searches every position of the suite to a fixed depth and reports the node count and speed
+#[allow(clippy::cast_precision_loss)]
+fn run_benchmark(config: &BenchConfig) {
+    let mut searcher: Searcher = Searcher::new(config.tt_size);
+    searcher.set_threads(config.threads);
+    let mut total = SearchStats::default();
+    let mut total_time = Duration::ZERO;
+    let mut ebfs = Vec::new();
     let moves = Vec::new();
-    for (i, fen) in include_str!("fen.csv").split('\n').take(50).enumerate() {
-        searcher.tt.clear();
+    for (i, fen) in config.fens.iter().enumerate() {
+        // Each position starts from scratch so that the node count is deterministic
+        searcher.new_game();
+        searcher.clear_tt();
         let mut board = fen.parse::<Board>().unwrap();
         let start = Instant::now();
         let mut stats = SearchStats::default();
-        let (bm, bv) = searcher.search_fixed_depth(&mut board, &moves, &mut stats, 7);
+        let (bm, bv) = searcher.search_fixed_depth(&mut board, &moves, &mut stats, config.depth);
         let duration = start.elapsed();
-        total_nodes += stats.nodes_visited;
-        total_time += duration.as_secs_f64();
+        total.add(&stats);
+        total_time += duration;
+        ebfs.extend(stats.effective_branching_factor());
 
-        println!(
+        uci_println!(
             "Position [{i:02}]: Move {:} Value {bv:8} | {:10} Nodes in {:6.3}s at {:10.2} KNPS",
             bm,
             stats.nodes_visited,
             duration.as_secs_f64(),
-            f64::from(stats.nodes_visited) / duration.as_secs_f64() / 1e3,
+            nps(stats.nodes_visited, duration) as f64 / 1e3,
         );
     }
 
-    println!(
+    let total_nodes = total.nodes_visited;
+    let ebf = ebfs.iter().sum::<f64>() / ebfs.len().max(1) as f64;
+    uci_println!(
+        "Cutoffs on first move {:.1}% | QSearch nodes {:.1}% | TT hits {:.1}% | PVS researches {} | Aspiration researches {} | EBF {ebf:.2}",
+        total.first_move_cutoff_rate(),
+        total.qsearch_share(),
+        total.tt_hit_rate(),
+        total.pvs_researches,
+        total.aspiration_researches,
+    );
+    uci_println!(
         "Total: {:12} Nodes in {:6.3}s at {:10.2} NPS",
         total_nodes,
-        total_time,
-        f64::from(total_nodes) / total_time / 1e3
+        total_time.as_secs_f64(),
+        nps(total_nodes, total_time) as f64 / 1e3
     );
+    // The last line is what OpenBench and similar tools parse
+    let nps = nps(total_nodes, total_time);
+    uci_println!("{total_nodes} nodes {nps} nps");
+}
+
+// Counts the leaf nodes under every root move, like the UCI `perft` command
+fn run_perft(args: &[String]) -> Result<(), String> {
+    let flags = Flags::parse(args, &[])?;
+    let (depth, fen) = match flags.positional() {
+        [depth] => (depth, None),
+        [depth, fen] => (depth, Some(fen)),
+        [] => return Err("missing depth".to_owned()),
+        [_, _, extra, ..] => return Err(format!("unexpected argument {extra}")),
+    };
+    let depth = depth
+        .parse::<u8>()
+        .map_err(|_| format!("invalid depth {depth}"))?;
+    let board = match fen {
+        Some(fen) => Board::from_fen(fen, false).map_err(|_| format!("invalid FEN {fen}"))?,
+        None => Board::default(),
+    };
+
+    let start = Instant::now();
+    let nodes = if depth == 0 {
+        perft(&board, 0)
+    } else {
+        let split = divide(&board, depth, false);
+        for (mv, nodes) in &split {
+            println!("{mv}: {nodes}");
+        }
+        split.iter().map(|&(_, nodes)| nodes).sum()
+    };
+    let seconds = start.elapsed().as_secs_f64();
+    println!();
+    println!("Nodes searched: {nodes}");
+    #[allow(clippy::cast_precision_loss)]
+    let nps = nodes as f64 / seconds.max(1e-9);
+    println!("Time: {seconds:.3}s ({nps:.0} nps)");
+    Ok(())
+}
+
+// Writes the built-in eval weights as a weights file for `EvalFile`, to FILE or to stdout, as a
+// starting point for editing them by hand
+fn write_weights(args: &[String]) -> Result<(), String> {
+    let flags = Flags::parse(args, &[])?;
+    match flags.positional() {
+        [] => print!("{}", EvalParams::default().to_text()),
+        [path] => EvalParams::default()
+            .save(Path::new(path))
+            .map_err(|e| format!("could not write {path}: {e}"))?,
+        [_, extra, ..] => return Err(format!("unexpected argument {extra}")),
+    }
+    Ok(())
+}
+
+// Proves a mate in N, or that there is none, with a search that prunes nothing. The FEN may also
+// be a named position.
+fn run_provemate(args: &[String]) -> Result<(), String> {
+    let flags = Flags::parse(args, &[])?;
+    let [fen, moves] = flags.positional() else {
+        return Err("expected a FEN and a number of moves".to_owned());
+    };
+    let fen = named_position(fen).unwrap_or(fen.as_str());
+    let board = Board::from_fen(fen, false).map_err(|_| format!("invalid FEN {fen}"))?;
+    let moves = moves
+        .parse::<usize>()
+        .ok()
+        .filter(|&moves| moves > 0)
+        .ok_or_else(|| format!("invalid number of moves {moves}"))?;
+
+    let start = Instant::now();
+    let mut prover = MateProver::default();
+    match prover.prove(&board, moves) {
+        MateProof::Mate(line) => {
+            let line = uci_line(&board, &line, false);
+            let line = line.iter().map(ToString::to_string).collect::<Vec<_>>();
+            println!("mate in {}: {}", line.len().div_ceil(2), line.join(" "));
+        }
+        MateProof::NoMate(refutations) => {
+            println!("no mate in {moves}");
+            for (mv, reply) in refutations {
+                let mut child = board.clone();
+                child.play_unchecked(mv);
+                let uci_mv = to_uci_move(&board, mv, false);
+                match reply {
+                    Some(reply) => println!("{uci_mv}: {}", to_uci_move(&child, reply, false)),
+                    None => println!("{uci_mv}: draw"),
+                }
+            }
+        }
+    }
+    let seconds = start.elapsed().as_secs_f64();
+    println!();
+    println!("Nodes searched: {}", prover.nodes);
+    #[allow(clippy::cast_precision_loss)]
+    let nps = prover.nodes as f64 / seconds.max(1e-9);
+    println!("Time: {seconds:.3}s ({nps:.0} nps)");
+    Ok(())
 }
 
 fn hyperfine() {
@@ -232,7 +704,7 @@ mod test {
     use cozy_chess::{Board, GameStatus};
     use std::{fs, time::Duration};
 
-    use crate::search::{SearchStats, Searcher, MATE_VALUE};
+    use chess_engine::search::{SearchStats, Searcher, MATE_VALUE};
 
     fn mate_in_i(mate_in: usize, fpath: &str, count: usize) {
         let ply = 2 * mate_in - 1;
@@ -0,0 +1,149 @@
+use cozy_chess::{Board, GameStatus, Move};
+
+// Outcome of an attempt to prove a mate
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MateProof {
+    // The shortest mate, along the line where the defender holds out longest
+    Mate(Vec<Move>),
+    // Every root move with a reply escaping the mate, or `None` if the move itself ends the game
+    // in a draw
+    NoMate(Vec<(Move, Option<Move>)>),
+}
+
+// Mate Proving
+// The search claims mates from a tree pruned by heuristics, so a claimed mate may not exist, and a
+// mate it misses may. This searches the full tree instead: at the attacker's nodes one move
+// leading to mate is enough, at the defender's nodes every reply has to. Nothing is pruned, apart
+// from quiet moves on the attacker's last move, which can't mate. Draws by the fifty move rule are
+// honoured, repetitions aren't as a mate in a few moves is never given a chance to repeat.
+#[derive(Debug, Default)]
+pub struct MateProver {
+    pub nodes: u64,
+}
+
+impl MateProver {
+    // Proves or refutes a mate in at most `moves` moves by the side to move
+    pub fn prove(&mut self, board: &Board, moves: usize) -> MateProof {
+        if let Some(line) = self.attack(board, moves) {
+            return MateProof::Mate(line);
+        }
+        let refutations = legal_moves(board)
+            .into_iter()
+            .map(|mv| {
+                let mut child = board.clone();
+                child.play_unchecked(mv);
+                (mv, self.escape(&child, moves.saturating_sub(1)))
+            })
+            .collect();
+        MateProof::NoMate(refutations)
+    }
+
+    // Line of the shortest mate in at most `moves` moves, with the side to move mating. Mates are
+    // tried from the shortest up, so that the defender's longest resistance can be told apart.
+    fn attack(&mut self, board: &Board, moves: usize) -> Option<Vec<Move>> {
+        self.nodes += 1;
+        if board.status() != GameStatus::Ongoing {
+            return None;
+        }
+        // Checks first, as they mate most often
+        let mut children = legal_moves(board)
+            .into_iter()
+            .map(|mv| {
+                let mut child = board.clone();
+                child.play_unchecked(mv);
+                (mv, child)
+            })
+            .collect::<Vec<_>>();
+        children.sort_by_key(|(_, child)| child.checkers().is_empty());
+
+        for remaining in 1..=moves {
+            for (mv, child) in &children {
+                if remaining == 1 && child.checkers().is_empty() {
+                    break;
+                }
+                if let Some(mut line) = self.defend(child, remaining - 1) {
+                    line.insert(0, *mv);
+                    return Some(line);
+                }
+            }
+        }
+        None
+    }
+
+    // Longest line of the defender to move against mates in at most `moves` more moves, if all
+    // of its replies get mated
+    fn defend(&mut self, board: &Board, moves: usize) -> Option<Vec<Move>> {
+        self.nodes += 1;
+        match board.status() {
+            GameStatus::Won => return Some(Vec::new()),
+            GameStatus::Drawn => return None,
+            GameStatus::Ongoing => {}
+        }
+        if moves == 0 {
+            return None;
+        }
+
+        let mut longest = Vec::new();
+        for mv in legal_moves(board) {
+            let mut child = board.clone();
+            child.play_unchecked(mv);
+            let line = self.attack(&child, moves)?;
+            if line.len() + 1 > longest.len() {
+                longest = line;
+                longest.insert(0, mv);
+            }
+        }
+        Some(longest)
+    }
+
+    // A reply of the defender to move escaping mates in at most `moves` moves
+    fn escape(&mut self, board: &Board, moves: usize) -> Option<Move> {
+        if board.status() != GameStatus::Ongoing {
+            return None;
+        }
+        legal_moves(board).into_iter().find(|&mv| {
+            let mut child = board.clone();
+            child.play_unchecked(mv);
+            self.attack(&child, moves).is_none()
+        })
+    }
+}
+
+fn legal_moves(board: &Board) -> Vec<Move> {
+    let mut moves = Vec::new();
+    board.generate_moves(|piece_moves| {
+        moves.extend(piece_moves);
+        false
+    });
+    moves
+}
+
+#[cfg(test)]
+mod test {
+    use cozy_chess::{Board, GameStatus};
+
+    use super::{legal_moves, MateProof, MateProver};
+
+    #[test]
+    fn proves_and_refutes_mates() {
+        let board = "r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 0 1"
+            .parse::<Board>()
+            .unwrap();
+        let mut prover = MateProver::default();
+        let MateProof::Mate(line) = prover.prove(&board, 3) else {
+            panic!("no mate found");
+        };
+        assert_eq!(line.len(), 3);
+        let mut end = board.clone();
+        for mv in line {
+            end.play(mv);
+        }
+        assert_eq!(end.status(), GameStatus::Won);
+
+        let MateProof::NoMate(refutations) = prover.prove(&board, 1) else {
+            panic!("mate in one found");
+        };
+        assert_eq!(refutations.len(), legal_moves(&board).len());
+        assert!(refutations.iter().all(|(_, reply)| reply.is_some()));
+    }
+}
@@ -0,0 +1,353 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use cozy_chess::{Board, GameStatus, Move};
+
+use crate::{
+    evaluate::{self, Evaluator},
+    search::{nps, uci_score, SearchStats, TimeControl},
+    types::Value,
+    uci_println,
+    utils::{uci_line, NULL_MOVE},
+};
+
+// Weight of the prior against the average result in PUCT
+const CPUCT: f32 = 1.5;
+// Centipawns per unit of the logistic curve mapping scores to expected results
+const VALUE_SCALE: f32 = 400.0;
+// Temperature in centipawns of the softmax turning the evals of the children into priors
+const PRIOR_TEMPERATURE: f32 = 100.0;
+// Expected results are kept this far from 0 and 1 when turned back into centipawns
+const MAX_CERTAINTY: f32 = 0.001;
+// Nodes in the tree at most, about 20 bytes each. Leaves past that are scored but not expanded.
+const MAX_NODES: usize = 1 << 22;
+// The clock and the stop flag are checked every this many playouts
+const CHECK_INTERVAL: u64 = 256;
+// Interval of the info lines printed while searching
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy)]
+struct Node {
+    // Move leading to this node from its parent
+    mv: Move,
+    prior: f32,
+    visits: u32,
+    // Sum of the results of the playouts through this node, for the side that played `mv`
+    total: f32,
+    // Children are stored next to each other. The root is nobody's child, so 0 means the node
+    // hasn't been expanded yet.
+    first_child: u32,
+    num_children: u8,
+}
+
+impl Node {
+    const fn new(mv: Move, prior: f32) -> Self {
+        Self {
+            mv,
+            prior,
+            visits: 0,
+            total: 0.0,
+            first_child: 0,
+            num_children: 0,
+        }
+    }
+
+    // Average result for the side that played `mv`
+    #[allow(clippy::cast_precision_loss)]
+    fn q(&self) -> f32 {
+        if self.visits == 0 {
+            0.5
+        } else {
+            self.total / self.visits as f32
+        }
+    }
+
+    fn children(&self) -> std::ops::Range<usize> {
+        let first = self.first_child as usize;
+        first..first + usize::from(self.num_children)
+    }
+}
+
+// Expected result for the side to move, from 0 for a loss to 1 for a win
+fn expected_result(value: Value) -> f32 {
+    1.0 / (1.0 + (-f32::from(value) / VALUE_SCALE).exp())
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn result_to_value(result: f32) -> Value {
+    let result = result.clamp(MAX_CERTAINTY, 1.0 - MAX_CERTAINTY);
+    (VALUE_SCALE * (result / (1.0 - result)).ln()).round() as Value
+}
+
+// Monte-Carlo Tree Search (MCTS)
+// An alternative to the alpha-beta search, for experiments. The tree grows by one node per
+// playout: starting from the root, the child with the best PUCT score is followed down to a leaf,
+// which is expanded and scored with the static eval, and the result is added up along the path.
+// PUCT weighs the average result of a child against its prior, taken from the evals of the
+// children, so that promising moves visited little so far get explored. The most visited root
+// move is played.
+#[derive(Debug)]
+pub struct Mcts<'a> {
+    nodes: Vec<Node>,
+    evaluator: Evaluator<'a>,
+    // Hashes of the game before the root and of the current playout, for repetitions
+    hashes: Vec<u64>,
+    reporting: bool,
+    chess960: bool,
+}
+
+impl<'a> Mcts<'a> {
+    pub fn new(evaluator: Evaluator<'a>, reporting: bool, chess960: bool) -> Self {
+        Self {
+            nodes: Vec::new(),
+            evaluator,
+            hashes: Vec::new(),
+            reporting,
+            chess960,
+        }
+    }
+
+    // Runs playouts until the time or node limit or until stopped. `history` holds the hashes of
+    // the positions before the root, and `root_moves` restricts the root moves unless empty.
+    #[allow(clippy::too_many_arguments)]
+    pub fn search(
+        &mut self,
+        board: &Board,
+        history: &[u64],
+        root_moves: &[Move],
+        timer: &TimeControl,
+        node_limit: u64,
+        stop: &AtomicBool,
+        stats: &mut SearchStats,
+    ) -> (Move, Value) {
+        self.nodes.clear();
+        self.nodes.push(Node::new(NULL_MOVE, 1.0));
+        self.expand(0, board, root_moves);
+        if self.nodes[0].num_children == 0 {
+            return (NULL_MOVE, 0);
+        }
+
+        let mut total_depth = 0;
+        let mut last_report = Duration::ZERO;
+        while stats.nodes_visited < node_limit {
+            if stats.nodes_visited % CHECK_INTERVAL == 0 {
+                if timer.time_up() || stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                if self.reporting && timer.elapsed() > last_report + REPORT_INTERVAL {
+                    last_report = timer.elapsed();
+                    self.report(board, stats, timer);
+                }
+            }
+
+            self.hashes.clear();
+            self.hashes.extend_from_slice(history);
+            let depth = self.playout(board);
+            stats.nodes_visited += 1;
+            stats.seldepth = stats.seldepth.max(depth);
+            total_depth += u64::from(depth);
+            stats.depth = u8::try_from(total_depth / stats.nodes_visited).unwrap_or(0);
+        }
+
+        if self.reporting {
+            self.report(board, stats, timer);
+        }
+        let best = self.best_child(0);
+        (self.nodes[best].mv, result_to_value(self.nodes[best].q()))
+    }
+
+    // Follows the tree from the root down to a leaf, scores it and adds the result up along the
+    // way. Returns the depth of the leaf.
+    fn playout(&mut self, root: &Board) -> u8 {
+        let mut board = root.clone();
+        let mut path = vec![0];
+        let mut node = 0;
+        let mut repeated = false;
+        while self.nodes[node].first_child != 0 && !repeated {
+            self.hashes.push(board.hash());
+            node = self.select_child(node);
+            board.play_unchecked(self.nodes[node].mv);
+            path.push(node);
+            repeated = self.hashes.contains(&board.hash());
+        }
+
+        // Result for the side to move at the leaf
+        let result = if repeated || evaluate::is_insufficient_material(&board) {
+            0.5
+        } else {
+            match board.status() {
+                GameStatus::Won => 0.0,
+                GameStatus::Drawn => 0.5,
+                GameStatus::Ongoing => {
+                    self.expand(node, &board, &[]);
+                    expected_result(self.evaluator.evaluate(&board))
+                }
+            }
+        };
+
+        // The leaf was reached by a move of the other side
+        let mut result = 1.0 - result;
+        for &idx in path.iter().rev() {
+            self.nodes[idx].visits += 1;
+            self.nodes[idx].total += result;
+            result = 1.0 - result;
+        }
+        u8::try_from(path.len() - 1).unwrap_or(u8::MAX)
+    }
+
+    // PUCT
+    // Children never visited are assumed to be as good as their parent, so that the prior
+    // decides which of them is tried first.
+    #[allow(clippy::cast_precision_loss)]
+    fn select_child(&self, node: usize) -> usize {
+        let parent = &self.nodes[node];
+        let explore = CPUCT * (parent.visits as f32).sqrt();
+        let unvisited_q = 1.0 - parent.q();
+        let mut best = parent.first_child as usize;
+        let mut best_score = f32::NEG_INFINITY;
+        for idx in parent.children() {
+            let child = &self.nodes[idx];
+            let q = if child.visits == 0 {
+                unvisited_q
+            } else {
+                child.q()
+            };
+            let score = q + explore * child.prior / (1.0 + child.visits as f32);
+            if score > best_score {
+                best_score = score;
+                best = idx;
+            }
+        }
+        best
+    }
+
+    // Adds the children of a leaf, with priors from a softmax over their evals
+    fn expand(&mut self, node: usize, board: &Board, root_moves: &[Move]) {
+        let mut moves = Vec::new();
+        board.generate_moves(|piece_moves| {
+            moves.extend(
+                piece_moves
+                    .into_iter()
+                    .filter(|mv| root_moves.is_empty() || root_moves.contains(mv)),
+            );
+            false
+        });
+        if moves.is_empty() || self.nodes.len() + moves.len() > MAX_NODES {
+            return;
+        }
+
+        let scores = moves
+            .iter()
+            .map(|&mv| {
+                let mut child = board.clone();
+                child.play_unchecked(mv);
+                -f32::from(self.evaluator.evaluate(&child)) / PRIOR_TEMPERATURE
+            })
+            .collect::<Vec<_>>();
+        let max_score = scores.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let weights = scores
+            .iter()
+            .map(|score| (score - max_score).exp())
+            .collect::<Vec<_>>();
+        let sum = weights.iter().sum::<f32>();
+
+        #[allow(clippy::cast_possible_truncation)]
+        {
+            self.nodes[node].first_child = self.nodes.len() as u32;
+            self.nodes[node].num_children = moves.len() as u8;
+        }
+        self.nodes.extend(
+            moves
+                .into_iter()
+                .zip(weights)
+                .map(|(mv, weight)| Node::new(mv, weight / sum)),
+        );
+    }
+
+    // The most visited child, which has the most reliable result
+    fn best_child(&self, node: usize) -> usize {
+        self.nodes[node]
+            .children()
+            .max_by(|&a, &b| {
+                let (a, b) = (&self.nodes[a], &self.nodes[b]);
+                a.visits.cmp(&b.visits).then(a.q().total_cmp(&b.q()))
+            })
+            .unwrap_or(node)
+    }
+
+    // Most visited line from the root
+    fn principal_variation(&self) -> Vec<Move> {
+        let mut pv = Vec::new();
+        let mut node = 0;
+        while self.nodes[node].num_children > 0 {
+            node = self.best_child(node);
+            if self.nodes[node].visits == 0 {
+                break;
+            }
+            pv.push(self.nodes[node].mv);
+        }
+        pv
+    }
+
+    fn report(&self, board: &Board, stats: &SearchStats, timer: &TimeControl) {
+        let elapsed = timer.elapsed();
+        let time = elapsed.as_millis();
+        let nodes = stats.nodes_visited;
+        let value = result_to_value(self.nodes[self.best_child(0)].q());
+        let pv = uci_line(board, &self.principal_variation(), self.chess960)
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(" ");
+        uci_println!(
+            "info depth {} seldepth {} score {} nodes {nodes} nps {} time {time} pv {pv}",
+            stats.depth,
+            stats.seldepth,
+            uci_score(value),
+            nps(nodes, elapsed),
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::atomic::AtomicBool, time::Duration};
+
+    use cozy_chess::Board;
+
+    use crate::{
+        eval_params::BUILTIN,
+        evaluate::Evaluator,
+        search::{SearchStats, TimeControl},
+    };
+
+    use super::{expected_result, result_to_value, Mcts};
+
+    #[test]
+    fn result_round_trip() {
+        for value in [-500, -40, 0, 40, 500] {
+            assert_eq!(result_to_value(expected_result(value)), value);
+        }
+    }
+
+    #[test]
+    fn finds_hanging_queen() {
+        let board: Board = "4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1".parse().unwrap();
+        let mut mcts = Mcts::new(Evaluator::Psqt(&BUILTIN), false, false);
+        let mut stats = SearchStats::default();
+        let (mv, value) = mcts.search(
+            &board,
+            &[],
+            &[],
+            &TimeControl::new(Duration::MAX),
+            2000,
+            &AtomicBool::new(false),
+            &mut stats,
+        );
+        assert_eq!(mv, "d2d5".parse().unwrap());
+        assert!(value > 0);
+        assert_eq!(stats.nodes_visited, 2000);
+    }
+}
@@ -1,7 +1,17 @@
 use arrayvec::ArrayVec;
-use cozy_chess::{Board, Move};
+use cozy_chess::{BitBoard, Board, Move};
 
-use crate::history::{HistoryTable, HISTORY_LIMIT};
+use crate::{
+    history::{HistoryTable, HISTORY_LIMIT},
+    see::{is_defended, piece_attacks},
+};
+
+// Quiet moves of quiescence search come after all captures
+const QUIET_EVAL: i32 = -100;
+// Added to the history of quiet moves taking a threatened piece to safety, and of those
+// defending a hanging one
+const ESCAPE_BONUS: i32 = 4096;
+const DEFENSE_BONUS: i32 = 2048;
 
 pub struct MovesIterator {
     moves_evals: ArrayVec<(Move, i32, bool), 218>,
@@ -9,15 +19,22 @@ pub struct MovesIterator {
 }
 
 impl MovesIterator {
+    // `threatened` are our pieces the opponent's last move attacked, see `threatened_pieces`
     pub fn with_all_moves(
         board: &Board,
         tt_move: Move,
-        killer: Option<Move>,
+        killers: [Option<Move>; 2],
         history: &HistoryTable,
+        threatened: BitBoard,
     ) -> Self {
         let mut moves_evals = ArrayVec::new();
 
-        let enemy = board.colors(!board.side_to_move());
+        let stm = board.side_to_move();
+        let enemy = board.colors(!stm);
+        let hanging = threatened
+            .into_iter()
+            .filter(|&sq| !is_defended(board, sq))
+            .fold(BitBoard::EMPTY, |hanging, sq| hanging | sq.bitboard());
         board.generate_moves(|moves| {
             let src_type = board.piece_on(moves.from).unwrap();
             for mv in moves {
@@ -35,15 +52,74 @@ impl MovesIterator {
                         true,
                     ));
                 } else {
-                    // Killer moves are ranked right after winning captures
-                    if let Some(kmv) = killer {
-                        if kmv == mv {
-                            moves_evals.push((mv, i32::from(HISTORY_LIMIT), false));
-                            continue;
+                    // Killer moves are ranked right after winning captures, the newest first
+                    if killers.contains(&Some(mv)) {
+                        let eval = i32::from(HISTORY_LIMIT) - i32::from(killers[0] != Some(mv));
+                        moves_evals.push((mv, eval, false));
+                        continue;
+                    }
+                    // Use history for all other non-capture moves. When the last move attacked
+                    // one of our pieces, moves taking it to safety or defending it are likely the
+                    // best answers even without the history to show for it, so they get a bonus,
+                    // though they still come after the killers.
+                    let mut eval = i32::from(history.get(board, mv));
+                    if threatened.has(mv.from) {
+                        eval += ESCAPE_BONUS;
+                    } else if !hanging.is_empty() {
+                        let occupied = board.occupied() ^ mv.from.bitboard();
+                        let attacks = piece_attacks(src_type, stm, mv.to, occupied);
+                        if !(attacks & hanging).is_empty() {
+                            eval += DEFENSE_BONUS;
                         }
                     }
-                    // Use history for all other non-capture moves
-                    moves_evals.push((mv, i32::from(history.get(board, mv)), false));
+                    moves_evals.push((mv, eval.min(i32::from(HISTORY_LIMIT) - 2), false));
+                }
+            }
+            false
+        });
+
+        Self {
+            moves_evals,
+            cur: 0,
+        }
+    }
+
+    // Moves of a quiescence search node: every move when in check, since any of them may be the
+    // only way out, and otherwise captures, followed by quiet checks if `checks` is set. The TT
+    // move goes first if it is among them.
+    pub fn with_quiescence_moves(board: &Board, tt_move: Move, checks: bool) -> Self {
+        let in_check = !board.checkers().is_empty();
+        let mut it = if in_check || checks {
+            Self::with_evasions_or_checks(board, in_check)
+        } else {
+            Self::with_capture_moves(board)
+        };
+        if let Some(entry) = it.moves_evals.iter_mut().find(|(mv, _, _)| *mv == tt_move) {
+            entry.1 = i32::MAX;
+        }
+        it
+    }
+
+    fn with_evasions_or_checks(board: &Board, in_check: bool) -> Self {
+        let mut moves_evals = ArrayVec::new();
+        let enemy = board.colors(!board.side_to_move());
+        board.generate_moves(|moves| {
+            let src_type = board.piece_on(moves.from).unwrap();
+            for mv in moves {
+                if enemy.has(mv.to) {
+                    moves_evals.push((
+                        mv,
+                        board.piece_on(mv.to).unwrap() as i32 * 10 - src_type as i32,
+                        true,
+                    ));
+                } else if in_check {
+                    moves_evals.push((mv, QUIET_EVAL, false));
+                } else {
+                    let mut child = board.clone();
+                    child.play_unchecked(mv);
+                    if !child.checkers().is_empty() {
+                        moves_evals.push((mv, QUIET_EVAL, false));
+                    }
                 }
             }
             false
@@ -77,6 +153,12 @@ impl MovesIterator {
             cur: 0,
         }
     }
+
+    // Number of moves generated, whether or not they were returned yet. Without a restriction to
+    // captures or checks, that is the number of legal moves.
+    pub fn num_moves(&self) -> usize {
+        self.moves_evals.len()
+    }
 }
 
 impl Iterator for MovesIterator {
@@ -0,0 +1,465 @@
+use std::{fs, io, path::Path};
+
+use cozy_chess::{BitBoard, Board, Color, Piece, Square};
+
+#[cfg(target_arch = "aarch64")]
+use neon::{add_row, screlu_dot, sub_row};
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+use scalar::{add_row, screlu_dot, sub_row};
+#[cfg(target_arch = "x86_64")]
+use x86::{add_row, screlu_dot, sub_row};
+
+// Quantization of the feature transformer and of the output layer, and the scale of the output
+// in centipawns, as in the networks bullet trains by default
+const QA: i32 = 255;
+const QB: i32 = 64;
+const SCALE: i32 = 400;
+// One input per piece type and colour on every square
+const FEATURES: usize = 2 * 6 * 64;
+// Weights per neuron of the hidden layer: a row of the feature transformer, its bias and the
+// output weights from both accumulators
+const WEIGHTS_PER_NEURON: usize = FEATURES + 1 + 2;
+// Trainers pad the file to a multiple of this many bytes
+const FILE_ALIGNMENT: usize = 64;
+// Evals are kept well clear of tablebase and mate scores
+const MAX_EVAL: i64 = 5_000;
+
+// Efficiently Updatable Neural Network (NNUE)
+// A network with one hidden layer, seen from both sides: the feature transformer turns the pieces
+// into an accumulator for the side to move and one for the opponent, and the output layer weighs
+// the two after a squared clipped ReLU (SCReLU). Networks are loaded from the quantized format
+// common to bullet-trained nets: little endian i16s, being the feature weights (one row per
+// feature), the feature biases, the output weights for our accumulator then the opponent's, and
+// the output bias. The size of the hidden layer follows from the size of the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Network {
+    hidden: usize,
+    feature_weights: Vec<i16>,
+    feature_bias: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i16,
+}
+
+// Accumulators of both perspectives, indexed by colour, and the pieces they stand for
+#[derive(Debug, Clone)]
+struct Accumulator {
+    values: [Vec<i16>; 2],
+    pieces: [[BitBoard; 6]; 2],
+}
+
+impl Network {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let values = bytes
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect::<Vec<_>>();
+        let hidden = values.len().saturating_sub(1) / WEIGHTS_PER_NEURON;
+        let used = 2 * (hidden * WEIGHTS_PER_NEURON + 1);
+        if hidden == 0 || bytes.len() - used >= FILE_ALIGNMENT {
+            return Err(invalid(format!("{} bytes is no network size", bytes.len())));
+        }
+
+        let (feature_weights, rest) = values.split_at(FEATURES * hidden);
+        let (feature_bias, rest) = rest.split_at(hidden);
+        let (output_weights, rest) = rest.split_at(2 * hidden);
+        Ok(Self {
+            hidden,
+            feature_weights: feature_weights.to_vec(),
+            feature_bias: feature_bias.to_vec(),
+            output_weights: output_weights.to_vec(),
+            output_bias: rest[0],
+        })
+    }
+
+    pub const fn hidden_size(&self) -> usize {
+        self.hidden
+    }
+
+    // Evaluation for the side to move, in centipawns, with accumulators built from scratch
+    pub fn evaluate(&self, board: &Board) -> i32 {
+        let mut acc = self.empty_accumulator();
+        self.update(&mut acc, board);
+        self.output(&acc, board.side_to_move())
+    }
+
+    // Accumulators of an empty board, from which any position can be reached by adding pieces
+    fn empty_accumulator(&self) -> Accumulator {
+        Accumulator {
+            values: [self.feature_bias.clone(), self.feature_bias.clone()],
+            pieces: [[BitBoard::EMPTY; 6]; 2],
+        }
+    }
+
+    // Brings the accumulators to `board` by removing the pieces that are gone and adding the new
+    // ones. Any accumulators will do, but the fewer pieces differ, the quicker it is.
+    fn update(&self, acc: &mut Accumulator, board: &Board) {
+        for color in Color::ALL {
+            for piece in Piece::ALL {
+                let old = acc.pieces[color as usize][piece as usize];
+                let new = board.colored_pieces(color, piece);
+                for sq in old & !new {
+                    for perspective in Color::ALL {
+                        let row = self.feature_row(perspective, piece, color, sq);
+                        sub_row(&mut acc.values[perspective as usize], row);
+                    }
+                }
+                for sq in new & !old {
+                    for perspective in Color::ALL {
+                        let row = self.feature_row(perspective, piece, color, sq);
+                        add_row(&mut acc.values[perspective as usize], row);
+                    }
+                }
+                acc.pieces[color as usize][piece as usize] = new;
+            }
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn output(&self, acc: &Accumulator, stm: Color) -> i32 {
+        let (our_weights, their_weights) = self.output_weights.split_at(self.hidden);
+        let output = screlu_dot(&acc.values[stm as usize], our_weights)
+            + screlu_dot(&acc.values[!stm as usize], their_weights);
+        let eval = (output / i64::from(QA) + i64::from(self.output_bias)) * i64::from(SCALE)
+            / i64::from(QA * QB);
+        eval.clamp(-MAX_EVAL, MAX_EVAL) as i32
+    }
+
+    fn feature_row(&self, perspective: Color, piece: Piece, color: Color, sq: Square) -> &[i16] {
+        let feature = feature_index(perspective, piece, color, sq);
+        &self.feature_weights[feature * self.hidden..][..self.hidden]
+    }
+}
+
+// Input of a piece as seen by `perspective`: its own pieces come first, and the board is flipped
+// for black so that both sides see themselves at the bottom
+fn feature_index(perspective: Color, piece: Piece, color: Color, sq: Square) -> usize {
+    let sq = if perspective == Color::White {
+        sq as usize
+    } else {
+        (sq as usize) ^ 0b111_000
+    };
+    usize::from(color != perspective) * 6 * 64 + piece as usize * 64 + sq
+}
+
+// Accumulator Stack
+// The search copies boards rather than making and unmaking moves, so instead of following the
+// moves, the accumulators of every ply are kept and brought up to date from whichever is closest
+// to the position: those of the parent, usually one move away, or those left at the same ply by
+// a sibling, usually two. Positions far from both are built from scratch.
+#[derive(Debug, Default)]
+pub struct AccumulatorStack {
+    entries: Vec<Accumulator>,
+}
+
+impl AccumulatorStack {
+    // Evaluation of `board`, the position at `ply`, for the side to move. The stack has to be
+    // cleared when the network changes.
+    pub fn evaluate(&mut self, network: &Network, ply: usize, board: &Board) -> i32 {
+        if self
+            .entries
+            .first()
+            .is_some_and(|acc| acc.values[0].len() != network.hidden)
+        {
+            self.entries.clear();
+        }
+        if self.entries.len() <= ply {
+            self.entries
+                .resize_with(ply + 1, || network.empty_accumulator());
+        }
+
+        let changes = |acc: &Accumulator| -> u32 {
+            let mut changes = 0;
+            for color in Color::ALL {
+                for piece in Piece::ALL {
+                    let old = acc.pieces[color as usize][piece as usize];
+                    changes += (old ^ board.colored_pieces(color, piece)).len();
+                }
+            }
+            changes
+        };
+        let in_place = changes(&self.entries[ply]);
+        let from_parent = if ply > 0 {
+            changes(&self.entries[ply - 1])
+        } else {
+            u32::MAX
+        };
+        let from_scratch = board.occupied().len();
+        let (parents, rest) = self.entries.split_at_mut(ply);
+        let acc = &mut rest[0];
+        if from_parent < in_place.min(from_scratch) {
+            let parent = &parents[ply - 1];
+            for (values, parent_values) in acc.values.iter_mut().zip(&parent.values) {
+                values.copy_from_slice(parent_values);
+            }
+            acc.pieces = parent.pieces;
+        } else if from_scratch < in_place {
+            *acc = network.empty_accumulator();
+        }
+
+        network.update(acc, board);
+        network.output(acc, board.side_to_move())
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+// Row Arithmetic
+// Adding and removing rows of the feature transformer and the dot product of the output layer
+// are nearly all the work of an eval. They use AVX2 where the CPU has it and NEON on ARM, and
+// plain loops elsewhere. All of them give the same results, with the accumulators wrapping on
+// overflow like the SIMD additions do. The products of the dot product fit an i32, since QA²
+// times any i16 does, but their sum may not.
+mod scalar {
+    use super::QA;
+
+    pub fn add_row(acc: &mut [i16], row: &[i16]) {
+        for (a, &r) in acc.iter_mut().zip(row) {
+            *a = a.wrapping_add(r);
+        }
+    }
+
+    pub fn sub_row(acc: &mut [i16], row: &[i16]) {
+        for (a, &r) in acc.iter_mut().zip(row) {
+            *a = a.wrapping_sub(r);
+        }
+    }
+
+    // Sum of SCReLU(acc) * weight
+    pub fn screlu_dot(acc: &[i16], weights: &[i16]) -> i64 {
+        acc.iter()
+            .zip(weights)
+            .map(|(&a, &w)| {
+                let a = i64::from(a).clamp(0, i64::from(QA));
+                a * a * i64::from(w)
+            })
+            .sum()
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[allow(clippy::cast_ptr_alignment)]
+mod x86 {
+    use std::arch::x86_64::{
+        __m256i, _mm256_add_epi16, _mm256_add_epi64, _mm256_castsi256_si128, _mm256_cvtepi16_epi32,
+        _mm256_cvtepi32_epi64, _mm256_extracti128_si256, _mm256_loadu_si256, _mm256_max_epi32,
+        _mm256_min_epi32, _mm256_mullo_epi32, _mm256_set1_epi32, _mm256_setzero_si256,
+        _mm256_storeu_si256, _mm256_sub_epi16, _mm_loadu_si128,
+    };
+
+    use super::{scalar, QA};
+
+    // i16s in a register
+    const LANES: usize = 16;
+
+    pub fn add_row(acc: &mut [i16], row: &[i16]) {
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: the CPU supports AVX2
+            unsafe { add_row_avx2(acc, row) }
+        } else {
+            scalar::add_row(acc, row);
+        }
+    }
+
+    pub fn sub_row(acc: &mut [i16], row: &[i16]) {
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: the CPU supports AVX2
+            unsafe { sub_row_avx2(acc, row) }
+        } else {
+            scalar::sub_row(acc, row);
+        }
+    }
+
+    pub fn screlu_dot(acc: &[i16], weights: &[i16]) -> i64 {
+        if is_x86_feature_detected!("avx2") {
+            // SAFETY: the CPU supports AVX2
+            unsafe { screlu_dot_avx2(acc, weights) }
+        } else {
+            scalar::screlu_dot(acc, weights)
+        }
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn add_row_avx2(acc: &mut [i16], row: &[i16]) {
+        let len = acc.len().min(row.len());
+        let simd_len = len / LANES * LANES;
+        for i in (0..simd_len).step_by(LANES) {
+            let a = _mm256_loadu_si256(acc.as_ptr().add(i).cast::<__m256i>());
+            let r = _mm256_loadu_si256(row.as_ptr().add(i).cast::<__m256i>());
+            let sum = _mm256_add_epi16(a, r);
+            _mm256_storeu_si256(acc.as_mut_ptr().add(i).cast::<__m256i>(), sum);
+        }
+        scalar::add_row(&mut acc[simd_len..len], &row[simd_len..len]);
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn sub_row_avx2(acc: &mut [i16], row: &[i16]) {
+        let len = acc.len().min(row.len());
+        let simd_len = len / LANES * LANES;
+        for i in (0..simd_len).step_by(LANES) {
+            let a = _mm256_loadu_si256(acc.as_ptr().add(i).cast::<__m256i>());
+            let r = _mm256_loadu_si256(row.as_ptr().add(i).cast::<__m256i>());
+            let diff = _mm256_sub_epi16(a, r);
+            _mm256_storeu_si256(acc.as_mut_ptr().add(i).cast::<__m256i>(), diff);
+        }
+        scalar::sub_row(&mut acc[simd_len..len], &row[simd_len..len]);
+    }
+
+    // Eight values at a time, widened to i32 for the products and to i64 for the sum
+    #[target_feature(enable = "avx2")]
+    unsafe fn screlu_dot_avx2(acc: &[i16], weights: &[i16]) -> i64 {
+        let len = acc.len().min(weights.len());
+        let simd_len = len / 8 * 8;
+        let zero = _mm256_setzero_si256();
+        let qa = _mm256_set1_epi32(QA);
+        let mut sum = _mm256_setzero_si256();
+        for i in (0..simd_len).step_by(8) {
+            let a = _mm256_cvtepi16_epi32(_mm_loadu_si128(acc.as_ptr().add(i).cast()));
+            let w = _mm256_cvtepi16_epi32(_mm_loadu_si128(weights.as_ptr().add(i).cast()));
+            let a = _mm256_min_epi32(_mm256_max_epi32(a, zero), qa);
+            let products = _mm256_mullo_epi32(_mm256_mullo_epi32(a, a), w);
+            let low = _mm256_cvtepi32_epi64(_mm256_castsi256_si128(products));
+            let high = _mm256_cvtepi32_epi64(_mm256_extracti128_si256::<1>(products));
+            sum = _mm256_add_epi64(sum, _mm256_add_epi64(low, high));
+        }
+        let mut lanes = [0_i64; 4];
+        _mm256_storeu_si256(lanes.as_mut_ptr().cast::<__m256i>(), sum);
+        lanes.iter().sum::<i64>() + scalar::screlu_dot(&acc[simd_len..len], &weights[simd_len..len])
+    }
+}
+
+// NEON is part of every aarch64 CPU, so it needs no detection
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use std::arch::aarch64::{
+        vaddq_s16, vaddvq_s64, vdupq_n_s32, vdupq_n_s64, vld1_s16, vld1q_s16, vmaxq_s32, vminq_s32,
+        vmovl_s16, vmulq_s32, vpadalq_s32, vst1q_s16, vsubq_s16,
+    };
+
+    use super::{scalar, QA};
+
+    // i16s in a register
+    const LANES: usize = 8;
+
+    pub fn add_row(acc: &mut [i16], row: &[i16]) {
+        let len = acc.len().min(row.len());
+        let simd_len = len / LANES * LANES;
+        for i in (0..simd_len).step_by(LANES) {
+            // SAFETY: the loads and the store are within both slices
+            unsafe {
+                let sum = vaddq_s16(
+                    vld1q_s16(acc.as_ptr().add(i)),
+                    vld1q_s16(row.as_ptr().add(i)),
+                );
+                vst1q_s16(acc.as_mut_ptr().add(i), sum);
+            }
+        }
+        scalar::add_row(&mut acc[simd_len..len], &row[simd_len..len]);
+    }
+
+    pub fn sub_row(acc: &mut [i16], row: &[i16]) {
+        let len = acc.len().min(row.len());
+        let simd_len = len / LANES * LANES;
+        for i in (0..simd_len).step_by(LANES) {
+            // SAFETY: the loads and the store are within both slices
+            unsafe {
+                let diff = vsubq_s16(
+                    vld1q_s16(acc.as_ptr().add(i)),
+                    vld1q_s16(row.as_ptr().add(i)),
+                );
+                vst1q_s16(acc.as_mut_ptr().add(i), diff);
+            }
+        }
+        scalar::sub_row(&mut acc[simd_len..len], &row[simd_len..len]);
+    }
+
+    // Four values at a time, widened to i32 for the products and to i64 for the sum
+    pub fn screlu_dot(acc: &[i16], weights: &[i16]) -> i64 {
+        let len = acc.len().min(weights.len());
+        let simd_len = len / 4 * 4;
+        // SAFETY: the loads are within both slices
+        let sum = unsafe {
+            let zero = vdupq_n_s32(0);
+            let qa = vdupq_n_s32(QA);
+            let mut sum = vdupq_n_s64(0);
+            for i in (0..simd_len).step_by(4) {
+                let a = vmovl_s16(vld1_s16(acc.as_ptr().add(i)));
+                let w = vmovl_s16(vld1_s16(weights.as_ptr().add(i)));
+                let a = vminq_s32(vmaxq_s32(a, zero), qa);
+                sum = vpadalq_s32(sum, vmulq_s32(vmulq_s32(a, a), w));
+            }
+            vaddvq_s64(sum)
+        };
+        sum + scalar::screlu_dot(&acc[simd_len..len], &weights[simd_len..len])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cozy_chess::{Board, Move};
+
+    use super::{scalar, screlu_dot, AccumulatorStack, Network, FEATURES, WEIGHTS_PER_NEURON};
+
+    // A network with made up weights, which are all that symmetry needs
+    fn test_network(hidden: usize) -> Vec<u8> {
+        (0..hidden * WEIGHTS_PER_NEURON + 1)
+            .map(|i| i16::try_from(i * 37 % 201).unwrap() - 100)
+            .flat_map(i16::to_le_bytes)
+            .collect()
+    }
+
+    #[test]
+    fn load_and_evaluate() {
+        let net = Network::from_bytes(&test_network(4)).unwrap();
+        assert_eq!(net.hidden_size(), 4);
+        assert_eq!(net.feature_weights.len(), FEATURES * 4);
+
+        // A position and its mirror image with the colours swapped are the same to the network
+        let board: Board = "r3k2r/pp3ppp/2n5/3q4/8/2N2N2/PP3PPP/R2QK2R w KQkq - 0 1"
+            .parse()
+            .unwrap();
+        let mirror: Board = "r2qk2r/pp3ppp/2n2n2/8/3Q4/2N5/PP3PPP/R3K2R b KQkq - 0 1"
+            .parse()
+            .unwrap();
+        assert_eq!(net.evaluate(&board), net.evaluate(&mirror));
+
+        // Padding is fine, but not missing weights
+        let mut padded = test_network(4);
+        padded.extend([0; 16]);
+        assert_eq!(Network::from_bytes(&padded).unwrap(), net);
+        let short = test_network(4);
+        assert!(Network::from_bytes(&short[..short.len() - 2]).is_err());
+        assert!(Network::from_bytes(&[0; 10]).is_err());
+    }
+
+    #[test]
+    fn incremental_updates_match_refreshes() {
+        // A hidden layer that isn't a multiple of the SIMD width leaves work to the plain loops
+        let net = Network::from_bytes(&test_network(37)).unwrap();
+        let mut stack = AccumulatorStack::default();
+        let mut board = Board::startpos();
+        let moves = [
+            "e2e4", "d7d5", "e4d5", "g8f6", "f1b5", "c7c6", "d5c6", "d8d2", "e1d2",
+        ];
+        for (ply, mv) in moves.iter().enumerate() {
+            assert_eq!(stack.evaluate(&net, ply, &board), net.evaluate(&board));
+            board.play(mv.parse::<Move>().unwrap());
+        }
+        // Back up the tree, where a sibling left its accumulators
+        let board = Board::startpos();
+        assert_eq!(stack.evaluate(&net, 1, &board), net.evaluate(&board));
+
+        let acc = (0..37).map(|i| i * 13 - 100).collect::<Vec<i16>>();
+        let weights = (0..37).map(|i| 32_000 - i * 1_700).collect::<Vec<i16>>();
+        assert_eq!(
+            screlu_dot(&acc, &weights),
+            scalar::screlu_dot(&acc, &weights)
+        );
+    }
+}
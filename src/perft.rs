@@ -0,0 +1,62 @@
+use cozy_chess::{Board, Move};
+
+use crate::utils::to_uci_move;
+
+// Counts the leaf nodes of the move tree down to `depth`, to check move generation and notation
+pub fn perft(board: &Board, depth: u8) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let mut nodes = 0;
+    board.generate_moves(|moves| {
+        if depth == 1 {
+            nodes += moves.len() as u64;
+        } else {
+            for mv in moves {
+                let mut child = board.clone();
+                child.play_unchecked(mv);
+                nodes += perft(&child, depth - 1);
+            }
+        }
+        false
+    });
+    nodes
+}
+
+// Leaf counts under each root move, with the moves in UCI notation
+pub fn divide(board: &Board, depth: u8, chess960: bool) -> Vec<(Move, u64)> {
+    let mut root_moves = Vec::new();
+    board.generate_moves(|moves| {
+        root_moves.extend(moves);
+        false
+    });
+    root_moves
+        .into_iter()
+        .map(|mv| {
+            let mut child = board.clone();
+            child.play_unchecked(mv);
+            (
+                to_uci_move(board, mv, chess960),
+                perft(&child, depth.saturating_sub(1)),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use cozy_chess::Board;
+
+    use super::{divide, perft};
+
+    #[test]
+    fn perft_counts() {
+        assert_eq!(perft(&Board::startpos(), 3), 8902);
+        let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"
+            .parse::<Board>()
+            .unwrap();
+        let split = divide(&kiwipete, 2, false);
+        assert_eq!(split.iter().map(|&(_, nodes)| nodes).sum::<u64>(), 2039);
+        assert!(split.iter().any(|(mv, _)| mv.to_string() == "e1g1"));
+    }
+}
@@ -0,0 +1,141 @@
+use crate::{eval_params::EvalParams, types::Value};
+
+// Term groups a personality scales together, by their sections of the weights file
+const MATERIAL: &[&str] = &["mg_value", "eg_value"];
+const ATTACK: &[&str] = &[
+    "king_tropism",
+    "king_attack_unit_value",
+    "pawn_storm",
+    "threat_mg",
+    "pawn_threat",
+    "battery",
+    "rook_lift",
+];
+const KING_SAFETY: &[&str] = &[
+    "shield_pushed",
+    "shield_missing",
+    "advanced_shield",
+    "weak_king_square",
+    "missing_fianchetto",
+    "castled_king",
+];
+const ACTIVITY: &[&str] = &[
+    "mobility_mg",
+    "mobility_eg",
+    "rook_open_file",
+    "rook_semi_open_file",
+    "rook_seventh_rank",
+];
+const STRUCTURE: &[&str] = &["connected_pawn", "bad_bishop_pawn"];
+const GROUPS: [&[&str]; 5] = [MATERIAL, ATTACK, KING_SAFETY, ACTIVITY, STRUCTURE];
+
+// Personalities
+// Playing styles for sparring, selected with the `Personality` option. Each one scales groups of
+// eval terms by a percentage and adds its own contempt: an aggressive engine values attacking
+// the king and active pieces over its own safety and avoids draws, a solid one values its king
+// and pawn structure and is happy to draw, and a materialist one values material over everything
+// else. They make the engine weaker than the balanced default, which leaves the weights alone.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Personality {
+    #[default]
+    Balanced,
+    Aggressive,
+    Solid,
+    Materialist,
+}
+
+impl Personality {
+    pub const ALL: [Self; 4] = [
+        Self::Balanced,
+        Self::Aggressive,
+        Self::Solid,
+        Self::Materialist,
+    ];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|personality| value.eq_ignore_ascii_case(personality.name()))
+    }
+
+    // Name of the personality as a UCI option value
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Balanced => "Balanced",
+            Self::Aggressive => "Aggressive",
+            Self::Solid => "Solid",
+            Self::Materialist => "Materialist",
+        }
+    }
+
+    // Percentages of the material, attack, king safety, activity and pawn structure terms
+    const fn multipliers(self) -> [i32; 5] {
+        match self {
+            Self::Balanced => [100, 100, 100, 100, 100],
+            Self::Aggressive => [100, 130, 80, 115, 90],
+            Self::Solid => [100, 85, 130, 95, 120],
+            Self::Materialist => [115, 85, 100, 90, 100],
+        }
+    }
+
+    // Contempt on top of the `Contempt` option or the one from the opponent's rating
+    pub const fn contempt(self) -> Value {
+        match self {
+            Self::Balanced => 0,
+            Self::Aggressive => 20,
+            Self::Solid => -15,
+            Self::Materialist => 10,
+        }
+    }
+
+    // The weights with the term groups of this personality scaled
+    pub fn apply(self, params: &EvalParams) -> EvalParams {
+        let mut params = params.clone();
+        for (sections, percent) in GROUPS.into_iter().zip(self.multipliers()) {
+            params.scale(sections, percent);
+        }
+        params
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{contempt::ContemptSettings, eval_params::BUILTIN};
+
+    use super::Personality;
+
+    #[test]
+    fn parse_personalities() {
+        for personality in Personality::ALL {
+            assert_eq!(Personality::parse(personality.name()), Some(personality));
+        }
+        assert_eq!(
+            Personality::parse("aggressive"),
+            Some(Personality::Aggressive)
+        );
+        assert_eq!(Personality::parse("reckless"), None);
+    }
+
+    #[test]
+    fn personalities_scale_term_groups() {
+        assert_eq!(Personality::Balanced.apply(&BUILTIN), BUILTIN);
+
+        let aggressive = Personality::Aggressive.apply(&BUILTIN);
+        assert!(aggressive.king_attack_unit_value > BUILTIN.king_attack_unit_value);
+        assert!(aggressive.shield_missing < BUILTIN.shield_missing);
+        assert_eq!(aggressive.mg_value, BUILTIN.mg_value);
+        let materialist = Personality::Materialist.apply(&BUILTIN);
+        assert!(materialist.mg_value[1] > BUILTIN.mg_value[1]);
+        assert_eq!(materialist.castled_king, BUILTIN.castled_king);
+
+        let mut settings = ContemptSettings {
+            manual: 10,
+            auto: false,
+            personality: Personality::Solid.contempt(),
+            ..ContemptSettings::default()
+        };
+        assert_eq!(settings.contempt(), -5);
+        settings.analyse_mode = true;
+        assert_eq!(settings.contempt(), 0);
+    }
+}
@@ -0,0 +1,404 @@
+use std::fmt::Write as _;
+
+use cozy_chess::{Board, Color, File, GameStatus, Move, Piece, Rank, Square};
+
+const STARTPOS_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+// Movetext lines are wrapped before this many characters, as the PGN standard asks
+const LINE_LENGTH: usize = 80;
+
+// A game read from a PGN file: its tags in the order they were given, the position it starts
+// from, which is the standard one unless a `FEN` tag says otherwise, and the moves played
+#[derive(Debug, Clone)]
+pub struct Game {
+    pub tags: Vec<(String, String)>,
+    pub start: Board,
+    pub moves: Vec<Move>,
+    pub result: String,
+}
+
+impl Game {
+    fn new(tags: Vec<(String, String)>) -> Result<Self, String> {
+        let fen = tags
+            .iter()
+            .find(|(name, _)| name == "FEN")
+            .map_or(STARTPOS_FEN, |(_, fen)| fen.as_str());
+        let start = Board::from_fen(fen, false).map_err(|_| format!("invalid FEN {fen}"))?;
+        Ok(Self {
+            tags,
+            start,
+            moves: Vec::new(),
+            result: "*".to_owned(),
+        })
+    }
+}
+
+// Parses every game of a PGN file. Comments, variations and NAGs are skipped, so only the moves of
+// the main line are kept.
+pub fn parse_games(text: &str) -> Result<Vec<Game>, String> {
+    let mut games = Vec::new();
+    let mut tags = Vec::new();
+    let mut game: Option<(Game, Board)> = None;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '[' => {
+                let tag = chars.by_ref().take_while(|&c| c != ']').collect::<String>();
+                tags.push(parse_tag(&tag)?);
+            }
+            '{' => chars.by_ref().take_while(|&c| c != '}').for_each(drop),
+            ';' => chars.by_ref().take_while(|&c| c != '\n').for_each(drop),
+            '(' => {
+                let mut depth = 1;
+                while depth > 0 {
+                    match chars.next() {
+                        Some('(') => depth += 1,
+                        Some(')') => depth -= 1,
+                        Some('{') => chars.by_ref().take_while(|&c| c != '}').for_each(drop),
+                        Some(_) => {}
+                        None => return Err("unterminated variation".to_owned()),
+                    }
+                }
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut token = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "[]{}();".contains(c) {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+
+                // The movetext of a game starts after its tags, and ends with the result
+                let (current, board) = match &mut game {
+                    Some(game) => game,
+                    None => {
+                        let new_game = Game::new(std::mem::take(&mut tags))?;
+                        let board = new_game.start.clone();
+                        game.insert((new_game, board))
+                    }
+                };
+                if matches!(token.as_str(), "1-0" | "0-1" | "1/2-1/2" | "*") {
+                    current.result = token;
+                    games.extend(game.take().map(|(game, _)| game));
+                    continue;
+                }
+                // Move numbers, possibly written together with the move as in `1.e4`
+                let san = token.rsplit('.').next().unwrap_or_default();
+                if san.is_empty() || san.starts_with('$') {
+                    continue;
+                }
+                let mv = parse_san(board, san).ok_or_else(|| {
+                    format!("illegal move {san} after {} moves", current.moves.len())
+                })?;
+                board.play_unchecked(mv);
+                current.moves.push(mv);
+            }
+        }
+    }
+    // The result is missing from the last game
+    games.extend(game.map(|(game, _)| game));
+    Ok(games)
+}
+
+// `Name "value"`, with `\"` and `\\` escaped inside the value
+fn parse_tag(tag: &str) -> Result<(String, String), String> {
+    let (name, value) = tag
+        .trim()
+        .split_once(char::is_whitespace)
+        .ok_or_else(|| format!("invalid tag [{tag}]"))?;
+    let value = value
+        .trim()
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .ok_or_else(|| format!("invalid tag [{tag}]"))?;
+    Ok((
+        name.to_owned(),
+        value.replace("\\\"", "\"").replace("\\\\", "\\"),
+    ))
+}
+
+fn legal_moves(board: &Board) -> Vec<Move> {
+    let mut moves = Vec::new();
+    board.generate_moves(|piece_moves| {
+        moves.extend(piece_moves);
+        false
+    });
+    moves
+}
+
+const fn piece_letter(piece: Piece) -> char {
+    match piece {
+        Piece::Pawn => 'P',
+        Piece::Knight => 'N',
+        Piece::Bishop => 'B',
+        Piece::Rook => 'R',
+        Piece::Queen => 'Q',
+        Piece::King => 'K',
+    }
+}
+
+fn letter_piece(letter: char) -> Option<Piece> {
+    Piece::ALL
+        .into_iter()
+        .find(|&piece| piece != Piece::Pawn && piece_letter(piece) == letter)
+}
+
+// cozy-chess encodes castling as the king capturing its own rook
+fn is_castling(board: &Board, mv: Move) -> bool {
+    board.piece_on(mv.from) == Some(Piece::King) && board.colors(board.side_to_move()).has(mv.to)
+}
+
+// Standard Algebraic Notation
+// Moves name the piece and its destination, plus the file or rank it comes from when another
+// piece of the same kind could go there too. Pawn captures name the file the pawn comes from.
+pub fn to_san(board: &Board, mv: Move) -> String {
+    let mut san = String::new();
+    if is_castling(board, mv) {
+        san.push_str(if mv.to.file() > mv.from.file() {
+            "O-O"
+        } else {
+            "O-O-O"
+        });
+    } else {
+        let piece = board.piece_on(mv.from).unwrap();
+        let capture = board.colors(!board.side_to_move()).has(mv.to)
+            || (piece == Piece::Pawn && mv.from.file() != mv.to.file());
+        if piece == Piece::Pawn {
+            if capture {
+                san.push(file_letter(mv.from.file()));
+            }
+        } else {
+            san.push(piece_letter(piece));
+            let rivals = legal_moves(board)
+                .into_iter()
+                .filter(|other| {
+                    other.to == mv.to
+                        && other.from != mv.from
+                        && board.piece_on(other.from) == Some(piece)
+                })
+                .collect::<Vec<_>>();
+            if !rivals.is_empty() {
+                if rivals
+                    .iter()
+                    .all(|other| other.from.file() != mv.from.file())
+                {
+                    san.push(file_letter(mv.from.file()));
+                } else if rivals
+                    .iter()
+                    .all(|other| other.from.rank() != mv.from.rank())
+                {
+                    san.push(rank_digit(mv.from.rank()));
+                } else {
+                    san.push(file_letter(mv.from.file()));
+                    san.push(rank_digit(mv.from.rank()));
+                }
+            }
+        }
+        if capture {
+            san.push('x');
+        }
+        san.push_str(&mv.to.to_string());
+        if let Some(promotion) = mv.promotion {
+            san.push('=');
+            san.push(piece_letter(promotion));
+        }
+    }
+
+    let mut child = board.clone();
+    child.play_unchecked(mv);
+    if child.status() == GameStatus::Won {
+        san.push('#');
+    } else if !child.checkers().is_empty() {
+        san.push('+');
+    }
+    san
+}
+
+// The legal move `san` stands for, if exactly one. Check marks and annotations like `!?` are
+// ignored, and so is a missing `=` before the promotion piece.
+pub fn parse_san(board: &Board, san: &str) -> Option<Move> {
+    let san = san.trim_end_matches(['+', '#', '!', '?']);
+    let moves = legal_moves(board);
+    let castling = match san {
+        "O-O" | "0-0" => Some(true),
+        "O-O-O" | "0-0-0" => Some(false),
+        _ => None,
+    };
+    if let Some(short) = castling {
+        return moves
+            .into_iter()
+            .find(|&mv| is_castling(board, mv) && (mv.to.file() > mv.from.file()) == short);
+    }
+
+    let mut chars = san.chars().collect::<Vec<_>>();
+    let piece = match chars.first().copied().and_then(letter_piece) {
+        Some(piece) => {
+            chars.remove(0);
+            piece
+        }
+        None => Piece::Pawn,
+    };
+    let promotion = match chars.last().copied().and_then(letter_piece) {
+        Some(promotion) => {
+            chars.pop();
+            if chars.last() == Some(&'=') {
+                chars.pop();
+            }
+            Some(promotion)
+        }
+        None => None,
+    };
+    chars.retain(|&c| c != 'x' && c != '-');
+    if chars.len() < 2 {
+        return None;
+    }
+    let to = chars
+        .split_off(chars.len() - 2)
+        .into_iter()
+        .collect::<String>();
+    let to: Square = to.parse().ok()?;
+    let mut from_file = None;
+    let mut from_rank = None;
+    for c in chars {
+        match c {
+            'a'..='h' => from_file = File::try_index(c as usize - 'a' as usize),
+            '1'..='8' => from_rank = Rank::try_index(c as usize - '1' as usize),
+            _ => return None,
+        }
+    }
+
+    let mut candidates = moves.into_iter().filter(|&mv| {
+        mv.to == to
+            && mv.promotion == promotion
+            && board.piece_on(mv.from) == Some(piece)
+            && !is_castling(board, mv)
+            && from_file.is_none_or(|file| mv.from.file() == file)
+            && from_rank.is_none_or(|rank| mv.from.rank() == rank)
+    });
+    let mv = candidates.next()?;
+    candidates.next().is_none().then_some(mv)
+}
+
+fn file_letter(file: File) -> char {
+    char::from(b'a' + file as u8)
+}
+
+fn rank_digit(rank: Rank) -> char {
+    char::from(b'1' + rank as u8)
+}
+
+// Writes a game with a comment and a NAG-like suffix (`?`, `!?`, ...) for every move. Tags are
+// written as given, and the movetext is wrapped to fit lines of `LINE_LENGTH`.
+pub fn write_game(game: &Game, annotations: &[(Option<&str>, Option<String>)]) -> String {
+    let mut out = String::new();
+    for (name, value) in &game.tags {
+        let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+        let _ = writeln!(out, "[{name} \"{value}\"]");
+    }
+    if !game.tags.is_empty() {
+        out.push('\n');
+    }
+
+    let mut tokens = Vec::new();
+    let mut board = game.start.clone();
+    let mut after_comment = false;
+    for (i, &mv) in game.moves.iter().enumerate() {
+        let number = board.fullmove_number();
+        if board.side_to_move() == Color::White {
+            tokens.push(format!("{number}."));
+        } else if i == 0 || after_comment {
+            tokens.push(format!("{number}..."));
+        }
+        let (suffix, comment) = annotations.get(i).cloned().unwrap_or((None, None));
+        tokens.push(format!("{}{}", to_san(&board, mv), suffix.unwrap_or("")));
+        after_comment = comment.is_some();
+        if let Some(comment) = comment {
+            tokens.extend(format!("{{{comment}}}").split(' ').map(str::to_owned));
+        }
+        board.play_unchecked(mv);
+    }
+    tokens.push(game.result.clone());
+
+    let mut line = String::new();
+    for token in tokens {
+        if !line.is_empty() && line.len() + 1 + token.len() >= LINE_LENGTH {
+            out.push_str(&line);
+            out.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&token);
+    }
+    out.push_str(&line);
+    out.push_str("\n\n");
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use cozy_chess::Board;
+
+    use super::{parse_games, parse_san, to_san, write_game};
+
+    #[test]
+    fn san_round_trip() {
+        let board = "r3k2r/1P3ppp/8/3pP3/8/2N3N1/8/R3K2R w KQkq d6 0 1"
+            .parse::<Board>()
+            .unwrap();
+        for (san, uci) in [
+            ("O-O", "e1h1"),
+            ("O-O-O", "e1a1"),
+            ("exd6", "e5d6"),
+            ("bxa8=Q+", "b7a8q"),
+            ("b8=N", "b7b8n"),
+            ("Nce4", "c3e4"),
+            ("Nge4", "g3e4"),
+            ("Rxa8+", "a1a8"),
+        ] {
+            let mv = parse_san(&board, san).unwrap();
+            assert_eq!(mv.to_string(), uci);
+            assert_eq!(to_san(&board, mv), san);
+        }
+        // Ambiguous or illegal
+        assert_eq!(parse_san(&board, "Ne4"), None);
+        assert_eq!(parse_san(&board, "Ke3"), None);
+        // Lax spellings
+        assert_eq!(parse_san(&board, "b8N").unwrap().to_string(), "b7b8n");
+        assert_eq!(parse_san(&board, "0-0").unwrap().to_string(), "e1h1");
+    }
+
+    #[test]
+    fn parse_and_write_games() {
+        let pgn = r#"[Event "Casual"]
+[White "A \"B\" C"]
+
+1. e4 e5 {A comment} 2. Qh5?! (2. Nf3 Nc6 (2... d6)) 2... Nc6 $6 3.Bc4 Nf6??
+4. Qxf7# 1-0
+
+[Event "Second"]
+[FEN "8/8/8/8/8/4k3/4p3/2K5 b - - 0 60"]
+
+60... e1=Q+ 61. Kb2 *
+"#;
+        let games = parse_games(pgn).unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].tags[1].1, "A \"B\" C");
+        assert_eq!(games[0].moves.len(), 7);
+        assert_eq!(games[0].result, "1-0");
+        assert_eq!(games[1].moves.len(), 2);
+        assert_eq!(games[1].result, "*");
+
+        let annotations = vec![(None, None), (None, Some("Forced".to_owned()))];
+        assert_eq!(
+            write_game(&games[1], &annotations),
+            "[Event \"Second\"]\n[FEN \"8/8/8/8/8/4k3/4p3/2K5 b - - 0 60\"]\n\n\
+             60... e1=Q+ 61. Kb2 {Forced} *\n\n"
+        );
+        assert!(write_game(&games[0], &[]).contains("[White \"A \\\"B\\\" C\"]"));
+        assert!(write_game(&games[0], &[]).contains("3. Bc4 Nf6 4. Qxf7# 1-0"));
+    }
+}
@@ -0,0 +1,200 @@
+use std::{
+    collections::HashMap,
+    sync::mpsc::{self, Receiver, Sender},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use cozy_chess::{Board, Move};
+
+use crate::{
+    search::{SearchStats, Searcher},
+    types::{Depth, Value},
+    utils::to_uci_move,
+};
+
+pub type EngineId = u64;
+
+// How long a search may run, and how deep
+#[derive(Debug, Clone, Copy)]
+pub enum SearchLimit {
+    Time(Duration),
+    Depth(Depth),
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    // Best move in UCI notation, ready to be sent to a GUI or server
+    pub best_move: Move,
+    pub value: Value,
+    pub depth: Depth,
+    pub nodes: u64,
+}
+
+enum EngineRequest {
+    Search {
+        board: Board,
+        moves: Vec<Move>,
+        limit: SearchLimit,
+        reply: Sender<SearchResult>,
+    },
+    NewGame,
+}
+
+// A single engine instance running on its own thread with its own TT and history tables
+struct Engine {
+    tx: Sender<EngineRequest>,
+    worker: JoinHandle<()>,
+}
+
+impl Engine {
+    fn spawn(tt_size: usize) -> Self {
+        let (tx, rx) = mpsc::channel();
+        let worker = thread::spawn(move || engine_worker(&rx, tt_size));
+        Self { tx, worker }
+    }
+}
+
+fn engine_worker(rx: &Receiver<EngineRequest>, tt_size: usize) {
+    let mut searcher = Searcher::new(tt_size);
+    // The loop ends once the owning pool drops the sender
+    while let Ok(request) = rx.recv() {
+        match request {
+            EngineRequest::Search {
+                mut board,
+                moves,
+                limit,
+                reply,
+            } => {
+                let mut stats = SearchStats::default();
+                let (bm, value) = match limit {
+                    SearchLimit::Time(time) => {
+                        searcher.search_for_time(&mut board, &moves, &mut stats, time)
+                    }
+                    SearchLimit::Depth(depth) => {
+                        searcher.search_fixed_depth(&mut board, &moves, &mut stats, depth)
+                    }
+                };
+                // The caller may have stopped waiting for the result, which is fine
+                let _ = reply.send(SearchResult {
+                    best_move: to_uci_move(&board, bm, false),
+                    value,
+                    depth: stats.depth,
+                    nodes: stats.nodes_visited,
+                });
+            }
+            EngineRequest::NewGame => searcher.new_game(),
+        }
+    }
+}
+
+// Manages independent engines so that one process can analyze several games concurrently, e.g.
+// a bot handling many simultaneous games. Engines share no state with each other.
+pub struct EnginePool {
+    engines: HashMap<EngineId, Engine>,
+    next_id: EngineId,
+    tt_size: usize,
+}
+
+impl EnginePool {
+    // `tt_size` is the transposition table size of every engine, in bytes
+    pub fn new(tt_size: usize) -> Self {
+        Self {
+            engines: HashMap::new(),
+            next_id: 0,
+            tt_size,
+        }
+    }
+
+    pub fn create_engine(&mut self) -> EngineId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.engines.insert(id, Engine::spawn(self.tt_size));
+        id
+    }
+
+    // Stops the engine once its queued searches are done
+    pub fn remove_engine(&mut self, id: EngineId) -> bool {
+        self.engines.remove(&id).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.engines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.engines.is_empty()
+    }
+
+    // Queues a search on the given engine. The result arrives on the returned receiver, so
+    // searches on different engines run in parallel.
+    pub fn search(
+        &self,
+        id: EngineId,
+        board: &Board,
+        moves: &[Move],
+        limit: SearchLimit,
+    ) -> Option<Receiver<SearchResult>> {
+        let engine = self.engines.get(&id)?;
+        let (reply, result) = mpsc::channel();
+        engine
+            .tx
+            .send(EngineRequest::Search {
+                board: board.clone(),
+                moves: moves.to_vec(),
+                limit,
+                reply,
+            })
+            .ok()?;
+        Some(result)
+    }
+
+    // Clears the per-game state of one engine
+    pub fn new_game(&self, id: EngineId) -> bool {
+        self.engines
+            .get(&id)
+            .is_some_and(|engine| engine.tx.send(EngineRequest::NewGame).is_ok())
+    }
+}
+
+impl Drop for EnginePool {
+    fn drop(&mut self) {
+        for (_, engine) in self.engines.drain() {
+            drop(engine.tx);
+            let _ = engine.worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cozy_chess::Board;
+
+    use super::{EnginePool, SearchLimit};
+    use crate::positions::named_position;
+
+    #[test]
+    fn engines_search_independently() {
+        let mut pool = EnginePool::new(1 << 20);
+        let first = pool.create_engine();
+        let second = pool.create_engine();
+        assert_eq!(pool.len(), 2);
+
+        let board = Board::startpos();
+        let endgame = named_position("cpw3").unwrap().parse::<Board>().unwrap();
+        let first_result = pool
+            .search(first, &board, &[], SearchLimit::Depth(4))
+            .unwrap();
+        let second_result = pool
+            .search(second, &endgame, &[], SearchLimit::Depth(4))
+            .unwrap();
+
+        assert!(board.is_legal(first_result.recv().unwrap().best_move));
+        assert!(endgame.is_legal(second_result.recv().unwrap().best_move));
+
+        assert!(pool.remove_engine(first));
+        assert!(pool
+            .search(first, &board, &[], SearchLimit::Depth(1))
+            .is_none());
+    }
+}
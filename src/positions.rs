@@ -0,0 +1,39 @@
+// Well-known test positions that can be referenced by name instead of pasting FENs
+pub const NAMED_POSITIONS: [(&str, &str); 6] = [
+    (
+        "kiwipete",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    ),
+    ("cpw3", "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1"),
+    (
+        "cpw4",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    ),
+    (
+        "cpw5",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    ),
+    (
+        "cpw6",
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    ),
+    ("lasker", "8/k7/3p4/p2P1p2/P2P1P2/8/8/K7 w - - 0 1"),
+];
+
+pub fn named_position(name: &str) -> Option<&'static str> {
+    NAMED_POSITIONS
+        .iter()
+        .find(|(pos_name, _)| pos_name.eq_ignore_ascii_case(name))
+        .map(|&(_, fen)| fen)
+}
+
+// Rewrites `position <name> [moves ...]` into the standard `position fen <fen> [moves ...]`
+pub fn expand_named_position(line: &str) -> Option<String> {
+    let mut tokens = line.split_whitespace();
+    if tokens.next() != Some("position") {
+        return None;
+    }
+    let fen = named_position(tokens.next()?)?;
+    let rest = tokens.collect::<Vec<_>>().join(" ");
+    Some(format!("position fen {fen} {rest}").trim_end().to_owned())
+}
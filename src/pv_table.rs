@@ -0,0 +1,81 @@
+use cozy_chess::Move;
+
+use crate::utils::NULL_MOVE;
+
+const MAX_PLY: usize = 256;
+
+// Triangular PV Table
+// Row `ply` holds the best line found from the node at that ply. Whenever a move raises alpha,
+// the node's row becomes that move followed by the row of the child it leads to, so the root row
+// ends up holding the whole principal variation.
+#[derive(Debug)]
+pub struct PvTable {
+    moves: Box<[[Move; MAX_PLY]]>,
+    len: Box<[usize]>,
+}
+
+impl Default for PvTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PvTable {
+    pub fn new() -> Self {
+        Self {
+            moves: vec![[NULL_MOVE; MAX_PLY]; MAX_PLY].into_boxed_slice(),
+            len: vec![0; MAX_PLY].into_boxed_slice(),
+        }
+    }
+
+    // Called on entering a node, which has no line until one of its moves raises alpha
+    pub fn clear(&mut self, ply: usize) {
+        self.len[ply] = 0;
+    }
+
+    pub fn update(&mut self, ply: usize, mv: Move) {
+        let (head, tail) = self.moves.split_at_mut(ply + 1);
+        let row = &mut head[ply];
+        row[0] = mv;
+        let child_len = match tail.first() {
+            Some(child) => {
+                let len = self.len[ply + 1].min(MAX_PLY - 1);
+                row[1..=len].copy_from_slice(&child[..len]);
+                len
+            }
+            None => 0,
+        };
+        self.len[ply] = child_len + 1;
+    }
+
+    pub fn line(&self, ply: usize) -> &[Move] {
+        &self.moves[ply][..self.len[ply]]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cozy_chess::Move;
+
+    use super::PvTable;
+
+    #[test]
+    fn lines_are_built_from_child_rows() {
+        let moves: Vec<Move> = ["e2e4", "e7e5", "g1f3"]
+            .iter()
+            .map(|mv| mv.parse().unwrap())
+            .collect();
+        let mut pv = PvTable::new();
+        pv.clear(2);
+        pv.update(2, moves[2]);
+        pv.update(1, moves[1]);
+        pv.update(0, moves[0]);
+        assert_eq!(pv.line(0), moves);
+        assert_eq!(pv.line(1), &moves[1..]);
+
+        // A new move at ply 1 whose child has no line replaces the old line
+        pv.clear(2);
+        pv.update(1, moves[2]);
+        assert_eq!(pv.line(1), &moves[2..]);
+    }
+}
@@ -1,78 +1,1000 @@
-use cozy_chess::{Board, GameStatus, Move, Piece};
+use arrayvec::ArrayVec;
+use cozy_chess::{BitBoard, Board, GameStatus, Move, Piece};
 
-use std::time::{Duration, Instant};
+use std::{
+    cell::RefCell,
+    cmp::Reverse,
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
 use crate::{
-    evaluate::{self, PIECE_VALUES},
+    checkpoint::Checkpoint,
+    correction_history::CorrectionHistory,
+    cuckoo::CuckooTable,
+    eval_params::EvalParams,
+    evaluate::{self, Evaluator, WindowEval, PIECE_VALUES},
     history::HistoryTable,
     lmr_table::LMRTable,
+    mcts::Mcts,
     move_ordering::MovesIterator,
-    transposition_table::{NodeType, TTEntry, TranspositionTable},
+    nnue::{AccumulatorStack, Network},
+    pv_table::PvTable,
+    rng::Rng,
+    search_params::{Heuristic, Heuristics, SearchParams},
+    search_stack::{SearchStack, NO_EVAL},
+    see::{creates_threat, see_ge, threatened_pieces},
+    strength::{EloLimit, SkillLevel},
+    tablebase::{Tablebase, Wdl, MAX_TB_PIECES},
+    trace::{SearchTracer, TraceReason, TraceRecord},
+    transposition_table::{value_from_tt, value_to_tt, NodeType, TTEntry, TranspositionTable},
     types::{Depth, Value},
-    utils::{uci_to_kxr_move, NULL_MOVE},
+    uci_println,
+    utils::{to_uci_move, uci_line, uci_to_kxr_move, NULL_MOVE},
+    wdl::uci_wdl,
 };
+#[cfg(feature = "selfcheck")]
+use crate::selfcheck;
 
 pub const MATE_VALUE: Value = PIECE_VALUES[Piece::King as usize];
 const SCORE_INF: Value = Value::MAX;
 const LMR_MIN_DEPTH: Depth = 3;
-const RFP_EVAL_MARGIN: Value = 75;
+// Scores this close to MATE_VALUE can only come from a forced mate
+const MATE_BOUND: Value = MATE_VALUE - Depth::MAX as Value;
+// Root moves are only reported once the search has run this long, to not flood the GUI
+const CURRMOVE_DELAY: Duration = Duration::from_secs(1);
+// Interval of the progress lines printed during long iterations
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+// Moves in a refutation line after the refuted move
+const REFUTATION_LEN: Depth = 3;
+// Moves are only deferred by ABDADA from this depth, shallower subtrees are cheap to share
+const ABDADA_MIN_DEPTH: Depth = 3;
+// Scores of tablebase wins, below any mate score so that a found mate is still preferred
+const TB_WIN_VALUE: Value = MATE_BOUND - Depth::MAX as Value;
+// Scores this close to TB_WIN_VALUE can only come from a tablebase win, or a forced mate
+const DECISIVE_BOUND: Value = TB_WIN_VALUE - Depth::MAX as Value;
+// Positions with as many pieces as the largest tables are only probed from this depth
+const TB_PROBE_MIN_DEPTH: Depth = 2;
+// Tablebase results are exact however deep the node is searched, so they are stored deeper
+const TB_DEPTH_BONUS: Depth = 6;
+// Depth of the searches scoring root moves for a weakened engine, see `pick_weakened_move`
+const WEAKENED_RESCORE_DEPTH: Depth = 4;
+// Quiescence search returns the static eval from this ply on, however the position looks
+const QS_MAX_PLY: u8 = 192;
+// Captures losing more than this in the exchange are skipped by quiescence search
+const QS_SEE_THRESHOLD: Value = 0;
+// Quiet moves at this depth or less are skipped if they lose more than `see_quiet_margin` per
+// squared ply
+const SEE_QUIET_MAX_DEPTH: Depth = 8;
+// Nodes without a TT move are searched one ply shallower from this depth on
+const IIR_MIN_DEPTH: Depth = 4;
+// Quiet moves at this depth or less are skipped if their history is below minus `hp_margin` per ply
+const HP_MAX_DEPTH: Depth = 3;
+// Quiet moves remembered per node for history penalties
+const MAX_QUIETS_TRIED: usize = 64;
+// Singular extensions are tried from this depth, with a TT entry at most this much shallower
+const SE_MIN_DEPTH: Depth = 7;
+const SE_TT_DEPTH_MARGIN: Depth = 3;
+// Fractional extensions are counted in these units per ply, and recaptures and threats add this
+// many units each
+const EXTENSION_UNITS_PER_PLY: u8 = 4;
+const RECAPTURE_EXTENSION_UNITS: u8 = 2;
+const THREAT_EXTENSION_UNITS: u8 = 1;
+// A line may be extended by one ply for every this many plies of its length
+const EXTENSION_BUDGET_PLIES: usize = 2;
+// Nominal speed of deterministic mode, converting time limits to nodes and nodes to reported time
+const DETERMINISTIC_NPS: u64 = 1_000_000;
+// Game moves root jitter is given for, unless set otherwise
+pub const DEFAULT_ROOT_JITTER_MOVES: u16 = 8;
+// The TT entries of the children of a node are checked for a cutoff from this depth
+const ETC_MIN_DEPTH: Depth = 6;
+
+pub const fn is_mate_score(value: Value) -> bool {
+    value.abs() >= MATE_BOUND
+}
+
+// Mate and tablebase scores, which count plies from the root
+pub const fn is_decisive_score(value: Value) -> bool {
+    value.abs() >= DECISIVE_BOUND
+}
+
+// Number of plies until mate for a mate score
+#[allow(clippy::cast_sign_loss)]
+pub const fn mate_distance(value: Value) -> usize {
+    (MATE_VALUE - value.abs()) as usize
+}
+
+fn report_debug_stats(stats: &SearchStats) {
+    uci_println!(
+        "info string tt hits {}/{} ({:.1}%) aspiration researches {} nmp cutoffs {} rfp cutoffs {} etc cutoffs {}",
+        stats.tt_hits,
+        stats.tt_probes,
+        stats.tt_hit_rate(),
+        stats.aspiration_researches,
+        stats.nmp_cutoffs,
+        stats.rfp_cutoffs,
+        stats.etc_cutoffs,
+    );
+    let ebf = stats
+        .effective_branching_factor()
+        .map_or_else(|| "-".to_owned(), |ebf| format!("{ebf:.2}"));
+    uci_println!(
+        "info string first move cutoffs {:.1}% qsearch nodes {:.1}% pvs researches {} ebf {ebf}",
+        stats.first_move_cutoff_rate(),
+        stats.qsearch_share(),
+        stats.pvs_researches,
+    );
+}
+
+// Formats a score for UCI info lines, in centipawns or in moves to mate
+pub fn uci_score(value: Value) -> String {
+    if is_mate_score(value) {
+        let moves_to_mate = mate_distance(value).div_ceil(2);
+        format!("mate {}{moves_to_mate}", if value < 0 { "-" } else { "" })
+    } else {
+        format!("cp {value}")
+    }
+}
+
+// Nodes per second, for UCI info lines and benchmarks. Measured in microseconds, so that very
+// short searches don't divide by zero.
+pub fn nps(nodes: u64, elapsed: Duration) -> u64 {
+    let nps = u128::from(nodes) * 1_000_000 / elapsed.as_micros().max(1);
+    u64::try_from(nps).unwrap_or(u64::MAX)
+}
+
+// Whether a score is a forced mate for the side to move within the given number of moves
+pub const fn is_mate_within(value: Value, moves: usize) -> bool {
+    value > 0 && is_mate_score(value) && mate_distance(value) < 2 * moves
+}
+
+// Percentage of the soft limit a search gets, by the number of iterations in a row that ended
+// with the same best move
+const STABILITY_SCALE: [u32; 5] = [250, 120, 90, 80, 75];
+// An iteration scoring this much below the previous one gets the soft limit extended by half
+const FAIL_LOW_MARGIN: Value = 30;
+const FAIL_LOW_SCALE: u32 = 150;
 
-// To end searches early
+// To end searches early. Past the soft limit no new iteration is started, since it would
+// likely not finish in time anyway. The hard limit aborts the running iteration.
 #[derive(Debug)]
 pub struct TimeControl {
     startt: Instant,
-    limit: Duration,
+    soft_limit: Duration,
+    hard_limit: Duration,
+    // Best move of the last completed iteration, and for how many iterations it has been
+    best_move: Move,
+    stable_iterations: usize,
+    // Score of the last completed iteration, and whether it fell sharply from the one before
+    last_value: Option<Value>,
+    failed_low: bool,
+    // The clock doesn't run while this is set, see `Searcher::set_ponder_flag`
+    ponder: Option<Arc<AtomicBool>>,
 }
 
 impl TimeControl {
     pub fn new(limit: Duration) -> Self {
         Self {
             startt: Instant::now(),
-            limit,
+            soft_limit: limit,
+            hard_limit: limit,
+            best_move: NULL_MOVE,
+            stable_iterations: 0,
+            last_value: None,
+            failed_low: false,
+            ponder: None,
         }
     }
 
+    // Allows iterations started before the soft limit to run until `hard_limit`
+    #[must_use]
+    pub fn with_hard_limit(mut self, hard_limit: Duration) -> Self {
+        self.hard_limit = hard_limit.max(self.soft_limit);
+        self
+    }
+
+    #[must_use]
+    pub fn with_ponder_flag(mut self, ponder: Option<Arc<AtomicBool>>) -> Self {
+        self.ponder = ponder;
+        self
+    }
+
+    fn pondering(&self) -> bool {
+        self.ponder
+            .as_ref()
+            .is_some_and(|ponder| ponder.load(Ordering::Relaxed))
+    }
+
+    // Whether the hard limit has passed
     pub fn time_up(&self) -> bool {
-        self.startt.elapsed() > self.limit
+        !self.pondering() && self.startt.elapsed() > self.hard_limit
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.startt.elapsed()
+    }
+
+    pub fn soft_time_up(&self) -> bool {
+        !self.pondering() && self.startt.elapsed() > self.scaled_soft_limit()
+    }
+
+    // Called after every completed iteration
+    pub fn record_iteration(&mut self, best_move: Move, value: Value) {
+        if best_move == self.best_move {
+            self.stable_iterations += 1;
+        } else {
+            self.best_move = best_move;
+            self.stable_iterations = 0;
+        }
+        self.failed_low = self
+            .last_value
+            .is_some_and(|last| value < last.saturating_sub(FAIL_LOW_MARGIN));
+        self.last_value = Some(value);
+    }
+
+    // Best Move Stability
+    // A best move that survived several iterations is unlikely to change with one more, so the
+    // search ends early. One that keeps changing gets more time to settle, up to the hard limit.
+    // A fixed move time, where both limits are the same, is always used in full.
+    fn scaled_soft_limit(&self) -> Duration {
+        if self.soft_limit == self.hard_limit {
+            return self.soft_limit;
+        }
+        let mut scale = STABILITY_SCALE[self.stable_iterations.min(STABILITY_SCALE.len() - 1)];
+        // Root Fail Low
+        // When the score just dropped, the best move was likely found to be bad, and the search
+        // needs time to find a better one rather than play it
+        if self.failed_low {
+            scale = scale * FAIL_LOW_SCALE / 100;
+        }
+        self.soft_limit
+            .checked_mul(scale)
+            .map_or(self.hard_limit, |limit| limit / 100)
+            .min(self.hard_limit)
+    }
+}
+
+// How helper threads share the work of a parallel search, see `Searcher::search`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchMode {
+    #[default]
+    LazySmp,
+    Abdada,
+}
+
+impl SearchMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        if value.eq_ignore_ascii_case("LazySMP") {
+            Some(Self::LazySmp)
+        } else if value.eq_ignore_ascii_case("ABDADA") {
+            Some(Self::Abdada)
+        } else {
+            None
+        }
+    }
+
+    // Name of the mode as a UCI option value
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::LazySmp => "LazySMP",
+            Self::Abdada => "ABDADA",
+        }
+    }
+}
+
+// Algorithm of the search, see `Searcher::search`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SearchBackend {
+    #[default]
+    AlphaBeta,
+    Mcts,
+}
+
+impl SearchBackend {
+    pub fn parse(value: &str) -> Option<Self> {
+        if value.eq_ignore_ascii_case("AlphaBeta") {
+            Some(Self::AlphaBeta)
+        } else if value.eq_ignore_ascii_case("MCTS") {
+            Some(Self::Mcts)
+        } else {
+            None
+        }
+    }
+
+    // Name of the backend as a UCI option value
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::AlphaBeta => "AlphaBeta",
+            Self::Mcts => "MCTS",
+        }
     }
 }
 
 #[derive(Debug, Default)]
 pub struct SearchStats {
-    pub nodes_visited: u32,
+    pub nodes_visited: u64,
     pub depth: u8,
+    // Deepest ply reached in the current iteration, including quiescence search
+    pub seldepth: u8,
+    // Diagnostics printed in debug mode
+    pub tt_probes: u64,
+    pub tt_hits: u64,
+    pub aspiration_researches: u64,
+    pub nmp_cutoffs: u64,
+    pub rfp_cutoffs: u64,
+    pub etc_cutoffs: u64,
+    // Beta cutoffs, and how many of them came from the first move searched
+    pub beta_cutoffs: u64,
+    pub first_move_cutoffs: u64,
+    // Nodes visited by quiescence search, included in `nodes_visited`
+    pub qsearch_nodes: u64,
+    // Null-window searches that had to be repeated with a full window
+    pub pvs_researches: u64,
+    // Nodes of the last two completed iterations, for the effective branching factor
+    pub prev_iteration_nodes: u64,
+    pub last_iteration_nodes: u64,
+    // Successful tablebase probes
+    pub tb_hits: u64,
+}
+
+#[allow(clippy::cast_precision_loss)]
+impl SearchStats {
+    // Percentage of beta cutoffs made by the first move, a measure of move ordering
+    pub fn first_move_cutoff_rate(&self) -> f64 {
+        self.first_move_cutoffs as f64 * 100.0 / self.beta_cutoffs.max(1) as f64
+    }
+
+    // Percentage of nodes visited by quiescence search
+    pub fn qsearch_share(&self) -> f64 {
+        self.qsearch_nodes as f64 * 100.0 / self.nodes_visited.max(1) as f64
+    }
+
+    pub fn tt_hit_rate(&self) -> f64 {
+        self.tt_hits as f64 * 100.0 / self.tt_probes.max(1) as f64
+    }
+
+    // How many times more nodes the last iteration took than the one before, once there are two
+    pub fn effective_branching_factor(&self) -> Option<f64> {
+        (self.prev_iteration_nodes > 0)
+            .then(|| self.last_iteration_nodes as f64 / self.prev_iteration_nodes as f64)
+    }
+
+    // Adds the counters of another search, e.g. to total a benchmark. The iteration node counts
+    // only make sense per search and are left alone.
+    pub fn add(&mut self, other: &Self) {
+        self.nodes_visited += other.nodes_visited;
+        self.tt_probes += other.tt_probes;
+        self.tt_hits += other.tt_hits;
+        self.aspiration_researches += other.aspiration_researches;
+        self.nmp_cutoffs += other.nmp_cutoffs;
+        self.rfp_cutoffs += other.rfp_cutoffs;
+        self.etc_cutoffs += other.etc_cutoffs;
+        self.beta_cutoffs += other.beta_cutoffs;
+        self.first_move_cutoffs += other.first_move_cutoffs;
+        self.qsearch_nodes += other.qsearch_nodes;
+        self.pvs_researches += other.pvs_researches;
+        self.tb_hits += other.tb_hits;
+    }
+}
+
+// A move of the root with what the last root search found out about it
+#[derive(Debug, Clone)]
+pub struct RootMove {
+    pub mv: Move,
+    // Exact for the best move, an upper bound for the others, and -SCORE_INF until searched
+    pub score: Value,
+    // Nodes its subtree took, for ordering the root moves of the next iteration
+    pub nodes: u64,
+    // Line starting with the move, as far as the PV table got it
+    pub pv: Vec<Move>,
 }
 
 #[derive(Debug)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Searcher {
-    pub tt: TranspositionTable,
-    board_history: Vec<u64>,
+    pub tt: Arc<TranspositionTable>,
     stop_search: bool,
+    // Set from outside the search thread, e.g. by the UCI `stop` command
+    stop_flag: Arc<AtomicBool>,
+    // Maximum number of nodes per search, for reproducible fixed-node testing
+    node_limit: u64,
+    // Time an iteration may run over the move time of the next search, see `TimeControl`
+    hard_time_limit: Option<Duration>,
+    ponder_flag: Option<Arc<AtomicBool>>,
+    // Length in moves of the mate a mate search is looking for
+    mate_target: Option<usize>,
+    // Root moves the next search is restricted to, as in `go searchmoves`. Empty means all moves.
+    root_filter: Vec<Move>,
+    // Root moves the next search must not play, the inverse of `root_filter`
+    root_avoid: Vec<Move>,
+    // Root moves skipped by the current root search, being the best lines found so far for MultiPV
+    root_excluded: Vec<Move>,
+    multi_pv: usize,
+    // Whether to print UCI info lines while searching
+    reporting: bool,
+    // Depth of the running iteration and search time at the last info line, for progress lines
+    iteration_depth: Depth,
+    last_report: Duration,
+    // Whether UCI moves use Chess960 castling notation
+    chess960: bool,
+    // Whether to print diagnostics as `info string`, see `SearchStats`
+    debug: bool,
+    // Whether info lines include win/draw/loss chances, see `wdl::wdl`
+    show_wdl: bool,
+    // Whether to print `info refutation` after iterations and `info currline` with progress lines
+    show_refutations: bool,
+    show_currline: bool,
+    // Root of the current search, for printing `info currline`
+    root_board: Board,
     history: HistoryTable,
-    killers: [Option<Move>; 257],
+    // Corrections of the static eval learned from search scores
+    correction: CorrectionHistory,
+    // State of the nodes on the current line, indexed by ply
+    stack: SearchStack,
+    pv: PvTable,
+    // Principal variation of the last completed iteration, and the hash of its root position
+    root_pv: Vec<Move>,
+    root_pv_hash: u64,
     lmr_table: LMRTable,
+    params: SearchParams,
+    heuristics: Heuristics,
+    cuckoo: CuckooTable,
     best_move: Move,
     ply: u8,
+    contempt: Value,
+    variety: Value,
+    // Largest random bonus of root moves, and the game moves it is given for, see Root Jitter
+    root_jitter: Value,
+    root_jitter_moves: u16,
+    // Seed of the root bonuses of the current search, if it gets any
+    jitter_key: Option<u64>,
+    elo_limit: Option<EloLimit>,
+    skill_level: SkillLevel,
+    rng: Rng,
+    // Seed of `rng` set through the `Seed` option, which deterministic mode reseeds it with
+    seed: u64,
+    // See Deterministic Mode in `search`
+    deterministic: bool,
+    // Set by `UCI_AnalyseMode`, where the whole move time is used to get the best analysis
+    analyse_mode: bool,
+    // Writes every node to a file when set, see `SearchTracer`
+    tracer: Option<SearchTracer>,
+    // How the node being left ended, for its trace record
+    trace_reason: Option<TraceReason>,
+    // Moves searched from the root, see `search_root`
+    root_moves: Vec<RootMove>,
+    checkpoint_path: Option<PathBuf>,
+    tablebase: Option<Arc<Tablebase>>,
+    eval_params: Arc<EvalParams>,
+    // Used instead of the eval params when set
+    nnue: Option<Arc<Network>>,
+    // Accumulators of the network by ply, see `node_eval`. Quiescence search updates them
+    // through a shared reference.
+    accumulators: RefCell<AccumulatorStack>,
+    // Root positions with more pieces than this aren't probed
+    tb_probe_limit: usize,
+    // Lazy SMP helper threads, see `search`
+    helpers: Vec<Self>,
+    search_mode: SearchMode,
+    backend: SearchBackend,
+    // 0 for the main thread, which reports results
+    thread_id: usize,
+    // Stops the helpers once the main thread is done
+    helper_stop: Arc<AtomicBool>,
 }
 
 impl Searcher {
     pub fn new(tt_size: usize) -> Self {
-        let mut board_history = Vec::new();
-        board_history.reserve(512);
+        Self::with_tt(Arc::new(TranspositionTable::new(tt_size)))
+    }
+
+    fn with_tt(tt: Arc<TranspositionTable>) -> Self {
         Self {
-            tt: TranspositionTable::new(tt_size),
-            board_history,
+            tt,
             stop_search: false,
+            stop_flag: Arc::new(AtomicBool::new(false)),
+            node_limit: u64::MAX,
+            hard_time_limit: None,
+            ponder_flag: None,
+            mate_target: None,
+            root_filter: Vec::new(),
+            root_avoid: Vec::new(),
+            root_excluded: Vec::new(),
+            multi_pv: 1,
+            reporting: false,
+            iteration_depth: 0,
+            last_report: Duration::ZERO,
+            chess960: false,
+            debug: false,
+            show_wdl: false,
+            show_refutations: false,
+            show_currline: false,
+            root_board: Board::startpos(),
             history: HistoryTable::new(),
-            killers: [None; 257],
+            correction: CorrectionHistory::new(),
+            stack: SearchStack::new(),
+            pv: PvTable::new(),
+            root_pv: Vec::new(),
+            root_pv_hash: 0,
             lmr_table: LMRTable::new(),
+            params: SearchParams::default(),
+            heuristics: Heuristics::default(),
+            cuckoo: CuckooTable::new(),
             best_move: NULL_MOVE,
             ply: 0,
+            contempt: 0,
+            variety: 0,
+            root_jitter: 0,
+            root_jitter_moves: DEFAULT_ROOT_JITTER_MOVES,
+            jitter_key: None,
+            elo_limit: None,
+            skill_level: SkillLevel::default(),
+            rng: Rng::from_time(),
+            seed: 0,
+            deterministic: false,
+            analyse_mode: false,
+            tracer: None,
+            trace_reason: None,
+            root_moves: Vec::new(),
+            checkpoint_path: None,
+            tablebase: None,
+            eval_params: Arc::new(EvalParams::default()),
+            nnue: None,
+            accumulators: RefCell::new(AccumulatorStack::default()),
+            tb_probe_limit: MAX_TB_PIECES,
+            helpers: Vec::new(),
+            search_mode: SearchMode::default(),
+            backend: SearchBackend::default(),
+            thread_id: 0,
+            helper_stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    // Total number of search threads, including this one
+    pub fn set_threads(&mut self, threads: usize) {
+        self.helpers = (1..threads.max(1))
+            .map(|thread_id| {
+                let mut helper = Self::with_tt(Arc::clone(&self.tt));
+                helper.thread_id = thread_id;
+                helper.search_mode = self.search_mode;
+                helper.contempt = self.contempt;
+                helper.chess960 = self.chess960;
+                helper.eval_params = Arc::clone(&self.eval_params);
+                helper.nnue.clone_from(&self.nnue);
+                helper.lmr_table = self.lmr_table.clone();
+                helper.params = self.params;
+                helper.heuristics = self.heuristics;
+                helper.tablebase.clone_from(&self.tablebase);
+                helper.tb_probe_limit = self.tb_probe_limit;
+                helper.set_stop_flag(Arc::clone(&self.helper_stop));
+                helper
+            })
+            .collect();
+    }
+
+    // Empties the TT with as many threads as the search uses
+    pub fn clear_tt(&self) {
+        self.tt.clear(self.helpers.len() + 1);
+    }
+
+    pub fn set_search_mode(&mut self, search_mode: SearchMode) {
+        self.search_mode = search_mode;
+        for helper in &mut self.helpers {
+            helper.search_mode = search_mode;
+        }
+    }
+
+    pub const fn set_search_backend(&mut self, backend: SearchBackend) {
+        self.backend = backend;
+    }
+
+    // Sets the search parameter with the given option name, see `SearchParams`. Returns whether
+    // there is such a parameter.
+    pub fn set_search_param(&mut self, name: &str, value: i32) -> bool {
+        let found = self.params.set(name, value);
+        self.lmr_table = LMRTable::with_params(self.params.lmr_base, self.params.lmr_divisor);
+        for helper in &mut self.helpers {
+            helper.params = self.params;
+            helper.lmr_table = self.lmr_table.clone();
+        }
+        found
+    }
+
+    pub fn set_heuristic(&mut self, heuristic: Heuristic, enabled: bool) {
+        self.heuristics.set(heuristic, enabled);
+        for helper in &mut self.helpers {
+            helper.heuristics = self.heuristics;
+        }
+    }
+
+    pub const fn set_variety(&mut self, variety: Value) {
+        self.variety = variety;
+    }
+
+    pub const fn set_elo_limit(&mut self, elo_limit: Option<EloLimit>) {
+        self.elo_limit = elo_limit;
+    }
+
+    pub const fn set_skill_level(&mut self, skill_level: SkillLevel) {
+        self.skill_level = skill_level;
+    }
+
+    pub const fn set_root_jitter(&mut self, root_jitter: Value) {
+        self.root_jitter = root_jitter;
+    }
+
+    pub const fn set_root_jitter_moves(&mut self, root_jitter_moves: u16) {
+        self.root_jitter_moves = root_jitter_moves;
+    }
+
+    pub const fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = Rng::new(seed);
+    }
+
+    pub const fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+
+    pub const fn set_analyse_mode(&mut self, analyse_mode: bool) {
+        self.analyse_mode = analyse_mode;
+    }
+
+    // Starts tracing the nodes of the main thread's searches to a new file at `path`, or stops
+    // tracing
+    pub fn set_trace_file(&mut self, path: Option<&Path>) -> io::Result<()> {
+        self.tracer = path.map(SearchTracer::create).transpose()?;
+        Ok(())
+    }
+
+    pub fn set_contempt(&mut self, contempt: Value) {
+        // Helpers share the TT, so they have to score draws the same way
+        self.contempt = contempt;
+        for helper in &mut self.helpers {
+            helper.contempt = contempt;
+        }
+    }
+
+    // Shares a stop flag with another thread. Setting it aborts the running search, which then
+    // returns the best move of the last completed iteration. Whoever sets the flag is also
+    // responsible for clearing it before the next search.
+    pub fn set_stop_flag(&mut self, flag: Arc<AtomicBool>) {
+        self.stop_flag = flag;
+    }
+
+    // Pondering
+    // While the shared flag is set, the search thinks on the opponent's time: the time limit
+    // doesn't apply and no result is returned until the flag is cleared (`ponderhit`) or the
+    // search is stopped. Time spent pondering counts towards the time limit, so after a long
+    // ponder the search usually ends right after `ponderhit` with the deep result.
+    pub fn set_ponder_flag(&mut self, flag: Arc<AtomicBool>) {
+        self.ponder_flag = Some(flag);
+    }
+
+    fn pondering(&self) -> bool {
+        self.ponder_flag
+            .as_ref()
+            .is_some_and(|ponder| ponder.load(Ordering::Relaxed))
+    }
+
+    pub fn set_node_limit(&mut self, limit: Option<u64>) {
+        self.node_limit = limit.unwrap_or(u64::MAX);
+    }
+
+    // Without a hard limit, searches end at the move time even in the middle of an iteration
+    pub const fn set_hard_time_limit(&mut self, limit: Option<Duration>) {
+        self.hard_time_limit = limit;
+    }
+
+    // Restricts the next search to the given root moves, in UCI notation
+    pub fn set_search_moves(&mut self, moves: Vec<Move>) {
+        self.root_filter = moves;
+    }
+
+    // Keeps the next search from playing the given root moves, in UCI notation. Unless they are
+    // all the legal moves, which are then searched as usual.
+    pub fn set_avoid_moves(&mut self, moves: Vec<Move>) {
+        self.root_avoid = moves;
+    }
+
+    // Number of best lines to report after each iteration
+    pub fn set_multi_pv(&mut self, multi_pv: usize) {
+        self.multi_pv = multi_pv.max(1);
+    }
+
+    pub fn set_chess960(&mut self, chess960: bool) {
+        self.chess960 = chess960;
+        for helper in &mut self.helpers {
+            helper.chess960 = chess960;
         }
     }
 
+    pub const fn set_debug(&mut self, debug: bool) {
+        self.debug = debug;
+    }
+
+    pub const fn set_show_wdl(&mut self, show_wdl: bool) {
+        self.show_wdl = show_wdl;
+    }
+
+    pub const fn set_show_refutations(&mut self, show_refutations: bool) {
+        self.show_refutations = show_refutations;
+    }
+
+    pub const fn set_show_currline(&mut self, show_currline: bool) {
+        self.show_currline = show_currline;
+    }
+
+    pub const fn set_reporting(&mut self, reporting: bool) {
+        self.reporting = reporting;
+    }
+
+    fn is_searched_root_move(&self, mv: Move) -> bool {
+        self.root_filter.is_empty() || self.root_filter.contains(&mv)
+    }
+
+    fn stop_requested(&self) -> bool {
+        self.stop_flag.load(Ordering::Relaxed)
+    }
+
+    // When set, a checkpoint is written to this path after every completed iteration
+    pub fn set_checkpoint_path(&mut self, path: Option<PathBuf>) {
+        self.checkpoint_path = path;
+    }
+
+    pub fn set_eval_params(&mut self, params: Arc<EvalParams>) {
+        for helper in &mut self.helpers {
+            helper.eval_params = Arc::clone(&params);
+        }
+        self.eval_params = params;
+    }
+
+    // Sets a term weight of the eval by its option name, see `eval_params::param_options`
+    pub fn set_eval_param(&mut self, name: &str, value: i32) -> bool {
+        let mut params = EvalParams::clone(&self.eval_params);
+        let found = params.set(name, value);
+        self.set_eval_params(Arc::new(params));
+        found
+    }
+
+    // Evaluates with the network instead of the eval params, until it is unset again
+    pub fn set_nnue(&mut self, network: Option<Arc<Network>>) {
+        for helper in &mut self.helpers {
+            helper.nnue.clone_from(&network);
+            helper.accumulators.get_mut().clear();
+        }
+        self.nnue = network;
+        self.accumulators.get_mut().clear();
+    }
+
+    fn evaluator(&self) -> Evaluator<'_> {
+        self.nnue
+            .as_deref()
+            .map_or(Evaluator::Psqt(&self.eval_params), Evaluator::Nnue)
+    }
+
+    // Evaluation of the position for the side to move, with the weights in use
+    pub fn static_eval(&self, board: &Board) -> Value {
+        self.evaluator().evaluate(board)
+    }
+
+    // Evaluation of the position at `ply` of the search, with the game phase the search keeps
+    // track of, which may be cut short when it is far outside alpha..beta. With a network, its
+    // accumulators are updated from those of nearby nodes instead of being built from scratch.
+    fn node_eval(
+        &self,
+        board: &Board,
+        ply: u8,
+        game_phase: i32,
+        alpha: Value,
+        beta: Value,
+    ) -> WindowEval {
+        debug_assert_eq!(game_phase, evaluate::game_phase(board));
+        #[cfg(feature = "selfcheck")]
+        if let Evaluator::Psqt(params) = self.evaluator() {
+            if let Err(msg) = selfcheck::check(board, params) {
+                panic!("asymmetric eval at {msg}");
+            }
+        }
+        let Some(network) = &self.nnue else {
+            let (alpha, beta) = if self.heuristics.is_enabled(Heuristic::LazyEval) {
+                (alpha, beta)
+            } else {
+                (-SCORE_INF, SCORE_INF)
+            };
+            return self
+                .evaluator()
+                .evaluate_within(board, game_phase, alpha, beta);
+        };
+        let eval = self
+            .accumulators
+            .borrow_mut()
+            .evaluate(network, usize::from(ply), board);
+        WindowEval {
+            value: evaluate::adjust_eval(board, eval),
+            lazy: false,
+        }
+    }
+
+    pub fn set_tablebase(&mut self, tablebase: Option<Arc<Tablebase>>) {
+        self.tablebase = tablebase;
+        for helper in &mut self.helpers {
+            helper.tablebase.clone_from(&self.tablebase);
+        }
+    }
+
+    pub fn set_tb_probe_limit(&mut self, pieces: usize) {
+        self.tb_probe_limit = pieces;
+        for helper in &mut self.helpers {
+            helper.tb_probe_limit = pieces;
+        }
+    }
+
+    // Keeps the entries of the last search, see `TranspositionTable::resized`
+    pub fn resize_tt(&mut self, mb: usize) {
+        self.tt = Arc::new(self.tt.resized(mb * 1024 * 1024));
+        for helper in &mut self.helpers {
+            helper.tt = Arc::clone(&self.tt);
+        }
+    }
+
+    // Forgets everything learned during the previous game, so that games don't influence each
+    // other. Only the TT keeps its entries, which the next search's generation replaces first, see
+    // `TranspositionTable::new_search`.
     pub fn new_game(&mut self) {
-        self.tt.clear();
+        self.history.clear();
+        self.correction.clear();
+        self.stack.clear();
+        self.root_moves.clear();
+        self.root_pv.clear();
+        self.best_move = NULL_MOVE;
+        for helper in &mut self.helpers {
+            helper.new_game();
+        }
+    }
+
+    pub fn checkpoint(
+        &self,
+        board: &Board,
+        depth: Depth,
+        best_move: Move,
+        best_value: Value,
+    ) -> Checkpoint {
+        Checkpoint {
+            fen: board.to_string(),
+            depth,
+            best_move,
+            best_value,
+            root_scores: self
+                .root_moves
+                .iter()
+                .map(|root_move| (root_move.mv, root_move.score))
+                .collect(),
+            tt_entries: self.tt.entries().collect(),
+            tt_buckets: self.tt.buckets(),
+        }
+    }
+
+    // Loads the TT of a checkpoint and returns the position to resume analysing. Iterative
+    // deepening restarts from depth 1, but quickly gets back to the saved depth thanks to the TT.
+    pub fn restore(&mut self, checkpoint: &Checkpoint) -> Option<Board> {
+        let board = Board::from_fen(&checkpoint.fen, false).ok()?;
+        self.clear_tt();
+        self.tt
+            .restore(&checkpoint.tt_entries, checkpoint.tt_buckets);
+        self.root_moves = checkpoint
+            .root_scores
+            .iter()
+            .map(|&(mv, score)| RootMove {
+                mv,
+                score,
+                nodes: 0,
+                pv: vec![mv],
+            })
+            .collect();
+        Some(board)
+    }
+
+    // Root moves of the last search, in the order they were last searched
+    pub fn root_moves(&self) -> &[RootMove] {
+        &self.root_moves
+    }
+
+    // The principal variation of the last completed iteration when `board` is its root, otherwise
+    // reconstructed from the TT
+    pub fn principal_variation(&self, board: &Board, max_len: Depth) -> Vec<Move> {
+        if board.hash() == self.root_pv_hash && !self.root_pv.is_empty() {
+            return self
+                .root_pv
+                .iter()
+                .take(usize::from(max_len))
+                .copied()
+                .collect();
+        }
+        self.tt_line(board, max_len)
+    }
+
+    // Follows the best moves stored in the TT
+    fn tt_line(&self, board: &Board, max_len: Depth) -> Vec<Move> {
+        let mut pv = Vec::new();
+        let mut board = board.clone();
+        let mut seen = Vec::new();
+        while pv.len() < usize::from(max_len) {
+            let Some(tte) = self.tt.get(board.hash()) else {
+                break;
+            };
+            // Stop on collisions and on repeated positions, which would loop forever
+            if !board.is_legal(tte.best_move) || seen.contains(&board.hash()) {
+                break;
+            }
+            seen.push(board.hash());
+            board.play_unchecked(tte.best_move);
+            pv.push(tte.best_move);
+        }
+        pv
+    }
+
+    // The expected reply to the best move, to ponder on. When the best move isn't the one of the
+    // principal variation, e.g. after a varied pick, the TT usually still has a reply.
+    pub fn ponder_move(&self, board: &Board, best_move: Move) -> Option<Move> {
+        if !board.is_legal(best_move) {
+            return None;
+        }
+        match self.principal_variation(board, 2)[..] {
+            [first, reply] if first == best_move => Some(reply),
+            _ => {
+                let mut child = board.clone();
+                child.play_unchecked(best_move);
+                self.tt_line(&child, 1).first().copied()
+            }
+        }
+    }
+
+    // The line of a root move, filled up from the TT where a TT cutoff cut it short
+    fn root_line(&self, board: &Board, best_move: Move, max_len: Depth) -> Vec<Move> {
+        let mut line = self
+            .root_moves
+            .iter()
+            .find(|root_move| root_move.mv == best_move)
+            .map_or_else(|| vec![best_move], |root_move| root_move.pv.clone());
+        let mut end = board.clone();
+        for &mv in &line {
+            end.play_unchecked(mv);
+        }
+        let missing = usize::from(max_len).saturating_sub(line.len());
+        line.extend(self.tt_line(&end, Depth::try_from(missing).unwrap_or(Depth::MAX)));
+        line
+    }
+
+    // For a mate score, returns the full forced line to checkmate: the principal variation, filled
+    // up from the TT where it was cut short. Nothing is searched, since this runs after the time
+    // for the move is used up, so the line ends early if the TT lost the way.
+    pub fn mate_line(&self, board: &Board, value: Value) -> Vec<Move> {
+        if !is_mate_score(value) {
+            return Vec::new();
+        }
+
+        let max_len = Depth::try_from(mate_distance(value)).unwrap_or(Depth::MAX);
+        let mut line = self.principal_variation(board, max_len);
+        let mut end = board.clone();
+        let mut legal = 0;
+        for &mv in &line {
+            if !end.is_legal(mv) {
+                break;
+            }
+            end.play_unchecked(mv);
+            legal += 1;
+        }
+        line.truncate(legal);
+        let missing = usize::from(max_len) - line.len();
+        line.extend(self.tt_line(&end, Depth::try_from(missing).unwrap_or(Depth::MAX)));
+        line
     }
 
     pub fn search_for_time(
@@ -95,6 +1017,56 @@ impl Searcher {
         self.search(board, moves, stats, depth, Duration::MAX)
     }
 
+    // Keeps deepening until stopped through the stop flag. UCI forbids reporting a best move
+    // before `stop` even if the search runs out of depth, so this waits for the flag.
+    pub fn search_infinite(
+        &mut self,
+        board: &mut Board,
+        moves: &Vec<Move>,
+        stats: &mut SearchStats,
+    ) -> (Move, Value) {
+        let result = self.search(board, moves, stats, Depth::MAX, Duration::MAX);
+        while !self.stop_requested() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        result
+    }
+
+    // Mate Search
+    // Looks for a forced mate in at most `moves_to_mate` moves, as in `go mate`. Such a mate is at
+    // most 2N - 1 plies away, so iterative deepening stops there, or as soon as a short enough
+    // mate is proven. Use `is_mate_within` on the returned value to check whether one was found.
+    pub fn search_mate(
+        &mut self,
+        board: &mut Board,
+        moves: &Vec<Move>,
+        stats: &mut SearchStats,
+        moves_to_mate: usize,
+        move_time: Duration,
+    ) -> (Move, Value) {
+        let max_ply = (2 * moves_to_mate).saturating_sub(1);
+        self.mate_target = Some(moves_to_mate);
+        let max_depth = Depth::try_from(max_ply).unwrap_or(Depth::MAX).max(1);
+        let result = self.search(board, moves, stats, max_depth, move_time);
+        self.mate_target = None;
+        result
+    }
+
+    // Lazy SMP
+    // With more than one thread, helper threads search the same position at the same time as the
+    // main thread, without any coordination besides the shared TT. The TT entries they leave
+    // behind give the main thread better move ordering and more cutoffs. Half of the helpers
+    // search one ply deeper, so that threads don't all follow each other through the same tree.
+    // In `SearchMode::Abdada`, threads instead search the same depth and keep out of each
+    // other's way by deferring the moves another thread is busy with.
+    // With `SearchBackend::Mcts`, timed, node limited and infinite searches use MCTS on a single
+    // thread instead. Depth limits mean nothing to it, so fixed depth searches stay alpha-beta.
+    //
+    // Deterministic Mode
+    // Two searches of the same position give the same output byte for byte, for regression tests
+    // and for chasing score differences. The clock is never looked at: time limits become node
+    // limits at a nominal `DETERMINISTIC_NPS`, and reported times are derived from the node
+    // count. Helper threads are left out, and the random generator is reseeded every search.
     pub fn search(
         &mut self,
         board: &mut Board,
@@ -103,19 +1075,189 @@ impl Searcher {
         max_depth: Depth,
         move_time: Duration,
     ) -> (Move, Value) {
-        let mut best_move = NULL_MOVE;
+        self.tt.new_search();
+        let node_limit = self.node_limit;
+        let hard_time_limit = self.hard_time_limit;
+        let move_time = if self.deterministic {
+            self.rng = Rng::new(self.seed);
+            if move_time != Duration::MAX {
+                let nodes = move_time.as_micros() * u128::from(DETERMINISTIC_NPS) / 1_000_000;
+                self.node_limit = node_limit.min(u64::try_from(nodes).unwrap_or(u64::MAX).max(1));
+            }
+            self.hard_time_limit = None;
+            Duration::MAX
+        } else {
+            move_time
+        };
+        let mcts = self.backend == SearchBackend::Mcts && max_depth == Depth::MAX;
+        let (mut best_move, value) = if mcts {
+            self.search_mcts(board, moves, stats, move_time)
+        } else if let Some(limit) = self.elo_limit {
+            // Helper threads would only make a weakened engine stronger again
+            self.node_limit = node_limit.min(limit.max_nodes());
+            let max_depth = max_depth.min(limit.max_depth());
+            self.search_thread(board, moves, stats, max_depth, move_time)
+        } else if self.helpers.is_empty() || self.deterministic {
+            self.search_thread(board, moves, stats, max_depth, move_time)
+        } else {
+            self.search_parallel(board, moves, stats, max_depth, move_time)
+        };
+        self.node_limit = node_limit;
+        self.hard_time_limit = hard_time_limit;
+        // Whatever went wrong above, a position with legal moves gets one of them, since an
+        // illegal `bestmove` forfeits the game
+        if !board.is_legal(best_move) {
+            board.generate_moves(|moves| {
+                best_move = moves.into_iter().next().unwrap_or(NULL_MOVE);
+                true
+            });
+        }
+        if let Some(tracer) = &mut self.tracer {
+            if let Err(e) = tracer.flush() {
+                uci_println!("info string could not write search trace: {e}");
+                self.tracer = None;
+            }
+        }
+        // A ponder search that ran out of depth still waits for `ponderhit` or `stop`
+        while self.pondering() && !self.stop_requested() {
+            thread::sleep(Duration::from_millis(1));
+        }
+        (best_move, value)
+    }
+
+    fn search_mcts(
+        &mut self,
+        board: &mut Board,
+        moves: &Vec<Move>,
+        stats: &mut SearchStats,
+        move_time: Duration,
+    ) -> (Move, Value) {
+        self.search_reset(board, moves);
+        self.prepare_root_filter(board);
+        self.probe_root_tablebase(board, stats);
+
+        let timer = TimeControl::new(move_time).with_ponder_flag(self.ponder_flag.clone());
+        let mut mcts = Mcts::new(self.evaluator(), self.reporting, self.chess960);
+        let result = mcts.search(
+            board,
+            self.stack.hashes(),
+            &self.root_filter,
+            &timer,
+            self.node_limit,
+            &self.stop_flag,
+            stats,
+        );
+        self.root_filter.clear();
+        self.root_avoid.clear();
+        result
+    }
+
+    fn search_parallel(
+        &mut self,
+        board: &mut Board,
+        moves: &Vec<Move>,
+        stats: &mut SearchStats,
+        max_depth: Depth,
+        move_time: Duration,
+    ) -> (Move, Value) {
+        let mut helpers = std::mem::take(&mut self.helpers);
+        self.helper_stop.store(false, Ordering::Relaxed);
+        let root = board.clone();
+        let result = thread::scope(|s| {
+            let workers = helpers
+                .iter_mut()
+                .map(|helper| {
+                    let mut board = root.clone();
+                    helper.root_filter.clone_from(&self.root_filter);
+                    helper.root_avoid.clone_from(&self.root_avoid);
+                    s.spawn(move || {
+                        let mut stats = SearchStats::default();
+                        helper.search_thread(
+                            &mut board,
+                            moves,
+                            &mut stats,
+                            Depth::MAX,
+                            Duration::MAX,
+                        );
+                        stats.nodes_visited
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            let result = self.search_thread(board, moves, stats, max_depth, move_time);
+            self.helper_stop.store(true, Ordering::Relaxed);
+            for worker in workers {
+                stats.nodes_visited = stats
+                    .nodes_visited
+                    .saturating_add(worker.join().unwrap_or(0));
+            }
+            result
+        });
+        self.helpers = helpers;
+        result
+    }
+
+    fn search_thread(
+        &mut self,
+        board: &mut Board,
+        moves: &Vec<Move>,
+        stats: &mut SearchStats,
+        max_depth: Depth,
+        move_time: Duration,
+    ) -> (Move, Value) {
         let mut best_value = 0;
 
-        let timer = TimeControl::new(move_time);
+        // Analysis Mode
+        // Analysing GUIs give a fixed time and want the deepest analysis it allows, so the time
+        // saving of match play is left out: the soft limit isn't cut short by a stable best move,
+        // and a single legal move is still analysed in full.
+        let hard_limit = if self.analyse_mode {
+            move_time
+        } else {
+            self.hard_time_limit.unwrap_or(move_time)
+        };
+        let mut timer = TimeControl::new(move_time)
+            .with_hard_limit(hard_limit)
+            .with_ponder_flag(self.ponder_flag.clone());
+        self.last_report = Duration::ZERO;
         self.search_reset(board, moves);
+        if self.show_currline {
+            self.root_board = board.clone();
+        }
+        self.prepare_root_filter(board);
+        // Every thread probes for itself, so that helpers search the same restricted root moves
+        self.probe_root_tablebase(board, stats);
+        let jitter = self.root_jitter > 0 && board.fullmove_number() <= self.root_jitter_moves;
+        self.jitter_key = jitter.then(|| self.rng.next_u64());
+        self.init_root_moves(board);
+        // Until an iteration completes, the best move is the first in the root move ordering, so
+        // that even a search stopped right away has a legal move to play
+        let mut best_move = self.root_moves.first().map_or(NULL_MOVE, |root_move| root_move.mv);
+
+        // With a single move to choose from, there is nothing to think about on the clock. One
+        // iteration still gives a score to report and leaves the TT ready for the next move.
+        let max_depth = if self.root_moves.len() == 1
+            && move_time != Duration::MAX
+            && !self.analyse_mode
+        {
+            max_depth.min(1)
+        } else {
+            max_depth
+        };
 
         // Iterative Deepening (ID)
         // Searching to a lower depth allows us to order moves better, so that higher depth searches
         // get more cutoffs. Number of nodes increases exponentially with depth, so smaller searches
         // are significantly cheaper.
         for i in 1..=max_depth {
-            let val = if i < 5 {
-                self.search_internal(board, stats, i, -SCORE_INF, SCORE_INF, &timer)
+            stats.seldepth = 0;
+            let iteration_start = stats.nodes_visited;
+            // With Lazy SMP, odd numbered helper threads stay one ply ahead
+            let ahead = self.search_mode == SearchMode::LazySmp && self.thread_id % 2 == 1;
+            let depth = i.saturating_add(Depth::from(ahead));
+            self.iteration_depth = depth;
+            let val = if i < 5 || !self.heuristics.is_enabled(Heuristic::AspirationWindows) {
+                self.search_root(board, stats, depth, -SCORE_INF, SCORE_INF, &timer)
             } else {
                 // Aspiration windows
                 // After a few shallow searches, instead of starting alpha/beta at -inf,inf use the
@@ -123,12 +1265,12 @@ impl Searcher {
                 // expected it to be, search again after increasing bounds. Since the bounds
                 // increase exponentially, we don't have to research much and searches with smaller
                 // bounds complete much quicker due to easier cutoffs.
-                let mut window_size = 20;
+                let mut window_size = self.params.aspiration_window;
                 let mut alpha = best_value - window_size;
                 let mut beta = best_value + window_size;
                 let mut tmp_val;
                 loop {
-                    tmp_val = self.search_internal(board, stats, i, alpha, beta, &timer);
+                    tmp_val = self.search_root(board, stats, depth, alpha, beta, &timer);
                     if tmp_val >= beta {
                         beta = beta.saturating_add(window_size);
                         window_size = window_size.saturating_mul(2);
@@ -138,6 +1280,7 @@ impl Searcher {
                     } else {
                         break;
                     }
+                    stats.aspiration_researches += 1;
                 }
                 tmp_val
             };
@@ -151,148 +1294,983 @@ impl Searcher {
             stats.depth = i;
             best_move = self.best_move;
             best_value = val;
+            timer.record_iteration(best_move, best_value);
+
+            // MultiPV
+            // Further lines are found by searching the root again without the first moves of the
+            // lines found so far. These searches use a full window since there is no previous
+            // score to center one on.
+            let mut lines = vec![(self.root_line(board, best_move, depth), best_value)];
+            self.root_pv.clone_from(&lines[0].0);
+            self.root_pv_hash = board.hash();
+            while lines.len() < self.multi_pv {
+                self.root_excluded = lines.iter().map(|(pv, _)| pv[0]).collect();
+                let value = self.search_root(board, stats, depth, -SCORE_INF, SCORE_INF, &timer);
+                if self.stop_search || self.best_move == NULL_MOVE {
+                    break;
+                }
+                lines.push((self.root_line(board, self.best_move, depth), value));
+            }
+            self.root_excluded.clear();
+            stats.prev_iteration_nodes = stats.last_iteration_nodes;
+            stats.last_iteration_nodes = stats.nodes_visited - iteration_start;
+
+            if self.reporting {
+                self.report_lines(board, i, &lines, stats, &timer);
+                if self.show_refutations {
+                    self.report_refutations(board, best_move);
+                }
+                self.last_report = self.report_elapsed(timer, stats.nodes_visited);
+                if self.debug {
+                    report_debug_stats(stats);
+                }
+            }
+
+            if let Some(path) = &self.checkpoint_path {
+                let checkpoint = self.checkpoint(board, i, best_move, best_value);
+                if let Err(e) = checkpoint.save(path) {
+                    eprintln!("could not save checkpoint to {}: {e}", path.display());
+                }
+            }
+
+            if self
+                .mate_target
+                .is_some_and(|moves| is_mate_within(best_value, moves))
+            {
+                break;
+            }
+            if timer.soft_time_up() {
+                break;
+            }
+        }
+
+        // A search stopped during depth 1 may still have found a better move than the first one
+        if stats.depth == 0 && self.best_move != NULL_MOVE {
+            best_move = self.best_move;
+        }
+
+        if let Some(limit) = self.elo_limit {
+            if stats.depth > 0 && !self.stop_requested() {
+                best_move = self.pick_weakened_move(board, best_move, stats.depth, limit);
+            }
+        } else if !self.skill_level.is_full_strength()
+            && stats.depth > 1
+            && !self.stop_requested()
+            && self.rng.next_f64() < self.skill_level.mistake_chance()
+        {
+            let margin = self.skill_level.mistake_margin();
+            best_move = self.pick_varied_move(board, best_move, best_value, stats.depth, margin);
+        } else if self.variety > 0 && stats.depth > 1 && !self.stop_requested() {
+            best_move =
+                self.pick_varied_move(board, best_move, best_value, stats.depth, self.variety);
         }
 
+        self.root_filter.clear();
+        self.root_avoid.clear();
         (best_move, best_value)
     }
 
-    fn search_reset(&mut self, board: &mut Board, moves: &Vec<Move>) {
+    // Root Move Filtering
+    // `searchmoves` and avoided moves come in UCI notation and may not be legal in the position.
+    // Avoided moves are folded into the filter, so that everything deciding what to play at the
+    // root, from the tablebases to the MCTS backend, only has to look at one list. Avoiding every
+    // legal move would leave nothing to play, and is ignored instead.
+    fn prepare_root_filter(&mut self, board: &Board) {
+        for mv in self.root_filter.iter_mut().chain(&mut self.root_avoid) {
+            uci_to_kxr_move(board, mv, self.chess960);
+        }
+        self.root_filter.retain(|&mv| board.is_legal(mv));
+        if self.root_avoid.is_empty() {
+            return;
+        }
+
+        let mut allowed = if self.root_filter.is_empty() {
+            let mut legal = Vec::new();
+            board.generate_moves(|moves| {
+                legal.extend(moves);
+                false
+            });
+            legal
+        } else {
+            self.root_filter.clone()
+        };
+        allowed.retain(|mv| !self.root_avoid.contains(mv));
+        if !allowed.is_empty() {
+            self.root_filter = allowed;
+        }
+    }
+
+    // Search time for UCI output, which in deterministic mode comes from the node count instead
+    // of the clock
+    fn report_elapsed(&self, timer: &TimeControl, nodes: u64) -> Duration {
+        if self.deterministic {
+            Duration::from_micros(nodes.saturating_mul(1_000_000) / DETERMINISTIC_NPS)
+        } else {
+            timer.elapsed()
+        }
+    }
+
+    // Prints a line without PV when an iteration has been running for a while, so that GUIs can
+    // show the search is still going
+    fn report_progress(&mut self, stats: &SearchStats, timer: &TimeControl) {
+        let elapsed = self.report_elapsed(timer, stats.nodes_visited);
+        if elapsed < self.last_report + PROGRESS_INTERVAL {
+            return;
+        }
+        self.last_report = elapsed;
+        let time = elapsed.as_millis();
+        let nodes = stats.nodes_visited;
+        uci_println!(
+            "info depth {} seldepth {} nodes {nodes} nps {} hashfull {} time {time}",
+            self.iteration_depth,
+            stats.seldepth,
+            nps(nodes, elapsed),
+            self.tt.hashfull(),
+        );
+        if self.show_currline {
+            let moves = self.stack.line(usize::from(self.stack.ply()));
+            let line = uci_line(&self.root_board, &moves, self.chess960)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(" ");
+            uci_println!("info currline {line}");
+        }
+    }
+
+    // For every root move other than the best one, prints the reply that shows why it's worse
+    fn report_refutations(&self, board: &Board, best_move: Move) {
+        for &RootMove { mv, .. } in &self.root_moves {
+            if mv == best_move {
+                continue;
+            }
+            let mut child = board.clone();
+            child.play_unchecked(mv);
+            let mut line = vec![mv];
+            line.extend(self.principal_variation(&child, REFUTATION_LEN));
+            if line.len() < 2 {
+                continue;
+            }
+            let line = uci_line(board, &line, self.chess960)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(" ");
+            uci_println!("info refutation {line}");
+        }
+    }
+
+    // Prints one UCI info line per PV line after a completed iteration
+    fn report_lines(
+        &self,
+        board: &Board,
+        depth: Depth,
+        lines: &[(Vec<Move>, Value)],
+        stats: &SearchStats,
+        timer: &TimeControl,
+    ) {
+        let elapsed = self.report_elapsed(timer, stats.nodes_visited);
+        let time = elapsed.as_millis();
+        let nodes = stats.nodes_visited;
+        let nps = nps(nodes, elapsed);
+        let hashfull = self.tt.hashfull();
+        for (idx, (pv, value)) in lines.iter().enumerate() {
+            let value = *value;
+            let pv = uci_line(board, pv, self.chess960)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(" ");
+            let score = if self.show_wdl {
+                format!("{} {}", uci_score(value), uci_wdl(value))
+            } else {
+                uci_score(value)
+            };
+            uci_println!(
+                "info depth {depth} seldepth {} multipv {} score {score} nodes {nodes} nps {nps} hashfull {hashfull} tbhits {} time {time} pv {pv}",
+                stats.seldepth,
+                idx + 1,
+                stats.tb_hits,
+            );
+        }
+    }
+
+    // Tablebase Root Probing
+    // With few enough pieces left, the tablebases know the outcome of every root move. The search
+    // is then restricted to the moves keeping the best outcome, and only has to choose among them.
+    fn probe_root_tablebase(&mut self, board: &Board, stats: &mut SearchStats) {
+        let Some(tablebase) = &self.tablebase else {
+            return;
+        };
+        let pieces = board.occupied().len() as usize;
+        if pieces > self.tb_probe_limit.min(tablebase.max_pieces()) {
+            return;
+        }
+        let Some(probe) = tablebase.probe_root(board) else {
+            return;
+        };
+        stats.tb_hits += 1;
+
+        let mut allowed = probe.moves;
+        if !self.root_filter.is_empty() {
+            allowed.retain(|mv| self.root_filter.contains(mv));
+        }
+        if allowed.is_empty() {
+            return;
+        }
+        self.root_filter = allowed;
+        if self.reporting {
+            uci_println!(
+                "info string tablebase {} dtz {}",
+                probe.wdl.name(),
+                probe.dtz
+            );
+        }
+    }
+
+    // Bonus of a root move for Root Jitter, between -root_jitter and root_jitter
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    fn jitter_of(&self, key: u64, mv: Move) -> Value {
+        let promotion = mv.promotion.map_or(0, |piece| piece as u64 + 1);
+        let move_key = (mv.from as u64) | (mv.to as u64) << 6 | promotion << 12;
+        let span = 2 * self.root_jitter.unsigned_abs() as usize + 1;
+        Rng::new(key ^ move_key).below(span) as Value - self.root_jitter
+    }
+
+    // Root Move Randomization
+    // Picks uniformly among root moves scoring within `margin` centipawns of the best one, so that
+    // games don't repeat move for move, and for the mistakes of lower skill levels. Each
+    // alternative is checked with a null-window search around the acceptance threshold, which is
+    // cheap since the TT is already warm.
+    fn pick_varied_move(
+        &mut self,
+        board: &Board,
+        best_move: Move,
+        best_value: Value,
+        depth: Depth,
+        margin: Value,
+    ) -> Move {
+        if is_mate_score(best_value) {
+            return best_move;
+        }
+
+        // The main search may have ended by running out of time
         self.stop_search = false;
-        self.history.clear();
-        self.killers.fill(None);
+        let threshold = best_value.saturating_sub(margin);
+        let timer = TimeControl::new(Duration::MAX);
+        let mut stats = SearchStats::default();
+        let mut candidates = vec![best_move];
+
+        let mut root_moves = Vec::new();
+        board.generate_moves(|moves| {
+            root_moves.extend(moves);
+            false
+        });
+
+        self.stack.push(board.hash());
+        for mv in root_moves {
+            if mv == best_move || !self.is_searched_root_move(mv) {
+                continue;
+            }
+            let mut move_board = board.clone();
+            move_board.play(mv);
+            self.stack[usize::from(self.stack.ply())].game_phase =
+                evaluate::game_phase(&move_board);
+            let value = -self.search_internal::<false>(
+                &move_board,
+                &mut stats,
+                depth - 1,
+                -threshold,
+                -threshold + 1,
+                &timer,
+            );
+            if value >= threshold {
+                candidates.push(mv);
+            }
+        }
+        self.stack.pop();
+
+        candidates[self.rng.below(candidates.len())]
+    }
+
+    // Strength Limiting
+    // Every root move gets a full-window score from a shallow search, and the move played is drawn
+    // with probability falling exponentially with how far it is behind the best one. Weak levels
+    // thus mostly play reasonable moves, but now and then a clear mistake.
+    fn pick_weakened_move(
+        &mut self,
+        board: &Board,
+        best_move: Move,
+        depth: Depth,
+        limit: EloLimit,
+    ) -> Move {
+        let scored = self
+            .rescore_root_moves(board, (depth - 1).min(WEAKENED_RESCORE_DEPTH))
+            .into_iter()
+            .map(|(mv, value)| (mv, f64::from(value)))
+            .collect::<Vec<_>>();
+
+        let best = scored
+            .iter()
+            .map(|&(_, value)| value)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let weights = scored
+            .iter()
+            .map(|&(mv, value)| (mv, ((value - best) / limit.temperature()).exp()))
+            .collect::<Vec<_>>();
+        let mut pick = self.rng.next_f64() * weights.iter().map(|&(_, w)| w).sum::<f64>();
+        for (mv, weight) in weights {
+            if pick < weight {
+                return mv;
+            }
+            pick -= weight;
+        }
+        best_move
+    }
+
+    // Full-window scores of the root moves from searches of `depth` after each of them
+    fn rescore_root_moves(&mut self, board: &Board, depth: Depth) -> Vec<(Move, Value)> {
+        // The node cap is for the main search, these searches are part of choosing the move
+        let node_limit = std::mem::replace(&mut self.node_limit, u64::MAX);
+        self.stop_search = false;
+        let timer = TimeControl::new(Duration::MAX);
+        let mut stats = SearchStats::default();
+
+        let mut root_moves = Vec::new();
+        board.generate_moves(|moves| {
+            root_moves.extend(moves);
+            false
+        });
+
+        let mut scored = Vec::new();
+        self.stack.push(board.hash());
+        for mv in root_moves {
+            if !self.is_searched_root_move(mv) {
+                continue;
+            }
+            let mut move_board = board.clone();
+            move_board.play(mv);
+            self.stack[usize::from(self.stack.ply())].game_phase =
+                evaluate::game_phase(&move_board);
+            let value = -self.search_internal::<true>(
+                &move_board,
+                &mut stats,
+                depth,
+                -SCORE_INF,
+                SCORE_INF,
+                &timer,
+            );
+            scored.push((mv, value));
+        }
+        self.stack.pop();
+        self.node_limit = node_limit;
+        scored
+    }
 
-        self.board_history.clear();
-        self.board_history.push(board.hash());
+    fn search_reset(&mut self, board: &mut Board, moves: &Vec<Move>) {
+        self.stop_search = false;
+        // Move ordering learned in the previous search mostly still holds a move later, so it is
+        // only aged, and forgotten in `new_game`
+        self.history.normalize();
+        self.stack.age();
 
         // Board history keeps track of past Zobrist hashes, which is used for repetition draw
         // checks
+        let mut board_history = Vec::with_capacity(moves.len() + 1);
+        board_history.push(board.hash());
         for &mv in moves {
             let mut mv = mv;
-            uci_to_kxr_move(board, &mut mv);
+            uci_to_kxr_move(board, &mut mv, self.chess960);
             board.play_unchecked(mv);
-            self.board_history.push(board.hash());
+            board_history.push(board.hash());
         }
-        self.board_history.pop();
+        board_history.pop();
+        self.stack.set_root(&board_history);
+
+        self.root_moves.clear();
+        self.best_move = NULL_MOVE;
+    }
+
+    // Lists the root moves to search, in the usual move order for the first iteration. Only the
+    // moves allowed by `go searchmoves` and the tablebases are kept.
+    fn init_root_moves(&mut self, board: &Board) {
+        let tt_move = self
+            .tt
+            .get(board.hash())
+            .map_or(NULL_MOVE, |tte| tte.best_move);
+        // The move that led to the root isn't known, and neither are the threats it made
+        let killers = self.stack[0].killers;
+        self.root_moves =
+            MovesIterator::with_all_moves(board, tt_move, killers, &self.history, BitBoard::EMPTY)
+                .map(|(mv, _)| mv)
+                .filter(|&mv| self.is_searched_root_move(mv))
+                .map(|mv| RootMove {
+                    mv,
+                    score: -SCORE_INF,
+                    nodes: 0,
+                    pv: vec![mv],
+                })
+                .collect();
+    }
 
+    // Root Search
+    // The root differs enough from other nodes to have its own routine. It keeps an explicit list
+    // of root moves, ordered for every search by the size of their subtrees in the previous one
+    // after the best move, since a move that took many nodes to refute is the most likely to
+    // become the new best move. Each move's score, node count and line are recorded for MultiPV,
+    // refutations and move selection. Root moves are never pruned or reduced, and the moves of
+    // MultiPV lines found so far are skipped.
+    fn search_root(
+        &mut self,
+        board: &Board,
+        stats: &mut SearchStats,
+        depth: Depth,
+        mut alpha: Value,
+        beta: Value,
+        timer: &TimeControl,
+    ) -> Value {
+        self.pv.clear(0);
+        stats.nodes_visited += 1;
         self.best_move = NULL_MOVE;
-        self.ply = 0;
+
+        let in_check = !board.checkers().is_empty();
+        if self.root_moves.is_empty() {
+            return if in_check {
+                -MATE_VALUE
+            } else {
+                self.draw_value()
+            };
+        }
+
+        let alpha_orig = alpha;
+        let board_hash = board.hash();
+        let tt_move = self.tt.get(board_hash).map_or(NULL_MOVE, |tte| tte.best_move);
+        self.stack[0].game_phase = evaluate::game_phase(board);
+        let raw_eval = self
+            .node_eval(board, 0, self.stack[0].game_phase, -SCORE_INF, SCORE_INF)
+            .value;
+        self.stack[0].static_eval = if in_check {
+            NO_EVAL
+        } else {
+            self.correction.correct(board, raw_eval)
+        };
+        // The sort is stable, so moves without a node count keep their order from the first
+        // iteration
+        self.root_moves
+            .sort_by_key(|root_move| (root_move.mv != tt_move, Reverse(root_move.nodes)));
+        // Searches without some root moves for MultiPV would leave them without a count
+        let count_nodes = self.root_excluded.is_empty();
+
+        let mut best_value = -SCORE_INF;
+        let mut best_move = NULL_MOVE;
+        // Scores of the best move and of the highest scoring one before Root Jitter
+        let mut best_raw = -SCORE_INF;
+        let mut max_raw = -SCORE_INF;
+        let mut quiets_tried = ArrayVec::<Move, MAX_QUIETS_TRIED>::new();
+        let enemy = board.colors(!board.side_to_move());
+        self.stack.push(board_hash);
+
+        let mut move_num = 0;
+        for idx in 0..self.root_moves.len() {
+            let mv = self.root_moves[idx].mv;
+            if self.root_excluded.contains(&mv) {
+                continue;
+            }
+            let iscapture = enemy.has(mv.to);
+            if self.reporting && self.report_elapsed(timer, stats.nodes_visited) > CURRMOVE_DELAY {
+                uci_println!(
+                    "info currmove {} currmovenumber {}",
+                    to_uci_move(board, mv, self.chess960),
+                    move_num + 1
+                );
+            }
+
+            let mut move_board = board.clone();
+            move_board.play(mv);
+            self.stack[1].game_phase =
+                self.stack[0].game_phase + evaluate::game_phase_change(board, mv);
+            self.stack[0].current_move = Some(mv);
+            self.stack[0].capture = iscapture;
+            self.stack[0].extension_units = 0;
+            self.stack[0].extensions = 0;
+
+            // Root Jitter
+            // Early in a game, root moves may get a small random bonus, fixed for the whole
+            // search, so that games from the same position don't all follow the same opening.
+            // The move is searched with the window shifted down by its bonus, so that its score
+            // with the bonus is bounded by alpha and beta like any other. Mates are left alone.
+            let jitter = self.jitter_key.map_or(0, |key| self.jitter_of(key, mv));
+            let shift = |bound: Value| bound.saturating_sub(jitter).clamp(-SCORE_INF, SCORE_INF);
+            let (move_alpha, move_beta) = (shift(alpha), shift(beta));
+
+            // The first move gets the full window and the others a null window, see PVS
+            let nodes_before = stats.nodes_visited;
+            let raw_value = if move_num == 0 {
+                -self.search_internal::<true>(
+                    &move_board,
+                    stats,
+                    depth - 1,
+                    -move_beta,
+                    -move_alpha,
+                    timer,
+                )
+            } else {
+                let tmp_value = -self.search_internal::<false>(
+                    &move_board,
+                    stats,
+                    depth - 1,
+                    -move_alpha - 1,
+                    -move_alpha,
+                    timer,
+                );
+                if move_alpha < tmp_value && tmp_value < move_beta {
+                    stats.pvs_researches += 1;
+                    -self.search_internal::<true>(
+                        &move_board,
+                        stats,
+                        depth - 1,
+                        -move_beta,
+                        -move_alpha,
+                        timer,
+                    )
+                } else {
+                    tmp_value
+                }
+            };
+            move_num += 1;
+            if self.stop_search {
+                break;
+            }
+            let cur_value = if is_mate_score(raw_value) {
+                raw_value
+            } else {
+                raw_value.saturating_add(jitter)
+            };
+
+            let root_move = &mut self.root_moves[idx];
+            root_move.score = cur_value;
+            if count_nodes {
+                root_move.nodes = stats.nodes_visited - nodes_before;
+            }
+            if cur_value > best_value {
+                best_value = cur_value;
+                best_move = mv;
+                best_raw = raw_value;
+            }
+            max_raw = max_raw.max(raw_value);
+            if cur_value > alpha {
+                self.pv.update(0, mv);
+                self.root_moves[idx].pv = self.pv.line(0).to_vec();
+            }
+            alpha = alpha.max(best_value);
+
+            if alpha >= beta {
+                stats.beta_cutoffs += 1;
+                stats.first_move_cutoffs += u64::from(move_num == 1);
+                // Killer and History Heuristics, as in `search_internal`
+                if !iscapture {
+                    let killers = &mut self.stack[0].killers;
+                    if killers[0] != Some(mv) {
+                        killers[1] = killers[0];
+                        killers[0] = Some(mv);
+                    }
+                    self.history.update(board, mv, depth);
+                    for &quiet in &quiets_tried {
+                        self.history.penalize(board, quiet, depth);
+                    }
+                }
+                break;
+            }
+            if !iscapture {
+                let _ = quiets_tried.try_push(mv);
+            }
+        }
+
+        self.stack.pop();
+        self.best_move = best_move;
+        self.trace_node(TraceRecord {
+            hash: board_hash,
+            ply: 0,
+            depth,
+            alpha: alpha_orig,
+            beta,
+            score: best_value,
+            best_move: self.pv.line(0).first().copied().unwrap_or(NULL_MOVE),
+            reason: if self.stop_search {
+                TraceReason::Stopped
+            } else {
+                TraceReason::Searched
+            },
+        });
+        if best_move == NULL_MOVE {
+            return best_value;
+        }
+
+        // Store TT entry, unless some root moves were left out for MultiPV. The TT only gets
+        // scores without Root Jitter: the best move's is then a lower bound of the root's, and
+        // when every move failed low, the highest is an upper bound.
+        if self.root_excluded.is_empty() && !self.stop_search {
+            let (value, node_type) = if best_value <= alpha_orig {
+                (max_raw, NodeType::UpperBound)
+            } else if best_value >= beta || self.jitter_key.is_some() {
+                (best_raw, NodeType::LowerBound)
+            } else {
+                (best_raw, NodeType::Exact)
+            };
+            self.tt.set(
+                board_hash,
+                TTEntry {
+                    hash: board_hash,
+                    best_move,
+                    best_value: value_to_tt(value, 0),
+                    static_eval: raw_eval,
+                    depth,
+                    node_type,
+                },
+            );
+        }
+
+        best_value
+    }
+
+    // Writes a node to the trace file. Tracing stops at the first write error rather than
+    // failing the search.
+    fn trace_node(&mut self, record: TraceRecord) {
+        if let Some(tracer) = &mut self.tracer {
+            if let Err(e) = tracer.record(&record) {
+                uci_println!("info string could not write search trace: {e}");
+                self.tracer = None;
+            }
+        }
+    }
+
+    // PV nodes are those searched with a full window, on the line the search expects to play. The
+    // compiler generates the two kinds of nodes separately, so that the checks for them are free.
+    fn search_internal<const PV: bool>(
+        &mut self,
+        board: &Board,
+        stats: &mut SearchStats,
+        depth: Depth,
+        alpha: Value,
+        beta: Value,
+        timer: &TimeControl,
+    ) -> Value {
+        if self.tracer.is_none() {
+            return self.search_node::<PV>(board, stats, depth, alpha, beta, timer);
+        }
+        // A node that didn't set a reason searched its moves. The reasons its children set are
+        // taken by their own records, see `trace_node`.
+        self.trace_reason = None;
+        let ply = self.stack.ply();
+        let score = self.search_node::<PV>(board, stats, depth, alpha, beta, timer);
+        self.trace_node(TraceRecord {
+            hash: board.hash(),
+            ply,
+            depth,
+            alpha,
+            beta,
+            score,
+            best_move: self.pv.line(usize::from(ply)).first().copied().unwrap_or(NULL_MOVE),
+            reason: self.trace_reason.take().unwrap_or(TraceReason::Searched),
+        });
+        score
     }
 
-    fn search_internal(
+    fn search_node<const PV: bool>(
         &mut self,
         board: &Board,
         stats: &mut SearchStats,
         depth: Depth,
         mut alpha: Value,
-        mut beta: Value,
+        beta: Value,
         timer: &TimeControl,
     ) -> Value {
+        // Taken first, so that it can't leak to another node on an early return
+        let excluded = self.stack[usize::from(self.stack.ply())]
+            .excluded_move
+            .take();
+        self.pv.clear(usize::from(self.stack.ply()));
         stats.nodes_visited += 1;
+        stats.seldepth = stats.seldepth.max(self.stack.ply());
 
         // If the search has timed out, ensure everyone knows about it and stop
         // searching
-        if self.stop_search || stats.nodes_visited % 1024 == 0 && timer.time_up() {
+        if self.stop_search
+            || self.stop_requested()
+            || stats.nodes_visited >= self.node_limit
+            || stats.nodes_visited % 1024 == 0 && timer.time_up()
+        {
             self.stop_search = true;
+            self.trace_reason = Some(TraceReason::Stopped);
             return 0;
         }
+        if self.reporting && stats.nodes_visited % 1024 == 0 {
+            self.report_progress(stats, timer);
+        }
+
+        // Upcoming Repetition
+        // If the side to move can go back to a position of the line with its next move, it can
+        // at least draw by repeating, so the node is worth no less than a draw. This finds
+        // perpetuals and fortresses a ply before the repetition is on the board.
+        let draw_value = self.draw_value();
+        if self.stack.ply() > 0
+            && alpha < draw_value
+            && self.cuckoo.has_upcoming_repetition(
+                board,
+                self.stack.hashes(),
+                usize::from(self.stack.ply()),
+            )
+        {
+            alpha = draw_value;
+            if alpha >= beta {
+                self.trace_reason = Some(TraceReason::Repetition);
+                return alpha;
+            }
+        }
 
         let alpha_orig = alpha;
         let board_hash = board.hash();
-        // PV nodes are not searched with a null window
-        // TODO: Consider making this a const generic
-        let is_pv_node = beta > alpha + 1;
+        let is_pv_node = PV;
+        let is_root = self.stack.ply() == 0;
 
         // Draw Detection
         // If the engine can detect repetition draws, it can force a draw from a losing position
         // and avoid draws from winning positions.
         if self.is_repetition_draw(board.halfmove_clock() as usize, board_hash) {
-            return 0;
+            self.trace_reason = Some(TraceReason::Repetition);
+            return self.draw_value();
         }
 
         // Transposition Table
         // Uses Zobrist hashes to store the results of past searches from the same position.
         // This allows us to save considerable work.
-        let tt_res = self.tt.get(board_hash);
+        let tt_res = self.tt.get(board_hash).map(|tte| TTEntry {
+            best_value: value_from_tt(tte.best_value, self.stack.ply()),
+            ..tte
+        });
+        stats.tt_probes += 1;
+        stats.tt_hits += u64::from(tt_res.is_some());
         let mut tt_move = NULL_MOVE;
-        let static_eval;
+        // Static eval before correction, stored in the TT along with the search score
+        let mut raw_eval = NO_EVAL;
 
         if let Some(tte) = tt_res {
             // Don't use TT at the root, and don't use it if it wasn't searched deeper than
             // we'll search this position.
-            if self.ply > 0 && tte.depth >= depth {
-                match tte.node_type {
+            // Nor when testing a move for singularity, since this node is searched without it.
+            // Entries of depth 0 come from quiescence searches without quiet checks, which
+            // aren't as deep as the quiescence search done here.
+            // The search is fail-soft, so bounds are often well outside the window they were
+            // found with, and cut off without touching the window of this node.
+            // PV nodes are always searched, so that the principal variation doesn't end at a TT
+            // hit and its scores are exact rather than bounds from other windows. The entry still
+            // gives them a move to try first and a static eval.
+            if !is_pv_node
+                && self.stack.ply() > 0
+                && tte.depth >= depth.max(1)
+                && excluded.is_none()
+                && self.heuristics.is_enabled(Heuristic::TtCutoffs)
+            {
+                let cutoff = match tte.node_type {
                     // If the node obtained an exact value for this position, just use it
-                    NodeType::Exact => {
-                        return tte.best_value;
-                    }
-                    // If the node obtained a lower bound on the value, use that to update ours
-                    NodeType::LowerBound => {
-                        alpha = alpha.max(tte.best_value);
-                    }
-                    // Similarly for upper bound
-                    NodeType::UpperBound => {
-                        beta = beta.min(tte.best_value);
-                    }
-                }
-                // In case updating the bounds causes a cutoff
-                if alpha >= beta {
+                    NodeType::Exact => true,
+                    // A lower bound at least beta fails high, an upper bound at most alpha low
+                    NodeType::LowerBound => tte.best_value >= beta,
+                    NodeType::UpperBound => tte.best_value <= alpha,
+                };
+                if cutoff {
+                    self.trace_reason = Some(TraceReason::TtCutoff);
                     return tte.best_value;
                 }
             }
 
-            tt_move = tte.best_move;
-            static_eval = tte.best_value;
-        } else {
-            static_eval = evaluate::evaluate(board);
+            // On a hash collision, the move belongs to another position. Entries without a move,
+            // e.g. from tablebase probes, are left out the same way.
+            if board.is_legal(tte.best_move) {
+                tt_move = tte.best_move;
+            }
+            raw_eval = tte.static_eval;
+        }
+        // Pruning, the TT and correction history all need the whole eval, so it is never cut
+        // short here. Lazy evals of quiescence search aren't stored, see `qsearch`.
+        if raw_eval == NO_EVAL {
+            let game_phase = self.stack[usize::from(self.stack.ply())].game_phase;
+            raw_eval = self
+                .node_eval(board, self.stack.ply(), game_phase, -SCORE_INF, SCORE_INF)
+                .value;
         }
+        let static_eval = self.correction.correct(board, raw_eval);
 
         if board.status() == GameStatus::Won {
             // If the board is in mate, the current side to move has lost
             // MATE_VALUE is unreachable except for mate
             // Subtracting the ply makes the engine look for faster mates
-            return -(MATE_VALUE - Value::from(self.ply));
+            self.trace_reason = Some(TraceReason::GameOver);
+            return -(MATE_VALUE - Value::from(self.stack.ply()));
         } else if board.status() == GameStatus::Drawn {
             // If the board is drawn (stalemate or 50-move rule)
-            return 0;
+            self.trace_reason = Some(TraceReason::GameOver);
+            return self.draw_value();
+        } else if evaluate::is_insufficient_material(board) {
+            // Neither side can mate, so searching on would only chase meaningless eval bonuses
+            self.trace_reason = Some(TraceReason::GameOver);
+            return self.draw_value();
+        }
+        // TODO: Other more advanced draws? (e.g. specific king-pawn vs king setups)
+
+        // Tablebase Probing
+        // Below the root, positions with few enough pieces are looked up in the WDL tables. The
+        // result is exact for draws, and a bound for wins and losses since a mate may be found
+        // that is better still. Whenever it settles the node, the node is stored in the TT like
+        // a deep search and left.
+        if self.stack.ply() > 0 && excluded.is_none() {
+            if let Some((value, node_type)) = self.probe_tablebase(board, depth) {
+                stats.tb_hits += 1;
+                let cutoff = match node_type {
+                    NodeType::Exact => true,
+                    NodeType::LowerBound => value >= beta,
+                    NodeType::UpperBound => value <= alpha,
+                };
+                if cutoff {
+                    self.tt.set(
+                        board_hash,
+                        TTEntry {
+                            hash: board_hash,
+                            best_move: NULL_MOVE,
+                            best_value: value_to_tt(value, self.stack.ply()),
+                            static_eval: raw_eval,
+                            depth: depth.saturating_add(TB_DEPTH_BONUS),
+                            node_type,
+                        },
+                    );
+                    self.trace_reason = Some(TraceReason::Tablebase);
+                    return value;
+                }
+            }
         }
-        // TODO: Insufficient material draw detection? Other more advanced draws?
-        // (e.g. specific king-pawn vs king setups)
+
+        // Improving
+        // Compares the static eval with the one two plies ago, when the same side was to move.
+        // If our position is getting better, the eval is trusted more: pruning based on it
+        // kicks in sooner and late moves are reduced less. Nodes in check have no usable eval.
+        let in_check = !board.checkers().is_empty();
+        let ply = usize::from(self.stack.ply());
+        self.stack[ply].static_eval = if in_check { NO_EVAL } else { static_eval };
+        let improving = !in_check
+            && ply >= 2
+            && self.stack[ply - 2].static_eval != NO_EVAL
+            && static_eval > self.stack[ply - 2].static_eval;
+
+        // Internal Iterative Reductions (IIR)
+        // Without a TT move, the move ordering here is poor and a full depth search is expensive.
+        // Such nodes are likely not important anyway, or they would have been searched before,
+        // so they are searched a ply shallower. If they turn out to matter, the next iteration
+        // finds the TT move stored by this search.
+        let depth = if tt_move == NULL_MOVE && depth >= IIR_MIN_DEPTH && excluded.is_none() {
+            depth - 1
+        } else {
+            depth
+        };
 
         // If we have reached the limit of the current search, evaluate the position using
         // Quiescence search
         if depth == 0 {
-            return qsearch(board, alpha, beta, timer, stats);
+            self.trace_reason = Some(TraceReason::Quiescence);
+            let game_phase = self.stack[ply].game_phase;
+            return self.qsearch(
+                board,
+                alpha,
+                beta,
+                self.stack.ply(),
+                game_phase,
+                true,
+                timer,
+                stats,
+            );
         }
 
         // Move Ordering
         // If we put moves more likely to cause cutoffs earlier, we avoid having to search useless moves
+        let threatened = if ply > 0 {
+            self.stack[ply - 1]
+                .current_move
+                .map_or(BitBoard::EMPTY, |prev| threatened_pieces(board, prev.to))
+        } else {
+            BitBoard::EMPTY
+        };
         let it = MovesIterator::with_all_moves(
             board,
             tt_move,
-            self.killers[usize::from(depth)],
+            self.stack[ply].killers,
             &self.history,
+            threatened,
         );
+        // Extension Budget
+        // Check, singular, recapture and threat extensions feed on each other, and without a
+        // limit a line full of them would never reach the horizon. Every line may be extended by
+        // one ply for every `EXTENSION_BUDGET_PLIES` of its length, and extensions beyond that
+        // are cut down to what is left of the budget.
+        let extensions_used = if ply > 0 {
+            self.stack[ply - 1].extensions
+        } else {
+            0
+        };
+        let extension_budget = i8::try_from(
+            ((ply + 1) / EXTENSION_BUDGET_PLIES).saturating_sub(usize::from(extensions_used)),
+        )
+        .unwrap_or(i8::MAX);
+        // One-Reply Extension
+        // With a single legal move, the node makes no decision of its own, so the ply it takes is
+        // given back. Such nodes are mostly check evasions, where the tactics are still going on.
+        let single_reply = !is_root && it.num_moves() == 1;
         let mut best_value = -SCORE_INF;
         let mut best_move = NULL_MOVE;
         // Push the current board hash to the stack for draw detection
-        self.push_board_hash(board_hash);
+        self.stack.push(board_hash);
 
-        if !is_pv_node && self.ply > 0 {
+        if !is_pv_node && self.stack.ply() > 0 && excluded.is_none() {
             // Null Move Heuristic (NMH) / Null Move Pruning (NMP)
             // This heuristic assumes that we can always improve our position with a legal move.
             // If we forfeit our right to move and still cause a cutoff, then there's no point searching
             // all moves from this position since they'll be better anyway and we just want a cutoff.
             // This is avoided for PV nodes and if the remaining search is shallow anyway. For PV nodes,
             // we want to calculate the line we will play as far as possible to ensure it is good.
-            if depth >= 3 {
+            // The assumption fails in zugzwang, where every move makes things worse. That is
+            // common when only pawns are left, so NMP is skipped then. It is also skipped when the
+            // static eval is already below beta, since passing is then unlikely to cut off.
+            let stm = board.side_to_move();
+            let has_pieces = board.colors(stm)
+                != board.colored_pieces(stm, Piece::Pawn) | board.colored_pieces(stm, Piece::King);
+            if depth >= self.params.nmp_min_depth
+                && has_pieces
+                && static_eval >= beta
+                && self.heuristics.is_enabled(Heuristic::NullMovePruning)
+            {
                 let null_move = board.null_move();
                 // Null move is not always guaranteed to be legal (King in check)
                 if let Some(move_board) = null_move {
-                    let null_move_value =
-                        -self.search_internal(&move_board, stats, depth - 3, -beta, -beta + 1, timer);
+                    // The child must not take a move of an earlier sibling for the last one made
+                    self.stack[ply].current_move = None;
+                    self.stack[ply].capture = false;
+                    self.stack[ply + 1].game_phase = self.stack[ply].game_phase;
+                    let null_move_value = -self.search_internal::<false>(
+                        &move_board,
+                        stats,
+                        depth.saturating_sub(self.params.nmp_reduction),
+                        -beta,
+                        -beta + 1,
+                        timer,
+                    );
                     if null_move_value >= beta {
-                        self.pop_board_hash();
-                        return null_move_value;
+                        stats.nmp_cutoffs += 1;
+                        self.stack.pop();
+                        // A mate found after passing isn't proven, since passing isn't legal
+                        self.trace_reason = Some(TraceReason::NullMovePruning);
+                        return if is_mate_score(null_move_value) {
+                            beta
+                        } else {
+                            null_move_value
+                        };
                     }
                 }
             }
@@ -304,16 +2282,235 @@ impl Searcher {
             // enough, no decent move will lose hard enough to not cause a cutoff. Thus, we might as well
             // assume a cutoff. Higher depth searches from the same position will fail this check, thus
             // the position will eventually be fully searched.
-            if depth <= 7 && board.checkers().is_empty() && static_eval >= (beta + RFP_EVAL_MARGIN * Value::from(depth)) {
-                self.pop_board_hash();
+            let rfp_depth = depth - u8::from(improving);
+            if depth <= self.params.rfp_max_depth
+                && !in_check
+                && self.heuristics.is_enabled(Heuristic::ReverseFutilityPruning)
+                && static_eval
+                    >= beta.saturating_add(self.params.rfp_margin * Value::from(rfp_depth))
+            {
+                stats.rfp_cutoffs += 1;
+                self.stack.pop();
+                self.trace_reason = Some(TraceReason::ReverseFutilityPruning);
                 return static_eval;
             }
         }
 
-        for (move_num, (mv, iscapture)) in it.enumerate() {
+        // Enhanced Transposition Cutoff (ETC)
+        // Deep nodes are expensive, so before searching any move, the TT is checked for the
+        // positions after each of them. If one of them is already known to be at most -beta for
+        // the opponent from a search deep enough, that move refutes this node, which is cut off
+        // without searching anything. Children that are repetitions on this line are left out,
+        // since their TT scores don't know about the repetition.
+        // It is a TT cutoff too, and is disabled along with them.
+        if !is_pv_node
+            && !is_root
+            && excluded.is_none()
+            && depth >= ETC_MIN_DEPTH
+            && self.heuristics.is_enabled(Heuristic::TtCutoffs)
+        {
+            let mut refutation = None;
+            board.generate_moves(|moves| {
+                for mv in moves {
+                    let mut child = board.clone();
+                    child.play_unchecked(mv);
+                    let child_hash = child.hash();
+                    let Some(tte) = self.tt.get(child_hash) else {
+                        continue;
+                    };
+                    // The board hash of this node is pushed, so the ply is the child's already
+                    let value = -value_from_tt(tte.best_value, self.stack.ply());
+                    if tte.depth >= depth - 1
+                        && tte.node_type != NodeType::LowerBound
+                        && value >= beta
+                        && !self.is_repetition_draw(child.halfmove_clock() as usize, child_hash)
+                    {
+                        refutation = Some((mv, value));
+                        return true;
+                    }
+                }
+                false
+            });
+            if let Some((mv, value)) = refutation {
+                stats.etc_cutoffs += 1;
+                self.stack.pop();
+                self.tt.set(
+                    board_hash,
+                    TTEntry {
+                        hash: board_hash,
+                        best_move: mv,
+                        best_value: value_to_tt(value, self.stack.ply()),
+                        static_eval: raw_eval,
+                        depth,
+                        node_type: NodeType::LowerBound,
+                    },
+                );
+                self.trace_reason = Some(TraceReason::EnhancedTtCutoff);
+                return value;
+            }
+        }
+
+        let mut quiets_tried = ArrayVec::<Move, MAX_QUIETS_TRIED>::new();
+        let mut it = it.filter(|(mv, _)| Some(*mv) != excluded);
+
+        // ABDADA
+        // Moves whose subtree another thread is searching right now are put off until the end of
+        // the move list, by which time the other thread has usually stored its result in the TT.
+        // Threads thus spread over different subtrees instead of all searching the same ones.
+        // The first move is never deferred, since the bounds of the other moves depend on it.
+        let abdada = self.search_mode == SearchMode::Abdada && depth >= ABDADA_MIN_DEPTH;
+        let mut deferred = ArrayVec::<(Move, bool), 218>::new();
+        let mut searching_deferred = false;
+        let mut next_move_num = 0;
+
+        loop {
+            let (mv, iscapture) = if let Some(next) = it.next() {
+                next
+            } else if let Some(next) = deferred.pop_at(0) {
+                searching_deferred = true;
+                next
+            } else {
+                break;
+            };
+            let move_num = next_move_num;
+            next_move_num += 1;
+
+            // Singular Extensions
+            // If the TT move is much better than every other move, the position hinges on it and
+            // it deserves a deeper look. To find out, this node is searched again without the TT
+            // move, at reduced depth and against a bound somewhat below the TT score. If no other
+            // move reaches the bound, the TT move is singular and is extended by one ply, or by
+            // two when the others fall far short. If instead the TT score is above beta, there
+            // are likely other moves that cut off too, so the TT move is searched one ply
+            // shallower (a negative extension).
+            let extension = match tt_res {
+                _ if single_reply => 1,
+                Some(tte)
+                    if move_num == 0
+                        && mv == tt_move
+                        && !is_root
+                        && excluded.is_none()
+                        && extension_budget > 0 =>
+                {
+                    self.singular_extension(board, stats, depth, beta, &tte, is_pv_node, timer)
+                }
+                _ => 0,
+            };
+
+            // SEE Pruning
+            // Close to the horizon, a quiet move that simply hangs material is not worth a
+            // search. The material that may be given up grows with depth, since deeper searches
+            // have more time to show what a sacrifice gets in return. The first move is always
+            // searched, and nothing is pruned in check, where every move may be needed.
+            if move_num > 0
+                && !is_root
+                && depth <= SEE_QUIET_MAX_DEPTH
+                && !iscapture
+                && mv.promotion.is_none()
+                && board.checkers().is_empty()
+                && self.heuristics.is_enabled(Heuristic::MovePruning)
+                && !see_ge(
+                    board,
+                    mv,
+                    -self.params.see_quiet_margin * Value::from(depth) * Value::from(depth),
+                )
+            {
+                continue;
+            }
+
+            // History Pruning
+            // Quiet moves that keep failing to be the best move wherever they are tried have
+            // very negative history scores. Near the horizon they are skipped altogether.
+            if move_num > 0
+                && !is_pv_node
+                && !is_root
+                && depth <= HP_MAX_DEPTH
+                && !iscapture
+                && mv.promotion.is_none()
+                && board.checkers().is_empty()
+                && self.heuristics.is_enabled(Heuristic::MovePruning)
+                && self.history.get(board, mv) < -self.params.hp_margin * i16::from(depth)
+            {
+                continue;
+            }
+
             let mut move_board = board.clone();
             move_board.play(mv);
+            // The hash is updated along with the move, so the child's TT bucket is on its way
+            // while the move is looked at below
+            let child_hash = move_board.hash();
+            self.tt.prefetch(child_hash);
+            self.stack[ply + 1].game_phase =
+                self.stack[ply].game_phase + evaluate::game_phase_change(board, mv);
+
+            // Futility Pruning
+            // The counterpart of RFP for the moves of a node: if even a generous margin on top of
+            // the static eval can't bring a quiet move up to alpha, it is skipped. Moves that give
+            // check are searched anyway, as their effect on the position is hard to foresee.
+            let futility_value = static_eval
+                .saturating_add(self.params.fp_margin * Value::from(depth + u8::from(improving)));
+            if move_num > 0
+                && !is_pv_node
+                && !is_root
+                && depth <= self.params.fp_max_depth
+                && !iscapture
+                && mv.promotion.is_none()
+                && board.checkers().is_empty()
+                && move_board.checkers().is_empty()
+                && futility_value <= alpha
+                && self.heuristics.is_enabled(Heuristic::MovePruning)
+            {
+                // The margin bounds what the move could score, which keeps the returned value
+                // a valid upper bound when the node fails low
+                best_value = best_value.max(futility_value);
+                continue;
+            }
+
+            if abdada && move_num > 0 && !searching_deferred && self.tt.is_busy(child_hash) {
+                // The move takes its number once it is searched
+                next_move_num -= 1;
+                deferred.push((mv, iscapture));
+                continue;
+            }
+
+            self.stack[ply].current_move = Some(mv);
+            self.stack[ply].capture = iscapture;
+
+            // Recapture and Threat Extensions
+            // Recaptures on the square of the last capture and moves attacking a piece worth more
+            // than the moving one are where exchanges get decided, and stopping in the middle of
+            // them misjudges the position. They are extended by a fraction of a ply, which adds up
+            // along the line and becomes a whole ply once there are enough units.
+            let mut extension_units = if ply > 0 {
+                self.stack[ply - 1].extension_units
+            } else {
+                0
+            };
+            if extension == 0 && extension_budget > 0 {
+                let recapture = ply > 0
+                    && iscapture
+                    && self.stack[ply - 1].capture
+                    && self.stack[ply - 1].current_move.is_some_and(|prev| prev.to == mv.to);
+                if recapture {
+                    extension_units += RECAPTURE_EXTENSION_UNITS;
+                }
+                if creates_threat(&move_board, mv.to) {
+                    extension_units += THREAT_EXTENSION_UNITS;
+                }
+            }
+            let extension = if extension_units >= EXTENSION_UNITS_PER_PLY {
+                extension_units -= EXTENSION_UNITS_PER_PLY;
+                extension + 1
+            } else {
+                extension
+            };
+            self.stack[ply].extension_units = extension_units;
+            let extension = extension.min(extension_budget);
+            self.stack[ply].extensions = extensions_used + extension.max(0).unsigned_abs();
 
+            if abdada {
+                self.tt.mark_busy(child_hash);
+            }
             // Principal Value Search (PVS)
             // This heuristic is dependent on having good move ordering. It searches the first move (TT move)
             // fully, assuming that it is likely the best move from this position. In a perfect world, no
@@ -323,7 +2520,26 @@ impl Searcher {
             // is searched again with a full window. If the move ordering is good enough, we won't do many
             // researches and overall reduce the time spent searching.
             let cur_value = if move_num == 0 {
-                -self.search_internal(&move_board, stats, depth - 1, -beta, -alpha, timer)
+                let new_depth = (depth - 1).saturating_add_signed(extension);
+                if PV {
+                    -self.search_internal::<true>(
+                        &move_board,
+                        stats,
+                        new_depth,
+                        -beta,
+                        -alpha,
+                        timer,
+                    )
+                } else {
+                    -self.search_internal::<false>(
+                        &move_board,
+                        stats,
+                        new_depth,
+                        -beta,
+                        -alpha,
+                        timer,
+                    )
+                }
             } else {
                 let mut reduction = 0;
                 // Late Move Reduction (LMR)
@@ -331,55 +2547,113 @@ impl Searcher {
                 // unlikely to be good, it shouldn't be searched for the full depth. We only do this depth
                 // reduction if the remaining depth is above a threshold, after already having searched a
                 // few moves without reduction, and if the move is not a capture, promotion or check.
-                // The amount of reduction is based on a formula precomputed in the lmr_table
+                // The amount of reduction is based on a formula precomputed in the lmr_table, and
+                // adjusted by what else is known about the move and the node: moves with good
+                // history and killers are reduced less, as are moves of PV nodes, while nodes
+                // that aren't improving or are expected to fail high anyway are reduced more.
                 if depth >= LMR_MIN_DEPTH
+                    && self.heuristics.is_enabled(Heuristic::LateMoveReductions)
                     && move_num >= (2 + 2 * usize::from(is_pv_node))
                     && !iscapture
                     && mv.promotion.is_none()
                     && move_board.checkers().is_empty()
                 {
-                    reduction = self.lmr_table.get(depth, move_num);
-                    reduction = reduction.clamp(0, depth - 2);
+                    let cut_node = !is_pv_node && static_eval >= beta;
+                    let killer = self.stack[ply].killers.contains(&Some(mv));
+                    let adjusted = i16::from(self.lmr_table.get(depth, move_num))
+                        + i16::from(!improving)
+                        + i16::from(cut_node)
+                        - i16::from(is_pv_node)
+                        - i16::from(killer)
+                        - self.history.get(board, mv) / self.params.lmr_history_divisor;
+                    reduction = u8::try_from(adjusted.max(0)).unwrap_or(0).min(depth - 2);
                 };
 
-                let new_depth = depth - reduction - 1;
+                let new_depth = (depth - reduction - 1).saturating_add_signed(extension);
                 // Do the null-window search to a reduced depth
-                let tmp_value =
-                    -self.search_internal(&move_board, stats, new_depth, -alpha - 1, -alpha, timer);
-                if alpha < tmp_value && tmp_value < beta {
+                let tmp_value = -self.search_internal::<false>(
+                    &move_board,
+                    stats,
+                    new_depth,
+                    -alpha - 1,
+                    -alpha,
+                    timer,
+                );
+                // Only PV nodes have a window to search the move again with
+                if PV && alpha < tmp_value && tmp_value < beta {
+                    stats.pvs_researches += 1;
                     // Re-search happens at the full depth
-                    -self.search_internal(&move_board, stats, depth - 1, -beta, -alpha, timer)
+                    -self.search_internal::<true>(
+                        &move_board,
+                        stats,
+                        (depth - 1).saturating_add_signed(extension),
+                        -beta,
+                        -alpha,
+                        timer,
+                    )
                 } else {
                     tmp_value
                 }
             };
 
+            if abdada {
+                self.tt.clear_busy(child_hash);
+            }
+
             if cur_value > best_value {
                 best_value = cur_value;
                 best_move = mv;
             }
+            // Lines of a singularity test are not part of the principal variation
+            if cur_value > alpha && excluded.is_none() {
+                self.pv.update(ply, mv);
+            }
 
             alpha = alpha.max(best_value);
 
             if alpha >= beta {
+                stats.beta_cutoffs += 1;
+                stats.first_move_cutoffs += u64::from(move_num == 0);
                 if !iscapture {
                     // Killer Heuristic
                     // We keep track of non-capture moves that caused a cutoff to rank them higher
-                    // in the move ordering, should they be legal again at this depth.
-                    self.killers[usize::from(depth)] = Some(mv);
+                    // in the move ordering, should they be legal again at this ply. Two are kept,
+                    // the newest first, so that one lucky cutoff doesn't push out a reliable one.
+                    let killers = &mut self.stack[ply].killers;
+                    if killers[0] != Some(mv) {
+                        killers[1] = killers[0];
+                        killers[0] = Some(mv);
+                    }
                     // History Heuristic
                     // This argues that board positions don't change very significantly, and if a
                     // move is good now it'll be good later. We maintain a table of values indexed
                     // by which colored piece moved to which square, and use these values to order
-                    // non-capture moves.
+                    // non-capture moves. The quiet moves searched before this one did not cause
+                    // the cutoff, so their scores are lowered.
                     self.history.update(board, mv, depth);
+                    for &quiet in &quiets_tried {
+                        self.history.penalize(board, quiet, depth);
+                    }
                 }
 
                 break;
             }
+            if !iscapture {
+                let _ = quiets_tried.try_push(mv);
+            }
         }
 
-        self.pop_board_hash();
+        self.stack.pop();
+
+        // The result of a singularity test only holds without the excluded move, so it isn't
+        // stored. If the excluded move was the only legal one, nothing reaches the bound.
+        if excluded.is_some() {
+            return if best_move == NULL_MOVE {
+                alpha_orig
+            } else {
+                best_value
+            };
+        }
 
         // Node type to be stored in the TT
         let node_type = if best_value <= alpha_orig {
@@ -390,43 +2664,289 @@ impl Searcher {
             NodeType::Exact
         };
 
-        // Store TT entry
         self.tt.set(
             board_hash,
             TTEntry {
                 hash: board_hash,
                 best_move,
-                best_value,
+                best_value: value_to_tt(best_value, self.stack.ply()),
+                static_eval: raw_eval,
                 depth,
                 node_type,
             },
         );
 
-        // Save best move at root
-        if self.ply == 0 {
-            self.best_move = best_move;
+        // Eval Correction
+        // Quiet positions teach the correction history how far off the static eval was. A bound
+        // only says something when the eval lies on the side of it that the bound rules out.
+        let best_is_quiet = best_move == NULL_MOVE
+            || !board.colors(!board.side_to_move()).has(best_move.to);
+        if !in_check
+            && best_is_quiet
+            && !is_mate_score(best_value)
+            && !(node_type == NodeType::UpperBound && best_value >= raw_eval)
+            && !(node_type == NodeType::LowerBound && best_value <= raw_eval)
+        {
+            self.correction.update(board, depth, raw_eval, best_value);
+        }
+
+        best_value
+    }
+
+    // Quiescence Search (QSearch)
+    // Instead of directly evaluating a position, evaluate it after there are no possible captures
+    // left. This helps combat the horizon effect, where we stop searching thinking we are up
+    // material not realizing that pieces are hanging. To finish faster, this uses alpha-beta
+    // pruning too. In check, the side to move can't simply keep the current evaluation, so every
+    // evasion is searched instead. With `checks`, quiet moves giving check are searched as well,
+    // which is done at the first ply only to find mating attacks just past the horizon without
+    // blowing up the tree.
+    #[allow(clippy::too_many_arguments)]
+    fn qsearch(
+        &self,
+        board: &Board,
+        mut alpha: Value,
+        beta: Value,
+        ply: u8,
+        game_phase: i32,
+        checks: bool,
+        timer: &TimeControl,
+        stats: &mut SearchStats,
+    ) -> Value {
+        stats.nodes_visited += 1;
+        stats.qsearch_nodes += 1;
+        stats.seldepth = stats.seldepth.max(ply);
+        if stats.nodes_visited % 1024 == 0 && timer.time_up() {
+            return 0;
+        }
+        // Long capture chains and strings of checks and evasions could otherwise go on until the
+        // ply no longer fits, so the line is cut off here with whatever the eval says
+        if ply >= QS_MAX_PLY {
+            return self.node_eval(board, ply, game_phase, alpha, beta).value;
+        }
+
+        // The same capture sequences are reached through many move orders, so results are
+        // stored in the TT as well. Any entry searched at least as deep as a quiescence search
+        // is good enough to end the search here. Searches with quiet checks neither use nor
+        // store entries, since they search more than the entries of depth 0 stand for.
+        let board_hash = board.hash();
+        let tt_res = if checks {
+            None
+        } else {
+            self.tt.get(board_hash).map(|tte| TTEntry {
+                best_value: value_from_tt(tte.best_value, ply),
+                ..tte
+            })
+        };
+        stats.tt_probes += u64::from(!checks);
+        stats.tt_hits += u64::from(tt_res.is_some());
+        let mut tt_move = NULL_MOVE;
+        if let Some(tte) = tt_res {
+            let cutoff = match tte.node_type {
+                NodeType::Exact => true,
+                NodeType::LowerBound => tte.best_value >= beta,
+                NodeType::UpperBound => tte.best_value <= alpha,
+            };
+            if cutoff && self.heuristics.is_enabled(Heuristic::TtCutoffs) {
+                return tte.best_value;
+            }
+            if board.is_legal(tte.best_move) {
+                tt_move = tte.best_move;
+            }
+        }
+        // The stand pat may be a lazy eval, which only settles this window, so it is stored as
+        // no eval at all
+        let eval = tt_res.filter(|tte| tte.static_eval != NO_EVAL).map_or_else(
+            || self.node_eval(board, ply, game_phase, alpha, beta),
+            |tte| WindowEval {
+                value: tte.static_eval,
+                lazy: false,
+            },
+        );
+        let static_eval = eval.value;
+
+        let alpha_orig = alpha;
+        let in_check = !board.checkers().is_empty();
+        let mut best_value = if in_check {
+            // Mated unless an evasion is found
+            -(MATE_VALUE - Value::from(ply))
+        } else {
+            // If the evaluation of the current position is enough to cause a cutoff,
+            // do it (all captures). Basically similar to NMP.
+            let stand_pat = static_eval;
+            if stand_pat >= beta {
+                return stand_pat;
+            }
+            alpha = alpha.max(stand_pat);
+            stand_pat
+        };
+        let mut best_move = NULL_MOVE;
+
+        let move_buf = MovesIterator::with_quiescence_moves(board, tt_move, checks);
+        for (mv, _) in move_buf {
+            // SEE Pruning
+            // A capture that loses material once the exchange on its square is played out is
+            // unlikely to raise alpha, and such captures make up most of the nodes in busy
+            // positions. The same goes for checks that hang the checking piece. Evasions are
+            // never pruned, as pruning all of them would make the position look mated.
+            if !in_check && !see_ge(board, mv, QS_SEE_THRESHOLD) {
+                continue;
+            }
+
+            let mut move_board = board.clone();
+            move_board.play(mv);
+
+            let cur_value = -self.qsearch(
+                &move_board,
+                -beta,
+                -alpha,
+                ply.saturating_add(1),
+                game_phase + evaluate::game_phase_change(board, mv),
+                false,
+                timer,
+                stats,
+            );
+
+            if cur_value > best_value {
+                best_value = cur_value;
+                best_move = mv;
+            }
+
+            alpha = alpha.max(cur_value);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        if checks {
+            return best_value;
         }
+        let node_type = if best_value <= alpha_orig {
+            NodeType::UpperBound
+        } else if best_value >= beta {
+            NodeType::LowerBound
+        } else {
+            NodeType::Exact
+        };
+        self.tt.set_shallow(
+            board_hash,
+            TTEntry {
+                hash: board_hash,
+                best_move,
+                best_value: value_to_tt(best_value, ply),
+                static_eval: if eval.lazy { NO_EVAL } else { static_eval },
+                depth: 0,
+                node_type,
+            },
+        );
 
         best_value
     }
 
-    // Check if a position is a draw by repetition
+    // Score and bound of a position from the WDL tables, see Tablebase Probing in
+    // `search_internal`. Positions with as many pieces as the largest tables are only probed
+    // with some depth left, since probing them is the most expensive.
+    fn probe_tablebase(&self, board: &Board, depth: Depth) -> Option<(Value, NodeType)> {
+        let tablebase = self.tablebase.as_ref()?;
+        let limit = self.tb_probe_limit.min(tablebase.max_pieces());
+        let pieces = board.occupied().len() as usize;
+        if pieces > limit || pieces == limit && depth < TB_PROBE_MIN_DEPTH {
+            return None;
+        }
+        let ply = Value::from(self.stack.ply());
+        let result = match tablebase.probe_wdl(board)? {
+            Wdl::Win => (TB_WIN_VALUE - ply, NodeType::LowerBound),
+            Wdl::Loss => (-TB_WIN_VALUE + ply, NodeType::UpperBound),
+            // Drawn under the 50 move rule
+            Wdl::CursedWin | Wdl::Draw | Wdl::BlessedLoss => (self.draw_value(), NodeType::Exact),
+        };
+        Some(result)
+    }
+
+    // Extension of the TT move of the node at `depth`, see Singular Extensions in
+    // `search_internal`. Called from the move loop, after the node's board hash was pushed.
+    #[allow(clippy::too_many_arguments)]
+    fn singular_extension(
+        &mut self,
+        board: &Board,
+        stats: &mut SearchStats,
+        depth: Depth,
+        beta: Value,
+        tte: &TTEntry,
+        is_pv_node: bool,
+        timer: &TimeControl,
+    ) -> i8 {
+        if depth < SE_MIN_DEPTH
+            || tte.depth + SE_TT_DEPTH_MARGIN < depth
+            || tte.node_type == NodeType::UpperBound
+            || is_mate_score(tte.best_value)
+        {
+            return 0;
+        }
+
+        let singular_beta = tte.best_value - self.params.se_margin * Value::from(depth);
+        // The test searches this same node again, so it has to be at this node's ply
+        self.stack.pop();
+        self.stack[usize::from(self.stack.ply())].excluded_move = Some(tte.best_move);
+        let value = self.search_internal::<false>(
+            board,
+            stats,
+            (depth - 1) / 2,
+            singular_beta - 1,
+            singular_beta,
+            timer,
+        );
+        self.stack.push(board.hash());
+
+        if value < singular_beta {
+            // Double extensions are kept out of PV nodes, where they cost the most
+            if !is_pv_node && value < singular_beta - self.params.se_double_margin {
+                2
+            } else {
+                1
+            }
+        } else if tte.best_value >= beta {
+            -1
+        } else {
+            0
+        }
+    }
+
+    // Score of a drawn position from the side to move's perspective. With contempt, the side
+    // that the engine is playing considers a draw slightly worse than equal.
+    const fn draw_value(&self) -> Value {
+        if self.stack.ply() % 2 == 1 {
+            self.contempt
+        } else {
+            -self.contempt
+        }
+    }
+
+    // Check if a position is a draw by repetition. Positions from the game need to have occurred
+    // twice already, but a single earlier occurrence after the root is enough: whichever side
+    // the line favours, the other side could have repeated, so it is a draw either way.
     fn is_repetition_draw(&self, halfmove_count: usize, board_hash: u64) -> bool {
         // Can't be a reptition if the halfmove clock (ply since last capture or pawn move) < 4
         if halfmove_count < 4 {
             return false;
         }
         let mut rep_count = 0;
-        for &hash in self
-            .board_history
+        for (idx, &hash) in self
+            .stack
+            .hashes()
             .iter()
             .rev() // Search hashes from recent to old
+            .enumerate()
             .take(halfmove_count) // Only care about the ones after the last capture/pawn move
             .skip(1) // Skip 1 since the first board hash is of the opposite side to move
             .step_by(2)
         // Only look at hashes when it was our turn to move
         {
             if hash == board_hash {
+                // The position occurred `idx + 1` plies ago
+                if idx + 1 < usize::from(self.stack.ply()) {
+                    return true;
+                }
                 rep_count += 1;
                 if rep_count >= 2 {
                     return true;
@@ -435,72 +2955,280 @@ impl Searcher {
         }
         false
     }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use arrayvec::ArrayVec;
+    use cozy_chess::{Board, GameStatus, Move};
+    use proptest::prelude::*;
+
+    use crate::{
+        evaluate,
+        search::SearchStats,
+        search_params::Heuristic,
+        search_stack::NO_EVAL,
+        strength::{EloLimit, MIN_ELO},
+        tablebase::Tablebase,
+        transposition_table::{value_from_tt, value_to_tt, NodeType, TTEntry},
+        types::{Depth, Value},
+    };
+
+    use super::{
+        is_mate_within, nps, SearchMode, Searcher, TimeControl, MATE_VALUE, QS_MAX_PLY, SCORE_INF,
+        TB_WIN_VALUE,
+    };
+
+    #[test]
+    fn stopped_search_returns_legal_move() {
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut searcher = Searcher::new(1 << 16);
+        searcher.set_stop_flag(Arc::clone(&stop));
+        stop.store(true, Ordering::Relaxed);
+
+        let mut board = Board::startpos();
+        let mut stats = SearchStats::default();
+        let (mv, _) = searcher.search_for_time(&mut board, &Vec::new(), &mut stats, Duration::MAX);
+        assert_eq!(stats.depth, 0);
+        assert!(board.is_legal(mv));
+    }
 
-    fn push_board_hash(&mut self, board_hash: u64) {
-        self.board_history.push(board_hash);
-        self.ply += 1;
+    #[test]
+    fn stats_describe_the_search() {
+        let mut searcher = Searcher::new(1 << 20);
+        let mut stats = SearchStats::default();
+        searcher.search_fixed_depth(&mut Board::startpos(), &Vec::new(), &mut stats, 6);
+        assert!(stats.first_move_cutoffs > 0 && stats.first_move_cutoffs <= stats.beta_cutoffs);
+        assert!(stats.qsearch_nodes > 0 && stats.qsearch_nodes < stats.nodes_visited);
+        assert!(stats.effective_branching_factor().is_some_and(|ebf| ebf > 1.0));
     }
 
-    fn pop_board_hash(&mut self) {
-        self.board_history.pop();
-        self.ply -= 1;
+    #[test]
+    fn deterministic_searches_repeat() {
+        let search = || {
+            let mut searcher = Searcher::new(1 << 20);
+            searcher.set_threads(4);
+            searcher.set_variety(50);
+            searcher.set_deterministic(true);
+            let mut stats = SearchStats::default();
+            let time = Duration::from_millis(20);
+            let result =
+                searcher.search_for_time(&mut Board::startpos(), &Vec::new(), &mut stats, time);
+            (result, stats.nodes_visited)
+        };
+        assert_eq!(search(), search());
     }
-}
 
-// Quiescence Search (QSearch)
-// Instead of directly evaluating a position, evaluate it after there are no possible captures left.
-// This helps combat the horizon effect, where we stop searching thinking we are up material not
-// realizing that pieces are hanging. To finish faster, this uses alpha-beta pruning too.
-fn qsearch(
-    board: &Board,
-    mut alpha: Value,
-    beta: Value,
-    timer: &TimeControl,
-    stats: &mut SearchStats,
-) -> Value {
-    stats.nodes_visited += 1;
-    if stats.nodes_visited % 1024 == 0 && timer.time_up() {
-        return 0;
+    #[test]
+    fn node_limited_search_returns_legal_move() {
+        let mut searcher = Searcher::new(1 << 16);
+        searcher.set_node_limit(Some(1));
+        let mut board = Board::startpos();
+        let mut stats = SearchStats::default();
+        let (mv, _) = searcher.search_for_time(&mut board, &Vec::new(), &mut stats, Duration::MAX);
+        assert_eq!(stats.depth, 0);
+        assert!(board.is_legal(mv));
     }
 
-    // If the evaluation of the current position is enough to cause a cutoff,
-    // do it (all captures). Basically similar to NMP.
-    let stand_pat = evaluate::evaluate(board);
-    if stand_pat >= beta {
-        return stand_pat;
+    #[test]
+    fn avoided_moves_are_not_played() {
+        let mut searcher = Searcher::new(1 << 20);
+        let mut board = Board::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", false).unwrap();
+        let mate: Move = "a1a8".parse().unwrap();
+        let mut search = |searcher: &mut Searcher| {
+            let mut stats = SearchStats::default();
+            let (mv, _) = searcher.search_fixed_depth(&mut board, &Vec::new(), &mut stats, 3);
+            mv
+        };
+        searcher.set_avoid_moves(vec![mate]);
+        assert_ne!(search(&mut searcher), mate);
+        // The list only applies to one search
+        assert_eq!(search(&mut searcher), mate);
     }
-    alpha = alpha.max(stand_pat);
 
-    // Only iterate over captures
-    let move_buf = MovesIterator::with_capture_moves(board);
-    let mut best_value = stand_pat;
-    for (mv, _) in move_buf {
-        let mut move_board = board.clone();
-        move_board.play(mv);
+    #[test]
+    fn illegal_tt_moves_are_not_played() {
+        // As after a hash collision, the entries of the root and of a child hold a move of
+        // another position
+        let mut searcher = Searcher::new(1 << 20);
+        let mut board = Board::startpos();
+        let illegal: Move = "e2e5".parse().unwrap();
+        let mut child = board.clone();
+        child.play("e2e4".parse().unwrap());
+        for position in [&board, &child] {
+            searcher.tt.set(
+                position.hash(),
+                TTEntry {
+                    hash: position.hash(),
+                    best_move: illegal,
+                    best_value: 0,
+                    static_eval: 0,
+                    depth: 1,
+                    node_type: NodeType::LowerBound,
+                },
+            );
+        }
 
-        let cur_value = -qsearch(&move_board, -beta, -alpha, timer, stats);
+        let mut stats = SearchStats::default();
+        let (mv, _) = searcher.search_fixed_depth(&mut board, &Vec::new(), &mut stats, 4);
+        assert_ne!(mv, illegal);
+        assert!(board.is_legal(mv));
+    }
 
-        best_value = best_value.max(cur_value);
+    #[test]
+    fn new_game_only_ages_the_tt() {
+        let search = |searcher: &mut Searcher| {
+            let mut stats = SearchStats::default();
+            searcher.search_fixed_depth(&mut Board::startpos(), &Vec::new(), &mut stats, 5);
+            stats.nodes_visited
+        };
+        let mut searcher = Searcher::new(1 << 20);
+        let fresh = search(&mut searcher);
+        // The TT is only aged, so the same search takes fewer nodes, until it is cleared too
+        searcher.new_game();
+        assert!(search(&mut searcher) < fresh);
+        searcher.new_game();
+        searcher.clear_tt();
+        assert_eq!(search(&mut searcher), fresh);
+    }
 
-        alpha = alpha.max(cur_value);
-        if alpha >= beta {
-            return alpha;
-        }
+    #[test]
+    fn root_moves_keep_scores_and_lines() {
+        let mut searcher = Searcher::new(1 << 20);
+        let mut board = Board::startpos();
+        let mut stats = SearchStats::default();
+        let (mv, value) = searcher.search_fixed_depth(&mut board, &Vec::new(), &mut stats, 5);
+        let root_moves = searcher.root_moves();
+        assert_eq!(root_moves.len(), 20);
+        let best = root_moves.iter().find(|root_move| root_move.mv == mv).unwrap();
+        assert_eq!(best.score, value);
+        assert_eq!(best.pv[0], mv);
+        assert!(root_moves
+            .iter()
+            .all(|root_move| root_move.score <= value && root_move.nodes > 0));
     }
 
-    best_value
-}
+    #[test]
+    fn weakened_search_returns_legal_move() {
+        let mut searcher = Searcher::new(1 << 20);
+        searcher.set_elo_limit(Some(EloLimit::new(MIN_ELO)));
+        let mut board = Board::startpos();
+        let mut stats = SearchStats::default();
+        let (mv, _) = searcher.search_fixed_depth(&mut board, &Vec::new(), &mut stats, 10);
+        assert_eq!(stats.depth, EloLimit::new(MIN_ELO).max_depth());
+        assert!(board.is_legal(mv));
+    }
 
-#[cfg(test)]
-mod test {
-    use std::time::Duration;
+    #[test]
+    fn weakened_root_moves_are_rescored_at_ply_one() {
+        let mut searcher = Searcher::new(1 << 20);
+        // Ra8 mates at once
+        let board = "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1"
+            .parse::<Board>()
+            .unwrap();
+        let mate = "a1a8".parse::<Move>().unwrap();
+        assert!(searcher.rescore_root_moves(&board, 2).contains(&(mate, MATE_VALUE - 1)));
 
-    use arrayvec::ArrayVec;
-    use cozy_chess::{Board, Move};
+        // Qc7 stalemates, a draw the engine plays with contempt for
+        searcher.set_contempt(30);
+        let board = "k7/8/1Q6/8/8/8/8/7K w - - 0 1".parse::<Board>().unwrap();
+        let stalemate = "b6c7".parse::<Move>().unwrap();
+        assert!(searcher.rescore_root_moves(&board, 2).contains(&(stalemate, -30)));
+    }
+
+    #[test]
+    fn parallel_search_returns_legal_move() {
+        let mut searcher = Searcher::new(1 << 20);
+        searcher.set_threads(4);
+        let mut board = Board::startpos();
+        let mut stats = SearchStats::default();
+        let (mv, _) = searcher.search_fixed_depth(&mut board, &Vec::new(), &mut stats, 4);
+        assert_eq!(stats.depth, 4);
+        assert!(board.is_legal(mv));
+    }
+
+    #[test]
+    fn abdada_search_returns_legal_move() {
+        let mut searcher = Searcher::new(1 << 20);
+        searcher.set_threads(4);
+        searcher.set_search_mode(SearchMode::Abdada);
+        let mut board = Board::startpos();
+        let mut stats = SearchStats::default();
+        let (mv, _) = searcher.search_fixed_depth(&mut board, &Vec::new(), &mut stats, 6);
+        assert_eq!(stats.depth, 6);
+        assert!(board.is_legal(mv));
+    }
 
-    use crate::search::SearchStats;
+    #[test]
+    fn stable_best_move_shortens_soft_limit() {
+        let mv = "e2e4".parse().unwrap();
+        let mut timer = TimeControl::new(Duration::from_millis(100))
+            .with_hard_limit(Duration::from_millis(300));
+        timer.record_iteration(mv, 0);
+        assert_eq!(timer.scaled_soft_limit(), Duration::from_millis(250));
+        for _ in 0..5 {
+            timer.record_iteration(mv, 0);
+        }
+        assert_eq!(timer.scaled_soft_limit(), Duration::from_millis(75));
+        timer.record_iteration("d2d4".parse().unwrap(), 0);
+        assert_eq!(timer.scaled_soft_limit(), Duration::from_millis(250));
+
+        // A fixed move time isn't scaled
+        let mut timer = TimeControl::new(Duration::from_millis(100));
+        timer.record_iteration(mv, 0);
+        assert_eq!(timer.scaled_soft_limit(), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn score_drop_extends_soft_limit() {
+        let mv = "e2e4".parse().unwrap();
+        let mut timer = TimeControl::new(Duration::from_millis(100))
+            .with_hard_limit(Duration::from_millis(300));
+        for _ in 0..5 {
+            timer.record_iteration(mv, 50);
+        }
+        assert_eq!(timer.scaled_soft_limit(), Duration::from_millis(75));
+        timer.record_iteration(mv, -50);
+        assert_eq!(timer.scaled_soft_limit(), Duration::from_millis(112));
+        timer.record_iteration(mv, -50);
+        assert_eq!(timer.scaled_soft_limit(), Duration::from_millis(75));
+    }
 
-    use super::Searcher;
+    #[test]
+    fn single_legal_move_is_played_at_once() {
+        // The king has to take the queen
+        let mut board = Board::from_fen("k7/8/8/8/8/8/6q1/7K w - - 0 1", false).unwrap();
+        let mut searcher = Searcher::new(1 << 16);
+        let mut stats = SearchStats::default();
+        let (mv, _) =
+            searcher.search_for_time(&mut board, &Vec::new(), &mut stats, Duration::from_secs(60));
+        assert_eq!(mv, "h1g2".parse().unwrap());
+        assert_eq!(stats.depth, 1);
+    }
+
+    #[test]
+    fn mate_search() {
+        let mut board = Board::from_fen("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", false).unwrap();
+        let mut searcher = Searcher::new(1 << 16);
+        let mut stats = SearchStats::default();
+        let (mv, value) =
+            searcher.search_mate(&mut board, &Vec::new(), &mut stats, 1, Duration::MAX);
+        assert!(is_mate_within(value, 1));
+        assert_eq!(mv, "d1d8".parse().unwrap());
+
+        let mut board = Board::startpos();
+        let (_, value) =
+            searcher.search_mate(&mut board, &Vec::new(), &mut stats, 2, Duration::MAX);
+        assert!(!is_mate_within(value, 2));
+    }
 
     #[test]
     fn repetition_draw_check() {
@@ -528,6 +3256,25 @@ mod test {
         assert_eq!(bv, 0);
     }
 
+    #[test]
+    fn repetition_within_search_is_a_draw() {
+        // The knights return to the position after the root move
+        let moves = ["b1c3", "g8f6", "g1f3", "f6g8", "f3g1"];
+        let mut board = Board::startpos();
+        let mut searcher = Searcher::new(1 << 16);
+        searcher.search_reset(&mut board.clone(), &Vec::new());
+        for mv in moves {
+            searcher.stack.push(board.hash());
+            board.play(mv.parse().unwrap());
+        }
+        assert!(searcher.is_repetition_draw(board.halfmove_clock() as usize, board.hash()));
+
+        // The same moves played in the game before the root are only a first repetition
+        let moves = moves.iter().map(|mv| mv.parse().unwrap()).collect();
+        searcher.search_reset(&mut Board::startpos(), &moves);
+        assert!(!searcher.is_repetition_draw(board.halfmove_clock() as usize, board.hash()));
+    }
+
     #[test]
     fn force_repetition() {
         let mut board =
@@ -541,4 +3288,284 @@ mod test {
         assert!(bm == "g4h4".parse::<Move>().unwrap() || bm == "g4c8".parse::<Move>().unwrap());
         assert_eq!(bv, 0);
     }
+
+    // Plays a random legal line from the start position, each byte choosing one move
+    fn random_position(choices: &[u8]) -> Board {
+        let mut board = Board::startpos();
+        for &choice in choices {
+            let mut legal = Vec::new();
+            board.generate_moves(|moves| {
+                legal.extend(moves);
+                false
+            });
+            if legal.is_empty() {
+                break;
+            }
+            board.play_unchecked(legal[usize::from(choice) % legal.len()]);
+        }
+        board
+    }
+
+    #[test]
+    fn qsearch_does_not_stand_pat_in_check() {
+        // White is a rook up on material but mated on the back rank
+        let board: Board = "k7/5RRR/8/8/8/8/5PPP/r5K1 w - - 0 1".parse().unwrap();
+        let searcher = Searcher::new(1 << 20);
+        let timer = TimeControl::new(Duration::MAX);
+        for checks in [true, false] {
+            let value = searcher.qsearch(
+                &board,
+                -SCORE_INF,
+                SCORE_INF,
+                3,
+                evaluate::game_phase(&board),
+                checks,
+                &timer,
+                &mut SearchStats::default(),
+            );
+            assert_eq!(value, -(MATE_VALUE - 3));
+        }
+    }
+
+    #[test]
+    fn qsearch_stops_at_max_ply() {
+        // Black can take the queen, but not past the last ply
+        let board: Board = "4k3/8/8/3r4/3Q4/8/8/4K3 b - - 0 1".parse().unwrap();
+        let searcher = Searcher::new(1 << 20);
+        let timer = TimeControl::new(Duration::MAX);
+        let mut stats = SearchStats::default();
+        let value = searcher.qsearch(
+            &board,
+            -SCORE_INF,
+            SCORE_INF,
+            QS_MAX_PLY,
+            evaluate::game_phase(&board),
+            false,
+            &timer,
+            &mut stats,
+        );
+        assert_eq!(value, searcher.static_eval(&board));
+        assert_eq!(stats.nodes_visited, 1);
+        assert_eq!(stats.seldepth, QS_MAX_PLY);
+    }
+
+    #[test]
+    fn lazy_evals_are_not_stored() {
+        // White is a queen down, far below a narrow window
+        let board: Board = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNB1KBNR w KQkq - 0 1"
+            .parse()
+            .unwrap();
+        let searcher = Searcher::new(1 << 20);
+        let timer = TimeControl::new(Duration::MAX);
+        let qsearch = |alpha, beta| {
+            searcher.qsearch(
+                &board,
+                alpha,
+                beta,
+                1,
+                evaluate::game_phase(&board),
+                false,
+                &timer,
+                &mut SearchStats::default(),
+            )
+        };
+        qsearch(-10, 10);
+        assert_eq!(searcher.tt.get(board.hash()).unwrap().static_eval, NO_EVAL);
+        qsearch(-SCORE_INF, SCORE_INF);
+        assert_eq!(
+            searcher.tt.get(board.hash()).unwrap().static_eval,
+            searcher.static_eval(&board)
+        );
+    }
+
+    #[test]
+    fn mate_line_reaches_mate() {
+        let board = Board::from_fen("7k/8/8/8/8/8/R7/1R5K w - - 0 1", false).unwrap();
+        let mut searcher = Searcher::new(1 << 20);
+        let (_, value) = searcher.search_fixed_depth(
+            &mut board.clone(),
+            &Vec::new(),
+            &mut SearchStats::default(),
+            4,
+        );
+        let line = searcher.mate_line(&board, value);
+        assert_eq!(line.len(), 3);
+        let mut end = board;
+        for mv in line {
+            assert!(end.is_legal(mv));
+            end.play_unchecked(mv);
+        }
+        assert_eq!(end.status(), GameStatus::Won);
+    }
+
+    #[test]
+    fn tablebase_wins_are_relative_to_the_node() {
+        // The tables aren't part of the repository, so this only runs with SYZYGY_PATH set at
+        // build time
+        let Some(path) = option_env!("SYZYGY_PATH") else {
+            return;
+        };
+        let mut searcher = Searcher::new(1 << 16);
+        searcher.set_tablebase(Some(Arc::new(Tablebase::open(path).unwrap())));
+        searcher.set_threads(2);
+        assert!(searcher
+            .helpers
+            .iter()
+            .all(|helper| helper.tablebase.is_some()));
+
+        let board: Board = "8/8/8/4k3/8/8/8/KQ6 w - - 0 1".parse().unwrap();
+        for hash in [1, 2, 3] {
+            searcher.stack.push(hash);
+        }
+        let (value, node_type) = searcher.probe_tablebase(&board, 4).unwrap();
+        assert_eq!(value, TB_WIN_VALUE - 3);
+        assert_eq!(node_type, NodeType::LowerBound);
+        // Reached again at another ply, the win is as far from that node
+        assert_eq!(value_from_tt(value_to_tt(value, 3), 5), TB_WIN_VALUE - 5);
+
+        // Above the probe limit, the search finds out for itself
+        searcher.set_tb_probe_limit(2);
+        assert!(searcher
+            .helpers
+            .iter()
+            .all(|helper| helper.tb_probe_limit == 2));
+        assert!(searcher.probe_tablebase(&board, 4).is_none());
+    }
+
+    #[test]
+    fn nps_of_short_searches() {
+        assert_eq!(nps(1000, Duration::from_secs(2)), 500);
+        assert_eq!(nps(1000, Duration::ZERO), 1_000_000_000);
+        assert_eq!(nps(u64::MAX, Duration::from_micros(1)), u64::MAX);
+    }
+
+    #[test]
+    fn root_jitter_is_bounded_and_repeatable() {
+        let mut searcher = Searcher::new(1 << 16);
+        searcher.set_root_jitter(5);
+        let mv = "e2e4".parse().unwrap();
+        for key in 0..100 {
+            let jitter = searcher.jitter_of(key, mv);
+            assert!((-5..=5).contains(&jitter), "{jitter}");
+            assert_eq!(searcher.jitter_of(key, mv), jitter);
+        }
+    }
+
+    #[test]
+    fn jittered_moves_fail_low_against_the_best() {
+        // With a bonus this large, a move failing low would often overtake the best move if its
+        // bonus were only added after the search
+        for seed in 0..4 {
+            let mut searcher = Searcher::new(1 << 20);
+            searcher.set_deterministic(true);
+            searcher.set_seed(seed);
+            searcher.set_root_jitter(50);
+            let mut stats = SearchStats::default();
+            let (mv, value) =
+                searcher.search_fixed_depth(&mut Board::startpos(), &Vec::new(), &mut stats, 4);
+            let root_moves = searcher.root_moves();
+            let best = root_moves
+                .iter()
+                .find(|root_move| root_move.mv == mv)
+                .unwrap();
+            assert_eq!(best.score, value);
+            assert!(root_moves.iter().all(|root_move| root_move.score <= value));
+        }
+    }
+
+    // A searcher whose scores don't depend on the window, the move order or what its TT holds:
+    // everything that prunes on bounds, cuts off on TT entries or evaluates lazily is switched off
+    fn exhaustive_searcher() -> Searcher {
+        let mut searcher = Searcher::new(1 << 20);
+        for heuristic in Heuristic::ALL {
+            searcher.heuristics.set(heuristic, false);
+        }
+        searcher
+    }
+
+    // Searches a single root with the given window, without iterative deepening
+    fn search_window(
+        mut searcher: Searcher,
+        board: &Board,
+        depth: Depth,
+        alpha: Value,
+        beta: Value,
+    ) -> Value {
+        let mut board = board.clone();
+        searcher.search_reset(&mut board, &Vec::new());
+        let timer = TimeControl::new(Duration::MAX);
+        let mut stats = SearchStats::default();
+        if beta > alpha + 1 {
+            searcher.search_internal::<true>(&board, &mut stats, depth, alpha, beta, &timer)
+        } else {
+            searcher.search_internal::<false>(&board, &mut stats, depth, alpha, beta, &timer)
+        }
+    }
+
+    #[test]
+    fn fail_high_returns_score_beyond_window() {
+        let board = Board::from_fen("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", false).unwrap();
+        assert_eq!(
+            search_window(Searcher::new(1 << 20), &board, 2, -10, 10),
+            MATE_VALUE - 1
+        );
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn window_respects_bounds(
+            choices in prop::collection::vec(any::<u8>(), 0..40),
+            depth in 1..=2u8,
+            alpha in -300..300i16,
+            width in 1..300i16,
+        ) {
+            let board = random_position(&choices);
+            prop_assume!(board.status() == GameStatus::Ongoing);
+            let beta = alpha + width;
+
+            // Each search gets a fresh TT, and nothing that depends on the window is left on
+            let exact = search_window(exhaustive_searcher(), &board, depth, -SCORE_INF, SCORE_INF);
+            let bounded = search_window(exhaustive_searcher(), &board, depth, alpha, beta);
+            if exact <= alpha {
+                // Fail low: the returned value is an upper bound on the true score
+                prop_assert!(bounded <= alpha && exact <= bounded);
+            } else if exact >= beta {
+                // Fail high: the returned value is a lower bound on the true score
+                prop_assert!(bounded >= beta && exact >= bounded);
+            } else {
+                prop_assert_eq!(bounded, exact);
+            }
+        }
+
+        #[test]
+        fn tt_does_not_change_fixed_depth_result(
+            choices in prop::collection::vec(any::<u8>(), 0..40),
+            depth in 1..=2u8,
+        ) {
+            let board = random_position(&choices);
+            prop_assume!(board.status() == GameStatus::Ongoing);
+
+            // Without TT cutoffs, the entries of an earlier search only give moves to try first
+            let mut cold = exhaustive_searcher();
+            let mut warm = exhaustive_searcher();
+            let moves = Vec::new();
+            let search = |searcher: &mut Searcher| {
+                searcher.search_fixed_depth(
+                    &mut board.clone(),
+                    &moves,
+                    &mut SearchStats::default(),
+                    depth,
+                )
+            };
+            let (_, cold_value) = search(&mut cold);
+            // Search once to fill the table, then again probing it
+            search(&mut warm);
+            let (bm, warm_value) = search(&mut warm);
+
+            prop_assert!(board.is_legal(bm));
+            prop_assert_eq!(cold_value, warm_value);
+        }
+    }
 }
@@ -0,0 +1,190 @@
+use crate::{
+    eval_params::param_options,
+    types::{Depth, Value},
+};
+
+// Declares the parameters with their UCI option names, defaults and ranges, so that the struct,
+// its defaults and the option list can't get out of step
+macro_rules! search_params {
+    ($($name:ident: $ty:ty = $default:literal, $min:literal..=$max:literal, $option:literal;)*) => {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub struct SearchParams {
+            $(pub $name: $ty,)*
+        }
+
+        impl Default for SearchParams {
+            fn default() -> Self {
+                Self {
+                    $($name: $default,)*
+                }
+            }
+        }
+
+        // Option name, default, minimum and maximum of every parameter
+        pub const PARAM_OPTIONS: &[(&str, i32, i32, i32)] = &[$(($option, $default, $min, $max),)*];
+
+        impl SearchParams {
+            // Sets the parameter with the given option name, clamped to its range. Returns
+            // whether there is such a parameter.
+            pub fn set(&mut self, name: &str, value: i32) -> bool {
+                $(
+                    if name.eq_ignore_ascii_case($option) {
+                        self.$name = <$ty>::try_from(value.clamp($min, $max)).unwrap_or($default);
+                        return true;
+                    }
+                )*
+                false
+            }
+        }
+    };
+}
+
+// Search Parameters
+// Margins and depth limits of the search heuristics. They are fixed for normal builds, but with
+// the `tune` feature each of them is a UCI spin option, so that they can be tuned with SPSA
+// (e.g. on OpenBench) without rebuilding for every trial.
+search_params! {
+    // Reverse futility pruning margin per ply, up to the given depth
+    rfp_margin: Value = 75, 10..=300, "RfpMargin";
+    rfp_max_depth: Depth = 7, 1..=16, "RfpMaxDepth";
+    // Null move pruning from the given depth, searching the null move this much shallower
+    nmp_min_depth: Depth = 3, 1..=8, "NmpMinDepth";
+    nmp_reduction: Depth = 3, 1..=6, "NmpReduction";
+    // Half width of the first aspiration window
+    aspiration_window: Value = 20, 5..=200, "AspirationWindow";
+    // Futility pruning margin per ply, up to the given depth
+    fp_margin: Value = 100, 10..=400, "FpMargin";
+    fp_max_depth: Depth = 3, 1..=10, "FpMaxDepth";
+    // SEE pruning of quiet moves, margin per squared ply
+    see_quiet_margin: Value = 20, 1..=200, "SeeQuietMargin";
+    // History pruning margin per ply
+    hp_margin: i16 = 1024, 128..=8192, "HpMargin";
+    // LMR formula in hundredths, see `LMRTable::with_params`
+    lmr_base: u32 = 75, 0..=300, "LMRBase";
+    lmr_divisor: u32 = 225, 50..=1000, "LMRDivisor";
+    // History per ply of LMR reduction
+    lmr_history_divisor: i16 = 8192, 1024..=16384, "LmrHistoryDivisor";
+    // Singular extension margins, see `Searcher::singular_extension`
+    se_margin: Value = 2, 1..=10, "SeMargin";
+    se_double_margin: Value = 25, 0..=200, "SeDoubleMargin";
+}
+
+// OpenBench SPSA input for every parameter, including the eval's, see
+// `eval_params::param_options`: name, type, default, minimum, maximum, final step size and final
+// learning rate
+pub fn spsa_inputs() -> Vec<String> {
+    PARAM_OPTIONS
+        .iter()
+        .map(|&(name, default, min, max)| (name.to_owned(), default, min, max))
+        .chain(param_options())
+        .map(|(name, default, min, max)| {
+            let step = ((max - min) / 20).max(1);
+            format!("{name}, int, {default}, {min}, {max}, {step}, 0.002")
+        })
+        .collect()
+}
+
+// Heuristics that can be switched off one at a time through the UCI options named after them,
+// for ablation tests and for tracking down search bugs that only show with one of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Heuristic {
+    NullMovePruning,
+    ReverseFutilityPruning,
+    LateMoveReductions,
+    AspirationWindows,
+    TtCutoffs,
+    // Futility, history and SEE pruning of the moves of a node
+    MovePruning,
+    // Evals cut short when far outside the window
+    LazyEval,
+}
+
+impl Heuristic {
+    pub const ALL: [Self; 7] = [
+        Self::NullMovePruning,
+        Self::ReverseFutilityPruning,
+        Self::LateMoveReductions,
+        Self::AspirationWindows,
+        Self::TtCutoffs,
+        Self::MovePruning,
+        Self::LazyEval,
+    ];
+
+    // Name of the UCI check option enabling the heuristic
+    pub const fn option_name(self) -> &'static str {
+        match self {
+            Self::NullMovePruning => "UseNMP",
+            Self::ReverseFutilityPruning => "UseRFP",
+            Self::LateMoveReductions => "UseLMR",
+            Self::AspirationWindows => "UseAspiration",
+            Self::TtCutoffs => "UseTTCutoffs",
+            Self::MovePruning => "UseMovePruning",
+            Self::LazyEval => "UseLazyEval",
+        }
+    }
+
+    pub fn parse_option(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|heuristic| name.eq_ignore_ascii_case(heuristic.option_name()))
+    }
+}
+
+// Which of the heuristics are enabled, all of them by default
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Heuristics {
+    enabled: [bool; Heuristic::ALL.len()],
+}
+
+impl Default for Heuristics {
+    fn default() -> Self {
+        Self {
+            enabled: [true; Heuristic::ALL.len()],
+        }
+    }
+}
+
+impl Heuristics {
+    pub const fn is_enabled(&self, heuristic: Heuristic) -> bool {
+        self.enabled[heuristic as usize]
+    }
+
+    pub const fn set(&mut self, heuristic: Heuristic, enabled: bool) {
+        self.enabled[heuristic as usize] = enabled;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Heuristic, Heuristics, SearchParams, PARAM_OPTIONS};
+
+    #[test]
+    fn options_match_defaults() {
+        let defaults = SearchParams::default();
+        for &(name, default, min, max) in PARAM_OPTIONS {
+            assert!(min <= default && default <= max, "{name}");
+            let mut params = defaults;
+            assert!(params.set(name, default));
+            assert_eq!(params, defaults, "{name}");
+        }
+    }
+
+    #[test]
+    fn values_are_clamped() {
+        let mut params = SearchParams::default();
+        assert!(params.set("rfpmargin", 10_000));
+        assert_eq!(params.rfp_margin, 300);
+        assert!(!params.set("NoSuchParam", 1));
+    }
+
+    #[test]
+    fn heuristics_are_toggled_by_option_name() {
+        let mut heuristics = Heuristics::default();
+        let lmr = Heuristic::parse_option("uselmr").unwrap();
+        assert_eq!(lmr, Heuristic::LateMoveReductions);
+        heuristics.set(lmr, false);
+        assert!(!heuristics.is_enabled(lmr));
+        assert!(heuristics.is_enabled(Heuristic::NullMovePruning));
+        assert_eq!(Heuristic::parse_option("UseNothing"), None);
+    }
+}
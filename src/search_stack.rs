@@ -0,0 +1,189 @@
+use std::ops::{Index, IndexMut};
+
+use cozy_chess::Move;
+
+use crate::types::Value;
+
+const MAX_PLY: usize = 256;
+// Static eval of nodes in check, which have no meaningful one
+pub const NO_EVAL: Value = Value::MIN;
+
+// State of a node, shared with the nodes around it on the same line
+#[derive(Debug, Clone, Copy)]
+pub struct StackEntry {
+    // Static eval of the node, `NO_EVAL` when in check
+    pub static_eval: Value,
+    // Move being searched from the node
+    pub current_move: Option<Move>,
+    // Whether `current_move` is a capture
+    pub capture: bool,
+    // Units of fractional extension carried over to the node after `current_move`
+    pub extension_units: u8,
+    // Plies of extension on the line up to and including `current_move`
+    pub extensions: u8,
+    // Quiet moves that caused cutoffs at this ply, most recent first
+    pub killers: [Option<Move>; 2],
+    // Move the node skips, for the singularity test of its TT move
+    pub excluded_move: Option<Move>,
+    // Game phase of the node's position, see `evaluate::game_phase`
+    pub game_phase: i32,
+}
+
+impl StackEntry {
+    const EMPTY: Self = Self {
+        static_eval: NO_EVAL,
+        current_move: None,
+        capture: false,
+        extension_units: 0,
+        extensions: 0,
+        killers: [None; 2],
+        excluded_move: None,
+        game_phase: 0,
+    };
+}
+
+// Search Stack
+// One entry per ply of the line being searched, indexed by ply. A node reads what the nodes
+// before it left behind, e.g. the static eval two plies up for improving, and its killers stay
+// around for its siblings. The stack also tracks the ply of the current node, and the hashes of
+// the positions before it for repetition checks.
+#[derive(Debug)]
+pub struct SearchStack {
+    entries: Box<[StackEntry]>,
+    // Hashes of the game's positions before the root, then of the line's up to the current node
+    hashes: Vec<u64>,
+    ply: u8,
+}
+
+impl Default for SearchStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SearchStack {
+    pub fn new() -> Self {
+        Self {
+            entries: vec![StackEntry::EMPTY; MAX_PLY].into_boxed_slice(),
+            hashes: Vec::with_capacity(512),
+            ply: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(StackEntry::EMPTY);
+        self.hashes.clear();
+        self.ply = 0;
+    }
+
+    // Starts the line of a search at the root, reached through the game positions with `hashes`
+    pub fn set_root(&mut self, hashes: &[u64]) {
+        self.hashes.clear();
+        self.hashes.extend_from_slice(hashes);
+        self.ply = 0;
+    }
+
+    // Goes one ply deeper, from the position with `hash`
+    pub fn push(&mut self, hash: u64) {
+        self.hashes.push(hash);
+        self.ply += 1;
+    }
+
+    pub fn pop(&mut self) {
+        self.hashes.pop();
+        self.ply -= 1;
+    }
+
+    pub const fn ply(&self) -> u8 {
+        self.ply
+    }
+
+    // Hashes of the positions before the current node, the most recent last
+    pub fn hashes(&self) -> &[u64] {
+        &self.hashes
+    }
+
+    // Prepares the stack for the next search of the same game. Killers are kept, moved two plies
+    // up since the next root is usually two plies further into the game, and the rest is reset.
+    pub fn age(&mut self) {
+        let killers = self
+            .entries
+            .iter()
+            .skip(2)
+            .map(|entry| entry.killers)
+            .collect::<Vec<_>>();
+        self.clear();
+        for (entry, killers) in self.entries.iter_mut().zip(killers) {
+            entry.killers = killers;
+        }
+    }
+
+    // Moves leading from the root to the node at `ply`
+    pub fn line(&self, ply: usize) -> Vec<Move> {
+        self.entries[..ply]
+            .iter()
+            .map_while(|entry| entry.current_move)
+            .collect()
+    }
+}
+
+impl Index<usize> for SearchStack {
+    type Output = StackEntry;
+
+    fn index(&self, ply: usize) -> &StackEntry {
+        &self.entries[ply]
+    }
+}
+
+impl IndexMut<usize> for SearchStack {
+    fn index_mut(&mut self, ply: usize) -> &mut StackEntry {
+        &mut self.entries[ply]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cozy_chess::Move;
+
+    use super::SearchStack;
+
+    #[test]
+    fn line_ends_at_the_node() {
+        let moves: Vec<Move> = ["e2e4", "e7e5", "g1f3"]
+            .iter()
+            .map(|mv| mv.parse().unwrap())
+            .collect();
+        let mut stack = SearchStack::new();
+        for (ply, &mv) in moves.iter().enumerate() {
+            stack[ply].current_move = Some(mv);
+        }
+        assert_eq!(stack.line(2), &moves[..2]);
+        stack.clear();
+        assert!(stack.line(2).is_empty());
+    }
+
+    #[test]
+    fn hashes_follow_the_line() {
+        let mut stack = SearchStack::new();
+        stack.set_root(&[1, 2]);
+        stack.push(3);
+        stack.push(4);
+        assert_eq!((stack.ply(), stack.hashes()), (2, &[1, 2, 3, 4][..]));
+        stack.pop();
+        assert_eq!((stack.ply(), stack.hashes()), (1, &[1, 2, 3][..]));
+        stack.set_root(&[5]);
+        assert_eq!((stack.ply(), stack.hashes()), (0, &[5][..]));
+    }
+
+    #[test]
+    fn aging_keeps_killers() {
+        let killer: Move = "g1f3".parse().unwrap();
+        let mut stack = SearchStack::new();
+        stack[3].killers[0] = Some(killer);
+        stack[3].current_move = Some(killer);
+        stack.age();
+        assert_eq!(stack[1].killers[0], Some(killer));
+        assert_eq!(stack[1].current_move, None);
+        assert_eq!(stack[3].killers[0], None);
+    }
+}
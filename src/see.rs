@@ -0,0 +1,207 @@
+use cozy_chess::{
+    get_bishop_moves, get_king_moves, get_knight_moves, get_pawn_attacks, get_rook_moves, BitBoard,
+    Board, Color, Move, Piece, Square,
+};
+
+use crate::{evaluate::PIECE_VALUES, types::Value};
+
+const fn value(piece: Piece) -> Value {
+    PIECE_VALUES[piece as usize]
+}
+
+// Pieces of both sides attacking `sq`, with sliders seeing through everything not in `occupied`
+fn attackers(board: &Board, sq: Square, occupied: BitBoard) -> BitBoard {
+    let bishops = board.pieces(Piece::Bishop) | board.pieces(Piece::Queen);
+    let rooks = board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+    let pawns = get_pawn_attacks(sq, Color::Black)
+        & board.colored_pieces(Color::White, Piece::Pawn)
+        | get_pawn_attacks(sq, Color::White) & board.colored_pieces(Color::Black, Piece::Pawn);
+
+    (pawns
+        | get_knight_moves(sq) & board.pieces(Piece::Knight)
+        | get_bishop_moves(sq, occupied) & bishops
+        | get_rook_moves(sq, occupied) & rooks
+        | get_king_moves(sq) & board.pieces(Piece::King))
+        & occupied
+}
+
+// Static Exchange Evaluation (SEE)
+// Plays out the sequence of captures on the target square of `mv`, each side always recapturing
+// with its least valuable piece and free to stop when continuing would lose more, and returns
+// the material won by the side making `mv`. Pins are ignored, but pieces lined up behind each
+// other (x-rays) join in as the pieces in front of them are traded off.
+pub fn see(board: &Board, mv: Move) -> Value {
+    let to = mv.to;
+    let mut occupied = board.occupied();
+    let mut gains = [0; 32];
+
+    // En passant is the only capture where nothing stands on the target square
+    let mover = board.piece_on(mv.from).unwrap_or(Piece::Pawn);
+    let en_passant = mover == Piece::Pawn && mv.from.file() != to.file() && !occupied.has(to);
+    gains[0] = if en_passant {
+        occupied ^= Square::new(to.file(), mv.from.rank()).bitboard();
+        value(Piece::Pawn)
+    } else {
+        board.piece_on(to).map_or(0, value)
+    };
+    // The piece standing on the square, which the next capture takes
+    let mut on_square = mover;
+    if let Some(promotion) = mv.promotion {
+        gains[0] += value(promotion) - value(Piece::Pawn);
+        on_square = promotion;
+    }
+    occupied ^= mv.from.bitboard();
+
+    let mut side = !board.side_to_move();
+    let mut depth = 0;
+    loop {
+        let all = attackers(board, to, occupied);
+        let ours = all & board.colors(side);
+        let Some(piece) = Piece::ALL
+            .into_iter()
+            .find(|&piece| !(ours & board.pieces(piece)).is_empty())
+        else {
+            break;
+        };
+        // The king can only recapture if nothing can take it back
+        if piece == Piece::King && !(all & board.colors(!side)).is_empty() {
+            break;
+        }
+        depth += 1;
+        gains[depth] = value(on_square) - gains[depth - 1];
+
+        let from = (ours & board.pieces(piece)).next_square().unwrap_or(to);
+        occupied ^= from.bitboard();
+        on_square = piece;
+        side = !side;
+        if depth + 1 == gains.len() {
+            break;
+        }
+    }
+
+    // Going back through the exchange, each side picks the better of stopping and capturing
+    while depth > 0 {
+        gains[depth - 1] = -(-gains[depth - 1]).max(gains[depth]);
+        depth -= 1;
+    }
+    gains[0]
+}
+
+// Whether `mv` wins at least `threshold` in the exchange on its target square
+pub fn see_ge(board: &Board, mv: Move, threshold: Value) -> bool {
+    see(board, mv) >= threshold
+}
+
+// Squares attacked by a `piece` of `color` standing on `sq`
+pub fn piece_attacks(piece: Piece, color: Color, sq: Square, occupied: BitBoard) -> BitBoard {
+    match piece {
+        Piece::Pawn => get_pawn_attacks(sq, color),
+        Piece::Knight => get_knight_moves(sq),
+        Piece::Bishop => get_bishop_moves(sq, occupied),
+        Piece::Rook => get_rook_moves(sq, occupied),
+        Piece::Queen => get_bishop_moves(sq, occupied) | get_rook_moves(sq, occupied),
+        Piece::King => get_king_moves(sq),
+    }
+}
+
+// Whether the piece on `sq` attacks an enemy piece worth more than itself, other than the king.
+// Such a threat usually wins material unless it is answered right away.
+pub fn creates_threat(board: &Board, sq: Square) -> bool {
+    let (Some(piece), Some(color)) = (board.piece_on(sq), board.color_on(sq)) else {
+        return false;
+    };
+    if piece == Piece::King {
+        return false;
+    }
+    let attacks = piece_attacks(piece, color, sq, board.occupied());
+    let targets = attacks & board.colors(!color) & !board.pieces(Piece::King);
+    targets
+        .into_iter()
+        .any(|target| board.piece_on(target).is_some_and(|p| value(p) > value(piece)))
+}
+
+// Enemy pieces the piece on `sq` could take with a gain: those worth more than it, and those
+// left undefended. Unlike `creates_threat`, hanging pieces count, and kings threaten as well.
+pub fn threatened_pieces(board: &Board, sq: Square) -> BitBoard {
+    let (Some(piece), Some(color)) = (board.piece_on(sq), board.color_on(sq)) else {
+        return BitBoard::EMPTY;
+    };
+    let attacks = piece_attacks(piece, color, sq, board.occupied());
+    let targets = attacks & board.colors(!color) & !board.pieces(Piece::King);
+    targets
+        .into_iter()
+        .filter(|&target| {
+            board.piece_on(target).is_some_and(|p| value(p) > value(piece))
+                || !is_defended(board, target)
+        })
+        .fold(BitBoard::EMPTY, |threatened, target| threatened | target.bitboard())
+}
+
+// Whether a piece of its own side attacks the piece on `sq`
+pub fn is_defended(board: &Board, sq: Square) -> bool {
+    board.color_on(sq).is_some_and(|color| {
+        !(attackers(board, sq, board.occupied()) & board.colors(color)).is_empty()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use cozy_chess::{Board, Move, Square};
+
+    use super::{creates_threat, see, threatened_pieces};
+
+    fn see_of(fen: &str, mv: &str) -> i16 {
+        let board: Board = fen.parse().unwrap();
+        see(&board, mv.parse::<Move>().unwrap())
+    }
+
+    #[test]
+    fn exchanges() {
+        // Undefended pawn
+        assert_eq!(
+            see_of("1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1", "e1e5"),
+            100
+        );
+        // Knight for pawn once the x-rays on the e-file have joined in
+        assert_eq!(
+            see_of(
+                "1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1",
+                "d3e5"
+            ),
+            100 - 250
+        );
+        // Quiet move to a square attacked by a pawn
+        assert_eq!(see_of("4k3/8/3p4/8/8/8/8/2B1K3 w - - 0 1", "c1e3"), 0);
+        assert_eq!(see_of("4k3/8/5p2/8/8/8/8/2B1K3 w - - 0 1", "c1g5"), -300);
+        // En passant, recaptured by the king
+        assert_eq!(see_of("8/8/8/3k4/4pP2/8/6K1/8 b - f3 0 1", "e4f3"), 0);
+        // Promotion
+        assert_eq!(see_of("8/4P3/8/8/8/8/k7/4K3 w - - 0 1", "e7e8q"), 800);
+    }
+
+    #[test]
+    fn threats() {
+        // Knight forking rook and queen
+        let board: Board = "4k3/8/3q1r2/8/4N3/8/8/4K3 b - - 0 1".parse().unwrap();
+        assert!(creates_threat(&board, Square::E4));
+        // Pawn attacking a knight, but not a pawn
+        let board: Board = "4k3/8/2n5/3P4/8/8/8/4K3 b - - 0 1".parse().unwrap();
+        assert!(creates_threat(&board, Square::D5));
+        let board: Board = "4k3/8/2p5/3P4/8/8/8/4K3 b - - 0 1".parse().unwrap();
+        assert!(!creates_threat(&board, Square::D5));
+        // Checks are not threats in this sense
+        let board: Board = "8/8/8/3k4/8/8/8/3QK3 b - - 0 1".parse().unwrap();
+        assert!(!creates_threat(&board, Square::D1));
+    }
+
+    #[test]
+    fn threatened_by_the_last_move() {
+        // Bishop attacking a rook, defended or not, and an undefended knight
+        let board: Board = "2r1k3/2r5/8/4B3/5n2/8/8/4K3 b - - 0 1".parse().unwrap();
+        let threatened = threatened_pieces(&board, Square::E5);
+        assert!(threatened.has(Square::C7) && threatened.has(Square::F4));
+        // A defended knight isn't threatened by a bishop
+        let board: Board = "4k3/8/8/4B1p1/5n2/8/8/4K3 b - - 0 1".parse().unwrap();
+        assert!(!threatened_pieces(&board, Square::E5).has(Square::F4));
+    }
+}
@@ -0,0 +1,161 @@
+use std::{fs, path::Path};
+
+use cozy_chess::{Board, BoardBuilder, Color, Square};
+
+use crate::{
+    cli::Flags,
+    eval_params::EvalParams,
+    evaluate::{evaluate_trace_with, Evaluator},
+};
+
+// The same position with the colours swapped: every piece moves to the other side of the board
+// and changes colour, and the other side is to move. En passant is dropped, which the eval ignores.
+pub fn color_flipped(board: &Board) -> Board {
+    let flip = |sq: Square| Square::new(sq.file(), sq.rank().relative_to(Color::Black));
+    let mut builder = BoardBuilder::empty();
+    for sq in board.occupied() {
+        let piece = board.piece_on(sq).unwrap();
+        let color = board.color_on(sq).unwrap();
+        *builder.square_mut(flip(sq)) = Some((piece, !color));
+    }
+    for color in Color::ALL {
+        *builder.castle_rights_mut(!color) = *board.castle_rights(color);
+    }
+    builder.side_to_move = !board.side_to_move();
+    builder.halfmove_clock = board.halfmove_clock();
+    builder.fullmove_number = board.fullmove_number();
+    builder
+        .build()
+        .expect("the colour flip of a legal position is legal")
+}
+
+// Eval Symmetry
+// The eval is for the side to move, so a position and its colour flip must get the same eval, and
+// every term must give each side what it gave the other before. A term that looks at the board
+// from white's point of view only, e.g. with a rank or shift that isn't made relative to the
+// colour, breaks this. Files aren't mirrored, as the tables and the castling terms rightly tell
+// the wings apart. Returns the terms that differ, or the evals if only they do.
+pub fn check(board: &Board, params: &EvalParams) -> Result<(), String> {
+    let flipped = color_flipped(board);
+    let trace = evaluate_trace_with(board, params);
+    let flipped_trace = evaluate_trace_with(&flipped, params);
+    let mut errors = Vec::new();
+    for ((name, term), (_, flipped_term)) in trace.terms().iter().zip(flipped_trace.terms()) {
+        if term.mg != [flipped_term.mg[1], flipped_term.mg[0]]
+            || term.eg != [flipped_term.eg[1], flipped_term.eg[0]]
+        {
+            errors.push(format!("{name} {term:?} flipped {flipped_term:?}"));
+        }
+    }
+    if trace.mop_up != [flipped_trace.mop_up[1], flipped_trace.mop_up[0]] {
+        errors.push(format!(
+            "mop_up {:?} flipped {:?}",
+            trace.mop_up, flipped_trace.mop_up
+        ));
+    }
+
+    let eval = Evaluator::Psqt(params).evaluate(board);
+    let flipped_eval = Evaluator::Psqt(params).evaluate(&flipped);
+    if errors.is_empty() && eval != flipped_eval {
+        errors.push(format!("eval {eval} flipped {flipped_eval}"));
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("{board}: {}", errors.join(", ")))
+    }
+}
+
+// The positions of the suite and every position one move away from them
+fn positions(fens: &[String]) -> Result<Vec<Board>, String> {
+    let mut boards = Vec::new();
+    for fen in fens {
+        let board = Board::from_fen(fen, false).map_err(|_| format!("invalid FEN {fen}"))?;
+        board.generate_moves(|moves| {
+            for mv in moves {
+                let mut child = board.clone();
+                child.play_unchecked(mv);
+                boards.push(child);
+            }
+            false
+        });
+        boards.push(board);
+    }
+    Ok(boards)
+}
+
+// Entry point of the `selfcheck` subcommand, which checks that the eval is symmetric, see `check`,
+// over the embedded FEN suite (or a file of FENs) and the positions one move away from it. Reports
+// every asymmetric position and fails if there are any.
+// Usage: selfcheck [FILE] [--weights FILE]
+pub fn run(args: &[String]) -> Result<(), String> {
+    let flags = Flags::parse(args, &["--weights"])?;
+    let contents = match flags.positional() {
+        [] => include_str!("fen.csv").to_owned(),
+        [path] => fs::read_to_string(path).map_err(|e| format!("could not read {path}: {e}"))?,
+        [_, extra, ..] => return Err(format!("unexpected argument {extra}")),
+    };
+    let params = match flags.get::<String>("--weights")? {
+        Some(path) => EvalParams::load(Path::new(&path))
+            .map_err(|e| format!("could not load weights {path}: {e}"))?,
+        None => EvalParams::default(),
+    };
+    let fens = contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(str::to_owned)
+        .collect::<Vec<_>>();
+
+    let boards = positions(&fens)?;
+    let mut asymmetric = 0;
+    for board in &boards {
+        if let Err(msg) = check(board, &params) {
+            println!("{msg}");
+            asymmetric += 1;
+        }
+    }
+    println!("{} positions, {asymmetric} asymmetric", boards.len());
+    if asymmetric == 0 {
+        Ok(())
+    } else {
+        Err(format!("the eval is asymmetric in {asymmetric} positions"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use cozy_chess::Board;
+
+    use crate::eval_params::BUILTIN;
+
+    use super::{check, color_flipped, positions};
+
+    #[test]
+    fn colour_flip() {
+        let board = "r3k2r/pp3ppp/2n2n2/2q5/8/2N5/PP3PPP/R3K1R1 w Qkq - 3 12"
+            .parse::<Board>()
+            .unwrap();
+        assert_eq!(
+            color_flipped(&board).to_string(),
+            "r3k1r1/pp3ppp/2n5/8/2Q5/2N2N2/PP3PPP/R3K2R b KQq - 3 12"
+        );
+        assert_eq!(
+            color_flipped(&color_flipped(&board)).to_string(),
+            board.to_string()
+        );
+    }
+
+    #[test]
+    fn eval_is_symmetric_over_the_suite() {
+        let fens = include_str!("fen.csv")
+            .lines()
+            .map(str::to_owned)
+            .collect::<Vec<_>>();
+        for board in positions(&fens).unwrap() {
+            if let Err(msg) = check(&board, &BUILTIN) {
+                panic!("{msg}");
+            }
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use std::time::Duration;
+
+use cozy_chess::{Board, Color, GameStatus, Move};
+
+use crate::{
+    cli::Flags,
+    search::{SearchStats, Searcher},
+    types::{Depth, Value},
+    utils::uci_line,
+};
+
+const DEFAULT_GAMES: usize = 1;
+const DEFAULT_MOVETIME_MS: u64 = 100;
+const DEFAULT_HASH_MB: usize = 16;
+// Without some randomness both sides would play the same game every time
+const DEFAULT_VARIETY: Value = 20;
+// Games still going after this many plies are scored as draws
+const MAX_PLIES: usize = 400;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl Outcome {
+    const fn result(self) -> &'static str {
+        match self {
+            Self::WhiteWins => "1-0",
+            Self::BlackWins => "0-1",
+            Self::Draw => "1/2-1/2",
+        }
+    }
+}
+
+// Entry point of the `selfplay` subcommand, which plays the engine against itself from the start
+// position (or a given FEN) and prints every game as UCI moves followed by the result.
+// Usage: selfplay [FEN] [--games N] [--depth N | --movetime MS] [--hash MB] [--variety CP]
+pub fn run(args: &[String]) -> Result<(), String> {
+    let flags = Flags::parse(
+        args,
+        &["--games", "--depth", "--movetime", "--hash", "--variety"],
+    )?;
+    let start = match flags.positional() {
+        [] => Board::default(),
+        [fen] => Board::from_fen(fen, false).map_err(|_| format!("invalid FEN {fen}"))?,
+        [_, extra, ..] => return Err(format!("unexpected argument {extra}")),
+    };
+    let games = flags.get_or("--games", DEFAULT_GAMES)?;
+    let depth = flags.get::<Depth>("--depth")?;
+    let move_time = match (depth, flags.get::<u64>("--movetime")?) {
+        (Some(_), None) => Duration::MAX,
+        (_, ms) => Duration::from_millis(ms.unwrap_or(DEFAULT_MOVETIME_MS)),
+    };
+    let hash_mb = flags.get_or("--hash", DEFAULT_HASH_MB)?.max(1);
+    let variety = flags.get_or("--variety", DEFAULT_VARIETY)?;
+
+    let mut players = [
+        Searcher::new(hash_mb * 1024 * 1024),
+        Searcher::new(hash_mb * 1024 * 1024),
+    ];
+    for player in &mut players {
+        player.set_variety(variety);
+    }
+
+    let mut tally = [0; 3];
+    for game in 1..=games {
+        for player in &mut players {
+            player.new_game();
+        }
+        let (moves, outcome) =
+            play_game(&mut players, &start, depth.unwrap_or(Depth::MAX), move_time);
+        tally[outcome as usize] += 1;
+        println!(
+            "game {game}: {} {}",
+            uci_line(&start, &moves, false)
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(" "),
+            outcome.result()
+        );
+    }
+    println!(
+        "white wins {} black wins {} draws {}",
+        tally[Outcome::WhiteWins as usize],
+        tally[Outcome::BlackWins as usize],
+        tally[Outcome::Draw as usize],
+    );
+    Ok(())
+}
+
+fn play_game(
+    players: &mut [Searcher; 2],
+    start: &Board,
+    depth: Depth,
+    move_time: Duration,
+) -> (Vec<Move>, Outcome) {
+    let mut board = start.clone();
+    let mut moves = Vec::new();
+    let mut seen = vec![board.hash()];
+    while moves.len() < MAX_PLIES {
+        match board.status() {
+            GameStatus::Won if board.side_to_move() == Color::White => {
+                return (moves, Outcome::BlackWins)
+            }
+            GameStatus::Won => return (moves, Outcome::WhiteWins),
+            GameStatus::Drawn => return (moves, Outcome::Draw),
+            GameStatus::Ongoing => {}
+        }
+        if seen.iter().filter(|&&hash| hash == board.hash()).count() >= 3 {
+            return (moves, Outcome::Draw);
+        }
+
+        let player = &mut players[board.side_to_move() as usize];
+        let (mv, _) = player.search(
+            &mut start.clone(),
+            &moves,
+            &mut SearchStats::default(),
+            depth,
+            move_time,
+        );
+        board.play(mv);
+        moves.push(mv);
+        seen.push(board.hash());
+    }
+    (moves, Outcome::Draw)
+}
@@ -0,0 +1,96 @@
+use crate::types::{Depth, Value};
+
+pub const MIN_ELO: u32 = 800;
+pub const MAX_ELO: u32 = 2200;
+
+// Playing strength for `UCI_LimitStrength`. The engine is weakened in two ways: searches are cut
+// short, and the move played is drawn at random with worse moves being less likely. The numbers
+// are rough calibrations from self-play against the full-strength engine, not exact ratings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EloLimit {
+    elo: u32,
+}
+
+impl EloLimit {
+    pub fn new(elo: u32) -> Self {
+        Self {
+            elo: elo.clamp(MIN_ELO, MAX_ELO),
+        }
+    }
+
+    // Steps of 200 Elo above the minimum
+    const fn level(self) -> u32 {
+        (self.elo - MIN_ELO) / 200
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn max_depth(self) -> Depth {
+        1 + self.level() as Depth
+    }
+
+    // Doubles every 200 Elo, from 500 nodes at the minimum to 64000 at the maximum
+    pub const fn max_nodes(self) -> u64 {
+        500 << self.level()
+    }
+
+    // Score difference in centipawns that makes a move e (~2.7) times less likely to be played
+    pub fn temperature(self) -> f64 {
+        f64::from(MAX_ELO - self.elo) / 5.0 + 10.0
+    }
+}
+
+pub const MAX_SKILL_LEVEL: u8 = 20;
+
+// `Skill Level` makes the engine play a slightly worse move now and then, which lower levels do
+// more often and with larger mistakes. Unlike `EloLimit` the search itself stays at full strength.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SkillLevel(u8);
+
+impl Default for SkillLevel {
+    fn default() -> Self {
+        Self(MAX_SKILL_LEVEL)
+    }
+}
+
+impl SkillLevel {
+    pub fn new(level: u8) -> Self {
+        Self(level.min(MAX_SKILL_LEVEL))
+    }
+
+    pub const fn is_full_strength(self) -> bool {
+        self.0 == MAX_SKILL_LEVEL
+    }
+
+    // Chance of deviating from the best move, up to one in two at level 0
+    pub fn mistake_chance(self) -> f64 {
+        f64::from(MAX_SKILL_LEVEL - self.0) / 40.0
+    }
+
+    // Largest mistake in centipawns, up to three pawns at level 0
+    pub fn mistake_margin(self) -> Value {
+        Value::from(MAX_SKILL_LEVEL - self.0) * 15
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{EloLimit, SkillLevel, MAX_ELO, MIN_ELO};
+
+    #[test]
+    fn stronger_means_deeper_and_more_accurate() {
+        let weak = EloLimit::new(MIN_ELO);
+        let strong = EloLimit::new(MAX_ELO);
+        assert!(weak.max_depth() < strong.max_depth());
+        assert!(weak.max_nodes() < strong.max_nodes());
+        assert!(weak.temperature() > strong.temperature());
+        assert_eq!(EloLimit::new(100), weak);
+    }
+
+    #[test]
+    fn skill_level_mistakes() {
+        assert!(SkillLevel::default().is_full_strength());
+        assert_eq!(SkillLevel::default().mistake_margin(), 0);
+        assert!(SkillLevel::new(0).mistake_chance() > SkillLevel::new(10).mistake_chance());
+        assert_eq!(SkillLevel::new(30), SkillLevel::default());
+    }
+}
@@ -0,0 +1,181 @@
+use std::time::Duration;
+
+use crate::types::{Depth, Value};
+
+const SWINGS_REPORTED: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveSource {
+    Search,
+    Book,
+    Tablebase,
+}
+
+// What the engine did for one of its moves
+#[derive(Debug, Clone, Copy)]
+pub struct MoveRecord {
+    pub depth: Depth,
+    pub nodes: u64,
+    pub time: Duration,
+    // Score from the engine's point of view
+    pub value: Value,
+    pub source: MoveSource,
+}
+
+// Collects per-move statistics over a game, to give operators quick feedback on engine health
+#[derive(Debug, Default)]
+pub struct GameSummary {
+    records: Vec<MoveRecord>,
+}
+
+impl GameSummary {
+    pub fn record(&mut self, record: MoveRecord) {
+        self.records.push(record);
+    }
+
+    pub fn clear(&mut self) {
+        self.records.clear();
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    // Largest drops of the engine's own evaluation between consecutive moves, as
+    // (move number, value before, value after). These usually point at the engine's mistakes.
+    pub fn largest_swings(&self, count: usize) -> Vec<(usize, Value, Value)> {
+        let mut swings = self
+            .records
+            .windows(2)
+            .enumerate()
+            .map(|(i, pair)| (i + 2, pair[0].value, pair[1].value))
+            .filter(|&(_, before, after)| after < before)
+            .collect::<Vec<_>>();
+        swings.sort_by_key(|&(_, before, after)| -(i32::from(before) - i32::from(after)));
+        swings.truncate(count);
+        swings
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    pub fn report(&self) -> Vec<String> {
+        if self.records.is_empty() {
+            return Vec::new();
+        }
+
+        let searched = self
+            .records
+            .iter()
+            .filter(|rec| rec.source == MoveSource::Search)
+            .collect::<Vec<_>>();
+        let count_source = |source| {
+            self.records
+                .iter()
+                .filter(|rec| rec.source == source)
+                .count()
+        };
+
+        let mut lines = Vec::new();
+        if !searched.is_empty() {
+            let n = searched.len() as f64;
+            let avg_depth = searched.iter().map(|rec| f64::from(rec.depth)).sum::<f64>() / n;
+            let avg_nodes = searched.iter().map(|rec| rec.nodes as f64).sum::<f64>() / n;
+            lines.push(format!(
+                "game summary: {} moves, {} searched, avg depth {avg_depth:.1}, avg nodes {avg_nodes:.0}",
+                self.records.len(),
+                searched.len(),
+            ));
+        }
+
+        let total_time = self.records.iter().map(|rec| rec.time).sum::<Duration>();
+        let curve = self
+            .records
+            .iter()
+            .map(|rec| rec.time.as_millis().to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+        lines.push(format!(
+            "time used {:.1}s, per move (ms): {curve}",
+            total_time.as_secs_f64()
+        ));
+
+        let swings = self.largest_swings(SWINGS_REPORTED);
+        if !swings.is_empty() {
+            let swings = swings
+                .iter()
+                .map(|(move_num, before, after)| format!("move {move_num} ({before} -> {after})"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            lines.push(format!("largest eval drops: {swings}"));
+        }
+
+        lines.push(format!(
+            "book moves {}, tablebase moves {}",
+            count_source(MoveSource::Book),
+            count_source(MoveSource::Tablebase),
+        ));
+        lines
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{GameSummary, MoveRecord, MoveSource};
+    use crate::types::{Depth, Value};
+
+    fn record(
+        source: MoveSource,
+        depth: Depth,
+        nodes: u64,
+        millis: u64,
+        value: Value,
+    ) -> MoveRecord {
+        MoveRecord {
+            depth,
+            nodes,
+            time: Duration::from_millis(millis),
+            value,
+            source,
+        }
+    }
+
+    #[test]
+    fn empty_summary_reports_nothing() {
+        let summary = GameSummary::default();
+        assert!(summary.is_empty());
+        assert!(summary.report().is_empty());
+    }
+
+    #[test]
+    fn averages_only_cover_searched_moves() {
+        let mut summary = GameSummary::default();
+        summary.record(record(MoveSource::Book, 0, 0, 0, 0));
+        summary.record(record(MoveSource::Search, 10, 1000, 200, 20));
+        summary.record(record(MoveSource::Search, 13, 3000, 300, 20));
+        summary.record(record(MoveSource::Tablebase, 0, 0, 1, 20));
+
+        let report = summary.report();
+        assert_eq!(
+            report,
+            [
+                "game summary: 4 moves, 2 searched, avg depth 11.5, avg nodes 2000",
+                "time used 0.5s, per move (ms): 0 200 300 1",
+                "book moves 1, tablebase moves 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn largest_drops_come_first() {
+        let mut summary = GameSummary::default();
+        for value in [50, 40, 60, -20, 0, -10] {
+            summary.record(record(MoveSource::Search, 8, 100, 10, value));
+        }
+        assert_eq!(summary.largest_swings(2), [(4, 60, -20), (2, 50, 40)]);
+        assert!(summary.report()[2].starts_with("largest eval drops: move 4 (60 -> -20)"));
+
+        summary.clear();
+        assert!(summary.is_empty());
+    }
+}
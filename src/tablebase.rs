@@ -0,0 +1,218 @@
+use std::cmp::Ordering;
+
+use cozy_chess::{
+    get_bishop_moves, get_king_moves, get_knight_moves, get_pawn_attacks, get_rook_moves, BitBoard,
+    Board, Color, File, Move, Piece, Rank, Square,
+};
+use pyrrhic_rs::{DtzProbeValue, EngineAdapter, TableBases, WdlProbeResult};
+
+// Syzygy tables exist for up to 7 pieces
+pub const MAX_TB_PIECES: usize = 7;
+
+// Lets the prober generate attacks with cozy-chess
+#[derive(Debug, Clone, Copy)]
+struct CozyAdapter;
+
+#[allow(clippy::cast_possible_truncation)]
+fn square(sq: u64) -> Square {
+    Square::index(sq as usize)
+}
+
+impl EngineAdapter for CozyAdapter {
+    fn pawn_attacks(color: pyrrhic_rs::Color, sq: u64) -> u64 {
+        let color = match color {
+            pyrrhic_rs::Color::White => Color::White,
+            pyrrhic_rs::Color::Black => Color::Black,
+        };
+        get_pawn_attacks(square(sq), color).0
+    }
+
+    fn knight_attacks(sq: u64) -> u64 {
+        get_knight_moves(square(sq)).0
+    }
+
+    fn bishop_attacks(sq: u64, occupied: u64) -> u64 {
+        get_bishop_moves(square(sq), BitBoard(occupied)).0
+    }
+
+    fn rook_attacks(sq: u64, occupied: u64) -> u64 {
+        get_rook_moves(square(sq), BitBoard(occupied)).0
+    }
+
+    fn queen_attacks(sq: u64, occupied: u64) -> u64 {
+        Self::bishop_attacks(sq, occupied) | Self::rook_attacks(sq, occupied)
+    }
+
+    fn king_attacks(sq: u64) -> u64 {
+        get_king_moves(square(sq)).0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wdl {
+    Loss,
+    // Lost, but drawn under the 50 move rule
+    BlessedLoss,
+    Draw,
+    // Won, but drawn under the 50 move rule
+    CursedWin,
+    Win,
+}
+
+impl Wdl {
+    const fn from_probe(result: WdlProbeResult) -> Self {
+        match result {
+            WdlProbeResult::Loss => Self::Loss,
+            WdlProbeResult::BlessedLoss => Self::BlessedLoss,
+            WdlProbeResult::Draw => Self::Draw,
+            WdlProbeResult::CursedWin => Self::CursedWin,
+            WdlProbeResult::Win => Self::Win,
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Loss => "loss",
+            Self::BlessedLoss => "blessed loss",
+            Self::Draw => "draw",
+            Self::CursedWin => "cursed win",
+            Self::Win => "win",
+        }
+    }
+}
+
+// Outcome of the root position with best play, and the moves that keep it
+#[derive(Debug, Clone)]
+pub struct RootProbe {
+    pub wdl: Wdl,
+    pub dtz: u32,
+    pub moves: Vec<Move>,
+}
+
+#[derive(Debug)]
+pub struct Tablebase {
+    tables: TableBases<CozyAdapter>,
+}
+
+impl Tablebase {
+    pub fn open(path: &str) -> Result<Self, String> {
+        TableBases::new(path)
+            .map(|tables| Self { tables })
+            .map_err(|e| format!("{e:?}"))
+    }
+
+    pub fn max_pieces(&self) -> usize {
+        self.tables.max_pieces() as usize
+    }
+
+    // Probes the DTZ tables for every root move. Among the moves that keep the best outcome, only
+    // those closest to zeroing the 50 move counter are kept when winning (and the furthest when
+    // losing), so that the engine makes progress instead of shuffling.
+    pub fn probe_root(&self, board: &Board) -> Option<RootProbe> {
+        if !can_castle_neither(board) {
+            return None;
+        }
+
+        let ep = en_passant_square(board);
+        let result = self
+            .tables
+            .probe_root(
+                board.colors(Color::White).0,
+                board.colors(Color::Black).0,
+                board.pieces(Piece::King).0,
+                board.pieces(Piece::Queen).0,
+                board.pieces(Piece::Rook).0,
+                board.pieces(Piece::Bishop).0,
+                board.pieces(Piece::Knight).0,
+                board.pieces(Piece::Pawn).0,
+                u32::from(board.halfmove_clock()),
+                ep,
+                board.side_to_move() == Color::White,
+            )
+            .ok()?;
+
+        let mut scored = Vec::new();
+        for value in &result.moves[..result.num_moves] {
+            let DtzProbeValue::DtzResult(res) = value else {
+                continue;
+            };
+            let promotion = match res.promotion {
+                pyrrhic_rs::Piece::Queen => Some(Piece::Queen),
+                pyrrhic_rs::Piece::Rook => Some(Piece::Rook),
+                pyrrhic_rs::Piece::Bishop => Some(Piece::Bishop),
+                pyrrhic_rs::Piece::Knight => Some(Piece::Knight),
+                _ => None,
+            };
+            let mv = Move {
+                from: Square::index(usize::from(res.from_square)),
+                to: Square::index(usize::from(res.to_square)),
+                promotion,
+            };
+            if board.is_legal(mv) {
+                scored.push((mv, Wdl::from_probe(res.wdl), res.dtz));
+            }
+        }
+
+        let wdl = scored.iter().map(|&(_, wdl, _)| wdl).max()?;
+        scored.retain(|&(_, move_wdl, _)| move_wdl == wdl);
+        let dtzs = scored.iter().map(|&(_, _, dtz)| dtz);
+        let dtz = match wdl.cmp(&Wdl::Draw) {
+            Ordering::Greater => dtzs.min()?,
+            Ordering::Less => dtzs.max()?,
+            Ordering::Equal => 0,
+        };
+        if wdl != Wdl::Draw {
+            scored.retain(|&(_, _, move_dtz)| move_dtz == dtz);
+        }
+
+        Some(RootProbe {
+            wdl,
+            dtz,
+            moves: scored.into_iter().map(|(mv, _, _)| mv).collect(),
+        })
+    }
+
+    // Outcome of the position with best play, from the side to move's point of view. The WDL
+    // tables assume the 50 move counter was just reset, so other positions aren't probed.
+    pub fn probe_wdl(&self, board: &Board) -> Option<Wdl> {
+        if board.halfmove_clock() != 0 || !can_castle_neither(board) {
+            return None;
+        }
+
+        self.tables
+            .probe_wdl(
+                board.colors(Color::White).0,
+                board.colors(Color::Black).0,
+                board.pieces(Piece::King).0,
+                board.pieces(Piece::Queen).0,
+                board.pieces(Piece::Rook).0,
+                board.pieces(Piece::Bishop).0,
+                board.pieces(Piece::Knight).0,
+                board.pieces(Piece::Pawn).0,
+                en_passant_square(board),
+                board.side_to_move() == Color::White,
+            )
+            .ok()
+            .map(Wdl::from_probe)
+    }
+}
+
+// The tables don't cover positions where castling is still possible
+fn can_castle_neither(board: &Board) -> bool {
+    [Color::White, Color::Black].into_iter().all(|color| {
+        let rights = board.castle_rights(color);
+        rights.short.is_none() && rights.long.is_none()
+    })
+}
+
+// In the prober's encoding, 0 when there is no en passant capture
+fn en_passant_square(board: &Board) -> u32 {
+    board.en_passant().map_or(0, |file: File| {
+        let rank = if board.side_to_move() == Color::White {
+            Rank::Sixth
+        } else {
+            Rank::Third
+        };
+        Square::new(file, rank) as u32
+    })
+}
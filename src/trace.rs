@@ -0,0 +1,167 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use cozy_chess::Move;
+
+use crate::{
+    checkpoint::{pack_move, unpack_move},
+    types::{Depth, Value},
+};
+
+const MAGIC: &[u8; 4] = b"CETR";
+const VERSION: u8 = 1;
+// Bytes of one record: hash, ply, depth, alpha, beta, score, move and reason
+const RECORD_SIZE: usize = 8 + 1 + 1 + 2 + 2 + 2 + 2 + 1;
+
+// Why a node returned the score it did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceReason {
+    // The moves of the node were searched
+    Searched,
+    // The search was stopped, and the score means nothing
+    Stopped,
+    // Repetition, or the upcoming one of the side to move
+    Repetition,
+    TtCutoff,
+    // Mate, stalemate or another draw by the rules
+    GameOver,
+    Tablebase,
+    // Depth ran out and quiescence search took over
+    Quiescence,
+    NullMovePruning,
+    ReverseFutilityPruning,
+    EnhancedTtCutoff,
+}
+
+impl TraceReason {
+    const ALL: [Self; 10] = [
+        Self::Searched,
+        Self::Stopped,
+        Self::Repetition,
+        Self::TtCutoff,
+        Self::GameOver,
+        Self::Tablebase,
+        Self::Quiescence,
+        Self::NullMovePruning,
+        Self::ReverseFutilityPruning,
+        Self::EnhancedTtCutoff,
+    ];
+}
+
+// One node of the search tree, with the window it was searched with and the result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub hash: u64,
+    pub ply: u8,
+    pub depth: Depth,
+    pub alpha: Value,
+    pub beta: Value,
+    pub score: Value,
+    // Move that raised alpha last, `NULL_MOVE` if none did
+    pub best_move: Move,
+    pub reason: TraceReason,
+}
+
+// Search Tracing
+// Writes a record of every node of the search to a file, for offline inspection of wrong scores
+// instead of adding prints to the search. Records are fixed size and little endian after a
+// short header, in the order the nodes finish, so children come before their parent.
+#[derive(Debug)]
+pub struct SearchTracer {
+    w: BufWriter<File>,
+}
+
+impl SearchTracer {
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut w = BufWriter::new(File::create(path)?);
+        w.write_all(MAGIC)?;
+        w.write_all(&[VERSION])?;
+        Ok(Self { w })
+    }
+
+    pub fn record(&mut self, record: &TraceRecord) -> io::Result<()> {
+        let mut buf = [0; RECORD_SIZE];
+        buf[..8].copy_from_slice(&record.hash.to_le_bytes());
+        buf[8] = record.ply;
+        buf[9] = record.depth;
+        buf[10..12].copy_from_slice(&record.alpha.to_le_bytes());
+        buf[12..14].copy_from_slice(&record.beta.to_le_bytes());
+        buf[14..16].copy_from_slice(&record.score.to_le_bytes());
+        buf[16..18].copy_from_slice(&pack_move(record.best_move).to_le_bytes());
+        buf[18] = record.reason as u8;
+        self.w.write_all(&buf)
+    }
+
+    // Called after every search, so that the file can be read while the engine keeps running
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+
+    pub fn load(path: &Path) -> io::Result<Vec<TraceRecord>> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let mut r = BufReader::new(File::open(path)?);
+        let mut header = [0; 5];
+        r.read_exact(&mut header)?;
+        if &header[..4] != MAGIC || header[4] != VERSION {
+            return Err(invalid("not a trace file"));
+        }
+
+        let mut bytes = Vec::new();
+        r.read_to_end(&mut bytes)?;
+        if bytes.len() % RECORD_SIZE != 0 {
+            return Err(invalid("truncated record"));
+        }
+        bytes
+            .chunks_exact(RECORD_SIZE)
+            .map(|buf| {
+                let value = |idx: usize| Value::from_le_bytes([buf[idx], buf[idx + 1]]);
+                let packed = u16::from_le_bytes([buf[16], buf[17]]);
+                Ok(TraceRecord {
+                    hash: u64::from_le_bytes(buf[..8].try_into().unwrap()),
+                    ply: buf[8],
+                    depth: buf[9],
+                    alpha: value(10),
+                    beta: value(12),
+                    score: value(14),
+                    best_move: unpack_move(packed).ok_or_else(|| invalid("invalid move"))?,
+                    reason: *TraceReason::ALL
+                        .get(usize::from(buf[18]))
+                        .ok_or_else(|| invalid("invalid reason"))?,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use cozy_chess::Board;
+
+    use crate::search::{SearchStats, Searcher};
+
+    use super::{SearchTracer, TraceReason};
+
+    #[test]
+    fn traced_search_round_trip() {
+        let path = env::temp_dir().join(format!("trace-{}.bin", std::process::id()));
+        let mut searcher = Searcher::new(1 << 20);
+        searcher.set_trace_file(Some(&path)).unwrap();
+        let mut stats = SearchStats::default();
+        let (mv, value) =
+            searcher.search_fixed_depth(&mut Board::startpos(), &Vec::new(), &mut stats, 4);
+        let records = SearchTracer::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // Quiescence nodes aren't recorded, and the root of the last iteration finishes last
+        assert!(records.len() as u64 <= stats.nodes_visited);
+        let root = records.last().unwrap();
+        assert_eq!((root.ply, root.depth), (0, 4));
+        assert_eq!((root.best_move, root.score), (mv, value));
+        assert_eq!(root.reason, TraceReason::Searched);
+    }
+}
@@ -1,8 +1,38 @@
-use std::mem::size_of;
+use std::{
+    array,
+    mem::size_of,
+    ops::RangeInclusive,
+    sync::atomic::{AtomicU16, AtomicU64, AtomicU8, Ordering},
+    thread,
+};
 
 use cozy_chess::Move;
 
-#[derive(Debug, Copy, Clone)]
+use crate::{
+    checkpoint::{pack_move, unpack_move},
+    search::is_decisive_score,
+    types::Value,
+    utils::NULL_MOVE,
+};
+
+// Slots of the table of nodes being searched. Only nodes near the root are marked, so this can
+// be much smaller than the table of entries.
+const BUSY_SLOTS: usize = 1 << 14;
+// Entries a position can be stored in: all but the last are depth-preferred, and the last is
+// always replaced
+const BUCKET_SIZE: usize = 3;
+const DEPTH_PREFERRED_SLOTS: usize = BUCKET_SIZE - 1;
+const ALWAYS_REPLACE_SLOT: usize = BUCKET_SIZE - 1;
+// Buckets a position is looked for in: its own and the ones after it
+const PROBE_BUCKETS: usize = 2;
+// An entry of the current search is only replaced by one of a different position if that was
+// searched at most this much shallower
+const REPLACE_DEPTH_MARGIN: u8 = 4;
+// Low bits of an entry's flags that hold its node type
+const NODE_TYPE_BITS: u8 = 2;
+const NODE_TYPE_MASK: u8 = (1 << NODE_TYPE_BITS) - 1;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum NodeType {
     Exact,
     UpperBound,
@@ -13,42 +43,691 @@ pub enum NodeType {
 pub struct TTEntry {
     pub hash: u64,
     pub best_move: Move,
-    pub best_value: i16,
+    pub best_value: Value,
+    // Static eval of the position before correction, kept apart from the search score since that
+    // is often only a bound
+    pub static_eval: Value,
     pub depth: u8,
     pub node_type: NodeType,
 }
 
+// An entry as it is stored: only the low bits of the hash, as the bucket already stands for the
+// top ones, the move packed like in checkpoints, and the node type and the generation of the
+// search that stored it in one byte. At 10 bytes rather than the 24 of a `TTEntry`, more than
+// twice as many entries fit in the same memory, and a bucket in a fraction of a cache line. Two
+// positions of a bucket with the same low bits can't be told apart, which the search copes with
+// like with any other collision.
+#[derive(Debug, Copy, Clone)]
+struct PackedEntry {
+    key: u16,
+    best_move: u16,
+    best_value: Value,
+    static_eval: Value,
+    depth: u8,
+    // The generation above `NODE_TYPE_BITS`, and below them the node type plus one, 0 for an
+    // empty slot
+    flags: u8,
+}
+
+impl PackedEntry {
+    const EMPTY: Self = Self {
+        key: 0,
+        best_move: 0,
+        best_value: 0,
+        static_eval: 0,
+        depth: 0,
+        flags: 0,
+    };
+
+    #[allow(clippy::cast_possible_truncation)]
+    const fn key(hash: u64) -> u16 {
+        hash as u16
+    }
+
+    fn pack(hash: u64, value: TTEntry, generation: u8) -> Self {
+        let node_type = match value.node_type {
+            NodeType::Exact => 1,
+            NodeType::UpperBound => 2,
+            NodeType::LowerBound => 3,
+        };
+        Self {
+            key: Self::key(hash),
+            best_move: pack_move(value.best_move),
+            best_value: value.best_value,
+            static_eval: value.static_eval,
+            depth: value.depth,
+            flags: generation << NODE_TYPE_BITS | node_type,
+        }
+    }
+
+    fn unpack(self, hash: u64) -> TTEntry {
+        TTEntry {
+            hash,
+            best_move: unpack_move(self.best_move).unwrap_or(NULL_MOVE),
+            best_value: self.best_value,
+            static_eval: self.static_eval,
+            depth: self.depth,
+            node_type: match self.flags & NODE_TYPE_MASK {
+                1 => NodeType::Exact,
+                2 => NodeType::UpperBound,
+                _ => NodeType::LowerBound,
+            },
+        }
+    }
+
+    const fn is_empty(self) -> bool {
+        self.flags & NODE_TYPE_MASK == 0
+    }
+
+    const fn generation(self) -> u8 {
+        self.flags >> NODE_TYPE_BITS
+    }
+
+    // Everything but the key in one word
+    #[allow(clippy::cast_sign_loss)]
+    fn data(self) -> u64 {
+        u64::from(self.best_move)
+            | u64::from(self.best_value as u16) << 16
+            | u64::from(self.static_eval as u16) << 32
+            | u64::from(self.depth) << 48
+            | u64::from(self.flags) << 56
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    const fn from_data(key: u16, data: u64) -> Self {
+        Self {
+            key,
+            best_move: data as u16,
+            best_value: (data >> 16) as u16 as Value,
+            static_eval: (data >> 32) as u16 as Value,
+            depth: (data >> 48) as u8,
+            flags: (data >> 56) as u8,
+        }
+    }
+}
+
+// Lockless Hashing
+// Buckets are shared between all search threads without any lock. An entry is stored as two
+// words written one after the other: its data, and its key XORed with a checksum of the data. A
+// thread that reads the data of one write together with the key of another gets a key that
+// matches neither, so a torn entry is a miss rather than the score of another position. When two
+// threads store to the same entry at once one of them is lost, as if it had been replaced.
+#[derive(Debug, Default)]
+struct Bucket {
+    keys: [AtomicU16; BUCKET_SIZE],
+    data: [AtomicU64; BUCKET_SIZE],
+}
+
+impl Bucket {
+    fn load(&self, i: usize) -> PackedEntry {
+        let data = self.data[i].load(Ordering::Relaxed);
+        let key = self.keys[i].load(Ordering::Relaxed) ^ checksum(data);
+        PackedEntry::from_data(key, data)
+    }
+
+    fn entries(&self) -> [PackedEntry; BUCKET_SIZE] {
+        array::from_fn(|i| self.load(i))
+    }
+
+    fn store(&self, i: usize, entry: PackedEntry) {
+        let data = entry.data();
+        self.data[i].store(data, Ordering::Relaxed);
+        self.keys[i].store(entry.key ^ checksum(data), Ordering::Relaxed);
+    }
+
+    fn clear(&self) {
+        for i in 0..BUCKET_SIZE {
+            self.store(i, PackedEntry::EMPTY);
+        }
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+const fn checksum(data: u64) -> u16 {
+    (data ^ data >> 16 ^ data >> 32 ^ data >> 48) as u16
+}
+
+// Shared between all search threads, see Lockless Hashing
 #[derive(Debug)]
 pub struct TranspositionTable {
-    buffer: Vec<Option<TTEntry>>,
+    buffer: Vec<Bucket>,
+    generation: AtomicU8,
+    // Hashes of the nodes some thread is searching right now, for ABDADA. A collision only makes
+    // a thread defer a move it didn't have to, or not defer one it could have.
+    busy: Vec<AtomicU64>,
 }
 
 impl TranspositionTable {
     pub fn new(bytes: usize) -> Self {
         Self {
-            buffer: vec![None; bytes_to_entries(bytes)],
+            buffer: (0..bytes_to_buckets(bytes).max(1))
+                .map(|_| Bucket::default())
+                .collect(),
+            generation: AtomicU8::new(0),
+            busy: (0..BUSY_SLOTS).map(|_| AtomicU64::new(0)).collect(),
         }
     }
 
-    #[allow(clippy::cast_possible_truncation)]
+    // Aging
+    // Every search starts a new generation of entries. Entries of earlier generations are
+    // still probed, as the positions after the next move are mostly ones the last search already
+    // looked at, but they are the first to be replaced. So the table keeps what is useful across
+    // moves and games instead of being cleared, while the deepest entries of the current search
+    // aren't lost to a stream of shallow ones. Generations wrap around after 64 of them, by when
+    // hardly any entry of the one with the same number is left.
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn generation(&self) -> u8 {
+        self.generation.load(Ordering::Relaxed) & (u8::MAX >> NODE_TYPE_BITS)
+    }
+
+    fn index(&self, hash: u64) -> usize {
+        bucket_index(hash, self.buffer.len())
+    }
+
+    pub fn buckets(&self) -> usize {
+        self.buffer.len()
+    }
+
+    fn bucket(&self, hash: u64) -> &Bucket {
+        &self.buffer[self.index(hash)]
+    }
+
+    // Multi-Probe
+    // A position is looked for and stored in the same short sequence of buckets, so that a burst
+    // of collisions in one bucket spills over to its neighbours instead of pushing out entries
+    // that are still useful. The last bucket wraps around to the first.
+    fn probe_buckets(&self, hash: u64) -> [&Bucket; PROBE_BUCKETS] {
+        let index = self.index(hash);
+        array::from_fn(|i| &self.buffer[(index + i) % self.buffer.len()])
+    }
+
+    // Prefetching
+    // Probing a bucket that isn't in the cache stalls the search on memory. The search asks for
+    // the bucket of a child as soon as it knows the child's hash, so that the bucket is loaded
+    // while the move is being pruned, reduced and extended, and is there by the time the child
+    // probes it.
+    pub fn prefetch(&self, hash: u64) {
+        #[cfg(target_arch = "x86_64")]
+        {
+            use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+
+            let bucket: *const Bucket = self.bucket(hash);
+            // SAFETY: a prefetch is only a hint to the cache, and the address is valid anyway
+            unsafe { _mm_prefetch::<_MM_HINT_T0>(bucket.cast()) };
+        }
+        #[cfg(not(target_arch = "x86_64"))]
+        let _ = hash;
+    }
+
     pub fn get(&self, hash: u64) -> Option<TTEntry> {
-        let idx = hash as usize % self.buffer.len();
-        self.buffer[idx].filter(|&tte| tte.hash == hash)
+        let key = PackedEntry::key(hash);
+        self.probe_buckets(hash)
+            .into_iter()
+            .flat_map(Bucket::entries)
+            .find(|entry| !entry.is_empty() && entry.key == key)
+            .map(|entry| entry.unpack(hash))
+    }
+
+    // Replaces the entry of the same position, and otherwise the least valuable depth-preferred
+    // entry of its buckets unless that was searched a good deal deeper in this search
+    pub fn set(&self, hash: u64, value: TTEntry) {
+        self.store(hash, value, REPLACE_DEPTH_MARGIN);
+    }
+
+    // Like `set`, but keeps any entry of this generation that was searched deeper. Quiescence
+    // search stores far more entries than the main search, which would otherwise push out the
+    // entries that took the most work.
+    pub fn set_shallow(&self, hash: u64, value: TTEntry) {
+        self.store(hash, value, 0);
+    }
+
+    // Replacement
+    // A position goes to a bucket by its hash, and can take any entry of it, see Multi-Probe for
+    // which. So a collision only pushes out the least valuable of a few entries rather than
+    // whatever was in a single slot.
+    // The entry of the same position is updated wherever it is. Otherwise the depth-preferred
+    // entries keep the ones that took the most work: an empty one is filled, else the one of an
+    // earlier generation or the shallowest one is replaced, unless it was searched more than
+    // `depth_margin` deeper in this search. The always-replace entry takes whatever they keep out,
+    // and the entry of this search they push out, so that recent shallow entries are still found
+    // while deep ones survive.
+    fn store(&self, hash: u64, value: TTEntry, depth_margin: u8) {
+        let generation = self.generation();
+        let key = PackedEntry::key(hash);
+        let packed = PackedEntry::pack(hash, value, generation);
+        let buckets = self.probe_buckets(hash);
+        let entries = buckets.map(Bucket::entries);
+        for (bucket, entries) in buckets.iter().zip(&entries) {
+            if let Some(i) = entries
+                .iter()
+                .position(|entry| !entry.is_empty() && entry.key == key)
+            {
+                bucket.store(i, packed);
+                return;
+            }
+        }
+
+        let (b, victim) = (0..PROBE_BUCKETS)
+            .flat_map(|b| (0..DEPTH_PREFERRED_SLOTS).map(move |i| (b, i)))
+            .min_by_key(|&(b, i)| {
+                let entry = entries[b][i];
+                (
+                    !entry.is_empty(),
+                    entry.generation() == generation,
+                    entry.depth,
+                )
+            })
+            .unwrap();
+        let bucket = buckets[b];
+        let old = entries[b][victim];
+        if old.is_empty()
+            || old.generation() != generation
+            || old.depth <= value.depth.saturating_add(depth_margin)
+        {
+            bucket.store(victim, packed);
+            if !old.is_empty() && old.generation() == generation {
+                bucket.store(ALWAYS_REPLACE_SLOT, old);
+            }
+        } else {
+            bucket.store(ALWAYS_REPLACE_SLOT, packed);
+        }
     }
 
     #[allow(clippy::cast_possible_truncation)]
-    pub fn set(&mut self, hash: u64, value: TTEntry) {
-        let idx = hash as usize % self.buffer.len();
-        self.buffer[idx] = Some(value);
+    fn busy_slot(&self, hash: u64) -> &AtomicU64 {
+        &self.busy[hash as usize % BUSY_SLOTS]
+    }
+
+    pub fn mark_busy(&self, hash: u64) {
+        self.busy_slot(hash).store(hash, Ordering::Relaxed);
+    }
+
+    // Leaves the slot alone if another node has taken it over in the meantime
+    pub fn clear_busy(&self, hash: u64) {
+        let _ = self
+            .busy_slot(hash)
+            .compare_exchange(hash, 0, Ordering::Relaxed, Ordering::Relaxed);
+    }
+
+    pub fn is_busy(&self, hash: u64) -> bool {
+        self.busy_slot(hash).load(Ordering::Relaxed) == hash
+    }
+
+    // Every stored entry, e.g. for saving the table to disk. Only the low bits of the hashes are
+    // kept, so an entry comes with a hash with those low bits that goes to its bucket, which
+    // stands for its position in a table of the same size, see `bucket_hash`.
+    pub fn entries(&self) -> impl Iterator<Item = TTEntry> + '_ {
+        let buckets = self.buffer.len();
+        self.buffer
+            .iter()
+            .enumerate()
+            .flat_map(move |(index, bucket)| {
+                bucket
+                    .entries()
+                    .into_iter()
+                    .filter(|entry| !entry.is_empty())
+                    .map(move |entry| entry.unpack(bucket_hash(index, buckets, entry.key)))
+            })
+    }
+
+    // Resizing
+    // A table of `bytes`, which needn't hold a power of two of buckets, with the entries of the
+    // last search, so that changing the `Hash` option mid-game doesn't throw away what the engine
+    // has learned. The packed keys don't tell where in the range of hashes of its bucket a
+    // position was. In a table no larger, that range overlaps at most two buckets, and the entry
+    // goes to both. In a larger table it overlaps many, and copies in all of them would crowd
+    // out the entries that belong there, so the entry only goes to the first. Positions of the
+    // other buckets lose their entry.
+    pub fn resized(&self, bytes: usize) -> Self {
+        let resized = Self::new(bytes);
+        resized
+            .generation
+            .store(self.generation.load(Ordering::Relaxed), Ordering::Relaxed);
+        let generation = self.generation();
+        let (old_len, new_len) = (self.buffer.len(), resized.buffer.len());
+        for (index, bucket) in self.buffer.iter().enumerate() {
+            for entry in bucket.entries() {
+                if entry.is_empty() || entry.generation() != generation {
+                    continue;
+                }
+                for new_index in resized.placements(index, old_len) {
+                    let hash = bucket_hash(new_index, new_len, entry.key);
+                    resized.set(hash, entry.unpack(hash));
+                }
+            }
+        }
+        resized
+    }
+
+    // Buckets of this table an entry of the bucket at `index` of a table of `buckets` goes to,
+    // see Resizing
+    fn placements(&self, index: usize, buckets: usize) -> RangeInclusive<usize> {
+        let first = self.index(bucket_start(index, buckets));
+        if self.buffer.len() > buckets {
+            return first..=first;
+        }
+        let last = self.index(bucket_start(index + 1, buckets) - 1);
+        first..=last
+    }
+
+    // Stores entries saved by `entries` from a table of `buckets`, which may be of another size,
+    // in the buckets of this table they go to as in `resized`
+    pub fn restore(&self, entries: &[TTEntry], buckets: usize) {
+        for entry in entries {
+            let key = PackedEntry::key(entry.hash);
+            for index in self.placements(bucket_index(entry.hash, buckets), buckets) {
+                let hash = bucket_hash(index, self.buffer.len(), key);
+                self.set(hash, TTEntry { hash, ..*entry });
+            }
+        }
     }
 
-    pub fn clear(&mut self) {
-        for i in 0..self.buffer.len() {
-            self.buffer[i] = None;
+    // Approximate occupancy by entries of the current search in permille, sampled from the start
+    // of the table like most engines do
+    pub fn hashfull(&self) -> usize {
+        let generation = self.generation();
+        let sample = &self.buffer[..self.buffer.len().min(1000 / BUCKET_SIZE)];
+        let filled = sample
+            .iter()
+            .map(|bucket| {
+                bucket
+                    .entries()
+                    .iter()
+                    .filter(|entry| !entry.is_empty() && entry.generation() == generation)
+                    .count()
+            })
+            .sum::<usize>();
+        filled * 1000 / (sample.len() * BUCKET_SIZE)
+    }
+
+    // Empties the table, split into one chunk per thread, as clearing a large table one bucket
+    // at a time takes long enough to delay the next search
+    pub fn clear(&self, threads: usize) {
+        let chunk_size = self.buffer.len().div_ceil(threads.max(1));
+        thread::scope(|s| {
+            for chunk in self.buffer.chunks(chunk_size) {
+                s.spawn(|| chunk.iter().for_each(Bucket::clear));
+            }
+        });
+        for slot in &self.busy {
+            slot.store(0, Ordering::Relaxed);
         }
     }
 }
 
-const fn bytes_to_entries(bytes: usize) -> usize {
-    bytes / size_of::<Option<TTEntry>>()
+const fn bytes_to_buckets(bytes: usize) -> usize {
+    bytes / size_of::<Bucket>()
+}
+
+// The bucket of a hash in a table of `buckets` by the multiply-shift reduction, which maps hashes
+// to any number of buckets evenly without the division of `hash % len`, by their top bits
+#[allow(clippy::cast_possible_truncation)]
+fn bucket_index(hash: u64, buckets: usize) -> usize {
+    ((u128::from(hash) * buckets as u128) >> 64) as usize
+}
+
+// The first hash that goes to the bucket at `index` of `buckets`, and the last hash of all for
+// the end of the table
+#[allow(clippy::cast_possible_truncation)]
+fn bucket_start(index: usize, buckets: usize) -> u64 {
+    ((index as u128) << 64)
+        .div_ceil(buckets as u128)
+        .min(u128::from(u64::MAX)) as u64
+}
+
+// A hash with the given key that goes to the bucket at `index` of `buckets`: the first hash of
+// the bucket rounded up to the next multiple of 2^16, as a bucket spans far more hashes than that
+fn bucket_hash(index: usize, buckets: usize, key: u16) -> u64 {
+    bucket_start(index, buckets).next_multiple_of(1 << 16) | u64::from(key)
+}
+
+// Mate and tablebase scores count plies from the root, but an entry can be reached again at any
+// ply. They are stored counting from the entry's own node instead, and converted back when probed.
+pub fn value_to_tt(value: Value, ply: u8) -> Value {
+    if !is_decisive_score(value) {
+        value
+    } else if value > 0 {
+        value + Value::from(ply)
+    } else {
+        value - Value::from(ply)
+    }
+}
+
+pub fn value_from_tt(value: Value, ply: u8) -> Value {
+    if !is_decisive_score(value) {
+        value
+    } else if value > 0 {
+        value - Value::from(ply)
+    } else {
+        value + Value::from(ply)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{mem::size_of, sync::atomic::Ordering};
+
+    use cozy_chess::Move;
+
+    use crate::search::MATE_VALUE;
+
+    use super::{
+        value_from_tt, value_to_tt, Bucket, NodeType, PackedEntry, TTEntry, TranspositionTable,
+    };
+
+    #[test]
+    fn buckets_keep_the_most_valuable_entries() {
+        // One bucket, so that every position collides
+        let tt = TranspositionTable::new(1);
+        let set = |n, depth, shallow| {
+            let entry = TTEntry {
+                hash: n,
+                best_move: "e2e4".parse::<Move>().unwrap(),
+                best_value: 0,
+                static_eval: 0,
+                depth,
+                node_type: NodeType::Exact,
+            };
+            if shallow {
+                tt.set_shallow(n, entry);
+            } else {
+                tt.set(n, entry);
+            }
+        };
+        let depth = |n| tt.get(n).map(|tte| tte.depth);
+        set(1, 12, false);
+        set(2, 10, false);
+        // Much shallower entries don't push out deep ones of the same search, and go to the
+        // always-replace entry instead
+        set(3, 3, false);
+        assert_eq!(tt.hashfull(), 1000);
+        set(4, 0, true);
+        assert_eq!(depth(3), None);
+        assert_eq!(depth(4), Some(0));
+        // A deep enough one replaces the shallowest, which takes the always-replace entry
+        set(5, 8, false);
+        assert_eq!(depth(5), Some(8));
+        assert_eq!(depth(2), Some(10));
+        assert_eq!(depth(4), None);
+
+        // From an earlier search, they are still probed, but give way to anything
+        tt.new_search();
+        assert_eq!(tt.hashfull(), 0);
+        set(1, 13, false);
+        set(6, 0, true);
+        assert_eq!(depth(6), Some(0));
+        assert_eq!(depth(5), None);
+        assert_eq!(depth(2), Some(10));
+        assert_eq!(depth(1), Some(13));
+        // The same position is always updated
+        set(6, 1, false);
+        assert_eq!(depth(6), Some(1));
+    }
+
+    #[test]
+    fn collisions_spill_over_to_the_next_bucket() {
+        // Two buckets, and every hash goes to the first
+        let tt = TranspositionTable::new(2 * size_of::<Bucket>());
+        for hash in 1..=5 {
+            tt.set(
+                hash,
+                TTEntry {
+                    hash,
+                    best_move: "e2e4".parse::<Move>().unwrap(),
+                    best_value: 0,
+                    static_eval: 0,
+                    depth: 10,
+                    node_type: NodeType::Exact,
+                },
+            );
+        }
+        assert!((1..=5).all(|hash| tt.get(hash).is_some()));
+        assert_eq!(tt.entries().count(), 5);
+    }
+
+    #[test]
+    fn hashes_spread_over_every_bucket() {
+        let tt = TranspositionTable::new(1000 * size_of::<Bucket>());
+        assert_eq!(tt.buffer.len(), 1000);
+        assert_eq!(tt.index(0), 0);
+        assert_eq!(tt.index(u64::MAX / 2), 499);
+        assert_eq!(tt.index(u64::MAX), 999);
+    }
+
+    #[test]
+    fn clear_empties_every_chunk() {
+        let tt = TranspositionTable::new(10 * size_of::<Bucket>());
+        let hashes = (0..10).map(|i| i * (u64::MAX / 10) + 7).collect::<Vec<_>>();
+        for &hash in &hashes {
+            tt.set(
+                hash,
+                TTEntry {
+                    hash,
+                    best_move: "d2d4".parse::<Move>().unwrap(),
+                    best_value: 10,
+                    static_eval: 10,
+                    depth: 4,
+                    node_type: NodeType::Exact,
+                },
+            );
+        }
+        assert_eq!(tt.entries().count(), 10);
+        // Three threads get uneven chunks of the ten buckets
+        tt.clear(3);
+        assert_eq!(tt.entries().count(), 0);
+        assert!(hashes.iter().all(|&hash| tt.get(hash).is_none()));
+    }
+
+    #[test]
+    fn resizing_keeps_the_last_search() {
+        let tt = TranspositionTable::new(4 * size_of::<Bucket>());
+        let entry = |hash, depth| TTEntry {
+            hash,
+            best_move: "b1c3".parse::<Move>().unwrap(),
+            best_value: -25,
+            static_eval: -10,
+            depth,
+            node_type: NodeType::LowerBound,
+        };
+        let old = 0x0123_4567_89ab_cdef;
+        tt.set(old, entry(old, 6));
+        tt.new_search();
+        let hashes = [
+            0x1234_5678_9abc_def0,
+            0x9876_5432_1fed_cba9,
+            0xdead_beef_0000_1111,
+        ];
+        for (depth, hash) in (1..).zip(hashes) {
+            tt.set(hash, entry(hash, depth));
+        }
+        // Smaller tables, with bucket boundaries that don't always line up, and one of the same
+        // size find every entry
+        for buckets in [2, 3, 4] {
+            let resized = tt.resized(buckets * size_of::<Bucket>());
+            for (depth, hash) in (1..).zip(hashes) {
+                assert_eq!(resized.get(hash).map(|tte| tte.depth), Some(depth));
+            }
+            assert!(resized.get(old).is_none());
+        }
+        // Larger ones get a single copy of each entry
+        for buckets in [7, 1000] {
+            let resized = tt.resized(buckets * size_of::<Bucket>());
+            assert_eq!(resized.entries().count(), hashes.len());
+        }
+    }
+
+    #[test]
+    fn entries_are_packed() {
+        let tt = TranspositionTable::new(1 << 12);
+        let hash = 0x1234_5678_9abc_def0;
+        let entry = TTEntry {
+            hash,
+            best_move: "a7a8q".parse::<Move>().unwrap(),
+            best_value: -(MATE_VALUE - 3),
+            static_eval: 42,
+            depth: 17,
+            node_type: NodeType::UpperBound,
+        };
+        let fields = |tte: TTEntry| {
+            (
+                tte.best_move,
+                tte.best_value,
+                tte.static_eval,
+                tte.depth,
+                tte.node_type,
+            )
+        };
+        tt.set(hash, entry);
+        assert_eq!(tt.get(hash).map(fields), Some(fields(entry)));
+        assert!(tt.get(hash ^ 1).is_none());
+
+        // Saved entries come with a hash that finds them again in a table of the same size, and
+        // are placed again for a smaller one
+        let saved = tt.entries().collect::<Vec<_>>();
+        assert_eq!(saved.len(), 1);
+        let restored = TranspositionTable::new(1 << 12);
+        restored.set(saved[0].hash, saved[0]);
+        assert_eq!(restored.get(hash).map(fields), Some(fields(entry)));
+        for bytes in [1 << 10, 3000] {
+            let restored = TranspositionTable::new(bytes);
+            restored.restore(&saved, tt.buckets());
+            assert_eq!(restored.get(hash).map(fields), Some(fields(entry)));
+        }
+    }
+
+    #[test]
+    fn torn_entries_are_misses() {
+        let tt = TranspositionTable::new(1);
+        let entry = |hash, depth| TTEntry {
+            hash,
+            best_move: "g1f3".parse::<Move>().unwrap(),
+            best_value: 30,
+            static_eval: 20,
+            depth,
+            node_type: NodeType::LowerBound,
+        };
+        let [first, second] = [1, 2];
+        tt.set(first, entry(first, 5));
+        assert!(tt.get(first).is_some());
+        // Another thread's write has only got as far as the data
+        let data = PackedEntry::pack(second, entry(second, 9), 0).data();
+        tt.buffer[0].data[0].store(data, Ordering::Relaxed);
+        assert!(tt.get(first).is_none());
+        assert!(tt.get(second).is_none());
+    }
+
+    #[test]
+    fn mate_scores_are_relative_to_the_node() {
+        // Mate in 2 plies from a node at ply 3
+        assert_eq!(value_to_tt(MATE_VALUE - 5, 3), MATE_VALUE - 2);
+        assert_eq!(value_to_tt(-(MATE_VALUE - 5), 3), -(MATE_VALUE - 2));
+        // Reached again at ply 7
+        assert_eq!(value_from_tt(MATE_VALUE - 2, 7), MATE_VALUE - 9);
+        for value in [-300, 0, 150] {
+            assert_eq!(value_from_tt(value_to_tt(value, 4), 4), value);
+        }
+    }
 }
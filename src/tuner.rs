@@ -0,0 +1,468 @@
+use std::{fmt::Write as _, fs, path::Path};
+
+use cozy_chess::{Board, Color};
+
+use crate::{
+    cli::Flags,
+    endgame,
+    eval_params::{EvalParams, PARAM_COUNT, TABLE_WEIGHTS},
+    evaluate::{
+        evaluate_trace_with, is_insufficient_material, psqt_eval, scale_by_halfmove_clock,
+        EvalBreakdown,
+    },
+    psqts::GAME_PHASE_INC,
+};
+
+const DEFAULT_ITERATIONS: usize = 1000;
+const DEFAULT_RATE: f64 = 1.0;
+// Progress is printed every this many iterations
+const REPORT_INTERVAL: usize = 50;
+// Decay rates of Adam's running averages of the gradient and its square
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+const EPSILON: f64 = 1e-8;
+// The scaling factor K of the sigmoid is searched for in this range
+const K_RANGE: (f64, f64) = (0.05, 5.0);
+const K_SEARCH_STEPS: usize = 60;
+// Eval the 50-move scaling of a position is measured with, which the scaling divides exactly
+const SCALE_PROBE: i32 = 8000;
+// Where each group of weights starts, in the order of a weights file
+const EG_VALUE_START: usize = 6;
+const MG_TABLE_START: usize = 12;
+const EG_TABLE_START: usize = 12 + 64 * 6;
+// Change of a term weight its coefficient is measured with, large enough for the rounding of the
+// terms not to matter
+const TERM_STEP: i32 = 10;
+
+// A labeled position, reduced to what the eval does with it
+struct Entry {
+    // Indices of the weights counting towards the eval and their coefficients, from white's
+    // point of view. Piece values and tables are blended by the game phase, so coefficients are
+    // the fraction of the middle or end game the position is in.
+    features: Vec<(usize, f64)>,
+    // Part of the eval no weight changes, such as king attacks and space
+    fixed: f64,
+    // Factor of the 50-move rule and drawish ending scaling
+    scale: f64,
+    // 1 for a white win, 0.5 for a draw and 0 for a black win
+    result: f64,
+}
+
+impl Entry {
+    fn new(board: &Board, result: f64, params: &EvalParams) -> Self {
+        let game_phase = board
+            .occupied()
+            .into_iter()
+            .map(|sq| GAME_PHASE_INC[board.piece_on(sq).unwrap() as usize])
+            .sum::<i32>();
+        let mg_phase = f64::from(game_phase.min(24)) / 24.0;
+        let eg_phase = 1.0 - mg_phase;
+
+        let mut coefficients = vec![0.0; PARAM_COUNT];
+        for sq in board.occupied() {
+            let piece = board.piece_on(sq).unwrap() as usize;
+            let color = board.color_on(sq).unwrap();
+            let mut tb_idx = sq as usize;
+            if color == Color::White {
+                tb_idx ^= 0b111_000;
+            }
+            tb_idx += piece * 64;
+            let sign = if color == Color::White { 1.0 } else { -1.0 };
+
+            coefficients[piece] += sign * mg_phase;
+            coefficients[EG_VALUE_START + piece] += sign * eg_phase;
+            coefficients[MG_TABLE_START + tb_idx] += sign * mg_phase;
+            coefficients[EG_TABLE_START + tb_idx] += sign * eg_phase;
+        }
+        // The term weights' coefficients are measured instead, by how much the eval moves when
+        // the weight does
+        let mut weights = params.weights();
+        let terms = white_terms(&evaluate_trace_with(board, params));
+        for (i, coefficient) in coefficients.iter_mut().enumerate().skip(TABLE_WEIGHTS) {
+            weights[i] += TERM_STEP;
+            let stepped = params.with_weights(&weights).unwrap();
+            weights[i] -= TERM_STEP;
+            let stepped_terms = white_terms(&evaluate_trace_with(board, &stepped));
+            *coefficient = (stepped_terms - terms) / f64::from(TERM_STEP);
+        }
+        // Opposite pieces on the same squares cancel out, like the kings' values
+        let features = coefficients
+            .into_iter()
+            .enumerate()
+            .filter(|(_, coefficient)| coefficient.abs() > f64::EPSILON)
+            .collect::<Vec<_>>();
+
+        let linear = features
+            .iter()
+            .map(|&(i, coefficient)| coefficient * f64::from(weights[i]))
+            .sum::<f64>();
+        let eval = psqt_eval(board, params);
+        let white_eval = match board.side_to_move() {
+            Color::White => eval,
+            Color::Black => -eval,
+        };
+        let halfmove_scale =
+            f64::from(scale_by_halfmove_clock(board, SCALE_PROBE)) / f64::from(SCALE_PROBE);
+        Self {
+            features,
+            fixed: f64::from(white_eval) - linear,
+            scale: halfmove_scale * f64::from(endgame::scale_factor(board, eval)) / 100.0,
+            result,
+        }
+    }
+
+    fn eval(&self, weights: &[f64]) -> f64 {
+        let linear = self
+            .features
+            .iter()
+            .map(|&(i, coefficient)| coefficient * weights[i])
+            .sum::<f64>();
+        (self.fixed + linear) * self.scale
+    }
+}
+
+// White's eval of the terms, blended by the game phase like `EvalBreakdown::total` but without
+// rounding
+fn white_terms(breakdown: &EvalBreakdown) -> f64 {
+    let (white, black) = (Color::White as usize, Color::Black as usize);
+    let (mg, eg) = breakdown
+        .terms()
+        .iter()
+        .fold((0, 0), |(mg, eg), (_, term)| {
+            (
+                mg + term.mg[white] - term.mg[black],
+                eg + term.eg[white] - term.eg[black],
+            )
+        });
+    let mg_phase = f64::from(breakdown.game_phase) / 24.0;
+    mg_phase * f64::from(mg)
+        + (1.0 - mg_phase) * f64::from(eg)
+        + f64::from(breakdown.mop_up[white] - breakdown.mop_up[black])
+}
+
+// Expected score of white for a white-relative eval
+fn sigmoid(k: f64, eval: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * eval / 400.0))
+}
+
+// Mean squared difference between the results and the scores the evals predict
+#[allow(clippy::cast_precision_loss)]
+fn error(entries: &[Entry], weights: &[f64], k: f64) -> f64 {
+    entries
+        .iter()
+        .map(|entry| (entry.result - sigmoid(k, entry.eval(weights))).powi(2))
+        .sum::<f64>()
+        / entries.len() as f64
+}
+
+// The K that best fits the current weights to the results, so that tuning moves the weights
+// rather than the scale of the whole eval. The error is unimodal in K, so a ternary search does.
+fn fit_k(entries: &[Entry], weights: &[f64]) -> f64 {
+    let (mut low, mut high) = K_RANGE;
+    for _ in 0..K_SEARCH_STEPS {
+        let a = low + (high - low) / 3.0;
+        let b = high - (high - low) / 3.0;
+        if error(entries, weights, a) < error(entries, weights, b) {
+            high = b;
+        } else {
+            low = a;
+        }
+    }
+    (low + high) / 2.0
+}
+
+// Texel Tuning
+// Tunes the eval's weights to predict game results: the eval of every position, put through a
+// sigmoid, should match the result of the game it was taken from. Every weight the eval is linear
+// in (piece values, piece-square tables and most term weights, see `EvalParams::weights`) is
+// optimized by gradient descent with Adam, which copes with weights as rarely used as a knight on
+// h8 as well as with the queen's value.
+struct Tuner {
+    // The starting weights, whose other term weights are kept as they are
+    base: EvalParams,
+    weights: Vec<f64>,
+    momentum: Vec<f64>,
+    velocity: Vec<f64>,
+    steps: i32,
+}
+
+impl Tuner {
+    fn new(params: &EvalParams) -> Self {
+        Self {
+            base: params.clone(),
+            weights: params.weights().into_iter().map(f64::from).collect(),
+            momentum: vec![0.0; PARAM_COUNT],
+            velocity: vec![0.0; PARAM_COUNT],
+            steps: 0,
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn step(&mut self, entries: &[Entry], k: f64, rate: f64) {
+        let mut gradient = vec![0.0; PARAM_COUNT];
+        for entry in entries {
+            let score = sigmoid(k, entry.eval(&self.weights));
+            let slope = (score - entry.result) * score * (1.0 - score) * entry.scale;
+            for &(i, coefficient) in &entry.features {
+                gradient[i] += slope * coefficient;
+            }
+        }
+        // Constant factors of the derivative, apart from the 2 of the square
+        let factor = k * std::f64::consts::LN_10 / 400.0 / entries.len() as f64;
+
+        self.steps += 1;
+        let momentum_correction = 1.0 - BETA1.powi(self.steps);
+        let velocity_correction = 1.0 - BETA2.powi(self.steps);
+        for (i, gradient) in gradient.into_iter().enumerate() {
+            let gradient = gradient * factor;
+            self.momentum[i] = BETA1 * self.momentum[i] + (1.0 - BETA1) * gradient;
+            self.velocity[i] = BETA2 * self.velocity[i] + (1.0 - BETA2) * gradient * gradient;
+            let momentum = self.momentum[i] / momentum_correction;
+            let velocity = self.velocity[i] / velocity_correction;
+            self.weights[i] -= rate * momentum / (velocity.sqrt() + EPSILON);
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn params(&self) -> EvalParams {
+        let weights = self
+            .weights
+            .iter()
+            .map(|weight| weight.round() as i32)
+            .collect::<Vec<_>>();
+        self.base.with_weights(&weights).unwrap()
+    }
+}
+
+// Positions are a FEN followed by the result, as 1-0, 1/2-1/2 or 0-1, or as 1.0, 0.5 or 0.0.
+// The result may be wrapped in brackets or quotes, and an EPD style `c9` opcode is skipped.
+fn parse_entry(line: &str) -> Option<(Board, f64)> {
+    let (fen, result) = line.trim().rsplit_once(char::is_whitespace)?;
+    let result = match result.trim_matches(|c| matches!(c, '[' | ']' | '"' | ';')) {
+        "1-0" | "1.0" | "1" => 1.0,
+        "1/2-1/2" | "0.5" => 0.5,
+        "0-1" | "0.0" | "0" => 0.0,
+        _ => return None,
+    };
+    let fen = fen.trim_end();
+    let fen = fen.strip_suffix(" c9").unwrap_or(fen).trim_end();
+    let board = Board::from_fen(fen, false).ok()?;
+    Some((board, result))
+}
+
+// Positions with insufficient material or a recognized ending are left out, as their eval doesn't
+// depend on the weights
+fn load_dataset(text: &str, params: &EvalParams) -> Result<Vec<Entry>, String> {
+    let mut entries = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (board, result) =
+            parse_entry(line).ok_or_else(|| format!("invalid position on line {}", n + 1))?;
+        if !is_insufficient_material(&board) && endgame::recognize(&board).is_none() {
+            entries.push(Entry::new(&board, result, params));
+        }
+    }
+    Ok(entries)
+}
+
+// Source of psqts.rs with the given weights, so tuned weights can be built in by replacing it
+fn psqts_source(params: &EvalParams) -> String {
+    let mut source = String::new();
+    for (name, table) in [
+        ("EG_TABLE", &params.eg_table),
+        ("MG_TABLE", &params.mg_table),
+    ] {
+        source += "#[rustfmt::skip]\n";
+        let _ = writeln!(source, "pub const {name}: [i32; 64 * 6] = [");
+        for (piece, squares) in table.chunks(64).enumerate() {
+            if piece > 0 {
+                source.push('\n');
+            }
+            for row in squares.chunks(8) {
+                let row = row.iter().fold(String::new(), |mut row, w| {
+                    let _ = write!(row, "{w:5},");
+                    row
+                });
+                let _ = writeln!(source, "   {row}");
+            }
+        }
+        source += "];\n\n";
+    }
+    let _ = writeln!(
+        source,
+        "pub const EG_VALUE: [i32; 6] = {:?};\n",
+        params.eg_value
+    );
+    let _ = writeln!(
+        source,
+        "pub const MG_VALUE: [i32; 6] = {:?};\n",
+        params.mg_value
+    );
+    let _ = writeln!(
+        source,
+        "pub const GAME_PHASE_INC: [i32; 6] = {GAME_PHASE_INC:?};"
+    );
+    source
+}
+
+// Entry point of the `tune` subcommand, which Texel tunes the piece values, piece-square tables
+// and linear term weights on a file of positions labeled with game results. The tuned values and
+// tables are printed as psqts.rs, or written to `--output`, and with `--weights` all the weights
+// are written as a weights file for `EvalFile`, the only place the term weights show up.
+// Usage: tune DATASET [--iterations N] [--rate R] [--start FILE] [--output FILE] [--weights FILE]
+pub fn run(args: &[String]) -> Result<(), String> {
+    let flags = Flags::parse(
+        args,
+        &["--iterations", "--rate", "--start", "--output", "--weights"],
+    )?;
+    let [path] = flags.positional() else {
+        return Err("expected a dataset".to_owned());
+    };
+    let iterations = flags.get_or("--iterations", DEFAULT_ITERATIONS)?;
+    let rate = flags.get_or("--rate", DEFAULT_RATE)?;
+    let start = match flags.get::<String>("--start")? {
+        Some(path) => EvalParams::load(Path::new(&path))
+            .map_err(|e| format!("could not load weights {path}: {e}"))?,
+        None => EvalParams::default(),
+    };
+
+    let text = fs::read_to_string(path).map_err(|e| format!("could not read {path}: {e}"))?;
+    let entries = load_dataset(&text, &start)?;
+    if entries.is_empty() {
+        return Err(format!("no positions in {path}"));
+    }
+    let mut tuner = Tuner::new(&start);
+    let k = fit_k(&entries, &tuner.weights);
+    eprintln!(
+        "positions {} k {k:.4} error {:.6}",
+        entries.len(),
+        error(&entries, &tuner.weights, k)
+    );
+
+    for iteration in 1..=iterations {
+        tuner.step(&entries, k, rate);
+        if iteration % REPORT_INTERVAL == 0 || iteration == iterations {
+            let error = error(&entries, &tuner.weights, k);
+            eprintln!("iteration {iteration} error {error:.6}");
+        }
+    }
+
+    let params = tuner.params();
+    let source = psqts_source(&params);
+    match flags.get::<String>("--output")? {
+        Some(path) => {
+            fs::write(&path, source).map_err(|e| format!("could not write {path}: {e}"))?
+        }
+        None => print!("{source}"),
+    }
+    if let Some(path) = flags.get::<String>("--weights")? {
+        params
+            .save(Path::new(&path))
+            .map_err(|e| format!("could not write {path}: {e}"))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use cozy_chess::Board;
+
+    use crate::{eval_params::BUILTIN, evaluate::psqt_eval, psqts};
+
+    use super::{error, fit_k, load_dataset, parse_entry, psqts_source, Entry, Tuner};
+
+    #[test]
+    fn parse_dataset_lines() {
+        let fen = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1";
+        for (line, result) in [
+            (format!("{fen} 1-0"), 1.0),
+            (format!("{fen} [0.5]"), 0.5),
+            (format!("{fen} c9 \"0-1\";"), 0.0),
+        ] {
+            let (board, parsed) = parse_entry(&line).unwrap();
+            assert_eq!(board.hash(), fen.parse::<Board>().unwrap().hash());
+            assert!((parsed - result).abs() < f64::EPSILON);
+        }
+        assert!(parse_entry(&format!("{fen} 2-0")).is_none());
+        assert!(load_dataset("not a position 1-0", &BUILTIN).is_err());
+    }
+
+    #[test]
+    fn tuning_lowers_the_error() {
+        let dataset = "\
+            4k3/8/8/8/8/8/PPP5/4K3 w - - 0 1 1-0
+            4k3/ppp5/8/8/8/8/8/4K3 w - - 0 1 0-1
+            4k3/pp6/8/8/8/8/PP6/4K3 w - - 0 1 1/2-1/2
+            4k3/8/8/8/8/8/8/3QK3 b - - 0 1 1-0
+            3qk3/8/8/8/8/8/P7/4K3 w - - 0 1 0-1";
+        let entries = load_dataset(dataset, &BUILTIN).unwrap();
+        // Kings alone are a draw by insufficient material, and are left out
+        assert_eq!(
+            load_dataset("4k3/8/8/8/8/8/8/4K3 w - - 0 1 1/2-1/2", &BUILTIN)
+                .unwrap()
+                .len(),
+            0
+        );
+        // The fixed part of the eval together with the weights gives back the real eval
+        let tuner = Tuner::new(&BUILTIN);
+        let eval = entries[3].eval(&tuner.weights);
+        assert!(eval > 900.0);
+
+        let mut tuner = Tuner::new(&BUILTIN);
+        let k = fit_k(&entries, &tuner.weights);
+        let before = error(&entries, &tuner.weights, k);
+        for _ in 0..100 {
+            tuner.step(&entries, k, 1.0);
+        }
+        assert!(error(&entries, &tuner.weights, k) < before);
+        assert_ne!(tuner.params(), BUILTIN);
+    }
+
+    #[test]
+    fn term_weights_are_features() {
+        // White's rook is on the seventh rank, with white to move
+        let board: Board = "6k1/R4ppp/8/8/8/8/5PPP/6K1 w - - 0 1".parse().unwrap();
+        let entry = Entry::new(&board, 1.0, &BUILTIN);
+        let mut params = BUILTIN.clone();
+        params.rook_seventh_rank[1] += 20;
+        let eval = |weights: Vec<i32>| {
+            let weights = weights.into_iter().map(f64::from).collect::<Vec<_>>();
+            entry.eval(&weights) / entry.scale
+        };
+        let change = eval(params.weights()) - eval(BUILTIN.weights());
+        let expected = psqt_eval(&board, &params) - psqt_eval(&board, &BUILTIN);
+        assert!(expected > 10);
+        assert!(
+            (change - f64::from(expected)).abs() < 1.0,
+            "{change} {expected}"
+        );
+    }
+
+    #[test]
+    fn builtin_weights_round_trip_as_source() {
+        let source = psqts_source(&BUILTIN);
+        assert!(source.contains(&format!(
+            "pub const MG_VALUE: [i32; 6] = {:?};",
+            psqts::MG_VALUE
+        )));
+        let weights = source
+            .split("pub const ")
+            .skip(1)
+            .map(|table| {
+                table
+                    .split_once('[')
+                    .and_then(|(_, rest)| rest.split_once("= ["))
+                    .map_or("", |(_, body)| body)
+                    .split(|c: char| c == ',' || c == ']' || c.is_whitespace())
+                    .take_while(|word| !word.starts_with(';'))
+                    .filter(|word| !word.is_empty())
+                    .map(|word| word.parse::<i32>().unwrap())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(weights[0], BUILTIN.eg_table);
+        assert_eq!(weights[1], BUILTIN.mg_table);
+    }
+}
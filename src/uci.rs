@@ -0,0 +1,803 @@
+use std::{
+    error::Error,
+    fmt,
+    io::{self, stdin},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{SendError, Sender},
+        Arc,
+    },
+    time::Duration,
+};
+
+use cozy_chess::{Board, Color, Move};
+use cozy_uci::{
+    command::UciCommand,
+    remark::{UciIdInfo, UciRemark},
+    UciFormatOptions, UciParseErrorKind,
+};
+use UciParseErrorKind::UnknownMessageKind;
+
+use crate::{
+    config::EngineConfig,
+    contempt::{ContemptSettings, Opponent, MAX_CONTEMPT},
+    personality::Personality,
+    positions::expand_named_position,
+    search::{SearchBackend, SearchMode, DEFAULT_ROOT_JITTER_MOVES},
+    search_params::Heuristic,
+    strength::{MAX_ELO, MAX_SKILL_LEVEL, MIN_ELO},
+    tablebase::MAX_TB_PIECES,
+    types::{Depth, Value},
+    uci_log::{log_line, set_log_file},
+    uci_println,
+    utils::{play_uci_moves, uci_to_kxr_move},
+};
+#[cfg(feature = "tune")]
+use crate::{
+    eval_params::param_options,
+    search_params::{spsa_inputs, PARAM_OPTIONS},
+};
+
+const MAX_VARIETY: Value = 100;
+const MAX_ROOT_JITTER: Value = 20;
+const MAX_ROOT_JITTER_MOVES: u16 = 100;
+pub const DEFAULT_HASH_MB: usize = 100;
+const MAX_HASH_MB: usize = 65536;
+const MAX_THREADS: usize = 256;
+const MAX_MULTI_PV: usize = 64;
+
+// Failures of the UCI thread. These end the session in an orderly way instead of panicking.
+#[derive(Debug)]
+pub enum UciError {
+    // Reading the GUI's input failed
+    Input(io::Error),
+    // The search thread has died, so nothing can be searched anymore
+    SearchThreadGone,
+}
+
+impl fmt::Display for UciError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Input(e) => write!(f, "could not read input: {e}"),
+            Self::SearchThreadGone => write!(f, "the search thread has stopped"),
+        }
+    }
+}
+
+impl Error for UciError {}
+
+impl From<SendError<ThreadMessage>> for UciError {
+    fn from(_: SendError<ThreadMessage>) -> Self {
+        Self::SearchThreadGone
+    }
+}
+
+// How long the search thread may think about a move
+#[derive(Debug, Clone, Copy)]
+pub enum TimeLimit {
+    // Remaining time on our clock and our increment
+    Clock {
+        time_left: Duration,
+        time_inc: Duration,
+        // Moves until the next time control, if the GUI sent `movestogo`
+        moves_to_go: Option<u32>,
+    },
+    // Fixed time for this move, as in `go movetime`
+    MoveTime(Duration),
+    // No time limit, the search ends on its other limits such as the node count
+    Unlimited,
+    // Search until told to stop
+    Infinite,
+}
+
+impl TimeLimit {
+    // Time to spend on the current move. No new iteration is started after it, see `TimeControl`.
+    pub fn move_time(self) -> Duration {
+        match self {
+            Self::Clock {
+                time_left,
+                time_inc,
+                moves_to_go,
+            } => {
+                // Without movestogo, assume the game lasts about 20 more moves. With it, the time
+                // left has to last until the next time control, keeping one move in reserve.
+                let moves_left = moves_to_go.map_or(20, |moves| moves + 1);
+                time_left / moves_left + time_inc / 2
+            }
+            Self::MoveTime(move_time) => move_time,
+            Self::Unlimited | Self::Infinite => Duration::MAX,
+        }
+    }
+
+    // Time after which the running iteration is aborted. On the clock an iteration may run well
+    // over the move time, as long as a good part of the time left stays in reserve. A fixed move
+    // time is never exceeded.
+    pub fn hard_time(self) -> Option<Duration> {
+        match self {
+            Self::Clock { time_left, .. } => {
+                Some((self.move_time() * 3).min(time_left / 2).max(self.move_time()))
+            }
+            Self::MoveTime(_) | Self::Unlimited | Self::Infinite => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ThreadMessage {
+    SearchTask {
+        board: Board,
+        moves: Vec<Move>,
+        limit: TimeLimit,
+        // Depth to stop deepening at, as in `go depth`
+        depth: Option<Depth>,
+        node_limit: Option<u64>,
+        // Only look for a mate in this many moves
+        mate: Option<usize>,
+        // Root moves to restrict the search to, empty for all moves
+        search_moves: Vec<Move>,
+        // Root moves the search must not play, from the nonstandard `go avoidmoves`
+        avoid_moves: Vec<Move>,
+    },
+    NewGame,
+    SetContempt(Value),
+    // Scales the eval terms of a playing style, see `Personality`
+    SetPersonality(Personality),
+    SetVariety(Value),
+    SetRootJitter(Value),
+    SetRootJitterMoves(u16),
+    // Search parameter by option name and value, see `SearchParams`
+    SetSearchParam(String, i32),
+    // Eval term weight by option name and value, see `eval_params::param_options`
+    SetEvalParam(String, i32),
+    // Enables or disables a search heuristic, for ablation tests
+    SetHeuristic(Heuristic, bool),
+    SetSeed(u64),
+    // See Deterministic Mode in `Searcher::search`
+    SetDeterministic(bool),
+    SetLimitStrength(bool),
+    SetElo(u32),
+    SetSkillLevel(u8),
+    // New TT size in MB
+    SetHash(usize),
+    ClearHash,
+    SetThreads(usize),
+    SetSearchMode(SearchMode),
+    SetSearchBackend(SearchBackend),
+    SetMultiPv(usize),
+    SetChess960(bool),
+    SetAnalyseMode(bool),
+    SetDebug(bool),
+    SetShowWdl(bool),
+    SetShowRefutations(bool),
+    SetShowCurrLine(bool),
+    SetEvalFile(String),
+    // Network to evaluate with instead of the piece-square tables, see `Network`
+    SetNnueFile(String),
+    // Path of the search trace, see `SearchTracer`
+    SetTraceFile(String),
+    // Runs the benchmark suite, like `./engine bench`
+    Bench,
+    // Prints the position for debugging, see `d`
+    Display {
+        board: Board,
+        moves: Vec<Move>,
+    },
+    Perft {
+        board: Board,
+        moves: Vec<Move>,
+        depth: u8,
+    },
+    SetSyzygyPath(String),
+    SetSyzygyProbeLimit(usize),
+    SetOwnBook(bool),
+    // Path of the Polyglot book
+    SetBookFile(String),
+    // Answered with `readyok` once the messages before it are handled
+    IsReady,
+    Quit,
+}
+
+// State of the UCI front end between commands
+#[derive(Debug)]
+pub struct UciState {
+    options: UciFormatOptions,
+    cur_board: Board,
+    moves: Vec<Move>,
+    contempt: ContemptSettings,
+    // Shared with the searcher, so that `stop` doesn't have to wait behind the running search
+    stop: Arc<AtomicBool>,
+    // Set while the searcher thinks on the opponent's time
+    ponder: Arc<AtomicBool>,
+    // Set from `go` until the search thread has sent `bestmove`
+    searching: Arc<AtomicBool>,
+}
+
+impl Default for UciState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UciState {
+    pub fn new() -> Self {
+        Self::with_flags(
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicBool::new(false)),
+            Arc::new(AtomicBool::new(false)),
+        )
+    }
+
+    pub fn with_flags(
+        stop: Arc<AtomicBool>,
+        ponder: Arc<AtomicBool>,
+        searching: Arc<AtomicBool>,
+    ) -> Self {
+        Self {
+            options: UciFormatOptions::default(),
+            cur_board: Board::startpos(),
+            moves: Vec::with_capacity(512),
+            contempt: ContemptSettings::default(),
+            stop,
+            ponder,
+            searching,
+        }
+    }
+
+    // Handles a single line of GUI input, forwarding work to the search thread through `tx`.
+    // Returns false once the GUI has asked the engine to quit.
+    pub fn handle_line(
+        &mut self,
+        line: &str,
+        tx: &Sender<ThreadMessage>,
+    ) -> Result<bool, UciError> {
+        let options = &self.options;
+        let expanded = expand_named_position(line);
+        let line = expanded.as_deref().unwrap_or(line);
+        if line.trim() == "bench" {
+            tx.send(ThreadMessage::Bench)?;
+            return Ok(true);
+        }
+        // Lists the tunable parameters in the format of OpenBench's SPSA inputs
+        #[cfg(feature = "tune")]
+        if line.trim() == "spsa" {
+            for input in spsa_inputs() {
+                uci_println!("{input}");
+            }
+            return Ok(true);
+        }
+        if line.trim() == "d" {
+            tx.send(ThreadMessage::Display {
+                board: self.cur_board.clone(),
+                moves: self.moves.clone(),
+            })?;
+            return Ok(true);
+        }
+        if let Some(depth) = parse_perft(line) {
+            tx.send(ThreadMessage::Perft {
+                board: self.cur_board.clone(),
+                moves: self.moves.clone(),
+                depth,
+            })?;
+            return Ok(true);
+        }
+
+        let (line, avoid_moves) = split_avoid_moves(line);
+
+        #[allow(clippy::match_same_arms)]
+        match UciCommand::parse_from(&line, options) {
+            Ok(cmd) => match cmd {
+                UciCommand::Uci => {
+                    uci_println!(
+                        "{:}",
+                        UciRemark::Id(UciIdInfo::Name("toy-engine".to_owned())).format(options)
+                    );
+
+                    uci_println!(
+                        "{:}",
+                        UciRemark::Id(UciIdInfo::Author("Aayush Sabharwal".to_owned()))
+                            .format(options)
+                    );
+
+                    uci_println!(
+                        "option name Contempt type spin default 0 min -{MAX_CONTEMPT} max {MAX_CONTEMPT}"
+                    );
+                    uci_println!("option name Auto Contempt type check default true");
+                    uci_println!(
+                        "option name Personality type combo default {} var {} var {} var {} var {}",
+                        Personality::default().name(),
+                        Personality::Balanced.name(),
+                        Personality::Aggressive.name(),
+                        Personality::Solid.name(),
+                        Personality::Materialist.name(),
+                    );
+                    uci_println!("option name Ponder type check default false");
+                    uci_println!("option name UCI_Chess960 type check default false");
+                    uci_println!("option name UCI_AnalyseMode type check default false");
+                    uci_println!("option name Log File type string default <empty>");
+                    uci_println!("option name UCI_ShowWDL type check default false");
+                    uci_println!("option name UCI_ShowRefutations type check default false");
+                    uci_println!("option name UCI_ShowCurrLine type check default false");
+                    uci_println!("option name EvalFile type string default <empty>");
+                    uci_println!("option name NnueFile type string default <empty>");
+                    uci_println!("option name TraceFile type string default <empty>");
+                    uci_println!("option name SyzygyPath type string default <empty>");
+                    uci_println!(
+                        "option name SyzygyProbeLimit type spin default {MAX_TB_PIECES} min 0 max {MAX_TB_PIECES}"
+                    );
+                    uci_println!("option name OwnBook type check default false");
+                    uci_println!("option name Book type string default <empty>");
+                    uci_println!(
+                        "option name UCI_Opponent type string default none none computer none"
+                    );
+
+                    uci_println!(
+                        "option name Hash type spin default {DEFAULT_HASH_MB} min 1 max {MAX_HASH_MB}"
+                    );
+                    uci_println!("option name Clear Hash type button");
+                    uci_println!("option name Threads type spin default 1 min 1 max {MAX_THREADS}");
+                    uci_println!(
+                        "option name SearchMode type combo default {} var {} var {}",
+                        SearchMode::default().name(),
+                        SearchMode::LazySmp.name(),
+                        SearchMode::Abdada.name(),
+                    );
+                    uci_println!(
+                        "option name SearchBackend type combo default {} var {} var {}",
+                        SearchBackend::default().name(),
+                        SearchBackend::AlphaBeta.name(),
+                        SearchBackend::Mcts.name(),
+                    );
+                    uci_println!(
+                        "option name MultiPV type spin default 1 min 1 max {MAX_MULTI_PV}"
+                    );
+                    uci_println!("option name Variety type spin default 0 min 0 max {MAX_VARIETY}");
+                    uci_println!(
+                        "option name RootJitter type spin default 0 min 0 max {MAX_ROOT_JITTER}"
+                    );
+                    uci_println!(
+                        "option name RootJitterMoves type spin default {DEFAULT_ROOT_JITTER_MOVES} min 0 max {MAX_ROOT_JITTER_MOVES}"
+                    );
+                    for heuristic in Heuristic::ALL {
+                        uci_println!(
+                            "option name {} type check default true",
+                            heuristic.option_name()
+                        );
+                    }
+                    #[cfg(feature = "tune")]
+                    for &(name, default, min, max) in PARAM_OPTIONS {
+                        uci_println!(
+                            "option name {name} type spin default {default} min {min} max {max}"
+                        );
+                    }
+                    #[cfg(feature = "tune")]
+                    for (name, default, min, max) in param_options() {
+                        uci_println!(
+                            "option name {name} type spin default {default} min {min} max {max}"
+                        );
+                    }
+                    uci_println!("option name UCI_LimitStrength type check default false");
+                    uci_println!(
+                        "option name UCI_Elo type spin default {MAX_ELO} min {MIN_ELO} max {MAX_ELO}"
+                    );
+                    uci_println!(
+                        "option name Skill Level type spin default {MAX_SKILL_LEVEL} min 0 max {MAX_SKILL_LEVEL}"
+                    );
+                    uci_println!("option name Seed type spin default 0 min 0 max 2147483647");
+                    uci_println!("option name Deterministic type check default false");
+
+                    uci_println!("{:}", UciRemark::UciOk.format(options));
+                }
+                UciCommand::Debug(enabled) => tx.send(ThreadMessage::SetDebug(enabled))?,
+                UciCommand::IsReady => {
+                    // A running search doesn't keep the engine from answering. Otherwise the
+                    // search thread answers, once it has applied e.g. a new hash size.
+                    if self.searching.load(Ordering::Relaxed) {
+                        uci_println!("{:}", UciRemark::ReadyOk.format(options));
+                    } else {
+                        tx.send(ThreadMessage::IsReady)?;
+                    }
+                }
+                UciCommand::Position {
+                    init_pos,
+                    moves: mvs,
+                } => {
+                    self.cur_board = Board::from(init_pos);
+
+                    // Check every move against the position it is played in, so that the search
+                    // thread never sees an illegal one
+                    let mut board = self.cur_board.clone();
+                    self.moves.clear();
+                    for mv in mvs {
+                        let mut kxr_mv = mv;
+                        uci_to_kxr_move(&board, &mut kxr_mv, options.chess960);
+                        if !board.is_legal(kxr_mv) {
+                            uci_println!(
+                                "info string illegal move {mv} after {} moves, ignoring the rest",
+                                self.moves.len()
+                            );
+                            break;
+                        }
+                        board.play_unchecked(kxr_mv);
+                        self.moves.push(mv);
+                    }
+                }
+                UciCommand::SetOption { name, value } => {
+                    let value = value.unwrap_or_default();
+                    #[cfg(feature = "tune")]
+                    if let Some(&(option, ..)) = PARAM_OPTIONS
+                        .iter()
+                        .find(|(option, ..)| name.eq_ignore_ascii_case(option))
+                    {
+                        if let Ok(v) = value.parse::<i32>() {
+                            tx.send(ThreadMessage::SetSearchParam(option.to_owned(), v))?;
+                        }
+                        return Ok(true);
+                    }
+                    #[cfg(feature = "tune")]
+                    if let Some((option, ..)) = param_options()
+                        .into_iter()
+                        .find(|(option, ..)| name.eq_ignore_ascii_case(option))
+                    {
+                        if let Ok(v) = value.parse::<i32>() {
+                            tx.send(ThreadMessage::SetEvalParam(option, v))?;
+                        }
+                        return Ok(true);
+                    }
+                    if let Some(heuristic) = Heuristic::parse_option(&name) {
+                        tx.send(ThreadMessage::SetHeuristic(heuristic, value == "true"))?;
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("OwnBook") {
+                        tx.send(ThreadMessage::SetOwnBook(value == "true"))?;
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("Book") {
+                        tx.send(ThreadMessage::SetBookFile(value))?;
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("EvalFile") {
+                        tx.send(ThreadMessage::SetEvalFile(value))?;
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("NnueFile") {
+                        tx.send(ThreadMessage::SetNnueFile(value))?;
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("TraceFile") {
+                        tx.send(ThreadMessage::SetTraceFile(value))?;
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("SyzygyPath") {
+                        tx.send(ThreadMessage::SetSyzygyPath(value))?;
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("SyzygyProbeLimit") {
+                        if let Ok(pieces) = value.parse::<usize>() {
+                            tx.send(ThreadMessage::SetSyzygyProbeLimit(
+                                pieces.min(MAX_TB_PIECES),
+                            ))?;
+                        }
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("Log File") {
+                        // Handled here rather than in the search thread, to not miss any input
+                        let path =
+                            Some(value.as_str()).filter(|p| !p.is_empty() && *p != "<empty>");
+                        if let Err(e) = set_log_file(path.map(Path::new)) {
+                            uci_println!("info string could not open log file: {e}");
+                        }
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("UCI_ShowRefutations") {
+                        tx.send(ThreadMessage::SetShowRefutations(value == "true"))?;
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("UCI_ShowCurrLine") {
+                        tx.send(ThreadMessage::SetShowCurrLine(value == "true"))?;
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("UCI_ShowWDL") {
+                        self.options.wdl = value == "true";
+                        tx.send(ThreadMessage::SetShowWdl(self.options.wdl))?;
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("UCI_Chess960") {
+                        // Also makes FENs with Shredder castling rights parse
+                        self.options.chess960 = value == "true";
+                        tx.send(ThreadMessage::SetChess960(self.options.chess960))?;
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("Hash") {
+                        if let Ok(mb) = value.parse::<usize>() {
+                            tx.send(ThreadMessage::SetHash(mb.clamp(1, MAX_HASH_MB)))?;
+                        }
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("UCI_LimitStrength") {
+                        tx.send(ThreadMessage::SetLimitStrength(value == "true"))?;
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("UCI_Elo") {
+                        if let Ok(elo) = value.parse::<u32>() {
+                            tx.send(ThreadMessage::SetElo(elo.clamp(MIN_ELO, MAX_ELO)))?;
+                        }
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("Skill Level") {
+                        if let Ok(level) = value.parse::<u8>() {
+                            tx.send(ThreadMessage::SetSkillLevel(level))?;
+                        }
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("Clear Hash") {
+                        tx.send(ThreadMessage::ClearHash)?;
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("Threads") {
+                        if let Ok(threads) = value.parse::<usize>() {
+                            tx.send(ThreadMessage::SetThreads(threads.clamp(1, MAX_THREADS)))?;
+                        }
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("SearchMode") {
+                        if let Some(mode) = SearchMode::parse(&value) {
+                            tx.send(ThreadMessage::SetSearchMode(mode))?;
+                        }
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("SearchBackend") {
+                        if let Some(backend) = SearchBackend::parse(&value) {
+                            tx.send(ThreadMessage::SetSearchBackend(backend))?;
+                        }
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("MultiPV") {
+                        if let Ok(lines) = value.parse::<usize>() {
+                            tx.send(ThreadMessage::SetMultiPv(lines.clamp(1, MAX_MULTI_PV)))?;
+                        }
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("Variety") {
+                        if let Ok(v) = value.parse::<Value>() {
+                            tx.send(ThreadMessage::SetVariety(v.clamp(0, MAX_VARIETY)))?;
+                        }
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("RootJitter") {
+                        if let Ok(v) = value.parse::<Value>() {
+                            tx.send(ThreadMessage::SetRootJitter(v.clamp(0, MAX_ROOT_JITTER)))?;
+                        }
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("RootJitterMoves") {
+                        if let Ok(moves) = value.parse::<u16>() {
+                            let moves = moves.min(MAX_ROOT_JITTER_MOVES);
+                            tx.send(ThreadMessage::SetRootJitterMoves(moves))?;
+                        }
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("Deterministic") {
+                        tx.send(ThreadMessage::SetDeterministic(value == "true"))?;
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("Seed") {
+                        if let Ok(seed) = value.parse::<u64>() {
+                            tx.send(ThreadMessage::SetSeed(seed))?;
+                        }
+                        return Ok(true);
+                    } else if name.eq_ignore_ascii_case("Contempt") {
+                        if let Ok(v) = value.parse::<Value>() {
+                            self.contempt.manual = v.clamp(-MAX_CONTEMPT, MAX_CONTEMPT);
+                        }
+                    } else if name.eq_ignore_ascii_case("Auto Contempt") {
+                        self.contempt.auto = value == "true";
+                    } else if name.eq_ignore_ascii_case("Personality") {
+                        if let Some(personality) = Personality::parse(&value) {
+                            self.contempt.personality = personality.contempt();
+                            tx.send(ThreadMessage::SetPersonality(personality))?;
+                        }
+                    } else if name.eq_ignore_ascii_case("UCI_Opponent") {
+                        self.contempt.opponent = Opponent::parse(&value);
+                    } else if name.eq_ignore_ascii_case("UCI_AnalyseMode") {
+                        self.contempt.analyse_mode = value == "true";
+                        tx.send(ThreadMessage::SetAnalyseMode(self.contempt.analyse_mode))?;
+                    } else {
+                        return Ok(true);
+                    }
+                    tx.send(ThreadMessage::SetContempt(self.contempt.contempt()))?;
+                }
+                UciCommand::UciNewGame => {
+                    tx.send(ThreadMessage::NewGame)?;
+                }
+                UciCommand::Stop => {
+                    self.ponder.store(false, Ordering::Relaxed);
+                    self.stop.store(true, Ordering::Relaxed);
+                }
+                // The ponder move was played, so the search continues as a normal timed search
+                UciCommand::PonderHit => self.ponder.store(false, Ordering::Relaxed),
+                UciCommand::Quit => {
+                    // Abort a running search so the search thread sees the message right away
+                    self.stop.store(true, Ordering::Relaxed);
+                    tx.send(ThreadMessage::Quit)?;
+                    return Ok(false);
+                }
+                UciCommand::Go(opts) => {
+                    self.stop.store(false, Ordering::Relaxed);
+                    self.searching.store(true, Ordering::Relaxed);
+                    self.ponder.store(opts.ponder, Ordering::Relaxed);
+                    let position = play_uci_moves(&self.cur_board, &self.moves, options.chess960);
+                    let (time_left, time_inc) = match position.side_to_move() {
+                        Color::White => (opts.wtime, opts.winc),
+                        Color::Black => (opts.btime, opts.binc),
+                    };
+                    let limit = if opts.infinite {
+                        TimeLimit::Infinite
+                    } else if let Some(move_time) = opts.movetime {
+                        TimeLimit::MoveTime(move_time)
+                    } else if let Some(time_left) = time_left {
+                        TimeLimit::Clock {
+                            time_left,
+                            time_inc: time_inc.unwrap_or_default(),
+                            moves_to_go: opts.movestogo,
+                        }
+                    } else if opts.depth.is_some() || opts.nodes.is_some() || opts.mate.is_some() {
+                        // Without our clock, searches with other limits end on those
+                        TimeLimit::Unlimited
+                    } else {
+                        // A bare `go` analyses until `stop`, like most engines do
+                        TimeLimit::Infinite
+                    };
+                    tx.send(ThreadMessage::SearchTask {
+                        board: self.cur_board.clone(),
+                        moves: self.moves.clone(),
+                        limit,
+                        depth: opts
+                            .depth
+                            .map(|depth| Depth::try_from(depth).unwrap_or(Depth::MAX)),
+                        node_limit: opts.nodes,
+                        mate: opts.mate.map(|n| n as usize),
+                        search_moves: opts.searchmoves,
+                        avoid_moves,
+                    })?;
+                }
+            },
+            Err(err) => {
+                if !matches!(err.kind, UnknownMessageKind(_)) {
+                    uci_println!("info string {err}");
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+// Accepts both `perft N` and `go perft N`, which cozy-uci doesn't know about
+fn parse_perft(line: &str) -> Option<u8> {
+    match line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["perft", depth] | ["go", "perft", depth] => depth.parse().ok(),
+        _ => None,
+    }
+}
+
+// Takes the moves after `avoidmoves` out of a `go` line, which cozy-uci doesn't know about. Like
+// `searchmoves`, the list ends at the first token that isn't a move, so that other limits may
+// follow it.
+fn split_avoid_moves(line: &str) -> (String, Vec<Move>) {
+    let mut tokens = line.split_whitespace();
+    if tokens.next() != Some("go") || !line.split_whitespace().any(|t| t == "avoidmoves") {
+        return (line.to_owned(), Vec::new());
+    }
+
+    let mut rest = vec!["go"];
+    let mut avoid_moves = Vec::new();
+    while let Some(token) = tokens.next() {
+        if token != "avoidmoves" {
+            rest.push(token);
+            continue;
+        }
+        for token in tokens.by_ref() {
+            match token.parse() {
+                Ok(mv) => avoid_moves.push(mv),
+                Err(_) => {
+                    rest.push(token);
+                    break;
+                }
+            }
+        }
+    }
+    (rest.join(" "), avoid_moves)
+}
+
+#[allow(clippy::needless_pass_by_value)]
+pub fn uci_handler(
+    tx: Sender<ThreadMessage>,
+    stop: Arc<AtomicBool>,
+    ponder: Arc<AtomicBool>,
+    searching: Arc<AtomicBool>,
+) -> Result<(), UciError> {
+    let mut state = UciState::with_flags(stop, ponder, searching);
+
+    // Defaults from the config file go through the same path as the GUI's options, before any
+    // input is read, so that the GUI can still override them
+    match EngineConfig::load_default() {
+        Ok(config) => {
+            for (name, value) in &config.options {
+                state.handle_line(&format!("setoption name {name} value {value}"), &tx)?;
+            }
+        }
+        Err(e) => uci_println!("info string {e}"),
+    }
+
+    loop {
+        let mut line = String::new();
+        match stdin().read_line(&mut line) {
+            // The GUI closing our input is treated like `quit`
+            Ok(0) => line.push_str("quit"),
+            Ok(_) => {}
+            // A line that isn't UTF-8 is skipped like any other garbage
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                uci_println!("info string ignoring unreadable input");
+                continue;
+            }
+            // Without input the engine can't continue, but the search thread still has to quit
+            Err(e) => {
+                let _ = state.handle_line("quit", &tx);
+                return Err(UciError::Input(e));
+            }
+        }
+        log_line(">>", line.trim_end());
+        if !state.handle_line(&line, &tx)? {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{sync::mpsc, time::Duration};
+
+    use super::{split_avoid_moves, ThreadMessage, TimeLimit, UciError, UciState};
+
+    #[test]
+    fn hard_time_keeps_a_reserve() {
+        let limit = TimeLimit::Clock {
+            time_left: Duration::from_secs(60),
+            time_inc: Duration::ZERO,
+            moves_to_go: None,
+        };
+        assert_eq!(limit.move_time(), Duration::from_secs(3));
+        assert_eq!(limit.hard_time(), Some(Duration::from_secs(9)));
+
+        let limit = TimeLimit::Clock {
+            time_left: Duration::from_secs(10),
+            time_inc: Duration::ZERO,
+            moves_to_go: Some(1),
+        };
+        assert_eq!(limit.hard_time(), Some(Duration::from_secs(5)));
+        assert_eq!(TimeLimit::MoveTime(Duration::from_secs(1)).hard_time(), None);
+    }
+
+    #[test]
+    fn illegal_moves_are_dropped() {
+        let (tx, _rx) = mpsc::channel();
+        let mut state = UciState::new();
+        state
+            .handle_line("position startpos moves e2e4 e7e5 e4e5 g1f3", &tx)
+            .unwrap();
+        assert_eq!(state.moves.len(), 2);
+        state
+            .handle_line("position startpos moves e2e4 e7e5 e1g1", &tx)
+            .unwrap();
+        assert_eq!(state.moves.len(), 2);
+    }
+
+    #[test]
+    fn avoid_moves_are_split_off() {
+        let (line, moves) = split_avoid_moves("go avoidmoves e2e4 d2d4 depth 10");
+        assert_eq!(line, "go depth 10");
+        let moves = moves.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(moves, ["e2e4", "d2d4"]);
+        let (line, moves) = split_avoid_moves("go depth 10");
+        assert_eq!((line.as_str(), moves.len()), ("go depth 10", 0));
+    }
+
+    #[test]
+    fn go_depth_ends_without_stop() {
+        let (tx, rx) = mpsc::channel();
+        let mut state = UciState::new();
+        state.handle_line("position startpos", &tx).unwrap();
+        state.handle_line("go depth 6", &tx).unwrap();
+        let Some(ThreadMessage::SearchTask { limit, depth, .. }) = rx.try_iter().last() else {
+            panic!("go should start a search");
+        };
+        assert!(matches!(limit, TimeLimit::Unlimited));
+        assert_eq!(depth, Some(6));
+    }
+
+    #[test]
+    fn dead_search_thread_is_an_error() {
+        let (tx, rx) = mpsc::channel();
+        drop(rx);
+        let mut state = UciState::new();
+        assert!(matches!(
+            state.handle_line("ucinewgame", &tx),
+            Err(UciError::SearchThreadGone)
+        ));
+        assert!(state.handle_line("position startpos", &tx).unwrap());
+    }
+}
@@ -0,0 +1,59 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::{Mutex, PoisonError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// Where UCI traffic is mirrored to, if anywhere. Global since output comes from several threads.
+static LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+
+// Starts appending to the file at `path`, or stops logging for `None`
+pub fn set_log_file(path: Option<&Path>) -> io::Result<()> {
+    let file = match path {
+        Some(path) => Some(OpenOptions::new().create(true).append(true).open(path)?),
+        None => None,
+    };
+    *LOG_FILE.lock().unwrap_or_else(PoisonError::into_inner) = file;
+    Ok(())
+}
+
+// Writes one line of traffic with a timestamp in seconds since the epoch. `>>` marks input from
+// the GUI and `<<` output from the engine.
+pub fn log_line(direction: &str, line: &str) {
+    let mut file = LOG_FILE.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(file) = file.as_mut() {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        // Losing a log line is better than losing the game over it
+        let _ = writeln!(
+            file,
+            "{}.{:03} {direction} {line}",
+            time.as_secs(),
+            time.subsec_millis()
+        );
+    }
+}
+
+// Writes a line to stdout. Unlike `println!` this doesn't panic when the GUI has closed our
+// output, since there is no one left to tell about it.
+pub fn print_line(line: &str) {
+    let mut stdout = io::stdout().lock();
+    let _ = writeln!(stdout, "{line}");
+    let _ = stdout.flush();
+}
+
+// `println!` for UCI output, which also goes to the log file
+#[macro_export]
+macro_rules! uci_println {
+    () => {
+        $crate::uci_println!("")
+    };
+    ($($arg:tt)*) => {{
+        let line = format!($($arg)*);
+        $crate::uci_log::print_line(&line);
+        $crate::uci_log::log_line("<<", &line);
+    }};
+}
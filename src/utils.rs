@@ -1,4 +1,4 @@
-use cozy_chess::{Board, Move, Piece, Square};
+use cozy_chess::{Board, Color, File, Move, Piece, Rank, Square};
 
 pub const NULL_MOVE: Move = Move {
     from: Square::A1,
@@ -6,26 +6,134 @@ pub const NULL_MOVE: Move = Move {
     promotion: None,
 };
 
-pub fn uci_to_kxr_move(board: &Board, mv: &mut Move) {
-    if board.piece_on(mv.from) == Some(Piece::King) && board.piece_on(mv.to) != Some(Piece::Rook) {
-        mv.to = match (mv.from, mv.to) {
-            (Square::E1, Square::G1) => Square::H1,
-            (Square::E8, Square::G8) => Square::H8,
-            (Square::E1, Square::C1) => Square::A1,
-            (Square::E8, Square::C8) => Square::A8,
-            _ => mv.to,
-        };
+// cozy-chess encodes castling as the king capturing its own rook, which is also the UCI notation
+// for Chess960. Standard UCI notation moves the king two squares towards the rook instead.
+pub fn uci_to_kxr_move(board: &Board, mv: &mut Move, chess960: bool) {
+    if chess960 || board.piece_on(mv.from) != Some(Piece::King) || mv.from.rank() != mv.to.rank() {
+        return;
+    }
+    let from_file = mv.from.file() as i8;
+    let to_file = mv.to.file() as i8;
+    if (to_file - from_file).abs() != 2 {
+        return;
+    }
+
+    let rights = board.castle_rights(board.side_to_move());
+    let rook_file = if to_file > from_file {
+        rights.short
+    } else {
+        rights.long
+    };
+    if let Some(file) = rook_file {
+        mv.to = Square::new(file, mv.from.rank());
     }
 }
 
-pub fn kxr_to_uci_move(board: &Board, mv: &mut Move) {
-    if board.piece_on(mv.from) == Some(Piece::King) && board.piece_on(mv.to) == Some(Piece::Rook) {
-        mv.to = match (mv.from, mv.to) {
-            (Square::E1, Square::H1) => Square::G1,
-            (Square::E8, Square::H8) => Square::G8,
-            (Square::E1, Square::A1) => Square::C1,
-            (Square::E8, Square::A8) => Square::C8,
-            _ => mv.to,
+pub fn kxr_to_uci_move(board: &Board, mv: &mut Move, chess960: bool) {
+    if !chess960
+        && board.piece_on(mv.from) == Some(Piece::King)
+        && board.piece_on(mv.to) == Some(Piece::Rook)
+        && board.color_on(mv.to) == board.color_on(mv.from)
+    {
+        let file = if mv.to.file() > mv.from.file() {
+            File::G
+        } else {
+            File::C
         };
+        mv.to = Square::new(file, mv.from.rank());
+    }
+}
+
+pub fn to_uci_move(board: &Board, mut mv: Move, chess960: bool) -> Move {
+    kxr_to_uci_move(board, &mut mv, chess960);
+    mv
+}
+
+// The position reached by playing moves given in UCI notation, up to the first illegal one
+pub fn play_uci_moves(board: &Board, moves: &[Move], chess960: bool) -> Board {
+    let mut board = board.clone();
+    for &mv in moves {
+        let mut mv = mv;
+        uci_to_kxr_move(&board, &mut mv, chess960);
+        if !board.is_legal(mv) {
+            break;
+        }
+        board.play_unchecked(mv);
+    }
+    board
+}
+
+// Board diagram with white at the bottom, uppercase letters being white pieces
+pub fn ascii_board(board: &Board) -> String {
+    let separator = " +---+---+---+---+---+---+---+---+\n";
+    let mut out = String::from(separator);
+    for &rank in Rank::ALL.iter().rev() {
+        for &file in &File::ALL {
+            let sq = Square::new(file, rank);
+            let symbol = board.piece_on(sq).map_or(' ', |piece| {
+                let symbol = match piece {
+                    Piece::Pawn => 'p',
+                    Piece::Knight => 'n',
+                    Piece::Bishop => 'b',
+                    Piece::Rook => 'r',
+                    Piece::Queen => 'q',
+                    Piece::King => 'k',
+                };
+                if board.color_on(sq) == Some(Color::White) {
+                    symbol.to_ascii_uppercase()
+                } else {
+                    symbol
+                }
+            });
+            out.push_str(" | ");
+            out.push(symbol);
+        }
+        out.push_str(" | ");
+        out.push(char::from(b'1' + rank as u8));
+        out.push('\n');
+        out.push_str(separator);
+    }
+    out.push_str("   a   b   c   d   e   f   g   h\n");
+    out
+}
+
+// Converts a line of moves into UCI notation, which needs the board each move is played on
+pub fn uci_line(board: &Board, line: &[Move], chess960: bool) -> Vec<Move> {
+    let mut board = board.clone();
+    line.iter()
+        .map(|&mv| {
+            let uci_mv = to_uci_move(&board, mv, chess960);
+            board.play_unchecked(mv);
+            uci_mv
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use cozy_chess::{Board, Move};
+
+    use super::{to_uci_move, uci_to_kxr_move};
+
+    fn round_trip(board: &Board, uci: &str, kxr: &str, chess960: bool) {
+        let mut mv = uci.parse::<Move>().unwrap();
+        uci_to_kxr_move(board, &mut mv, chess960);
+        assert_eq!(mv, kxr.parse::<Move>().unwrap());
+        assert!(board.is_legal(mv));
+        assert_eq!(
+            to_uci_move(board, mv, chess960),
+            uci.parse::<Move>().unwrap()
+        );
+    }
+
+    #[test]
+    fn castling_notation() {
+        let board = Board::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", false).unwrap();
+        round_trip(&board, "e1g1", "e1h1", false);
+        round_trip(&board, "e1c1", "e1a1", false);
+
+        // King on b1 with rooks on a1 and h1
+        let board = Board::from_fen("1k5r/8/8/8/8/8/8/RK5R w HAh - 0 1", true).unwrap();
+        round_trip(&board, "b1h1", "b1h1", true);
     }
 }
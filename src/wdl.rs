@@ -0,0 +1,51 @@
+use crate::{search::is_mate_score, types::Value};
+
+// Centipawn score at which a win and a draw are equally likely
+const WIN_SCORE: f64 = 200.0;
+// How quickly the win probability grows around `WIN_SCORE`
+const WIN_SCALE: f64 = 80.0;
+
+// Win, draw and loss chances in permille for the side to move, following the usual logistic model:
+// every extra pawn of advantage makes a win more likely and a loss less likely, and whatever is
+// left over is a draw.
+pub fn wdl(value: Value) -> (u32, u32, u32) {
+    if is_mate_score(value) {
+        return if value > 0 {
+            (1000, 0, 0)
+        } else {
+            (0, 0, 1000)
+        };
+    }
+    let win = win_rate(f64::from(value));
+    let loss = win_rate(-f64::from(value));
+    (win, 1000 - win - loss, loss)
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn win_rate(value: f64) -> u32 {
+    (1000.0 / (1.0 + ((WIN_SCORE - value) / WIN_SCALE).exp())).round() as u32
+}
+
+// Formats the `wdl` field of UCI info lines
+pub fn uci_wdl(value: Value) -> String {
+    let (win, draw, loss) = wdl(value);
+    format!("wdl {win} {draw} {loss}")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::search::MATE_VALUE;
+
+    use super::wdl;
+
+    #[test]
+    fn wdl_is_consistent() {
+        for value in [-1000, -250, -30, 0, 30, 250, 1000] {
+            let (win, draw, loss) = wdl(value);
+            assert_eq!(win + draw + loss, 1000);
+            assert_eq!(wdl(-value), (loss, draw, win));
+        }
+        assert!(wdl(100).0 > wdl(50).0);
+        assert_eq!(wdl(MATE_VALUE - 3), (1000, 0, 0));
+    }
+}